@@ -0,0 +1,285 @@
+//! The `ras_asm!` macro, which assembles AT&T syntax at compile time.
+
+mod statement;
+
+use proc_macro2::{Ident, Literal, Span, TokenStream};
+use quote::{quote, quote_spanned};
+use ras_x86::assembler::{Assembler, Item};
+use ras_x86::builder::CodeBuilder;
+use ras_x86::error::{ParseError, ParseErrorKind, RasError};
+use ras_x86::instruction::Instruction;
+use ras_x86::parser::parse_asm;
+
+use statement::{InstructionTokens, OperandTokens, PlaceholderKind, Statement};
+
+use std::collections::HashSet;
+
+/// A compile error, and the span of the token it's reported on.
+type Error = (Span, String);
+
+/// Assemble AT&T syntax (in long mode) at compile time.
+///
+/// The instructions are separated by `;`, and the macro expands to a `&'static [u8]`:
+///
+/// ```
+/// use ras_x86_macros::ras_asm;
+///
+/// let code: &'static [u8] = ras_asm! {
+///     mov %rdi, %rax;
+///     loop: dec %rax;
+///     jnz loop;
+///     ret
+/// };
+/// assert_eq!(code[..3], [0x48, 0x89, 0xf8]);
+/// ```
+///
+/// The instructions that can't be parsed or encoded are compile errors, which are reported on
+/// the mnemonic or on the operand that caused them:
+///
+/// ```compile_fail
+/// # use ras_x86_macros::ras_asm;
+/// let code = ras_asm! { mov %rdi, %eax };
+/// ```
+///
+/// Runtime values can be used as immediates (`$#value`) or as registers (`%#value`), where
+/// `value` is an identifier or a parenthesized expression that can be converted into an
+/// `Immediate` or a `Register`. An instruction with placeholders can only be encoded once the
+/// values are known, so in this case the macro expands to an expression that assembles the code
+/// at runtime, and returns a `RasResult<Vec<u8>>`:
+///
+/// ```
+/// use ras_x86::RDI;
+/// use ras_x86_macros::ras_asm;
+///
+/// let count = 3;
+/// let code = ras_asm! { mov $#(count * 2), %#RDI; ret }.unwrap();
+/// assert_eq!(code, [0x48, 0xc7, 0xc7, 0x06, 0x00, 0x00, 0x00, 0xc3]);
+/// ```
+///
+/// The expansion refers to `ras_x86`, so the crates that use placeholders must depend on
+/// `ras-x86` too.
+#[proc_macro]
+pub fn ras_asm(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let output = expand(input.into()).unwrap_or_else(|errors| {
+        let errors = errors
+            .into_iter()
+            .map(|(span, msg)| quote_spanned!(span=> compile_error!(#msg);));
+        quote! {{ #(#errors)* }}
+    });
+
+    output.into()
+}
+
+/// An operand to replace with the value of a placeholder at runtime.
+struct Substitution {
+    /// The index of the instruction in the parsed items.
+    item: usize,
+    /// The index of the operand (in Intel order).
+    operand: usize,
+    kind: PlaceholderKind,
+    expr: TokenStream,
+}
+
+fn expand(input: TokenStream) -> Result<TokenStream, Vec<Error>> {
+    let statements = statement::parse_statements(input)?;
+
+    let mut errors = vec![];
+    // Each statement is assembled as a separate line (so it's parsed into exactly one item).
+    let mut lines = vec![];
+    let mut labels = HashSet::new();
+    let mut substitutions = vec![];
+
+    for statement in &statements {
+        match statement {
+            Statement::Label(label) => {
+                if !labels.insert(label.name.as_str()) {
+                    let err = RasError::DuplicateLabel(label.name.clone());
+                    errors.push((label.span, err.to_string()));
+                }
+                lines.push(label.text());
+            }
+            Statement::Instruction(inst) => {
+                match check_instruction(inst) {
+                    Ok(operands) => {
+                        let placeholders = operands.into_iter().zip(inst.placeholders());
+                        substitutions.extend(placeholders.map(|(operand, placeholder)| {
+                            Substitution {
+                                item: lines.len(),
+                                operand,
+                                kind: placeholder.kind,
+                                expr: placeholder.expr.clone(),
+                            }
+                        }));
+                    }
+                    Err(err) => errors.push(err),
+                }
+                lines.push(inst.text(None));
+            }
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let text = lines.join("\n");
+    if substitutions.is_empty() {
+        let code = assemble(&text, &statements)?
+            .into_iter()
+            .map(Literal::u8_suffixed);
+
+        return Ok(quote! {{
+            let code: &'static [u8] = &[#(#code),*];
+            code
+        }});
+    }
+
+    // The identifiers of the expansion can't be referred to by the placeholder expressions.
+    let items = Ident::new("items", Span::mixed_site());
+    let inst = Ident::new("inst", Span::mixed_site());
+    let substitutions = substitutions.iter().map(|sub| {
+        let Substitution {
+            item,
+            operand,
+            kind,
+            expr,
+        } = sub;
+        let value = match kind {
+            PlaceholderKind::Immediate => quote! {
+                ::ras_x86::operand::Operand::Immediate(
+                    ::std::convert::Into::<::ras_x86::operand::Immediate>::into(#expr)
+                )
+            },
+            PlaceholderKind::Register => quote! {
+                ::ras_x86::operand::Operand::Register(
+                    ::std::convert::Into::<::ras_x86::operand::Register>::into(#expr)
+                )
+            },
+        };
+
+        quote! {
+            match &mut #items[#item] {
+                ::ras_x86::assembler::Item::Instruction(#inst) => {
+                    #inst.operands_mut()[#operand] = #value;
+                }
+                _ => ::std::unreachable!(),
+            }
+        }
+    });
+
+    Ok(quote! {{
+        let mut #items = ::ras_x86::parser::parse_asm(#text)
+            .expect("the assembly was checked at compile time");
+        #(#substitutions)*
+        ::ras_x86::assembler::Assembler::long_mode()
+            .items(#items)
+            .dump_text()
+    }})
+}
+
+/// Check that the instruction can be parsed, and (unless it has placeholders) encoded.
+///
+/// Returns the index of the operand each placeholder stands for.
+fn check_instruction(inst: &InstructionTokens) -> Result<Vec<usize>, Error> {
+    let parsed = parse_instruction(inst, None)?;
+    let placeholder_count = inst.placeholders().count();
+
+    if placeholder_count == 0 {
+        CodeBuilder::long_mode()
+            .instruction(parsed)
+            .map_err(|err| (inst.mnemonic.span(), err.to_string()))?;
+
+        return Ok(vec![]);
+    }
+
+    // Find each placeholder by substituting it with a different dummy operand.
+    (0..placeholder_count)
+        .map(|i| {
+            let alternate = parse_instruction(inst, Some(i))?;
+            parsed
+                .operands()
+                .iter()
+                .zip(alternate.operands())
+                .position(|(op, alternate)| op != alternate)
+                .ok_or_else(|| {
+                    let msg = "the placeholder can't be used in this instruction".to_string();
+                    (inst.mnemonic.span(), msg)
+                })
+        })
+        .collect()
+}
+
+fn parse_instruction(
+    inst: &InstructionTokens,
+    alternate: Option<usize>,
+) -> Result<Instruction, Error> {
+    match parse_asm(&inst.text(alternate)) {
+        Ok(mut items) => match items.pop() {
+            Some(Item::Instruction(parsed)) => Ok(parsed),
+            _ => unreachable!("a statement is parsed into a single instruction"),
+        },
+        Err(errors) => {
+            let (_, err) = &errors.errors()[0];
+            Err((parse_error_span(inst, err), err.to_string()))
+        }
+    }
+}
+
+/// Find the token that caused a parse error.
+fn parse_error_span(inst: &InstructionTokens, err: &ParseError) -> Span {
+    match err.kind() {
+        ParseErrorKind::InvalidMnemonic(_) => inst.mnemonic.span(),
+        ParseErrorKind::InvalidPseudoPrefix(name) => inst
+            .pseudo_prefixes
+            .iter()
+            .find(|prefix| prefix.stream().to_string() == *name)
+            .or_else(|| inst.pseudo_prefixes.last())
+            .map(|prefix| prefix.span())
+            .unwrap_or_else(|| inst.mnemonic.span()),
+        // The error is caused by one of the operands, so find the first one that can't be parsed
+        // on its own.
+        _ => inst
+            .operands
+            .iter()
+            .filter(|operand| operand.placeholder.is_none())
+            .find(|operand| parse_asm(&format!("nop {}", operand.text())).is_err())
+            .and_then(OperandTokens::span)
+            .unwrap_or_else(|| inst.mnemonic.span()),
+    }
+}
+
+fn assemble(text: &str, statements: &[Statement]) -> Result<Vec<u8>, Vec<Error>> {
+    let items = parse_asm(text).expect("the statements were checked");
+
+    Assembler::long_mode()
+        .items(items)
+        .dump_text()
+        .map_err(|err| match err {
+            RasError::UndefinedSymbols(symbols) => symbols
+                .into_iter()
+                .map(|symbol| {
+                    let span = symbol_span(statements, &symbol).unwrap_or_else(Span::call_site);
+                    let err = RasError::UndefinedSymbols(vec![symbol]);
+                    (span, err.to_string().trim_end().to_string())
+                })
+                .collect(),
+            err => vec![(Span::call_site(), err.to_string())],
+        })
+}
+
+/// Find the first operand that refers to the specified symbol.
+fn symbol_span(statements: &[Statement], symbol: &str) -> Option<Span> {
+    statements
+        .iter()
+        .filter_map(|statement| match statement {
+            Statement::Instruction(inst) => Some(inst),
+            Statement::Label(_) => None,
+        })
+        .flat_map(|inst| &inst.operands)
+        .find(|operand| {
+            let text = operand.text();
+            let text = text.trim_start_matches('*');
+            text == symbol || text.starts_with(&format!("{}(", symbol))
+        })
+        .and_then(OperandTokens::span)
+}
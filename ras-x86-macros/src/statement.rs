@@ -0,0 +1,296 @@
+//! Split the input of `ras_asm!` into statements, and turn them back into AT&T syntax.
+
+use proc_macro2::{Delimiter, Group, Ident, Span, TokenStream, TokenTree};
+
+use crate::Error;
+
+const PLACEHOLDER_ERR: &str = "a placeholder must be a whole operand: `$#value` (an \
+                               immediate) or `%#value` (a register), where `value` is an \
+                               identifier or a parenthesized expression";
+
+/// A label definition (e.g. `loop:`) or an instruction.
+pub enum Statement {
+    Label(Label),
+    Instruction(InstructionTokens),
+}
+
+pub struct Label {
+    pub name: String,
+    pub span: Span,
+}
+
+pub struct InstructionTokens {
+    /// The pseudo-prefixes (e.g. `{load}`).
+    pub pseudo_prefixes: Vec<Group>,
+    pub mnemonic: Ident,
+    /// The operands (in AT&T order).
+    pub operands: Vec<OperandTokens>,
+}
+
+pub struct OperandTokens {
+    pub tokens: Vec<TokenTree>,
+    pub placeholder: Option<Placeholder>,
+}
+
+/// A runtime value interpolated as an operand: `$#value` (an immediate) or `%#value` (a
+/// register), where `value` is an identifier or a parenthesized expression.
+pub struct Placeholder {
+    pub kind: PlaceholderKind,
+    pub expr: TokenStream,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum PlaceholderKind {
+    Immediate,
+    Register,
+}
+
+impl PlaceholderKind {
+    /// The operands that stand in for the placeholder while the statement is checked. The
+    /// placeholder is substituted with the first one, except when looking for the position of the
+    /// placeholder in the parsed instruction.
+    fn dummies(self) -> [&'static str; 2] {
+        match self {
+            PlaceholderKind::Immediate => ["$0", "$1"],
+            PlaceholderKind::Register => ["%rax", "%rcx"],
+        }
+    }
+}
+
+impl Label {
+    pub fn text(&self) -> String {
+        format!("{}:", self.name)
+    }
+}
+
+impl InstructionTokens {
+    pub fn placeholders(&self) -> impl Iterator<Item = &Placeholder> {
+        self.operands
+            .iter()
+            .filter_map(|op| op.placeholder.as_ref())
+    }
+
+    /// Returns the instruction in AT&T syntax, with each placeholder substituted with a dummy
+    /// operand. If `alternate` is specified, the placeholder with that index is substituted with
+    /// a different dummy operand.
+    pub fn text(&self, alternate: Option<usize>) -> String {
+        let mut text = String::new();
+        for prefix in &self.pseudo_prefixes {
+            render(Some(TokenTree::Group(prefix.clone())), &mut text);
+            text.push(' ');
+        }
+        text.push_str(&self.mnemonic.to_string());
+
+        let mut placeholder_idx = 0;
+        for (i, operand) in self.operands.iter().enumerate() {
+            text.push_str(if i == 0 { " " } else { ", " });
+            match &operand.placeholder {
+                Some(placeholder) => {
+                    let dummy = usize::from(alternate == Some(placeholder_idx));
+                    text.push_str(placeholder.kind.dummies()[dummy]);
+                    placeholder_idx += 1;
+                }
+                None => render(operand.tokens.iter().cloned(), &mut text),
+            }
+        }
+
+        text
+    }
+}
+
+impl OperandTokens {
+    pub fn text(&self) -> String {
+        render_to_string(&self.tokens)
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.tokens.first().map(TokenTree::span)
+    }
+}
+
+/// Split the input of the macro into statements.
+///
+/// The statements are separated by `;`, except for the labels, which can also be followed by an
+/// instruction on the same line (e.g. `loop: dec %ecx; jnz loop`).
+pub fn parse_statements(input: TokenStream) -> Result<Vec<Statement>, Vec<Error>> {
+    let mut statements = vec![];
+    let mut errors = vec![];
+    let mut tokens = vec![];
+
+    let mut input = input.into_iter().peekable();
+    while let Some(tt) = input.next() {
+        let is_separator = matches!(&tt, TokenTree::Punct(p) if p.as_char() == ';');
+        if !is_separator {
+            tokens.push(tt);
+        }
+
+        if is_separator || input.peek().is_none() {
+            match parse_statement(std::mem::take(&mut tokens), &mut statements) {
+                Ok(()) => {}
+                Err(err) => errors.push(err),
+            }
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(statements)
+    } else {
+        Err(errors)
+    }
+}
+
+fn parse_statement(
+    mut tokens: Vec<TokenTree>,
+    statements: &mut Vec<Statement>,
+) -> Result<(), Error> {
+    while let Some((label, len)) = parse_label(&tokens) {
+        statements.push(Statement::Label(label));
+        tokens.drain(..len);
+    }
+
+    let mut tokens = tokens.into_iter().peekable();
+    let mut pseudo_prefixes = vec![];
+    while let Some(TokenTree::Group(group)) = tokens.peek() {
+        if group.delimiter() != Delimiter::Brace {
+            break;
+        }
+        pseudo_prefixes.push(group.clone());
+        tokens.next();
+    }
+
+    let mnemonic = match tokens.next() {
+        Some(TokenTree::Ident(mnemonic)) => mnemonic,
+        Some(tt) => return Err((tt.span(), "expected a mnemonic".into())),
+        None if pseudo_prefixes.is_empty() => return Ok(()),
+        None => {
+            let span = pseudo_prefixes[pseudo_prefixes.len() - 1].span();
+            return Err((span, "expected a mnemonic after the pseudo-prefixes".into()));
+        }
+    };
+
+    let mut operands = vec![];
+    let mut operand = vec![];
+    while let Some(tt) = tokens.next() {
+        let is_comma = matches!(&tt, TokenTree::Punct(p) if p.as_char() == ',');
+        if !is_comma {
+            operand.push(tt);
+        }
+
+        if is_comma || tokens.peek().is_none() {
+            operands.push(parse_operand(std::mem::take(&mut operand))?);
+        }
+    }
+
+    statements.push(Statement::Instruction(InstructionTokens {
+        pseudo_prefixes,
+        mnemonic,
+        operands,
+    }));
+
+    Ok(())
+}
+
+/// Parse the label at the beginning of a statement (e.g. `loop:` or `.L1:`), and return it along
+/// with the number of tokens it spans.
+fn parse_label(tokens: &[TokenTree]) -> Option<(Label, usize)> {
+    let dots = tokens
+        .iter()
+        .take_while(|tt| matches!(tt, TokenTree::Punct(p) if p.as_char() == '.'))
+        .count();
+
+    match (tokens.get(dots), tokens.get(dots + 1)) {
+        (Some(TokenTree::Ident(ident)), Some(TokenTree::Punct(p))) if p.as_char() == ':' => {
+            let label = Label {
+                name: format!("{}{}", ".".repeat(dots), ident),
+                span: ident.span(),
+            };
+            Some((label, dots + 2))
+        }
+        _ => None,
+    }
+}
+
+fn parse_operand(tokens: Vec<TokenTree>) -> Result<OperandTokens, Error> {
+    let hash = tokens
+        .iter()
+        .position(|tt| matches!(tt, TokenTree::Punct(p) if p.as_char() == '#'));
+    let hash = match hash {
+        Some(hash) => hash,
+        // A placeholder nested in a memory operand (e.g. `8(%#reg)`).
+        None if render_to_string(&tokens).contains('#') => {
+            return Err((tokens[0].span(), PLACEHOLDER_ERR.into()));
+        }
+        None => {
+            return Ok(OperandTokens {
+                tokens,
+                placeholder: None,
+            })
+        }
+    };
+
+    let placeholder = match &tokens[..] {
+        [TokenTree::Punct(sigil), TokenTree::Punct(_), value] if hash == 1 => {
+            let kind = match sigil.as_char() {
+                '$' => Some(PlaceholderKind::Immediate),
+                '%' => Some(PlaceholderKind::Register),
+                _ => None,
+            };
+            let expr = match value {
+                TokenTree::Ident(ident) => Some(TokenTree::Ident(ident.clone()).into()),
+                TokenTree::Group(group) if group.delimiter() == Delimiter::Parenthesis => {
+                    Some(group.stream())
+                }
+                _ => None,
+            };
+            kind.zip(expr)
+                .map(|(kind, expr)| Placeholder { kind, expr })
+        }
+        _ => None,
+    };
+
+    match placeholder {
+        Some(placeholder) => Ok(OperandTokens {
+            tokens,
+            placeholder: Some(placeholder),
+        }),
+        None => Err((tokens[hash].span(), PLACEHOLDER_ERR.into())),
+    }
+}
+
+fn render_to_string(tokens: &[TokenTree]) -> String {
+    let mut text = String::new();
+    render(tokens.iter().cloned(), &mut text);
+    text
+}
+
+/// Write the tokens as AT&T syntax.
+///
+/// The tokens are written without any whitespace in between, except for adjacent identifiers and
+/// literals.
+fn render(tokens: impl IntoIterator<Item = TokenTree>, text: &mut String) {
+    let mut prev_is_word = false;
+    for tt in tokens {
+        let is_word = matches!(tt, TokenTree::Ident(_) | TokenTree::Literal(_));
+        match tt {
+            TokenTree::Group(group) => {
+                let (open, close) = match group.delimiter() {
+                    Delimiter::Parenthesis => ("(", ")"),
+                    Delimiter::Brace => ("{", "}"),
+                    Delimiter::Bracket => ("[", "]"),
+                    Delimiter::None => ("", ""),
+                };
+                text.push_str(open);
+                render(group.stream(), text);
+                text.push_str(close);
+            }
+            TokenTree::Punct(p) => text.push(p.as_char()),
+            TokenTree::Ident(_) | TokenTree::Literal(_) => {
+                if prev_is_word {
+                    text.push(' ');
+                }
+                text.push_str(&tt.to_string());
+            }
+        }
+        prev_is_word = is_word;
+    }
+}
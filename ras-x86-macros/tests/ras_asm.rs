@@ -0,0 +1,78 @@
+use ras_x86::operand::{Immediate, Register};
+use ras_x86::{RasError, EAX, ECX, RAX, RDI};
+use ras_x86_macros::ras_asm;
+
+#[test]
+fn static_code() {
+    let code: &'static [u8] = ras_asm! {
+        mov %rdi, %rax;
+        ret
+    };
+
+    assert_eq!(code, [0x48, 0x89, 0xf8, 0xc3]);
+}
+
+#[test]
+fn labels_and_memory_operands() {
+    let code = ras_asm! {
+        xor %eax, %eax;
+        .L1: add -8(%rbp,%rcx,4), %eax;
+        {disp32} mov (%rsp), %rdx;
+        dec %rcx;
+        jnz .L1
+    };
+
+    assert_eq!(
+        code,
+        [
+            0x31, 0xc0, // xor %eax, %eax
+            0x03, 0x44, 0x8d, 0xf8, // add -8(%rbp,%rcx,4), %eax
+            0x48, 0x8b, 0x94, 0x24, 0x00, 0x00, 0x00, 0x00, // {disp32} mov (%rsp), %rdx
+            0x48, 0xff, 0xc9, // dec %rcx
+            0x0f, 0x85, 0xeb, 0xff, 0xff, 0xff, // jnz .L1
+        ]
+    );
+}
+
+#[test]
+fn avx512_decorators() {
+    let code = ras_asm! { vaddps {rn-sae}, %zmm2, %zmm1, %zmm0{%k1}{z} };
+
+    assert_eq!(code, [0x62, 0xf1, 0x74, 0x99, 0x58, 0xc2]);
+}
+
+#[test]
+fn placeholders() {
+    let imm = 1000;
+    let reg = ECX;
+    let code = ras_asm! {
+        mov $#imm, %#reg;
+        add $#(Immediate::Imm8(2)), %#(Register::from(EAX));
+        lea 8(%rdi), %#RAX;
+        ret
+    };
+
+    assert_eq!(
+        code.unwrap(),
+        [
+            0xb9, 0xe8, 0x03, 0x00, 0x00, // mov $1000, %ecx
+            0x83, 0xc0, 0x02, // add $2, %eax
+            0x48, 0x8d, 0x47, 0x08, // lea 8(%rdi), %rax
+            0xc3, // ret
+        ]
+    );
+}
+
+#[test]
+fn placeholders_that_cant_be_encoded() {
+    let reg = RDI;
+    let err = ras_asm! { mov %#reg, %eax }.unwrap_err();
+
+    assert!(matches!(err, RasError::MissingInstructionRepr(_)));
+}
+
+#[test]
+fn compile_errors() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
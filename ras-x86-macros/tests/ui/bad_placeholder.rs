@@ -0,0 +1,6 @@
+use ras_x86_macros::ras_asm;
+
+fn main() {
+    let reg = ras_x86::RAX;
+    let _code = ras_asm! { mov 8(%#reg), %rax; ret };
+}
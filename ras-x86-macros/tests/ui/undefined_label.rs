@@ -0,0 +1,5 @@
+use ras_x86_macros::ras_asm;
+
+fn main() {
+    let _code = ras_asm! { dec %rax; jnz done; ret };
+}
@@ -0,0 +1,248 @@
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
+use std::iter::FromIterator;
+use std::str::FromStr;
+
+/// An instruction set extension, as specified in the `Feature Flags` column of the [x86-csv].
+///
+/// [x86-csv]: https://github.com/GregoryComer/x86-csv/tree/c638bbbaa17f0c81abaa7e84a968335c985542fa
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CpuFeature {
+    Mmx,
+    Sse,
+    Sse2,
+    Sse3,
+    Ssse3,
+    Sse41,
+    Sse42,
+    Popcnt,
+    Cmpxchg16b,
+    Lzcnt,
+    Bmi1,
+    Bmi2,
+    Movbe,
+    F16c,
+    Xsave,
+    Avx,
+    Avx2,
+    Fma,
+    Avx512f,
+    Avx512vl,
+    Avx512bw,
+    Avx512dq,
+    Avx512cd,
+    Adx,
+    Rdrand,
+    Rdseed,
+    Aes,
+    Pclmulqdq,
+    Clflushopt,
+    Clwb,
+}
+
+impl CpuFeature {
+    const ALL: &'static [CpuFeature] = &[
+        CpuFeature::Mmx,
+        CpuFeature::Sse,
+        CpuFeature::Sse2,
+        CpuFeature::Sse3,
+        CpuFeature::Ssse3,
+        CpuFeature::Sse41,
+        CpuFeature::Sse42,
+        CpuFeature::Popcnt,
+        CpuFeature::Cmpxchg16b,
+        CpuFeature::Lzcnt,
+        CpuFeature::Bmi1,
+        CpuFeature::Bmi2,
+        CpuFeature::Movbe,
+        CpuFeature::F16c,
+        CpuFeature::Xsave,
+        CpuFeature::Avx,
+        CpuFeature::Avx2,
+        CpuFeature::Fma,
+        CpuFeature::Avx512f,
+        CpuFeature::Avx512vl,
+        CpuFeature::Avx512bw,
+        CpuFeature::Avx512dq,
+        CpuFeature::Avx512cd,
+        CpuFeature::Adx,
+        CpuFeature::Rdrand,
+        CpuFeature::Rdseed,
+        CpuFeature::Aes,
+        CpuFeature::Pclmulqdq,
+        CpuFeature::Clflushopt,
+        CpuFeature::Clwb,
+    ];
+
+    /// The name of the feature, as it appears in the `Feature Flags` column of the x86-csv.
+    pub fn name(&self) -> &'static str {
+        use CpuFeature::*;
+
+        match self {
+            Mmx => "MMX",
+            Sse => "SSE",
+            Sse2 => "SSE2",
+            Sse3 => "SSE3",
+            Ssse3 => "SSSE3",
+            Sse41 => "SSE4_1",
+            Sse42 => "SSE4_2",
+            Popcnt => "POPCNT",
+            Cmpxchg16b => "CMPXCHG16B",
+            Lzcnt => "LZCNT",
+            Bmi1 => "BMI1",
+            Bmi2 => "BMI2",
+            Movbe => "MOVBE",
+            F16c => "F16C",
+            Xsave => "XSAVE",
+            Avx => "AVX",
+            Avx2 => "AVX2",
+            Fma => "FMA",
+            Avx512f => "AVX512F",
+            Avx512vl => "AVX512VL",
+            Avx512bw => "AVX512BW",
+            Avx512dq => "AVX512DQ",
+            Avx512cd => "AVX512CD",
+            Adx => "ADX",
+            Rdrand => "RDRAND",
+            Rdseed => "RDSEED",
+            Aes => "AES",
+            Pclmulqdq => "PCLMULQDQ",
+            Clflushopt => "CLFLUSHOPT",
+            Clwb => "CLWB",
+        }
+    }
+}
+
+impl Display for CpuFeature {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for CpuFeature {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Also accept the names used by GNU as and LLVM (e.g. `sse4.1`)
+        let name = s.replace('.', "_");
+
+        CpuFeature::ALL
+            .iter()
+            .find(|feature| feature.name().eq_ignore_ascii_case(&name))
+            .copied()
+            .ok_or_else(|| format!("unknown CPU feature: {}", s))
+    }
+}
+
+/// A set of [`CpuFeature`]s.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CpuFeatures(u64);
+
+impl CpuFeatures {
+    /// An empty set of features.
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// All the known features.
+    pub fn all() -> Self {
+        CpuFeature::ALL.iter().copied().collect()
+    }
+
+    /// The baseline `x86-64` microarchitecture level (as defined by the [x86-64 psABI]).
+    ///
+    /// [x86-64 psABI]: https://gitlab.com/x86-psABIs/x86-64-ABI
+    pub fn x86_64() -> Self {
+        Self::empty()
+            .with(CpuFeature::Mmx)
+            .with(CpuFeature::Sse)
+            .with(CpuFeature::Sse2)
+    }
+
+    /// The `x86-64-v2` microarchitecture level.
+    pub fn x86_64_v2() -> Self {
+        Self::x86_64()
+            .with(CpuFeature::Cmpxchg16b)
+            .with(CpuFeature::Popcnt)
+            .with(CpuFeature::Sse3)
+            .with(CpuFeature::Ssse3)
+            .with(CpuFeature::Sse41)
+            .with(CpuFeature::Sse42)
+    }
+
+    /// The `x86-64-v3` microarchitecture level.
+    pub fn x86_64_v3() -> Self {
+        Self::x86_64_v2()
+            .with(CpuFeature::Avx)
+            .with(CpuFeature::Avx2)
+            .with(CpuFeature::Bmi1)
+            .with(CpuFeature::Bmi2)
+            .with(CpuFeature::F16c)
+            .with(CpuFeature::Fma)
+            .with(CpuFeature::Lzcnt)
+            .with(CpuFeature::Movbe)
+            .with(CpuFeature::Xsave)
+    }
+
+    /// The `x86-64-v4` microarchitecture level.
+    pub fn x86_64_v4() -> Self {
+        Self::x86_64_v3()
+            .with(CpuFeature::Avx512f)
+            .with(CpuFeature::Avx512bw)
+            .with(CpuFeature::Avx512cd)
+            .with(CpuFeature::Avx512dq)
+            .with(CpuFeature::Avx512vl)
+    }
+
+    /// Add `feature` to the set.
+    pub fn with(mut self, feature: CpuFeature) -> Self {
+        self.0 |= Self::bit(feature);
+        self
+    }
+
+    pub fn contains(&self, feature: CpuFeature) -> bool {
+        self.0 & Self::bit(feature) != 0
+    }
+
+    /// Returns the features from `features` that aren't in the set.
+    pub fn missing<'f>(&self, features: &'f [CpuFeature]) -> impl Iterator<Item = CpuFeature> + 'f {
+        let set = *self;
+        features
+            .iter()
+            .copied()
+            .filter(move |feature| !set.contains(*feature))
+    }
+
+    fn bit(feature: CpuFeature) -> u64 {
+        1 << feature as u64
+    }
+}
+
+impl FromIterator<CpuFeature> for CpuFeatures {
+    fn from_iter<I: IntoIterator<Item = CpuFeature>>(iter: I) -> Self {
+        iter.into_iter().fold(Self::empty(), CpuFeatures::with)
+    }
+}
+
+impl FromStr for CpuFeatures {
+    type Err = String;
+
+    /// Parse a comma-separated list of microarchitecture levels and features (e.g.
+    /// `x86-64-v2,aes,pclmulqdq`).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .try_fold(Self::empty(), |features, s| {
+                let level = match s.to_ascii_lowercase().as_str() {
+                    "x86-64" => Self::x86_64(),
+                    "x86-64-v2" => Self::x86_64_v2(),
+                    "x86-64-v3" => Self::x86_64_v3(),
+                    "x86-64-v4" => Self::x86_64_v4(),
+                    _ => return Ok(features.with(CpuFeature::from_str(s)?)),
+                };
+
+                Ok(Self(features.0 | level.0))
+            })
+    }
+}
@@ -1,26 +1,33 @@
+use crate::feature::CpuFeature;
 use crate::operand::OperandRepr;
-use crate::prefix::RexPrefix;
+use crate::prefix::{EvexPrefix, RexPrefix, VexOperand, VexPrefix};
 use crate::Mode;
 
 use serde::{Deserialize, Serialize};
 
-use std::cmp::{Ordering, PartialOrd};
+use std::borrow::Cow;
 use std::str::FromStr;
 
+/// The representation of an instruction.
+///
+/// The operands, modes, features and bytecodes are stored as `Cow`s so that the instruction
+/// tables can be generated as `static` data (see the `ras-x86` build script).
 #[derive(Serialize, Deserialize, Debug)]
 pub struct InstructionRepr {
     /// The recipe for instruction encoding.
     pub encoding: InstructionEncoding,
     /// The representation of the operands.
-    pub operands: Vec<OperandRepr>,
+    pub operands: Cow<'static, [OperandRepr]>,
     /// The assembly [`Mode`](enum.Mode.html)s in which this instruction's encoding is possible.
-    pub modes: Vec<Mode>,
+    pub modes: Cow<'static, [Mode]>,
+    /// The CPU features required by the instruction.
+    pub features: Cow<'static, [CpuFeature]>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct InstructionEncoding {
     /// The bytecodes describing how the instruction's encoding.
-    pub bytecode: Vec<EncodingBytecode>,
+    pub bytecode: Cow<'static, [EncodingBytecode]>,
     /// According to the [Intel® 64 and IA-32 architectures software developer's manual volume 2]: "Indicates
     /// the use of 66/F2/F3 prefixes (beyond those already part of the instructions opcode) are not
     /// allowed with the instruction. Such use will either cause an invalid-opcode exception (#UD)
@@ -35,11 +42,13 @@ impl InstructionRepr {
         encoding: InstructionEncoding,
         operands: Vec<OperandRepr>,
         modes: Vec<Mode>,
+        features: Vec<CpuFeature>,
     ) -> Self {
         Self {
             encoding,
-            operands,
-            modes,
+            operands: operands.into(),
+            modes: modes.into(),
+            features: features.into(),
         }
     }
 
@@ -56,49 +65,137 @@ impl InstructionRepr {
         // XXX do we really need this?
         true
     }
+}
 
-    fn cmp_size(&self, other: &Self) -> Ordering {
-        let has_smaller_op_sizes = self
-            .operands
-            .iter()
-            .zip(other.operands.iter())
-            .any(|(op, other_op)| op.size().cmp(&other_op.size()) == Ordering::Less);
+impl InstructionEncoding {
+    pub fn new(bytecode: Vec<EncodingBytecode>, is_np: bool) -> Self {
+        Self {
+            bytecode: bytecode.into(),
+            is_np,
+        }
+    }
 
-        if has_smaller_op_sizes {
-            return Ordering::Less;
+    /// Returns the VEX prefix of the instruction, if it's VEX-encoded.
+    pub fn vex_prefix(&self) -> Option<&VexPrefix> {
+        self.bytecode.iter().find_map(|code| match code {
+            EncodingBytecode::Vex(prefix) => Some(prefix),
+            _ => None,
+        })
+    }
+
+    /// Returns the EVEX prefix of the instruction, if it's EVEX-encoded.
+    pub fn evex_prefix(&self) -> Option<&EvexPrefix> {
+        self.bytecode.iter().find_map(|code| match code {
+            EncodingBytecode::Evex(prefix) => Some(prefix),
+            _ => None,
+        })
+    }
+
+    pub fn evex_prefix_mut(&mut self) -> Option<&mut EvexPrefix> {
+        self.bytecode
+            .to_mut()
+            .iter_mut()
+            .find_map(|code| match code {
+                EncodingBytecode::Evex(prefix) => Some(prefix),
+                _ => None,
+            })
+    }
+
+    /// Returns the role of the VEX.vvvv (or EVEX.vvvv) field.
+    pub fn vvvv_operand(&self) -> VexOperand {
+        match (self.vex_prefix(), self.evex_prefix()) {
+            (Some(vex), _) => vex.vvvv,
+            (_, Some(evex)) => evex.vvvv,
+            (None, None) => VexOperand::None,
         }
+    }
 
-        self.encoding
-            .bytecode
-            .len()
-            .cmp(&other.encoding.bytecode.len())
+    /// Returns `true` if the operand size of the instruction is implied by its opcode, so it never
+    /// needs an operand-size prefix.
+    ///
+    /// This is the case for the x87 FPU instructions (e.g. `FNSTSW AX`), for the system
+    /// instructions that load a 16-bit selector or the machine status word (`LLDT`, `LTR`, `VERR`,
+    /// `VERW`, `LMSW` and `MOV Sreg, r/m16`), for `ENTER imm16, imm8` (whose 16-bit immediate is the size of the
+    /// stack frame), and for `RET imm16` (whose immediate is the number of bytes to pop).
+    pub fn has_fixed_operand_size(&self) -> bool {
+        use EncodingBytecode::*;
+
+        self.is_x87()
+            || matches!(
+                self.bytecode[..],
+                [Opcode(0x0f), Opcode(0x00), ModRmWithReg(2..=5)]
+                    | [Opcode(0x0f), Opcode(0x01), ModRmWithReg(6)]
+                    | [Opcode(0x8e), ModRm]
+                    | [Opcode(0xc8), Iw, Ib]
+                    | [Opcode(0xc2 | 0xca), Iw]
+            )
     }
-}
 
-impl PartialEq for InstructionRepr {
-    fn eq(&self, other: &Self) -> bool {
-        self.cmp_size(other) == Ordering::Equal
+    /// Returns `true` if the operand size of the instruction is the size of its r/m operand,
+    /// rather than the size of its (larger) destination register.
+    ///
+    /// This is the case for `CRC32 r32, r/m16` and `CRC32 r32, r/m32`, which share the same
+    /// opcode and only differ in the operand-size prefix.
+    pub fn has_rm_operand_size(&self) -> bool {
+        use EncodingBytecode::*;
+
+        matches!(
+            self.bytecode[..],
+            [
+                Prefix(0xf2),
+                Opcode(0x0f),
+                Opcode(0x38),
+                Opcode(0xf1),
+                ModRm
+            ]
+        )
     }
-}
 
-impl Eq for InstructionRepr {}
+    /// Returns `true` if the CPU sign-extends the 8-bit immediate of the instruction to its
+    /// operand size.
+    ///
+    /// This is the case for the arithmetic instructions with an 8-bit immediate (`83 /digit ib`),
+    /// for `IMUL r, r/m, imm8` (`6B /r ib`) and for `PUSH imm8` (`6A ib`). The other 8-bit
+    /// immediates (e.g. the count of `SHL r/m32, imm8`) are never extended.
+    pub fn has_sign_extended_imm8(&self) -> bool {
+        let opcode = self.bytecode.iter().find_map(|code| match code {
+            EncodingBytecode::Opcode(opcode) => Some(*opcode),
+            _ => None,
+        });
 
-// Used for sorting `InstructionRepr`s by size.
-impl PartialOrd for InstructionRepr {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp_size(other))
+        self.vex_prefix().is_none()
+            && self.evex_prefix().is_none()
+            && matches!(opcode, Some(0x83 | 0x6a | 0x6b))
     }
-}
 
-impl Ord for InstructionRepr {
-    fn cmp(&self, other: &Self) -> Ordering {
-        self.cmp_size(other)
+    /// Returns `true` if this is an x87 FPU instruction, i.e. if its opcode is one of the escape
+    /// opcodes (D8-DF), optionally preceded by a `WAIT` (9B).
+    pub fn is_x87(&self) -> bool {
+        let mut opcodes = self.bytecode.iter().filter_map(|code| match code {
+            EncodingBytecode::Opcode(opcode) => Some(*opcode),
+            _ => None,
+        });
+
+        match opcodes.next() {
+            Some(0x9b) => matches!(opcodes.next(), Some(0xd8..=0xdf)),
+            Some(opcode) => (0xd8..=0xdf).contains(&opcode),
+            None => false,
+        }
     }
-}
 
-impl InstructionEncoding {
-    pub fn new(bytecode: Vec<EncodingBytecode>, is_np: bool) -> Self {
-        Self { bytecode, is_np }
+    /// Returns the estimated length of the encoding (in bytes).
+    ///
+    /// Every bytecode is assumed to be one byte long, except for the VEX and EVEX prefixes, which
+    /// take (at least) 2 and 4 bytes respectively.
+    pub fn estimated_len(&self) -> usize {
+        self.bytecode
+            .iter()
+            .map(|code| match code {
+                EncodingBytecode::Vex(_) => 2,
+                EncodingBytecode::Evex(_) => 4,
+                _ => 1,
+            })
+            .sum()
     }
 }
 
@@ -106,7 +203,8 @@ impl InstructionEncoding {
 ///
 /// Bytecodes are extracted from the `Opcode` column of the [x86-csv].
 ///
-/// See Section "3.1.1.1 Opcode Column in the Instruction Summary Table (Instructions without VEX
+/// See Sections "3.1.1.1 Opcode Column in the Instruction Summary Table (Instructions without VEX
+/// Prefix)" and "3.1.1.2 Opcode Column in the Instruction Summary Table (Instructions with VEX
 /// Prefix)" of the [Intel® 64 and IA-32 architectures software developer's manual volume 2] for
 /// more details.
 ///
@@ -115,17 +213,24 @@ impl InstructionEncoding {
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum EncodingBytecode {
     Rex(RexPrefix),
+    Vex(VexPrefix),
+    Evex(EvexPrefix),
     Prefix(u8),
     Opcode(u8),
     OpcodeRb(u8),
     OpcodeRw(u8),
     OpcodeRd(u8),
     OpcodeRo(u8),
+    /// An opcode byte to which the number of the ST(i) operand is added (`+i`).
+    OpcodeI(u8),
     ModRm,
     ModRmWithReg(u8),
     Ib,
     Iw,
     Id,
+    Io,
+    /// An 8-bit immediate whose upper 4 bits encode a register operand (`/is4`).
+    Is4,
     Cb,
     Cw,
     Cd,
@@ -142,6 +247,8 @@ impl FromStr for EncodingBytecode {
             "ib" => Ok(EncodingBytecode::Ib),
             "iw" => Ok(EncodingBytecode::Iw),
             "id" => Ok(EncodingBytecode::Id),
+            "io" => Ok(EncodingBytecode::Io),
+            "/is4" => Ok(EncodingBytecode::Is4),
             "cb" => Ok(EncodingBytecode::Cb),
             "cw" => Ok(EncodingBytecode::Cw),
             "cd" => Ok(EncodingBytecode::Cd),
@@ -5,31 +5,29 @@
 //! * an [`InstructionEncoding`](instruction/struct.InstructionEncoding.html), which specifies how the
 //!   instruction should be encoded
 //! * a list of [`OperandRepr`](operand/struct.OperandRepr.html)s
-//!   (the representation of its operands), and
-//! * the assembly [`Mode`](enum.Mode.html)s in which its encoding is possible
+//!   (the representation of its operands),
+//! * the assembly [`Mode`](enum.Mode.html)s in which its encoding is possible, and
+//! * the [`CpuFeature`](feature/enum.CpuFeature.html)s it requires
 
+pub mod feature;
 pub mod instruction;
 pub mod operand;
 pub mod prefix;
 
 use serde::{Deserialize, Serialize};
 
+pub use feature::{CpuFeature, CpuFeatures};
 pub use instruction::{EncodingBytecode, InstructionEncoding, InstructionRepr};
 pub use operand::{OperandKind, OperandRepr};
-pub use prefix::{Prefix, RexPrefix};
+pub use prefix::{EvexPrefix, Prefix, RexPrefix, VexPrefix};
 
-#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Serialize, Deserialize, Debug, Default, Copy, Clone, PartialEq, Eq)]
 pub enum Mode {
     /// 16-bit real mode.
     Real,
     /// 32-bit protected mode.
     Protected,
     /// 64-bit long mode.
+    #[default]
     Long,
 }
-
-impl Default for Mode {
-    fn default() -> Self {
-        Self::Long
-    }
-}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display, Formatter};
 use std::str::FromStr;
 
 /// The internal representation of x86 instruction operand.
@@ -97,6 +98,10 @@ pub enum OperandKind {
     M64,
     /// m128
     M128,
+    /// m256
+    M256,
+    /// m512
+    M512,
     /// ptr16:16
     FarPointer16,
     /// ptr16:32
@@ -115,14 +120,28 @@ pub enum OperandKind {
     Mm2,
     /// mm2/m64
     Mm2M64,
+    /// mm/m32
+    MmM32,
     /// mm/m64
     MmM64,
     /// xmm
     Xmm,
+    /// xmm/m32
+    XmmM32,
     /// xmm/m64
     XmmM64,
     /// xmm/m128
     XmmM128,
+    /// ymm
+    Ymm,
+    /// ymm/m256
+    YmmM256,
+    /// zmm
+    Zmm,
+    /// zmm/m512
+    ZmmM512,
+    /// k1-k7 (an opmask register)
+    K,
     /// m32fp
     M32Fp,
     /// m64fp
@@ -185,8 +204,8 @@ impl FromStr for OperandRepr {
             "r/m64" | "r64/m64" => Ok(OperandRepr::new(OperandKind::ModRmRegMem, 64)),
             "r8" => Ok(OperandRepr::new(OperandKind::ModRmReg, 8)),
             "r16" => Ok(OperandRepr::new(OperandKind::ModRmReg, 16)),
-            "r32" => Ok(OperandRepr::new(OperandKind::ModRmReg, 32)),
-            "r64" => Ok(OperandRepr::new(OperandKind::ModRmReg, 64)),
+            "r32" | "r32a" | "r32b" => Ok(OperandRepr::new(OperandKind::ModRmReg, 32)),
+            "r64" | "r64a" | "r64b" => Ok(OperandRepr::new(OperandKind::ModRmReg, 64)),
             "imm8" => Ok(OperandRepr::new(OperandKind::Imm, 8)),
             "imm16" => Ok(OperandRepr::new(OperandKind::Imm, 16)),
             "imm32" => Ok(OperandRepr::new(OperandKind::Imm, 32)),
@@ -196,9 +215,9 @@ impl FromStr for OperandRepr {
             "moffs32" => Ok(OperandRepr::new(OperandKind::Moffs, 32)),
             "moffs64" => Ok(OperandRepr::new(OperandKind::Moffs, 64)),
             "m16&16" => Ok(OperandRepr::new(OperandKind::M16And16, 32)),
-            "m16&32" => Ok(OperandRepr::new(OperandKind::M16And16, 48)),
-            "m32&32" => Ok(OperandRepr::new(OperandKind::M16And16, 64)),
-            "m16&64" => Ok(OperandRepr::new(OperandKind::M16And16, 80)),
+            "m16&32" => Ok(OperandRepr::new(OperandKind::M16And32, 48)),
+            "m32&32" => Ok(OperandRepr::new(OperandKind::M32And32, 64)),
+            "m16&64" => Ok(OperandRepr::new(OperandKind::M16And64, 80)),
             "rel8" => Ok(OperandRepr::new(OperandKind::Rel8, 8)),
             "rel16" => Ok(OperandRepr::new(OperandKind::Rel16, 16)),
             "rel32" => Ok(OperandRepr::new(OperandKind::Rel32, 32)),
@@ -208,20 +227,46 @@ impl FromStr for OperandRepr {
             "m32" => Ok(OperandRepr::new(OperandKind::M32, 32)),
             "m64" => Ok(OperandRepr::new(OperandKind::M64, 64)),
             "m128" => Ok(OperandRepr::new(OperandKind::M128, 128)),
-            // XXX check the sizes:
+            "m256" => Ok(OperandRepr::new(OperandKind::M256, 256)),
+            "m512" => Ok(OperandRepr::new(OperandKind::M512, 512)),
+            // The size of a far pointer is the size of its offset (the operand-size attribute of
+            // the instruction), not including the 16-bit segment selector.
             "ptr16:16" => Ok(OperandRepr::new(OperandKind::FarPointer16, 16)),
-            "ptr16:32" => Ok(OperandRepr::new(OperandKind::FarPointer16, 32)),
+            "ptr16:32" => Ok(OperandRepr::new(OperandKind::FarPointer32, 32)),
             "m16:16" => Ok(OperandRepr::new(OperandKind::MemIndirectFarPointer16, 16)),
-            "m16:32" => Ok(OperandRepr::new(OperandKind::MemIndirectFarPointer16, 32)),
-            "m16:64" => Ok(OperandRepr::new(OperandKind::MemIndirectFarPointer16, 64)),
+            "m16:32" => Ok(OperandRepr::new(OperandKind::MemIndirectFarPointer32, 32)),
+            "m16:64" => Ok(OperandRepr::new(OperandKind::MemIndirectFarPointer64, 64)),
             "mm" => Ok(OperandRepr::new(OperandKind::Mm, 64)),
             "mm1" => Ok(OperandRepr::new(OperandKind::Mm1, 64)),
             "mm2" => Ok(OperandRepr::new(OperandKind::Mm2, 64)),
             "mm2/m64" => Ok(OperandRepr::new(OperandKind::Mm2M64, 64)),
+            "mm/m32" => Ok(OperandRepr::new(OperandKind::MmM32, 64)),
             "mm/m64" => Ok(OperandRepr::new(OperandKind::MmM64, 64)),
-            "xmm" => Ok(OperandRepr::new(OperandKind::Mm, 128)),
-            "xmm/m64" => Ok(OperandRepr::new(OperandKind::XmmM64, 64)),
-            "xmm/m128" => Ok(OperandRepr::new(OperandKind::XmmM128, 128)),
+            // The size of an xmm/m operand is the size of the register. The size of the memory
+            // operand is implied by the kind of the operand.
+            "xmm" | "xmm1" | "xmm2" | "xmm3" | "xmm4" => {
+                Ok(OperandRepr::new(OperandKind::Xmm, 128))
+            }
+            "xmm/m32" | "xmm1/m32" | "xmm2/m32" | "xmm3/m32" => {
+                Ok(OperandRepr::new(OperandKind::XmmM32, 128))
+            }
+            "xmm/m64" | "xmm1/m64" | "xmm2/m64" | "xmm3/m64" => {
+                Ok(OperandRepr::new(OperandKind::XmmM64, 128))
+            }
+            "xmm/m128" | "xmm1/m128" | "xmm2/m128" | "xmm3/m128" => {
+                Ok(OperandRepr::new(OperandKind::XmmM128, 128))
+            }
+            "ymm" | "ymm1" | "ymm2" | "ymm3" | "ymm4" => {
+                Ok(OperandRepr::new(OperandKind::Ymm, 256))
+            }
+            "ymm/m256" | "ymm1/m256" | "ymm2/m256" | "ymm3/m256" => {
+                Ok(OperandRepr::new(OperandKind::YmmM256, 256))
+            }
+            "zmm" | "zmm1" | "zmm2" | "zmm3" => Ok(OperandRepr::new(OperandKind::Zmm, 512)),
+            "zmm/m512" | "zmm1/m512" | "zmm2/m512" | "zmm3/m512" => {
+                Ok(OperandRepr::new(OperandKind::ZmmM512, 512))
+            }
+            "k" | "k1" | "k2" | "k3" => Ok(OperandRepr::new(OperandKind::K, 64)),
             "m32fp" => Ok(OperandRepr::new(OperandKind::M32Fp, 32)),
             "m64fp" => Ok(OperandRepr::new(OperandKind::M64Fp, 64)),
             "m80fp" => Ok(OperandRepr::new(OperandKind::M80Fp, 80)),
@@ -240,3 +285,90 @@ impl FromStr for OperandRepr {
         }
     }
 }
+
+/// Formats the operand using the notation of the Intel manual (e.g. `r/m32` or `xmm/m128`).
+impl Display for OperandRepr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        use OperandKind::*;
+
+        let size = self.size;
+        let name = match self.kind {
+            ModRmReg | OpcodeRd => return write!(f, "r{}", size),
+            ModRmRegMem => return write!(f, "r/m{}", size),
+            Imm => return write!(f, "imm{}", size),
+            Moffs => return write!(f, "moffs{}", size),
+            Rel8 | Rel16 | Rel32 => return write!(f, "rel{}", size),
+            Al => match size {
+                8 => "AL",
+                16 => "AX",
+                32 => "EAX",
+                _ => "RAX",
+            },
+            Reg => "reg",
+            R32M16 => "r32/m16",
+            R64M16 => "r64/m16",
+            Zero => "0",
+            One => "1",
+            Three => "3",
+            Sreg => "Sreg",
+            Cr => "CR0-CR7",
+            Cr8 => "CR8",
+            Dr => "DR0-DR7",
+            Cs => "CS",
+            Ds => "DS",
+            Es => "ES",
+            Fs => "FS",
+            Gs => "GS",
+            Ss => "SS",
+            Cl => "CL",
+            Dx => "DX",
+            M16And16 => "m16&16",
+            M16And32 => "m16&32",
+            M32And32 => "m32&32",
+            M16And64 => "m16&64",
+            M => "m",
+            M8 => "m8",
+            M16 => "m16",
+            M32 => "m32",
+            M64 => "m64",
+            M128 => "m128",
+            M256 => "m256",
+            M512 => "m512",
+            FarPointer16 => "ptr16:16",
+            FarPointer32 => "ptr16:32",
+            MemIndirectFarPointer16 => "m16:16",
+            MemIndirectFarPointer32 => "m16:32",
+            MemIndirectFarPointer64 => "m16:64",
+            Mm => "mm",
+            Mm1 => "mm1",
+            Mm2 => "mm2",
+            Mm2M64 => "mm2/m64",
+            MmM32 => "mm/m32",
+            MmM64 => "mm/m64",
+            Xmm => "xmm",
+            XmmM32 => "xmm/m32",
+            XmmM64 => "xmm/m64",
+            XmmM128 => "xmm/m128",
+            Ymm => "ymm",
+            YmmM256 => "ymm/m256",
+            Zmm => "zmm",
+            ZmmM512 => "zmm/m512",
+            K => "k",
+            M32Fp => "m32fp",
+            M64Fp => "m64fp",
+            M80Fp => "m80fp",
+            M16Int => "m16int",
+            M32Int => "m32int",
+            M64Int => "m64int",
+            St0 => "ST(0)",
+            Sti => "ST(i)",
+            M80Bcd => "m80bcd",
+            M2Byte => "m2byte",
+            M14M28Byte => "m14/28byte",
+            M94M108Byte => "m94/108byte",
+            M512Byte => "m512byte",
+        };
+
+        write!(f, "{}", name)
+    }
+}
@@ -4,18 +4,17 @@ use std::str::FromStr;
 /// REX bits: 0100WRXB
 const REX: u8 = 0b0100_0000;
 const REX_W: u8 = 0b0000_1000;
-#[allow(unused)]
 const REX_R: u8 = 0b0000_0100;
-#[allow(unused)]
 const REX_X: u8 = 0b0000_0010;
-#[allow(unused)]
 const REX_B: u8 = 0b0000_0001;
 
 const OPERAND_SIZE_PREFIX: u8 = 0x66;
+const ADDRESS_SIZE_PREFIX: u8 = 0x67;
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Prefix {
     OperandSize,
+    AddressSize,
     Rex(RexPrefix),
 }
 
@@ -23,6 +22,7 @@ impl From<Prefix> for u8 {
     fn from(prefix: Prefix) -> u8 {
         match prefix {
             Prefix::OperandSize => OPERAND_SIZE_PREFIX,
+            Prefix::AddressSize => ADDRESS_SIZE_PREFIX,
             Prefix::Rex(rex_prefix) => rex_prefix.into(),
         }
     }
@@ -52,9 +52,11 @@ pub enum RexPrefix {
 impl From<RexPrefix> for u8 {
     fn from(prefix: RexPrefix) -> u8 {
         match prefix {
-            RexPrefix::W => REX | REX_W,
             RexPrefix::None => REX,
-            _ => unimplemented!("prefix={:?}", prefix),
+            RexPrefix::W => REX | REX_W,
+            RexPrefix::R => REX | REX_R,
+            RexPrefix::X => REX | REX_X,
+            RexPrefix::B => REX | REX_B,
         }
     }
 }
@@ -73,3 +75,393 @@ impl FromStr for RexPrefix {
         }
     }
 }
+
+/// A VEX prefix, as described by the `Opcode` column (e.g. `VEX.NDS.256.66.0F38.W0`).
+///
+/// See Section "3.1.1.2 Opcode Column in the Instruction Summary Table (Instructions with VEX
+/// Prefix)" of the [Intel® 64 and IA-32 architectures software developer's manual volume 2] for
+/// more details.
+///
+/// [Intel® 64 and IA-32 architectures software developer's manual volume 2]: https://software.intel.com/content/www/us/en/develop/articles/intel-sdm.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VexPrefix {
+    /// The operand encoded in VEX.vvvv (if any).
+    pub vvvv: VexOperand,
+    /// The vector length (VEX.L).
+    pub l: VexLength,
+    /// The implied mandatory prefix (VEX.pp).
+    pub pp: VexPp,
+    /// The implied leading opcode bytes (VEX.mmmmm).
+    pub mmmmm: VexMap,
+    /// VEX.W
+    pub w: VexW,
+}
+
+/// The role of the VEX.vvvv field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VexOperand {
+    /// VEX.vvvv is unused (it must be 0b1111).
+    None,
+    /// VEX.vvvv encodes the first source register (the second operand).
+    Nds,
+    /// VEX.vvvv encodes the destination register (the first operand), and ModRM.reg is an opcode
+    /// extension.
+    Ndd,
+    /// VEX.vvvv encodes the second source register (the second operand), which is also the
+    /// destination register of the instruction.
+    Dds,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VexLength {
+    /// VEX.L = 0 (128-bit vectors). This also covers `LZ` and `L0`.
+    L128,
+    /// VEX.L = 1 (256-bit vectors). This also covers `L1`.
+    L256,
+    /// VEX.L is ignored by the processor (`LIG`).
+    Ignored,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VexPp {
+    None,
+    P66,
+    PF3,
+    PF2,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VexMap {
+    M0F,
+    M0F38,
+    M0F3A,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VexW {
+    W0,
+    W1,
+    /// VEX.W is ignored by the processor (`WIG`).
+    Ignored,
+}
+
+impl From<VexLength> for u8 {
+    fn from(l: VexLength) -> u8 {
+        match l {
+            // Like GNU as, use VEX.L = 0 if VEX.L is ignored.
+            VexLength::L128 | VexLength::Ignored => 0,
+            VexLength::L256 => 1,
+        }
+    }
+}
+
+impl From<VexPp> for u8 {
+    fn from(pp: VexPp) -> u8 {
+        match pp {
+            VexPp::None => 0b00,
+            VexPp::P66 => 0b01,
+            VexPp::PF3 => 0b10,
+            VexPp::PF2 => 0b11,
+        }
+    }
+}
+
+impl From<VexMap> for u8 {
+    fn from(map: VexMap) -> u8 {
+        match map {
+            VexMap::M0F => 0b00001,
+            VexMap::M0F38 => 0b00010,
+            VexMap::M0F3A => 0b00011,
+        }
+    }
+}
+
+impl From<VexW> for u8 {
+    fn from(w: VexW) -> u8 {
+        match w {
+            VexW::W0 | VexW::Ignored => 0,
+            VexW::W1 => 1,
+        }
+    }
+}
+
+impl FromStr for VexPrefix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (vvvv, l, pp, mmmmm, w) = parse_vex_fields(s, "VEX", |l| match l {
+            "128" | "LZ" | "L0" => Some(VexLength::L128),
+            "256" | "L1" => Some(VexLength::L256),
+            "LIG" => Some(VexLength::Ignored),
+            _ => None,
+        })?;
+
+        Ok(VexPrefix {
+            vvvv,
+            l,
+            pp,
+            mmmmm,
+            w,
+        })
+    }
+}
+
+/// An EVEX prefix, as described by the `Opcode` column (e.g. `EVEX.NDS.512.0F.W0`), along with
+/// the EVEX features supported by the instruction (which are described by the `Instruction` and
+/// `Tuple Type` columns).
+///
+/// See Section "2.6 EVEX Encoding" of the [Intel® 64 and IA-32 architectures software developer's
+/// manual volume 2] for more details.
+///
+/// [Intel® 64 and IA-32 architectures software developer's manual volume 2]: https://software.intel.com/content/www/us/en/develop/articles/intel-sdm.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EvexPrefix {
+    /// The operand encoded in EVEX.vvvv (if any).
+    pub vvvv: VexOperand,
+    /// The vector length (EVEX.L'L).
+    pub l: EvexLength,
+    /// The implied mandatory prefix (EVEX.pp).
+    pub pp: VexPp,
+    /// The implied leading opcode bytes (EVEX.mm).
+    pub mm: VexMap,
+    /// EVEX.W
+    pub w: VexW,
+    /// The tuple type, which determines the scaling factor of compressed (disp8*N) displacements.
+    pub tuple_type: Option<TupleType>,
+    /// The kind of masking supported by the instruction (`{k1}` or `{k1}{z}`).
+    pub masking: EvexMasking,
+    /// The size of the elements that can be broadcast from memory (`m32bcst` or `m64bcst`).
+    pub broadcast: Option<u32>,
+    /// Whether the instruction supports static rounding (`{er}`) or suppressing all exceptions
+    /// (`{sae}`).
+    pub rounding: EvexRounding,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EvexLength {
+    /// EVEX.L'L = 00 (128-bit vectors).
+    L128,
+    /// EVEX.L'L = 01 (256-bit vectors).
+    L256,
+    /// EVEX.L'L = 10 (512-bit vectors).
+    L512,
+    /// EVEX.L'L is ignored by the processor (`LIG`).
+    Ignored,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EvexMasking {
+    /// The instruction doesn't support opmask registers.
+    None,
+    /// Merging-masking (`{k1}`).
+    Merge,
+    /// Merging-masking or zeroing-masking (`{k1}{z}`).
+    MergeOrZero,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EvexRounding {
+    None,
+    /// Static rounding (`{er}`), which implies `{sae}`.
+    Er,
+    /// Suppress all exceptions (`{sae}`).
+    Sae,
+}
+
+/// The tuple type of an EVEX-encoded instruction.
+///
+/// See Table 2-34 "EVEX DISP8*N for Instructions Not Affected by Embedded Broadcast" and Table
+/// 2-35 "EVEX DISP8*N for Instructions Affected by Embedded Broadcast" of the [Intel® 64 and IA-32
+/// architectures software developer's manual volume 2].
+///
+/// [Intel® 64 and IA-32 architectures software developer's manual volume 2]: https://software.intel.com/content/www/us/en/develop/articles/intel-sdm.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TupleType {
+    /// Full vector (FV).
+    Full,
+    /// Half vector (HV).
+    Half,
+    /// Full vector memory (FVM).
+    FullMem,
+    /// Half vector memory (HVM).
+    HalfMem,
+    /// Quarter vector memory (QVM).
+    QuarterMem,
+    /// Eighth vector memory (OVM).
+    EighthMem,
+    /// Tuple1 scalar (T1S).
+    Tuple1Scalar,
+    /// Tuple1 fixed (T1F).
+    Tuple1Fixed,
+    /// Tuple2 (T2).
+    Tuple2,
+    /// Tuple4 (T4).
+    Tuple4,
+    /// Tuple8 (T8).
+    Tuple8,
+    /// Mem128 (M128).
+    Mem128,
+    /// MOVDDUP (DUP).
+    Movddup,
+}
+
+impl EvexPrefix {
+    /// Returns the vector length of the instruction in bits.
+    pub fn vector_len(&self) -> u32 {
+        match self.l {
+            EvexLength::L128 | EvexLength::Ignored => 128,
+            EvexLength::L256 => 256,
+            EvexLength::L512 => 512,
+        }
+    }
+
+    /// Returns the scaling factor N of the disp8*N displacements of the instruction (in bytes).
+    ///
+    /// `mem_size` is the size of the memory operand (in bits), and `is_broadcast` specifies
+    /// whether the memory operand is broadcast.
+    pub fn disp8_scale(&self, mem_size: u32, is_broadcast: bool) -> Option<u32> {
+        let vector_len = self.vector_len() / 8;
+        // The size of a vector element (in bytes), as determined by EVEX.W
+        let elem_size = match self.w {
+            VexW::W1 => 8,
+            VexW::W0 | VexW::Ignored => 4,
+        };
+
+        let scale = match self.tuple_type? {
+            TupleType::Full | TupleType::Half if is_broadcast => self.broadcast? / 8,
+            TupleType::Full | TupleType::FullMem => vector_len,
+            TupleType::Half | TupleType::HalfMem => vector_len / 2,
+            TupleType::QuarterMem => vector_len / 4,
+            TupleType::EighthMem => vector_len / 8,
+            TupleType::Tuple1Scalar | TupleType::Tuple1Fixed => mem_size / 8,
+            TupleType::Tuple2 => elem_size * 2,
+            TupleType::Tuple4 => elem_size * 4,
+            TupleType::Tuple8 => elem_size * 8,
+            TupleType::Mem128 => 16,
+            TupleType::Movddup if vector_len == 16 => 8,
+            TupleType::Movddup => vector_len,
+        };
+
+        Some(scale)
+    }
+}
+
+impl From<EvexLength> for u8 {
+    fn from(l: EvexLength) -> u8 {
+        match l {
+            // Like GNU as, use EVEX.L'L = 00 if EVEX.L'L is ignored.
+            EvexLength::L128 | EvexLength::Ignored => 0b00,
+            EvexLength::L256 => 0b01,
+            EvexLength::L512 => 0b10,
+        }
+    }
+}
+
+impl FromStr for EvexPrefix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (vvvv, l, pp, mm, w) = parse_vex_fields(s, "EVEX", |l| match l {
+            "128" => Some(EvexLength::L128),
+            "256" => Some(EvexLength::L256),
+            "512" => Some(EvexLength::L512),
+            "LIG" => Some(EvexLength::Ignored),
+            _ => None,
+        })?;
+
+        Ok(EvexPrefix {
+            vvvv,
+            l,
+            pp,
+            mm,
+            w,
+            tuple_type: None,
+            masking: EvexMasking::None,
+            broadcast: None,
+            rounding: EvexRounding::None,
+        })
+    }
+}
+
+impl FromStr for TupleType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_uppercase().as_str() {
+            "FULL" | "FV" => Ok(TupleType::Full),
+            "HALF" | "HV" => Ok(TupleType::Half),
+            "FULL MEM" | "FVM" => Ok(TupleType::FullMem),
+            "HALF MEM" | "HVM" => Ok(TupleType::HalfMem),
+            "QUARTER MEM" | "QVM" => Ok(TupleType::QuarterMem),
+            "EIGHTH MEM" | "OVM" => Ok(TupleType::EighthMem),
+            "TUPLE1 SCALAR" | "T1S" => Ok(TupleType::Tuple1Scalar),
+            "TUPLE1 FIXED" | "T1F" => Ok(TupleType::Tuple1Fixed),
+            "TUPLE2" | "T2" => Ok(TupleType::Tuple2),
+            "TUPLE4" | "T4" => Ok(TupleType::Tuple4),
+            "TUPLE8" | "T8" => Ok(TupleType::Tuple8),
+            "MEM128" | "M128" => Ok(TupleType::Mem128),
+            "MOVDDUP" | "DUP" => Ok(TupleType::Movddup),
+            _ => Err(format!("failed to parse tuple type: {}", s)),
+        }
+    }
+}
+
+/// Parse the fields of a VEX or EVEX prefix (e.g. `VEX.NDS.128.66.0F38.W0`).
+///
+/// The vector length field is parsed by `parse_len`, as its values are different for VEX and
+/// EVEX.
+fn parse_vex_fields<L>(
+    s: &str,
+    escape: &str,
+    parse_len: impl Fn(&str) -> Option<L>,
+) -> Result<(VexOperand, L, VexPp, VexMap, VexW), String> {
+    let err = || format!("failed to parse {} prefix: {}", escape, s);
+    let mut fields = s.split('.').peekable();
+
+    if fields.next() != Some(escape) {
+        return Err(err());
+    }
+
+    let vvvv = match fields.peek() {
+        Some(&"NDS") => VexOperand::Nds,
+        Some(&"NDD") => VexOperand::Ndd,
+        Some(&"DDS") => VexOperand::Dds,
+        _ => VexOperand::None,
+    };
+    if vvvv != VexOperand::None {
+        fields.next();
+    }
+
+    let l = fields.next().and_then(parse_len).ok_or_else(err)?;
+
+    let pp = match fields.peek() {
+        Some(&"66") => VexPp::P66,
+        Some(&"F3") => VexPp::PF3,
+        Some(&"F2") => VexPp::PF2,
+        _ => VexPp::None,
+    };
+    if pp != VexPp::None {
+        fields.next();
+    }
+
+    let map = match fields.next() {
+        Some("0F") => VexMap::M0F,
+        Some("0F38") => VexMap::M0F38,
+        Some("0F3A") => VexMap::M0F3A,
+        _ => return Err(err()),
+    };
+
+    // The W field is optional (e.g. VEX.128.0F 77)
+    let w = match fields.next() {
+        Some("W0") => VexW::W0,
+        Some("W1") => VexW::W1,
+        Some("WIG") | None => VexW::Ignored,
+        _ => return Err(err()),
+    };
+
+    if fields.next().is_some() {
+        return Err(err());
+    }
+
+    Ok((vvvv, l, pp, map, w))
+}
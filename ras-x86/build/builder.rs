@@ -0,0 +1,167 @@
+//! Generate the methods of the `CodeBuilder` (one per mnemonic and number of operands).
+
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use ras_x86_repr::{InstructionRepr, OperandKind};
+
+use std::collections::BTreeMap;
+
+/// The mnemonics that are Rust keywords (their methods have a trailing underscore).
+const KEYWORDS: &[&str] = &["in", "loop"];
+
+/// The Rust type an operand is converted from.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum OperandClass {
+    Register,
+    Immediate,
+    Memory,
+    /// Any `Operand` (e.g. an operand that can be a register or a memory location).
+    Any,
+}
+
+/// Generate an `impl CodeBuilder` block with a method for each mnemonic, which is meant to be
+/// `include!`d in `src/builder.rs`.
+///
+/// If the instructions of a mnemonic have a different number of operands, there is a method for
+/// each number of operands: the one with the fewest operands is named after the mnemonic, and the
+/// others have the number of operands as a suffix (e.g. `imul`, `imul_2` and `imul_3`).
+pub fn generate_builder(insts: &[(String, Vec<InstructionRepr>)]) -> TokenStream {
+    let mut methods = vec![];
+
+    for (mnemonic, reprs) in insts {
+        let mut by_arity: BTreeMap<usize, Vec<&InstructionRepr>> = BTreeMap::new();
+        for repr in reprs {
+            by_arity.entry(repr.operands.len()).or_default().push(repr);
+        }
+
+        let min_arity = by_arity.keys().next().copied().unwrap_or_default();
+        for (arity, reprs) in by_arity {
+            methods.push(builder_method(mnemonic, arity, arity != min_arity, &reprs));
+        }
+    }
+
+    quote! {
+        impl CodeBuilder {
+            #(#methods)*
+        }
+    }
+}
+
+fn builder_method(
+    mnemonic: &str,
+    arity: usize,
+    has_suffix: bool,
+    reprs: &[&InstructionRepr],
+) -> TokenStream {
+    let mut name = mnemonic.to_ascii_lowercase();
+    if KEYWORDS.contains(&name.as_str()) {
+        name.push('_');
+    }
+    if has_suffix {
+        name = format!("{}_{}", name, arity);
+    }
+    let name = format_ident!("{}", name);
+    let variant = format_ident!("{}", mnemonic);
+
+    let mut params = vec![];
+    let mut operands = vec![];
+    for i in 0..arity {
+        let param = format_ident!("op{}", i + 1);
+        let class = operand_class(reprs.iter().map(|repr| repr.operands[i].kind));
+        let (ty, operand) = match class {
+            OperandClass::Register => (
+                quote! { crate::operand::Register },
+                quote! { crate::operand::Operand::Register(#param.into()) },
+            ),
+            OperandClass::Immediate => (
+                quote! { crate::operand::Immediate },
+                quote! { crate::operand::Operand::Immediate(#param.into()) },
+            ),
+            OperandClass::Memory => (
+                quote! { crate::operand::Memory },
+                quote! { crate::operand::Operand::Memory(#param.into()) },
+            ),
+            OperandClass::Any => (quote! { crate::operand::Operand }, quote! { #param.into() }),
+        };
+
+        params.push(quote! { #param: impl Into<#ty> });
+        operands.push(operand);
+    }
+
+    let mut forms = vec![];
+    for repr in reprs {
+        let form = repr
+            .operands
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+        if !forms.contains(&form) {
+            forms.push(form);
+        }
+    }
+
+    let summary = format!(" Append a `{}` instruction.", mnemonic);
+    let forms = forms.iter().map(|form| match form.as_str() {
+        "" => " * no operands".to_string(),
+        form => format!(" * `{} {}`", mnemonic, form),
+    });
+
+    quote! {
+        #[doc = #summary]
+        ///
+        /// The supported forms are:
+        #(#[doc = #forms])*
+        pub fn #name(&mut self, #(#params),*) -> crate::RasResult<&mut Self> {
+            self.instruction(crate::instruction::Instruction::new(
+                crate::mnemonic::Mnemonic::#variant,
+                vec![#(#operands),*],
+            ))
+        }
+    }
+}
+
+/// Returns the type of operand that can be encoded as any of the specified operand kinds.
+fn operand_class(mut kinds: impl Iterator<Item = OperandKind>) -> OperandClass {
+    let first = kinds.next().map(kind_class).unwrap_or(OperandClass::Any);
+
+    if kinds.all(|kind| kind_class(kind) == first) {
+        first
+    } else {
+        OperandClass::Any
+    }
+}
+
+fn kind_class(kind: OperandKind) -> OperandClass {
+    use OperandKind::*;
+
+    match kind {
+        Reg | ModRmReg | OpcodeRd | Al | Sreg | Cr | Cr8 | Dr | Cs | Ds | Es | Fs | Gs | Ss
+        | Cl | Dx | Mm | Mm1 | Mm2 | Xmm | Ymm | Zmm | K | St0 | Sti => OperandClass::Register,
+        Imm | Zero | One | Three => OperandClass::Immediate,
+        Moffs | M | M8 | M16 | M32 | M64 | M128 | M256 | M512 | M16And16 | M16And32 | M32And32
+        | M16And64 | M32Fp | M64Fp | M80Fp | M16Int | M32Int | M64Int | M80Bcd | M2Byte
+        | M14M28Byte | M94M108Byte | M512Byte => OperandClass::Memory,
+        // The far pointers, relative offsets, and the operands that can be either registers or
+        // memory locations.
+        ModRmRegMem
+        | R32M16
+        | R64M16
+        | Rel8
+        | Rel16
+        | Rel32
+        | FarPointer16
+        | FarPointer32
+        | MemIndirectFarPointer16
+        | MemIndirectFarPointer32
+        | MemIndirectFarPointer64
+        | Mm2M64
+        | MmM32
+        | MmM64
+        | XmmM32
+        | XmmM64
+        | XmmM128
+        | YmmM256
+        | ZmmM512 => OperandClass::Any,
+    }
+}
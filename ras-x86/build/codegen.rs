@@ -0,0 +1,177 @@
+//! Generate the static instruction tables from the parsed instruction reprs.
+
+use proc_macro2::{Literal, TokenStream};
+use quote::{format_ident, quote};
+use ras_x86_repr::prefix::{EvexPrefix, VexPrefix};
+use ras_x86_repr::{CpuFeature, EncodingBytecode, InstructionRepr, Mode, OperandRepr};
+
+use std::fmt::Debug;
+
+/// Generate a `static` array of instruction reprs for each mnemonic, an `instruction_reprs`
+/// function that maps each `Mnemonic` to its reprs, and an `instruction_table` function that
+/// returns the reprs of all the mnemonics.
+///
+/// The generated code is meant to be `include!`d in a module of the `ras-x86` crate.
+pub fn generate_instruction_table(insts: &[(String, Vec<InstructionRepr>)]) -> TokenStream {
+    let mut arms = vec![];
+    let mut entries = vec![];
+    let mut tables = vec![];
+
+    for (mnemonic, reprs) in insts {
+        let variant = format_ident!("{}", mnemonic);
+        let len = reprs.len();
+        let reprs = reprs.iter().map(instruction_repr);
+
+        arms.push(quote! { crate::mnemonic::Mnemonic::#variant => &#variant });
+        entries.push(quote! { (crate::mnemonic::Mnemonic::#variant, &#variant) });
+        tables.push(quote! {
+            static #variant: [crate::repr::InstructionRepr; #len] = [#(#reprs),*];
+        });
+    }
+
+    let table_len = insts.len();
+
+    quote! {
+        /// Returns the instruction reprs (the possible encodings) of the specified mnemonic.
+        pub fn instruction_reprs(
+            mnemonic: crate::mnemonic::Mnemonic,
+        ) -> &'static [crate::repr::InstructionRepr] {
+            match mnemonic {
+                #(#arms),*
+            }
+        }
+
+        /// Returns the instruction reprs of all the mnemonics.
+        pub fn instruction_table(
+        ) -> &'static [(crate::mnemonic::Mnemonic, &'static [crate::repr::InstructionRepr])] {
+            &INSTRUCTION_TABLE
+        }
+
+        static INSTRUCTION_TABLE: [
+            (crate::mnemonic::Mnemonic, &[crate::repr::InstructionRepr]);
+            #table_len
+        ] = [#(#entries),*];
+
+        #(#tables)*
+    }
+}
+
+fn instruction_repr(repr: &InstructionRepr) -> TokenStream {
+    let bytecode = repr.encoding.bytecode.iter().map(encoding_bytecode);
+    let is_np = repr.encoding.is_np;
+    let operands = repr.operands.iter().map(operand_repr);
+    let modes = repr.modes.iter().map(mode);
+    let features = repr.features.iter().map(cpu_feature);
+
+    quote! {
+        crate::repr::InstructionRepr {
+            encoding: crate::repr::InstructionEncoding {
+                bytecode: std::borrow::Cow::Borrowed(&[#(#bytecode),*]),
+                is_np: #is_np,
+            },
+            operands: std::borrow::Cow::Borrowed(&[#(#operands),*]),
+            modes: std::borrow::Cow::Borrowed(&[#(#modes),*]),
+            features: std::borrow::Cow::Borrowed(&[#(#features),*]),
+        }
+    }
+}
+
+fn encoding_bytecode(code: &EncodingBytecode) -> TokenStream {
+    use EncodingBytecode::*;
+
+    let (variant, arg) = match code {
+        Rex(prefix) => ("Rex", Some(variant("crate::repr::RexPrefix", prefix))),
+        Vex(prefix) => ("Vex", Some(vex_prefix(prefix))),
+        Evex(prefix) => ("Evex", Some(evex_prefix(prefix))),
+        Prefix(b) => ("Prefix", Some(byte(*b))),
+        Opcode(b) => ("Opcode", Some(byte(*b))),
+        OpcodeRb(b) => ("OpcodeRb", Some(byte(*b))),
+        OpcodeRw(b) => ("OpcodeRw", Some(byte(*b))),
+        OpcodeRd(b) => ("OpcodeRd", Some(byte(*b))),
+        OpcodeRo(b) => ("OpcodeRo", Some(byte(*b))),
+        OpcodeI(b) => ("OpcodeI", Some(byte(*b))),
+        ModRmWithReg(reg) => ("ModRmWithReg", Some(byte(*reg))),
+        ModRm | Ib | Iw | Id | Io | Is4 | Cb | Cw | Cd | Cp | Co | Ct => {
+            return variant("crate::repr::EncodingBytecode", code)
+        }
+    };
+
+    let variant = format_ident!("{}", variant);
+    quote! { crate::repr::EncodingBytecode::#variant(#arg) }
+}
+
+fn vex_prefix(prefix: &VexPrefix) -> TokenStream {
+    let vvvv = variant("crate::repr::prefix::VexOperand", &prefix.vvvv);
+    let l = variant("crate::repr::prefix::VexLength", &prefix.l);
+    let pp = variant("crate::repr::prefix::VexPp", &prefix.pp);
+    let mmmmm = variant("crate::repr::prefix::VexMap", &prefix.mmmmm);
+    let w = variant("crate::repr::prefix::VexW", &prefix.w);
+
+    quote! {
+        crate::repr::VexPrefix { vvvv: #vvvv, l: #l, pp: #pp, mmmmm: #mmmmm, w: #w }
+    }
+}
+
+fn evex_prefix(prefix: &EvexPrefix) -> TokenStream {
+    let vvvv = variant("crate::repr::prefix::VexOperand", &prefix.vvvv);
+    let l = variant("crate::repr::prefix::EvexLength", &prefix.l);
+    let pp = variant("crate::repr::prefix::VexPp", &prefix.pp);
+    let mm = variant("crate::repr::prefix::VexMap", &prefix.mm);
+    let w = variant("crate::repr::prefix::VexW", &prefix.w);
+    let tuple_type = match &prefix.tuple_type {
+        Some(tuple_type) => {
+            let tuple_type = variant("crate::repr::prefix::TupleType", tuple_type);
+            quote! { Some(#tuple_type) }
+        }
+        None => quote! { None },
+    };
+    let masking = variant("crate::repr::prefix::EvexMasking", &prefix.masking);
+    let broadcast = match prefix.broadcast {
+        Some(size) => {
+            let size = Literal::u32_unsuffixed(size);
+            quote! { Some(#size) }
+        }
+        None => quote! { None },
+    };
+    let rounding = variant("crate::repr::prefix::EvexRounding", &prefix.rounding);
+
+    quote! {
+        crate::repr::EvexPrefix {
+            vvvv: #vvvv,
+            l: #l,
+            pp: #pp,
+            mm: #mm,
+            w: #w,
+            tuple_type: #tuple_type,
+            masking: #masking,
+            broadcast: #broadcast,
+            rounding: #rounding,
+        }
+    }
+}
+
+fn operand_repr(op: &OperandRepr) -> TokenStream {
+    let kind = variant("crate::repr::OperandKind", &op.kind);
+    let size = Literal::u32_unsuffixed(op.size);
+
+    quote! { crate::repr::OperandRepr { kind: #kind, size: #size } }
+}
+
+fn mode(mode: &Mode) -> TokenStream {
+    variant("crate::repr::Mode", mode)
+}
+
+fn cpu_feature(feature: &CpuFeature) -> TokenStream {
+    variant("crate::repr::CpuFeature", feature)
+}
+
+fn byte(b: u8) -> TokenStream {
+    let b = Literal::u8_unsuffixed(b);
+    quote! { #b }
+}
+
+/// Returns the path of a fieldless enum variant (whose `Debug` representation is the name of
+/// the variant).
+fn variant(ty: &str, value: &impl Debug) -> TokenStream {
+    format!("{}::{:?}", ty, value).parse().unwrap()
+}
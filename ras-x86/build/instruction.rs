@@ -1,4 +1,5 @@
 use crate::parsers::{operand_repr, repeat, seq, tok, until, ParseResult};
+use ras_x86_repr::prefix::{EvexMasking, EvexPrefix, EvexRounding};
 use ras_x86_repr::OperandRepr;
 
 pub fn parse_instruction_column(inst: &str) -> ParseResult<(String, Vec<OperandRepr>)> {
@@ -11,3 +12,64 @@ pub fn parse_instruction_column(inst: &str) -> ParseResult<(String, Vec<OperandR
     let ((mnemonic, operands), _) = parse_instruction(inst)?;
     Ok((mnemonic.to_string(), operands))
 }
+
+/// The EVEX features of an instruction, which are specified in the `Instruction` column as
+/// operand decorators (e.g. `VADDPS zmm1 {k1}{z}, zmm2, zmm3/m512/m32bcst{er}`).
+#[derive(Debug)]
+pub struct EvexDecorators {
+    masking: EvexMasking,
+    broadcast: Option<u32>,
+    rounding: EvexRounding,
+}
+
+impl EvexDecorators {
+    /// Record the EVEX features in the EVEX prefix of the instruction.
+    pub fn apply(&self, evex: &mut EvexPrefix) {
+        evex.masking = self.masking;
+        evex.broadcast = self.broadcast;
+        evex.rounding = self.rounding;
+    }
+}
+
+/// Remove the EVEX decorators from the `Instruction` column, so that it can be parsed by
+/// [`parse_instruction_column`].
+pub fn strip_evex_decorators(inst: &str) -> (String, EvexDecorators) {
+    let mut inst = inst.to_string();
+    let mut strip = |decorator: &str| {
+        let found = inst.contains(decorator);
+        inst = inst.replace(decorator, "");
+        found
+    };
+
+    let masking = if strip("{k1}{z}") {
+        EvexMasking::MergeOrZero
+    } else if strip("{k1}") || strip("{k2}") {
+        EvexMasking::Merge
+    } else {
+        EvexMasking::None
+    };
+
+    let broadcast = if strip("/m32bcst") {
+        Some(32)
+    } else if strip("/m64bcst") {
+        Some(64)
+    } else {
+        None
+    };
+
+    let rounding = if strip("{er}") {
+        EvexRounding::Er
+    } else if strip("{sae}") {
+        EvexRounding::Sae
+    } else {
+        EvexRounding::None
+    };
+
+    let decorators = EvexDecorators {
+        masking,
+        broadcast,
+        rounding,
+    };
+
+    (inst, decorators)
+}
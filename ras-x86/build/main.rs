@@ -1,44 +1,83 @@
+mod builder;
+mod codegen;
 mod csv_util;
 mod instruction;
 mod opcode;
 mod parsers;
 
+use builder::generate_builder;
+use codegen::generate_instruction_table;
 use csv_util::{is_valid_mode, CsvHeader};
-use instruction::parse_instruction_column;
+use instruction::{parse_instruction_column, strip_evex_decorators};
 use opcode::parse_opcode_column;
 use parsers::ParseResult;
 
-use std::collections::{HashMap, HashSet};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
 use std::fs::{self, File};
-use std::path::Path;
-use std::process::Command;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use proc_macro2::TokenStream;
 use quote::quote;
-use ras_x86_repr::{InstructionRepr, Mode};
+use ras_x86_repr::prefix::TupleType;
+use ras_x86_repr::{CpuFeature, InstructionRepr, Mode};
 
+const BUILDER: &str = "builder.rs";
 const INST_CSV: &str = "./x86-csv/x86.csv";
-const INST_MAP: &str = "inst_map.json";
-const RUSTFMT_BIN: &str = "rustfmt";
+const INST_TABLE: &str = "inst_table.rs";
+const MNEMONIC_ENUM: &str = "mnemonic.rs";
 
 fn main() -> ParseResult<()> {
     let inst_csv = Path::new(env!("CARGO_MANIFEST_DIR")).join(INST_CSV);
     println!("cargo:rerun-if-changed={}", inst_csv.to_str().unwrap());
 
     let mut rdr = csv::Reader::from_reader(File::open(&inst_csv).unwrap());
+    let mut extra_rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .from_reader(EXTRA_ROWS.as_bytes());
     let mut mnemonics = HashSet::new();
     let mut insts: HashMap<String, Vec<InstructionRepr>> = Default::default();
+    // The number of rows that couldn't be parsed.
+    let mut skipped_rows = 0;
+    // The unknown CPU features, and the number of rows that require them.
+    let mut unknown_features: BTreeMap<String, usize> = Default::default();
 
-    for rec in rdr.records() {
+    for rec in rdr.records().chain(extra_rdr.records()) {
         let rec = rec.unwrap();
 
-        // XXX: forget about special instructions for now
-        if matches!(rec.get(CsvHeader::FeatureFlags as usize), Some(flags) if !flags.is_empty()) {
-            continue;
-        }
-
-        let (mnemonic, operands) = parse_instruction_column(get_header!(rec, Instruction))?;
-        let inst_enc = parse_opcode_column(get_header!(rec, Opcode))?;
+        let feature_flags = get_header!(rec, FeatureFlags);
+        let features = match parse_feature_flags(feature_flags) {
+            Ok(features) => features,
+            Err(feature) => {
+                *unknown_features.entry(feature).or_default() += 1;
+                continue;
+            }
+        };
+
+        let (instruction, opcode) =
+            fix_row(get_header!(rec, Instruction), get_header!(rec, Opcode));
+        let (instruction, decorators) = strip_evex_decorators(instruction);
+        let instruction = parse_instruction_column(&instruction);
+        let opcode = parse_opcode_column(&opcode).and_then(|mut opcode| {
+            if let Some(evex) = opcode.evex_prefix_mut() {
+                decorators.apply(evex);
+                let tuple_type = get_header!(rec, TupleType);
+                if !tuple_type.is_empty() {
+                    evex.tuple_type = Some(TupleType::from_str(tuple_type)?);
+                }
+            }
+            Ok(opcode)
+        });
+        let ((mnemonic, operands), inst_enc) = match (instruction, opcode) {
+            (Ok(instruction), Ok(opcode)) => (instruction, opcode),
+            // XXX: not all the operands of the instructions from the extensions can be parsed yet
+            _ => {
+                skipped_rows += 1;
+                continue;
+            }
+        };
 
         let mut modes = vec![];
         if is_valid_mode(get_header!(rec, Valid16)) {
@@ -53,24 +92,125 @@ fn main() -> ParseResult<()> {
             modes.push(Mode::Long);
         }
 
-        let inst_repr = InstructionRepr::new(inst_enc, operands, modes);
+        let inst_repr = InstructionRepr::new(inst_enc, operands, modes, features);
         insts.entry(mnemonic.clone()).or_default().push(inst_repr);
 
         mnemonics.insert(mnemonic);
     }
 
+    if skipped_rows > 0 {
+        println!(
+            "cargo:warning=skipped {} rows of {} that couldn't be parsed",
+            skipped_rows, INST_CSV
+        );
+    }
+
+    for (feature, rows) in &unknown_features {
+        println!(
+            "cargo:warning=skipped {} rows of {} that require the unknown CPU feature {}",
+            rows, INST_CSV, feature
+        );
+    }
+
     let mut insts = insts.into_iter().collect::<Vec<(_, _)>>();
     insts.sort_by_key(|inst| inst.0.clone());
 
-    let inst_map = Path::new(env!("CARGO_MANIFEST_DIR")).join(INST_MAP);
-    fs::write(inst_map, serde_json::to_string_pretty(&insts).unwrap()).unwrap();
-
-    generate_mnemonic_enum(mnemonics);
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(
+        out_dir.join(INST_TABLE),
+        generate_instruction_table(&insts).to_string(),
+    )
+    .unwrap();
+    fs::write(out_dir.join(BUILDER), generate_builder(&insts).to_string()).unwrap();
+    fs::write(
+        out_dir.join(MNEMONIC_ENUM),
+        generate_mnemonic_enum(mnemonics).to_string(),
+    )
+    .unwrap();
 
     Ok(())
 }
 
-fn generate_mnemonic_enum(mnemonics: HashSet<String>) {
+/// The `Instruction` and `Opcode` columns of a row of the x86-csv.
+type Row = (&'static str, &'static str);
+
+/// The rows of the x86-csv that are wrong, or that use a different (but equivalent) encoding than
+/// GNU as, as `((instruction, opcode), (fixed instruction, fixed opcode))`.
+const ROW_FIXES: &[(Row, Row)] = &[
+    // Without REX.W, the byte would be sign-extended to 32 bits.
+    (
+        ("MOVSX r64, r/m8", "REX + 0F BE /r"),
+        ("MOVSX r64, r/m8", "REX.W + 0F BE /r"),
+    ),
+    // The selectors are 16 bits wide, so REX.W doesn't change anything (and GNU as omits it).
+    (
+        ("MOV r/m64, Sreg", "REX.W + 8C /r"),
+        ("MOV r/m64, Sreg", "8C /r"),
+    ),
+    (
+        ("MOV Sreg, r/m64", "REX.W + 8E /r"),
+        ("MOV Sreg, r/m64", "8E /r"),
+    ),
+    (
+        ("SLDT r64/m16", "REX.W + 0F 00 /0"),
+        ("SLDT r64/m16", "0F 00 /0"),
+    ),
+    // `reg` is either a 32 or a 64-bit register, but the 64-bit form needs REX.W (see the
+    // `LAR r64, r32/m16` row of `EXTRA_ROWS`).
+    (
+        ("LAR reg, r32/m16", "0F 02 /r"),
+        ("LAR r32, r32/m16", "0F 02 /r"),
+    ),
+];
+
+/// The rows that are missing from the x86-csv (in the same format, without the header).
+///
+/// The x86-csv has no mnemonic for the 64-bit forms of SYSEXIT and SYSRET (like IRETQ for IRET),
+/// so they're added as separate instructions, like GNU as does.
+const EXTRA_ROWS: &str = r#""LAR r64, r32/m16",REX.W + 0F 02 /r,Valid,Invalid,Invalid,,,,,,,
+INT imm8,CD ib,Valid,Valid,Valid,,,,,,,
+"MOV r32/m16, Sreg",8C /r,Valid,Valid,Valid,,,,,,,
+"MOV Sreg, r32/m16",8E /r,Valid,Valid,Valid,,,,,,,
+SYSEXITQ,REX.W + 0F 35,Valid,Invalid,Invalid,,,,,,,
+SYSRETQ,REX.W + 0F 07,Valid,Invalid,Invalid,,,,,,,
+"VMOVQ xmm1, xmm2/m64",VEX.128.F3.0F.WIG 7E /r,Valid,Valid,Invalid,AVX,,,,,,
+"VMOVQ xmm1/m64, xmm2",VEX.128.66.0F.WIG D6 /r,Valid,Valid,Invalid,AVX,,,,,,
+"VPERMILPS xmm1, xmm2/m128, imm8",VEX.128.66.0F3A.W0 04 /r ib,Valid,Valid,Invalid,AVX,,,,,,
+"VPERMILPS ymm1, ymm2/m256, imm8",VEX.256.66.0F3A.W0 04 /r ib,Valid,Valid,Invalid,AVX,,,,,,
+"#;
+
+/// Fixes the `Instruction` and `Opcode` columns of a row of the x86-csv (see [`ROW_FIXES`]).
+///
+/// The SETcc instructions have a ModRM byte whose reg field is ignored (and set to 0 by GNU as),
+/// but the x86-csv omits it (e.g. `0F 94`).
+fn fix_row<'a>(instruction: &'a str, opcode: &'a str) -> (&'a str, Cow<'a, str>) {
+    if let Some((_, (instruction, opcode))) = ROW_FIXES
+        .iter()
+        .find(|(row, _)| *row == (instruction, opcode))
+    {
+        return (instruction, Cow::Borrowed(opcode));
+    }
+
+    if instruction.starts_with("SET") && instruction.ends_with(" r/m8") && !opcode.contains('/') {
+        return (instruction, Cow::Owned(format!("{} /0", opcode)));
+    }
+
+    (instruction, Cow::Borrowed(opcode))
+}
+
+/// Parses the features from the `Feature Flags` column.
+///
+/// Returns the first unknown feature as an error, if any.
+fn parse_feature_flags(feature_flags: &str) -> Result<Vec<CpuFeature>, String> {
+    feature_flags
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .filter(|flag| !flag.is_empty())
+        .map(|flag| CpuFeature::from_str(flag).map_err(|_| flag.to_string()))
+        .collect()
+}
+
+/// Generate the `Mnemonic` enum, which is meant to be `include!`d in `src/mnemonic.rs`.
+fn generate_mnemonic_enum(mnemonics: HashSet<String>) -> TokenStream {
     let mut mnemonics = mnemonics.into_iter().collect::<Vec<_>>();
     mnemonics.sort();
 
@@ -79,8 +219,7 @@ fn generate_mnemonic_enum(mnemonics: HashSet<String>) {
         .map(|mnemonic| mnemonic.parse::<TokenStream>().unwrap())
         .collect::<Vec<TokenStream>>();
 
-    let content = quote! {
-        //! This file was autogenerated by build.rs.
+    quote! {
         use serde::{Serialize, Deserialize};
         use std::hash::Hash;
         use std::str::FromStr;
@@ -103,12 +242,5 @@ fn generate_mnemonic_enum(mnemonics: HashSet<String>) {
                 }
             }
         }
-    };
-
-    let mnemonic_file = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/mnemonic.rs");
-    fs::write(&mnemonic_file, content.to_string()).unwrap();
-    Command::new(RUSTFMT_BIN)
-        .arg(mnemonic_file)
-        .spawn()
-        .expect("failed to run rustfmt");
+    }
 }
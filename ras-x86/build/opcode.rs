@@ -1,12 +1,25 @@
 use crate::parsers::{
-    alt, encoding_bytecode, hex_byte, lit, map, opt, repeat_until, seq, tok, ParseResult,
+    alt, encoding_bytecode, evex_prefix, hex_byte, lit, map, opt, repeat, seq, tok, vex_prefix,
+    ParseResult,
 };
 use ras_x86_repr::{EncodingBytecode, InstructionEncoding, RexPrefix};
 use std::str::{self, FromStr};
 
 pub fn parse_opcode_column(inst: &str) -> ParseResult<InstructionEncoding> {
+    if inst.starts_with("VEX.") {
+        return parse_vex_opcode_column(inst, map(vex_prefix(), EncodingBytecode::Vex));
+    }
+
+    if inst.starts_with("EVEX.") {
+        return parse_vex_opcode_column(inst, map(evex_prefix(), EncodingBytecode::Evex));
+    }
+
     let mut bytecode = vec![];
-    let parse_np = map(tok(opt(lit("NP")), is_separator), |out| out.is_some());
+    // NFx (F2 and F3 aren't allowed) is a constraint on the prefixes the encoding doesn't need to
+    // care about.
+    let parse_np = map(tok(opt(alt(lit("NP"), lit("NFx"))), is_separator), |out| {
+        out == Some("NP")
+    });
     let parse_mandatory_prefix = map(
         tok(opt(alt(lit("66"), alt(lit("F2"), lit("F3")))), |c| {
             is_separator(c)
@@ -47,37 +60,86 @@ pub fn parse_opcode_column(inst: &str) -> ParseResult<InstructionEncoding> {
         bytecode.push(rex_prefix);
     }
 
-    let parse_opcode = repeat_until(
-        tok(
+    // The opcode bytes, optionally followed by the ModRM byte, the immediates and the code
+    // offsets (e.g. `REX.W + B8+ rd io`).
+    let parse_opcode = repeat(tok(
+        alt(
+            opcode_with_register(),
             alt(
                 map(hex_byte(), EncodingBytecode::Opcode),
                 encoding_bytecode(),
             ),
-            |c| c == ' ',
-        ),
-        map(
-            seq(
-                tok(hex_byte(), |c| c == ' '),
-                alt(lit("+rb"), alt(lit("+rd"), lit("+rw"))),
-            ),
-            |(out, suffix)| match suffix {
-                "+rb" => EncodingBytecode::OpcodeRb(out),
-                "+rd" => EncodingBytecode::OpcodeRd(out),
-                "+rw" => EncodingBytecode::OpcodeRw(out),
-                s => unreachable!("invalid suffix: {}", s),
-            },
         ),
-    );
+        |c| c == ' ',
+    ));
 
-    let ((opcodes, opcode), _) = parse_opcode(inst)?;
-    bytecode.extend_from_slice(&opcodes);
-    if let Some(opcode) = opcode {
-        bytecode.push(opcode);
+    let (opcodes, rest) = parse_opcode(inst)?;
+    if !rest.trim().is_empty() {
+        return Err(format!("failed to parse opcode: {}", rest));
     }
-    // TODO parse the remaining parts of the column
+
+    bytecode.extend(opcodes);
     Ok(InstructionEncoding::new(bytecode, is_np))
 }
 
+/// Parse an opcode byte to which the number of a register operand is added (e.g. `B8+ rd`,
+/// `50+rd` or `D8 C0+i`).
+fn opcode_with_register<'i>() -> impl Fn(&'i str) -> ParseResult<(EncodingBytecode, &'i str)> {
+    let parse_suffix = alt(
+        alt(alt(lit("rb"), lit("rw")), alt(lit("rd"), lit("ro"))),
+        lit("i"),
+    );
+
+    map(
+        seq(
+            tok(hex_byte(), |c| c == ' '),
+            seq(tok(lit("+"), |c| c == ' '), parse_suffix),
+        ),
+        |(opcode, (_, suffix))| match suffix {
+            "rb" => EncodingBytecode::OpcodeRb(opcode),
+            "rw" => EncodingBytecode::OpcodeRw(opcode),
+            "rd" => EncodingBytecode::OpcodeRd(opcode),
+            "ro" => EncodingBytecode::OpcodeRo(opcode),
+            "i" => EncodingBytecode::OpcodeI(opcode),
+            s => unreachable!("invalid suffix: {}", s),
+        },
+    )
+}
+
+/// Parse the `Opcode` column of an instruction with a VEX or EVEX prefix (e.g.
+/// `VEX.NDS.128.66.0F.WIG 58 /r`).
+///
+/// The mandatory prefixes and the escape bytes are part of the VEX prefix, so the VEX prefix is
+/// followed by the opcode byte, the ModRM byte and the immediate.
+fn parse_vex_opcode_column<'i, P>(
+    inst: &'i str,
+    parse_prefix: P,
+) -> ParseResult<InstructionEncoding>
+where
+    P: Fn(&'i str) -> ParseResult<(EncodingBytecode, &'i str)>,
+{
+    let parse_vex_prefix = tok(parse_prefix, |c| c == ' ');
+    let parse_opcode = repeat(tok(
+        alt(
+            map(hex_byte(), EncodingBytecode::Opcode),
+            encoding_bytecode(),
+        ),
+        |c| c == ' ',
+    ));
+
+    let (prefix, rest) = parse_vex_prefix(inst)?;
+    let (opcodes, rest) = parse_opcode(rest)?;
+
+    if !rest.trim().is_empty() {
+        return Err(format!("failed to parse VEX opcode: {}", inst));
+    }
+
+    let mut bytecode = vec![prefix];
+    bytecode.extend(opcodes);
+
+    Ok(InstructionEncoding::new(bytecode, false))
+}
+
 fn is_separator(c: char) -> bool {
     c == ' ' || c == '+'
 }
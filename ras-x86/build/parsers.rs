@@ -1,4 +1,4 @@
-use ras_x86_repr::{EncodingBytecode, OperandRepr};
+use ras_x86_repr::{EncodingBytecode, EvexPrefix, OperandRepr, VexPrefix};
 use std::str::{self, FromStr};
 
 pub type ParseResult<T> = Result<T, ParseError>;
@@ -23,10 +23,22 @@ pub fn hex_byte() -> impl Fn(&str) -> ParseResult<(u8, &str)> {
 
 pub fn encoding_bytecode() -> impl Fn(&str) -> ParseResult<(EncodingBytecode, &str)> {
     move |input| {
-        if input.len() < 2 {
-            return Err(format!("invalid hex byte: {}", input));
-        }
-        EncodingBytecode::from_str(&input[..2]).map(|bytecode| (bytecode, &input[2..]))
+        let (bytecode, rest) = until(|c| c == ' ')(input)?;
+        EncodingBytecode::from_str(bytecode).map(|bytecode| (bytecode, rest))
+    }
+}
+
+pub fn vex_prefix() -> impl Fn(&str) -> ParseResult<(VexPrefix, &str)> {
+    move |input| {
+        let (prefix, rest) = until(|c| c == ' ')(input)?;
+        VexPrefix::from_str(prefix).map(|prefix| (prefix, rest))
+    }
+}
+
+pub fn evex_prefix() -> impl Fn(&str) -> ParseResult<(EvexPrefix, &str)> {
+    move |input| {
+        let (prefix, rest) = until(|c| c == ' ')(input)?;
+        EvexPrefix::from_str(prefix).map(|prefix| (prefix, rest))
     }
 }
 
@@ -115,32 +127,6 @@ where
     move |input| p(input).map(|(result, rest)| (map_fn(result), rest))
 }
 
-pub fn repeat_until<'i, P1, P2, O1, O2>(
-    p1: P1,
-    p2: P2,
-) -> impl Fn(&'i str) -> ParseResult<((Vec<O1>, Option<O2>), &'i str)>
-where
-    P1: Fn(&'i str) -> ParseResult<(O1, &'i str)>,
-    P2: Fn(&'i str) -> ParseResult<(O2, &'i str)>,
-{
-    move |mut input| {
-        let mut results = vec![];
-        loop {
-            if let Ok((out2, rest)) = p2(input) {
-                return Ok(((results, Some(out2)), rest));
-            }
-
-            match p1(input) {
-                Ok((out, rest)) => {
-                    input = rest;
-                    results.push(out);
-                }
-                _ => return Ok(((results, None), input)),
-            }
-        }
-    }
-}
-
 pub fn repeat<'i, P, O>(p: P) -> impl Fn(&'i str) -> ParseResult<(Vec<O>, &'i str)>
 where
     P: Fn(&'i str) -> ParseResult<(O, &'i str)>,
@@ -14,7 +14,7 @@ fn main() -> RasResult<()> {
         eprintln!("Usage: {} <obj file name>", args.next().unwrap());
         std::process::exit(1);
     } else {
-        args.skip(1).next().unwrap()
+        args.nth(1).unwrap()
     };
 
     let insts = vec![
@@ -0,0 +1,796 @@
+//! The output of the assembler: the assembled sections, the symbols they define, and the
+//! references to symbols that are left for a linker (or a loader) to resolve.
+//!
+//! ```
+//! use ras_x86::assembled::{RelocationKind, SymbolBinding};
+//! use ras_x86::assembler::Assembler;
+//! use ras_x86::parser::parse_asm;
+//! use ras_x86::symbol::{Symbol, SymbolAttribute, SymbolType};
+//!
+//! let items = parse_asm("start:\ncall exit\nret").unwrap();
+//! let exit = Symbol::new_decl(SymbolType::Quad, SymbolAttribute::Global as u8);
+//! let out = Assembler::long_mode()
+//!     .items(items)
+//!     .symbols(&[("exit".into(), exit)])
+//!     .assemble()
+//!     .unwrap();
+//!
+//! assert_eq!(out.text(), [0xe8, 0x00, 0x00, 0x00, 0x00, 0xc3]);
+//!
+//! let start = out.symbol("start").unwrap();
+//! assert_eq!((start.offset, start.size, start.binding), (0, 6, SymbolBinding::Local));
+//!
+//! let reloc = &out.relocations()[0];
+//! assert_eq!((reloc.offset, reloc.kind), (1, RelocationKind::Relative32));
+//! assert_eq!((reloc.symbol.as_str(), reloc.addend), ("exit", -4));
+//!
+//! // The `ret` is the third item.
+//! assert_eq!(out.item_at(0, 5), Some(2));
+//! ```
+
+use crate::assembler::SymbolTable;
+use crate::encoder::{fits_in, Encoder};
+use crate::symbol::{SymbolId, SymbolOffset};
+use crate::{Mode, RasError, RasResult};
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::ops::Range;
+
+/// The name of the section the instructions are assembled into by default.
+pub const TEXT_SECTION: &str = ".text";
+
+/// Assembled code, along with its symbols and relocations.
+#[derive(Debug, Clone)]
+pub struct Assembled {
+    mode: Mode,
+    sections: Vec<Section>,
+    /// The defined symbols, sorted by section and offset.
+    symbols: Vec<ResolvedSymbol>,
+    /// The symbols defined at absolute addresses (outside of the sections), sorted by name.
+    absolute_symbols: Vec<(SymbolId, u64)>,
+    /// The unresolved references, sorted by section and offset.
+    relocations: Vec<Relocation>,
+    /// The bytes each item was assembled into, sorted by section and offset.
+    source_map: Vec<ItemSpan>,
+}
+
+/// A section of assembled code or data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub kind: SectionKind,
+    /// The contents of the section (all zeros for a [`SectionKind::Bss`] section).
+    pub data: Vec<u8>,
+    /// The alignment of the address of the section (in bytes).
+    pub align: u64,
+}
+
+/// What a section holds, which determines how it's loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SectionKind {
+    /// Executable code.
+    Text,
+    /// Initialized data.
+    Data,
+    /// Read-only data (e.g. `.rodata`).
+    ReadOnlyData,
+    /// Zero-initialized data, which doesn't take any space in the files.
+    Bss,
+}
+
+impl SectionKind {
+    /// The kind of section implied by its name, like GNU as does (e.g. `.text.startup` holds
+    /// code, and `.rodata.str` holds read-only data).
+    pub fn of(name: &str) -> Self {
+        let is = |prefix: &str| {
+            name.strip_prefix(prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('.'))
+        };
+
+        if is(".text") {
+            SectionKind::Text
+        } else if is(".bss") {
+            SectionKind::Bss
+        } else if is(".rodata") {
+            SectionKind::ReadOnlyData
+        } else {
+            SectionKind::Data
+        }
+    }
+}
+
+/// A symbol defined by the assembled code (e.g. a label).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedSymbol {
+    pub name: SymbolId,
+    /// The index of the section the symbol is defined in.
+    pub section: usize,
+    /// The offset of the symbol in its section.
+    pub offset: SymbolOffset,
+    /// The number of bytes up to the next symbol (or to the end of the section).
+    pub size: u64,
+    pub binding: SymbolBinding,
+}
+
+/// The visibility of a symbol to the other object files it's linked with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymbolBinding {
+    Local,
+    Global,
+    Weak,
+}
+
+/// A reference that can't be resolved until the code is loaded or linked: a reference to a
+/// symbol that isn't defined by the assembled code (e.g. an external symbol), or to the address
+/// of a symbol.
+///
+/// The value that needs to be written at the offset of the relocation is computed like for the
+/// ELF relocations: `S + A - P` for the relative relocations, and `S + A` for the absolute ones,
+/// where `S` is the address of the symbol, `A` is the addend, and `P` is the address of the
+/// relocation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    /// The index of the section the relocation applies to.
+    pub section: usize,
+    /// The offset of the bytes to patch in the section.
+    pub offset: SymbolOffset,
+    pub kind: RelocationKind,
+    pub symbol: SymbolId,
+    pub addend: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocationKind {
+    /// A PC-relative 8-bit offset (e.g. the `rel8` of a `jmp`).
+    Relative8,
+    /// A PC-relative 16-bit offset.
+    Relative16,
+    /// A PC-relative 32-bit offset (e.g. the `rel32` of a `call`).
+    Relative32,
+    /// An 8-bit address (e.g. `.byte label`).
+    Absolute8,
+    /// A 16-bit address (e.g. `.word label`, or `mov $label, %ax` in real mode).
+    Absolute16,
+    /// A 32-bit address (e.g. `.long label`, or `mov $label, %eax`).
+    Absolute32,
+    /// A 32-bit address that's sign-extended to 64 bits (e.g. the `R_X86_64_32S` relocations
+    /// of `mov $label, %rax`).
+    Absolute32Signed,
+    /// A 64-bit address (e.g. `.quad label`).
+    Absolute64,
+}
+
+impl RelocationKind {
+    /// The number of bytes to patch.
+    pub fn size(self) -> usize {
+        use RelocationKind::*;
+
+        match self {
+            Relative8 | Absolute8 => 1,
+            Relative16 | Absolute16 => 2,
+            Relative32 | Absolute32 | Absolute32Signed => 4,
+            Absolute64 => 8,
+        }
+    }
+
+    pub fn is_relative(self) -> bool {
+        use RelocationKind::*;
+
+        matches!(self, Relative8 | Relative16 | Relative32)
+    }
+
+    /// Returns `true` if the value of the relocation fits in the bytes it's written to.
+    pub(crate) fn fits(self, value: i64) -> bool {
+        match self.size() {
+            1 if self.is_relative() => i8::try_from(value).is_ok(),
+            2 if self.is_relative() => i16::try_from(value).is_ok(),
+            4 if self.is_relative() || self == RelocationKind::Absolute32Signed => {
+                i32::try_from(value).is_ok()
+            }
+            size => fits_in(value, size as u64),
+        }
+    }
+
+    pub(crate) fn relative(size: u64) -> Option<Self> {
+        match size {
+            1 => Some(RelocationKind::Relative8),
+            2 => Some(RelocationKind::Relative16),
+            4 => Some(RelocationKind::Relative32),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn absolute(size: u64) -> Option<Self> {
+        match size {
+            1 => Some(RelocationKind::Absolute8),
+            2 => Some(RelocationKind::Absolute16),
+            4 => Some(RelocationKind::Absolute32),
+            8 => Some(RelocationKind::Absolute64),
+            _ => None,
+        }
+    }
+}
+
+/// The bytes an item (an instruction) was assembled into.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ItemSpan {
+    /// The index of the item in the items of the [`Assembler`](crate::assembler::Assembler).
+    pub item: usize,
+    /// The index of the section the item was assembled into.
+    pub section: usize,
+    /// The offsets of the bytes of the item in the section.
+    pub range: Range<SymbolOffset>,
+}
+
+impl Assembled {
+    /// Collect the output of the assembler.
+    ///
+    /// `sections` are the encoders of the sections (the first one is `.text`), and
+    /// `item_ranges` are the section and the bytes each item was assembled into (indexed by
+    /// item).
+    pub(crate) fn new(
+        sections: Vec<(String, Encoder)>,
+        sym_tab: SymbolTable,
+        item_ranges: Vec<(usize, Range<SymbolOffset>)>,
+    ) -> Self {
+        let mut relocations = vec![];
+        for (section, (_, enc)) in sections.iter().enumerate() {
+            let relative = enc
+                .unresolved_fixups()
+                .map(|(symbol_id, fixup)| Relocation {
+                    section,
+                    offset: fixup.offset,
+                    kind: RelocationKind::relative(fixup.size)
+                        .expect("relative offsets are 1, 2 or 4 bytes long"),
+                    symbol: symbol_id.clone(),
+                    // The relative offsets are relative to the end of the bytes that hold them.
+                    addend: -(fixup.size as i64),
+                });
+            let absolute = enc
+                .unresolved_address_fixups()
+                .map(|(symbol_id, fixup)| Relocation {
+                    section,
+                    offset: fixup.offset,
+                    kind: fixup
+                        .address_kind()
+                        .expect("addresses are 1, 2, 4 or 8 bytes long"),
+                    symbol: symbol_id.clone(),
+                    addend: 0,
+                });
+            relocations.extend(relative.chain(absolute));
+        }
+
+        let symbols = sym_tab
+            .into_iter()
+            .filter_map(|(name, sym)| {
+                let binding = if sym.is_weak() {
+                    SymbolBinding::Weak
+                } else if sym.is_global() {
+                    SymbolBinding::Global
+                } else {
+                    SymbolBinding::Local
+                };
+
+                sym.offset.map(|offset| ResolvedSymbol {
+                    name,
+                    section: sym.section,
+                    offset,
+                    size: 0,
+                    binding,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let source_map = item_ranges
+            .into_iter()
+            .enumerate()
+            .filter(|(_, (_, range))| !range.is_empty())
+            .map(|(item, (section, range))| ItemSpan {
+                item,
+                section,
+                range,
+            })
+            .collect();
+
+        let mode = sections[0].1.mode;
+        let sections = sections
+            .into_iter()
+            .map(|(name, enc)| Section {
+                kind: SectionKind::of(&name),
+                name,
+                align: enc.align,
+                data: enc.out,
+            })
+            .collect();
+        Self::from_parts(mode, sections, symbols, vec![], relocations, source_map)
+    }
+
+    /// Sort the symbols and the relocations, and work out the sizes of the symbols.
+    pub(crate) fn from_parts(
+        mode: Mode,
+        sections: Vec<Section>,
+        mut symbols: Vec<ResolvedSymbol>,
+        mut absolute_symbols: Vec<(SymbolId, u64)>,
+        mut relocations: Vec<Relocation>,
+        source_map: Vec<ItemSpan>,
+    ) -> Self {
+        relocations.sort_by_key(|reloc| (reloc.section, reloc.offset));
+        absolute_symbols.sort();
+        symbols.sort_by(|a, b| (a.section, a.offset, &a.name).cmp(&(b.section, b.offset, &b.name)));
+
+        // A symbol extends up to the next symbol at a higher offset in its section.
+        for i in 0..symbols.len() {
+            let (section, start) = (symbols[i].section, symbols[i].offset);
+            let end = symbols[i..]
+                .iter()
+                .take_while(|next| next.section == section)
+                .map(|next| next.offset)
+                .find(|&offset| offset > start)
+                .unwrap_or(sections[section].data.len() as u64);
+            symbols[i].size = end - start;
+        }
+
+        Self {
+            mode,
+            sections,
+            symbols,
+            absolute_symbols,
+            relocations,
+            source_map,
+        }
+    }
+
+    /// The mode the code was assembled in.
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    /// The instructions assembled into the `.text` section.
+    pub fn text(&self) -> &[u8] {
+        &self.sections[0].data
+    }
+
+    /// The symbols defined by the code, sorted by section and offset.
+    pub fn symbols(&self) -> &[ResolvedSymbol] {
+        &self.symbols
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<&ResolvedSymbol> {
+        self.symbols.iter().find(|sym| sym.name == name)
+    }
+
+    /// The symbols defined at absolute addresses, which aren't in any section (e.g. the absolute
+    /// symbols of linked objects), sorted by name.
+    pub fn absolute_symbols(&self) -> &[(SymbolId, u64)] {
+        &self.absolute_symbols
+    }
+
+    /// The references to the symbols that aren't defined by the code, and to the addresses of
+    /// the symbols, sorted by section and offset.
+    pub fn relocations(&self) -> &[Relocation] {
+        &self.relocations
+    }
+
+    /// The bytes each item was assembled into, sorted by item (the items that weren't
+    /// assembled into any bytes, like labels, are left out).
+    pub fn source_map(&self) -> &[ItemSpan] {
+        &self.source_map
+    }
+
+    /// Lay out the sections one after the other as a flat binary (like `nasm -f bin`), which is
+    /// loaded at the `origin` address.
+    ///
+    /// Each section starts at the next address that's aligned to the alignment of the section
+    /// (the bytes in between, and the `.bss` sections, are filled with zeros), and the references
+    /// to the addresses of the symbols are resolved to their final addresses. It's an error if
+    /// the code refers to external symbols, which would have to be relocated.
+    ///
+    /// ```
+    /// use ras_x86::assembler::Assembler;
+    /// use ras_x86::parser::parse_asm;
+    ///
+    /// let items = parse_asm("start:\nmov $msg, %si\nmsg:\n.byte 0x2a").unwrap();
+    /// let out = Assembler::real_mode().items(items).assemble().unwrap();
+    ///
+    /// let bin = out.flat_binary(0x7c00).unwrap();
+    /// assert_eq!(bin, [0xbe, 0x03, 0x7c, 0x2a]);
+    /// ```
+    pub fn flat_binary(&self, origin: u64) -> RasResult<Vec<u8>> {
+        // The address of each section.
+        let mut addresses = vec![];
+        let mut end = origin;
+        for section in &self.sections {
+            let address = align_up(end, section.align);
+            addresses.push(address);
+            end = address + section.data.len() as u64;
+        }
+
+        let mut bin = vec![];
+        for (address, data) in addresses.iter().zip(self.relocate(&addresses)?) {
+            bin.resize((address - origin) as usize, 0);
+            bin.extend(data);
+        }
+        Ok(bin)
+    }
+
+    /// Returns the contents of the sections loaded at the specified addresses (indexed by
+    /// section), with the references to the symbols resolved to their final addresses.
+    ///
+    /// It's an error if the code refers to external symbols, which would have to be relocated.
+    pub(crate) fn relocate(&self, addresses: &[u64]) -> RasResult<Vec<Vec<u8>>> {
+        let mut sections = self
+            .sections
+            .iter()
+            .map(|section| section.data.clone())
+            .collect::<Vec<_>>();
+        // The linked code can have several local symbols with the same name, but the relocations
+        // only refer to the local symbols of the sections.
+        let (local, nonlocal): (Vec<_>, Vec<_>) = self
+            .symbols
+            .iter()
+            .partition(|sym| sym.binding == SymbolBinding::Local);
+        let symbols = local
+            .into_iter()
+            .chain(nonlocal)
+            .map(|sym| (&sym.name, addresses[sym.section] + sym.offset))
+            .chain(
+                self.absolute_symbols
+                    .iter()
+                    .map(|(name, address)| (name, *address)),
+            )
+            .collect::<HashMap<_, _>>();
+
+        let mut external = vec![];
+        for reloc in &self.relocations {
+            let symbol = match symbols.get(&reloc.symbol) {
+                Some(symbol) => *symbol,
+                None => {
+                    external.push(reloc.symbol.clone());
+                    continue;
+                }
+            };
+
+            let address = addresses[reloc.section] + reloc.offset;
+            let mut value = (symbol as i64).wrapping_add(reloc.addend);
+            if reloc.kind.is_relative() {
+                value = value.wrapping_sub(address as i64);
+            }
+
+            if !reloc.kind.fits(value) {
+                return Err(RasError::Encoding(format!(
+                    "the reference to {} at {:#x} doesn't fit in {} bytes",
+                    reloc.symbol,
+                    address,
+                    reloc.kind.size()
+                )));
+            }
+
+            let start = reloc.offset as usize;
+            let size = reloc.kind.size();
+            sections[reloc.section][start..start + size]
+                .copy_from_slice(&value.to_le_bytes()[..size]);
+        }
+
+        if !external.is_empty() {
+            external.sort();
+            external.dedup();
+            return Err(RasError::UnresolvedRelocations(external));
+        }
+
+        Ok(sections)
+    }
+
+    /// Returns the index of the item that was assembled into the byte at the specified offset.
+    pub fn item_at(&self, section: usize, offset: SymbolOffset) -> Option<usize> {
+        self.source_map
+            .iter()
+            .find(|span| span.section == section && span.range.contains(&offset))
+            .map(|span| span.item)
+    }
+}
+
+/// Round `addr` up to the next multiple of `align`.
+pub(crate) fn align_up(addr: u64, align: u64) -> u64 {
+    let align = align.max(1);
+    addr.div_ceil(align) * align
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Relocation, RelocationKind, SectionKind, SymbolBinding};
+    use crate::assembler::Assembler;
+    use crate::parser::parse_asm;
+    use crate::symbol::{Symbol, SymbolAttribute, SymbolType};
+    use crate::RasError;
+
+    #[test]
+    fn symbols_and_relocations() {
+        let items = parse_asm(
+            "
+            foo:
+            bar:
+            call ext
+            jmp foo
+            baz:
+            jmp weak
+            ",
+        )
+        .unwrap();
+        let global = SymbolAttribute::Global as u8;
+        let weak = global | SymbolAttribute::Weak as u8;
+        let out = Assembler::long_mode()
+            .items(items)
+            .symbols(&[
+                ("ext".into(), Symbol::new_decl(SymbolType::Quad, global)),
+                ("weak".into(), Symbol::new_decl(SymbolType::Quad, weak)),
+                ("bar".into(), Symbol::new_decl(SymbolType::Quad, global)),
+            ])
+            .assemble()
+            .unwrap();
+
+        assert_eq!(out.text().len(), 15);
+        let symbols = out
+            .symbols()
+            .iter()
+            .map(|sym| (sym.name.as_str(), sym.offset, sym.size, sym.binding))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            symbols,
+            [
+                ("bar", 0, 10, SymbolBinding::Global),
+                ("foo", 0, 10, SymbolBinding::Local),
+                ("baz", 10, 5, SymbolBinding::Local),
+            ]
+        );
+
+        let reloc = |offset, symbol: &str| Relocation {
+            section: 0,
+            offset,
+            kind: RelocationKind::Relative32,
+            symbol: symbol.into(),
+            addend: -4,
+        };
+        assert_eq!(out.relocations(), [reloc(1, "ext"), reloc(11, "weak")]);
+    }
+
+    #[test]
+    fn flat_binary() {
+        let items = parse_asm(
+            "
+            start:
+            mov $msg, %esi
+            jmp end
+            msg:
+            .long msg, 0x2a
+            .org 0x20
+            end:
+            .quad start
+            ",
+        )
+        .unwrap();
+        let out = Assembler::long_mode().items(items).assemble().unwrap();
+
+        let relocs = out
+            .relocations()
+            .iter()
+            .map(|reloc| (reloc.offset, reloc.kind, reloc.symbol.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            relocs,
+            [
+                (1, RelocationKind::Absolute32, "msg"),
+                (10, RelocationKind::Absolute32, "msg"),
+                (0x20, RelocationKind::Absolute64, "start"),
+            ]
+        );
+
+        let mut expected = vec![
+            0xbe, 0x0a, 0x00, 0x40, 0x00, // mov $msg, %esi
+            0xe9, 0x16, 0x00, 0x00, 0x00, // jmp end
+            0x0a, 0x00, 0x40, 0x00, 0x2a, 0x00, 0x00, 0x00, // .long msg, 0x2a
+        ];
+        expected.resize(0x20, 0);
+        expected.extend([0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(out.flat_binary(0x400000).unwrap(), expected);
+
+        // The address of msg doesn't fit in 32 bits.
+        assert!(out.flat_binary(0x1_0000_0000).is_err());
+    }
+
+    #[test]
+    fn aligned_sections() {
+        let items = parse_asm(
+            "
+            start:
+            mov $value, %eax
+            ret
+            .data
+            .byte 1
+            .balign 8
+            value:
+            .long 2
+            .p2align 4
+            ",
+        )
+        .unwrap();
+        let out = Assembler::long_mode().items(items).assemble().unwrap();
+        let sections = out
+            .sections()
+            .iter()
+            .map(|section| (section.name.as_str(), section.data.len(), section.align))
+            .collect::<Vec<_>>();
+        assert_eq!(sections, [(".text", 6, 1), (".data", 16, 16)]);
+
+        // The .data section starts at the next multiple of 16 after the 6 bytes of .text.
+        let bin = out.flat_binary(0x1000).unwrap();
+        assert_eq!(bin[..6], [0xb8, 0x18, 0x10, 0x00, 0x00, 0xc3]);
+        assert_eq!(bin[6..16], [0; 10]);
+        assert_eq!(bin[16..], [1, 0, 0, 0, 0, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0]);
+
+        // The code is padded with NOPs.
+        let items = parse_asm("ret\n.balign 16\nret").unwrap();
+        let out = Assembler::long_mode().items(items).assemble().unwrap();
+        assert_eq!(
+            out.text()[..16],
+            [
+                0xc3, 0x66, 0x66, 0x2e, 0x0f, 0x1f, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0f, 0x1f,
+                0x40, 0x00
+            ]
+        );
+        let out = Assembler::real_mode()
+            .items(parse_asm("ret\n.balign 4\nret").unwrap())
+            .assemble()
+            .unwrap();
+        assert_eq!(out.text(), [0xc3, 0x90, 0x90, 0x90, 0xc3]);
+    }
+
+    #[test]
+    fn sign_extended_addresses() {
+        let items = parse_asm("start:\nmov $start, %rax\npush $start\nmov $start, %eax").unwrap();
+        let out = Assembler::long_mode().items(items).assemble().unwrap();
+        let relocs = out
+            .relocations()
+            .iter()
+            .map(|reloc| (reloc.offset, reloc.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            relocs,
+            [
+                (3, RelocationKind::Absolute32Signed),
+                (8, RelocationKind::Absolute32Signed),
+                (13, RelocationKind::Absolute32),
+            ]
+        );
+
+        // The sign-extended addresses must be below 2 GB (or in the top 2 GB of the address
+        // space): at 0x80000000, `mov $start, %rax` would load 0xffffffff80000000.
+        assert!(out.flat_binary(0x7fff_0000).is_ok());
+        assert!(out.flat_binary(0x8000_0000).is_err());
+
+        let items = parse_asm("start:\nmov $start, %rax\npush $start").unwrap();
+        let out = Assembler::long_mode().items(items).assemble().unwrap();
+        assert_eq!(
+            out.flat_binary(0xffff_ffff_8000_0000).unwrap(),
+            [
+                0x48, 0xc7, 0xc0, 0x00, 0x00, 0x00, 0x80, // mov $start, %rax
+                0x68, 0x00, 0x00, 0x00, 0x80, // push $start
+            ]
+        );
+    }
+
+    #[test]
+    fn flat_binary_errors() {
+        let items = parse_asm("call exit\n.quad exit").unwrap();
+        let exit = Symbol::new_decl(SymbolType::Quad, SymbolAttribute::Global as u8);
+        let out = Assembler::long_mode()
+            .items(items)
+            .symbols(&[("exit".into(), exit)])
+            .assemble()
+            .unwrap();
+        assert_eq!(
+            out.flat_binary(0),
+            Err(RasError::UnresolvedRelocations(vec!["exit".into()]))
+        );
+
+        let items = parse_asm("nop\nnop\n.org 1").unwrap();
+        assert!(Assembler::long_mode().items(items).assemble().is_err());
+
+        // The addresses of symbols are as wide as the default operand size.
+        let items = parse_asm("foo:\nmov $foo, %ax").unwrap();
+        assert!(Assembler::long_mode().items(items).assemble().is_err());
+        let items = parse_asm("foo:\nmov $foo, %ax").unwrap();
+        let out = Assembler::real_mode().items(items).assemble().unwrap();
+        assert_eq!(out.flat_binary(0x100).unwrap(), [0xb8, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn sections() {
+        let items = parse_asm(
+            "
+            start:
+            mov $value, %esi
+            .data
+            value:
+            .long 1
+            .bss
+            buffer:
+            .skip 8
+            .text
+            jmp buffer
+            ",
+        )
+        .unwrap();
+        let out = Assembler::long_mode().items(items).assemble().unwrap();
+
+        let sections = out
+            .sections()
+            .iter()
+            .map(|section| (section.name.as_str(), section.kind, section.data.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sections,
+            [
+                (".text", SectionKind::Text, 10),
+                (".data", SectionKind::Data, 4),
+                (".bss", SectionKind::Bss, 8),
+            ]
+        );
+        let symbols = out
+            .symbols()
+            .iter()
+            .map(|sym| (sym.name.as_str(), sym.section, sym.offset, sym.size))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            symbols,
+            [("start", 0, 0, 10), ("value", 1, 0, 4), ("buffer", 2, 0, 8)]
+        );
+        // The references to the symbols of the other sections are left as relocations.
+        let relocs = out
+            .relocations()
+            .iter()
+            .map(|reloc| {
+                (
+                    reloc.section,
+                    reloc.offset,
+                    reloc.kind,
+                    reloc.symbol.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            relocs,
+            [
+                (0, 1, RelocationKind::Absolute32, "value"),
+                (0, 6, RelocationKind::Relative32, "buffer"),
+            ]
+        );
+        assert_eq!(out.item_at(1, 0), Some(4));
+
+        let items = parse_asm(".bss\n.byte 1").unwrap();
+        assert!(Assembler::long_mode().items(items).assemble().is_err());
+        assert_eq!(SectionKind::of(".text.startup"), SectionKind::Text);
+        assert_eq!(SectionKind::of(".rodata.str1.1"), SectionKind::ReadOnlyData);
+        assert_eq!(SectionKind::of(".bssx"), SectionKind::Data);
+    }
+
+    #[test]
+    fn source_map() {
+        let items = parse_asm("start:\nnop\nmov %rdi, %rax\nend:\nret").unwrap();
+        let out = Assembler::long_mode().items(items).assemble().unwrap();
+
+        let spans = out
+            .source_map()
+            .iter()
+            .map(|span| (span.item, span.range.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(spans, [(1, 0..1), (2, 1..4), (4, 4..5)]);
+        assert_eq!(out.item_at(0, 3), Some(2));
+        assert_eq!(out.item_at(0, 5), None);
+        assert_eq!(out.item_at(1, 0), None);
+    }
+}
@@ -1,34 +1,79 @@
+use crate::assembled::{Assembled, SectionKind, TEXT_SECTION};
+use crate::directive::Directive;
 use crate::encoder::Encoder;
+use crate::executable::ExecutableWriter;
 use crate::instruction::Instruction;
+pub use crate::object::ObjectFormat;
 use crate::object::ObjectWriter;
-use crate::{Mode, RasError, RasResult};
+use crate::{CpuFeatures, Mode, RasError, RasResult};
 
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::Write;
+use std::ops::Range;
 
 pub use crate::symbol::{Symbol, SymbolId, SymbolOffset, SymbolType};
 
 pub type SymbolTable = HashMap<SymbolId, Symbol>;
 
 pub struct Assembler {
-    /// The instruction encoder.
-    encoder: Encoder,
+    /// The names and the encoders of the sections the items are assembled into (the first one
+    /// is `.text`).
+    sections: Vec<(String, Encoder)>,
     /// The instructions to encode.
     items: Vec<Item>,
     /// The symbols used in the assembly program.
     sym_tab: SymbolTable,
+    /// The format of the object files written by [`Assembler::write_obj`].
+    object_format: ObjectFormat,
 }
 
 impl Assembler {
     pub fn long_mode() -> Self {
+        Self::new(Mode::Long)
+    }
+
+    pub fn protected_mode() -> Self {
+        Self::new(Mode::Protected)
+    }
+
+    pub fn real_mode() -> Self {
+        Self::new(Mode::Real)
+    }
+
+    pub(crate) fn new(mode: Mode) -> Self {
         Self {
-            encoder: Encoder::new(Mode::Long),
+            sections: vec![(TEXT_SECTION.into(), Encoder::new(mode))],
             items: Default::default(),
             sym_tab: Default::default(),
+            object_format: Default::default(),
         }
     }
 
+    /// Only allow the instructions from the specified CPU features (all the known features are
+    /// enabled by default).
+    ///
+    /// ```
+    /// # use ras_x86::assembler::Assembler;
+    /// # use ras_x86::CpuFeatures;
+    /// let asm = Assembler::long_mode().cpu_features("x86-64-v2,aes".parse().unwrap());
+    /// ```
+    pub fn cpu_features(mut self, features: CpuFeatures) -> Self {
+        self.sections[0].1.features = features;
+        self
+    }
+
+    /// Write the object files in the specified format (ELF by default).
+    ///
+    /// ```
+    /// # use ras_x86::assembler::{Assembler, ObjectFormat};
+    /// let asm = Assembler::long_mode().object_format(ObjectFormat::Coff);
+    /// ```
+    pub fn object_format(mut self, format: ObjectFormat) -> Self {
+        self.object_format = format;
+        self
+    }
+
     pub fn items<I: Into<Item>>(mut self, items: Vec<I>) -> Self {
         self.items.extend(items.into_iter().map(Into::into));
         self
@@ -39,28 +84,92 @@ impl Assembler {
         self
     }
 
-    /// Get the assembled instructions.
+    /// Assemble the instructions, and return them along with their symbols and relocations.
+    ///
+    /// Unlike [`Assembler::dump_text`], this doesn't lose track of the references to the
+    /// external symbols (see [`Assembled::relocations`]).
+    pub fn assemble(mut self) -> RasResult<Assembled> {
+        let item_ranges = self.encode_items()?;
+        Ok(Assembled::new(self.sections, self.sym_tab, item_ranges))
+    }
+
+    /// Get the instructions assembled into the `.text` section.
+    ///
+    /// The references that need to be relocated (see [`Assembler::assemble`]) are left as zeros.
     pub fn dump_text(mut self) -> RasResult<Vec<u8>> {
-        self.assemble()?;
-        Ok(self.encoder.out)
+        self.encode_items()?;
+        let (_, text) = self.sections.swap_remove(0);
+        Ok(text.out)
+    }
+
+    /// Write the assembled instructions into the specified `writer` as a flat binary, which is
+    /// loaded at the `origin` address (see [`Assembled::flat_binary`]).
+    pub fn write_bin(self, origin: u64, mut writer: impl Write) -> RasResult<()> {
+        let bin = self.assemble()?.flat_binary(origin)?;
+        writer.write_all(&bin)?;
+        Ok(())
+    }
+
+    /// Write a static ELF executable with the assembled sections into the specified `writer`
+    /// (see [`ExecutableWriter`]).
+    pub fn write_exec(self, exec: &ExecutableWriter, mut writer: impl Write) -> RasResult<()> {
+        let exe = exec.write(&self.assemble()?)?;
+        writer.write_all(&exe)?;
+        Ok(())
     }
 
     /// Write an object file with the assembled instructions into the specified `writer`.
-    pub fn write_obj(mut self, mut writer: impl Write) -> RasResult<()> {
-        self.assemble()?;
-        let mut obj = ObjectWriter::new(self.encoder.mode);
-        // write the assembled instructions in the .text section of the object file
-        obj.append_text_section(&self.encoder.out);
+    pub fn write_obj(self, mut writer: impl Write) -> RasResult<()> {
+        // The assembled code only keeps track of the symbols it defines.
+        let weak_externals = self
+            .sym_tab
+            .iter()
+            .filter(|(_, sym)| !sym.is_defined() && sym.is_weak())
+            .map(|(sym_id, _)| sym_id.clone())
+            .collect::<HashSet<_>>();
+        let format = self.object_format;
+        let out = self.assemble()?;
+        let mut obj = ObjectWriter::new(format, out.mode());
+        // write the assembled sections in the object file
+        let section_ids = out
+            .sections()
+            .iter()
+            .map(|section| obj.append_section(section))
+            .collect::<Vec<_>>();
         // emit all the symbols
-        for (sym_id, sym) in &self.sym_tab {
-            obj.add_text_symbol(sym_id, sym);
+        for sym in out.symbols() {
+            obj.add_symbol(sym, section_ids[sym.section])?;
+        }
+        // emit the references the linker needs to relocate
+        for reloc in out.relocations() {
+            let weak = weak_externals.contains(&reloc.symbol);
+            obj.add_relocation(section_ids[reloc.section], reloc, weak)?;
         }
         // write the object file
         writer.write_all(&obj.write()?)?;
         Ok(())
     }
 
-    fn assemble(&mut self) -> RasResult<()> {
+    /// Assemble the instructions, and return the encoder and the symbol table.
+    ///
+    /// The references to the external symbols are left for the caller to resolve.
+    pub(crate) fn assemble_parts(mut self) -> RasResult<(Encoder, SymbolTable)> {
+        self.encode_items()?;
+        if let Some((name, _)) = self.sections.get(1) {
+            return Err(RasError::Encoding(format!(
+                "only the .text section can be loaded, but the code has a {} section",
+                name
+            )));
+        }
+
+        let (_, text) = self.sections.swap_remove(0);
+        Ok((text, self.sym_tab))
+    }
+
+    /// Encode the items, and return the section and the range of offsets each item was encoded
+    /// into.
+    fn encode_items(&mut self) -> RasResult<Vec<(usize, Range<SymbolOffset>)>> {
+        let mut section = 0;
         for item in &self.items {
             match item {
                 Item::Label(label) => match self.sym_tab.entry(label.to_string()) {
@@ -68,39 +177,83 @@ impl Assembler {
                         return Err(RasError::DuplicateLabel(label.to_string()));
                     }
                     Entry::Occupied(mut entry) => {
-                        entry.get_mut().offset = Some(self.encoder.current_offset());
+                        entry.get_mut().offset = Some(0);
+                        entry.get_mut().section = section;
                     }
                     Entry::Vacant(entry) => {
-                        let sym = Symbol::new(
-                            SymbolType::Quad,
-                            self.encoder.current_offset(),
-                            Default::default(),
-                        );
+                        let mut sym = Symbol::new(SymbolType::Quad, 0, Default::default());
+                        sym.section = section;
                         entry.insert(sym);
                     }
                 },
-                Item::Instruction(_) => { /* forget about instructions in the first pass */ }
+                Item::Directive(Directive::Section(name)) => {
+                    section = section_index(&mut self.sections, name);
+                }
+                Item::Instruction(_) | Item::Directive(_) => {
+                    /* forget about instructions and directives in the first pass */
+                }
             }
         }
 
+        let mut item_ranges = Vec::with_capacity(self.items.len());
+        let mut section = 0;
         for item in &self.items {
+            if let Item::Directive(Directive::Section(name)) = item {
+                section = section_index(&mut self.sections, name);
+            }
+
+            let (name, enc) = &mut self.sections[section];
+            let start = enc.current_offset();
             match item {
-                Item::Instruction(inst) => {
-                    inst.encode(&mut self.encoder, &self.sym_tab)?;
+                Item::Instruction(inst) => inst.encode(enc)?,
+                Item::Directive(directive) => directive.emit(enc, SectionKind::of(name))?,
+                // The labels were added to the symbol table in the first pass, but their offsets
+                // are only known now that the preceding instructions have been encoded.
+                Item::Label(label) => {
+                    if let Some(sym) = self.sym_tab.get_mut(label) {
+                        sym.offset = Some(enc.current_offset());
+                    }
                 }
-                Item::Label(_) => { /* the labels were processed in the first pass */ }
             }
+            item_ranges.push((section, start..enc.current_offset()));
         }
 
-        self.encoder.fixup_symbol_references(&self.sym_tab)?;
-        Ok(())
+        for (section, (name, enc)) in self.sections.iter_mut().enumerate() {
+            enc.fixup_symbol_references(&self.sym_tab, section)?;
+
+            let is_zero = enc.out.iter().all(|&byte| byte == 0);
+            if SectionKind::of(name) == SectionKind::Bss
+                && (!is_zero || !enc.unresolved_symbols().is_empty())
+            {
+                return Err(RasError::Encoding(format!(
+                    "the {} section can only hold zeros",
+                    name
+                )));
+            }
+        }
+        Ok(item_ranges)
+    }
+}
+
+/// Returns the index of the section with the specified name, which is created (with the same
+/// settings as the `.text` section) if it doesn't exist yet.
+fn section_index(sections: &mut Vec<(String, Encoder)>, name: &str) -> usize {
+    if let Some(index) = sections.iter().position(|(section, _)| section == name) {
+        return index;
     }
+
+    let text = &sections[0].1;
+    let mut enc = Encoder::new(text.mode);
+    enc.features = text.features;
+    sections.push((name.into(), enc));
+    sections.len() - 1
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub enum Item {
     Label(SymbolId),
     Instruction(Instruction),
+    Directive(Directive),
 }
 
 impl From<Instruction> for Item {
@@ -109,6 +262,12 @@ impl From<Instruction> for Item {
     }
 }
 
+impl From<Directive> for Item {
+    fn from(directive: Directive) -> Item {
+        Item::Directive(directive)
+    }
+}
+
 impl From<SymbolId> for Item {
     fn from(label: SymbolId) -> Item {
         Item::Label(label)
@@ -0,0 +1,191 @@
+//! A fluent API for assembling instructions from Rust code.
+//!
+//! [`CodeBuilder`] has a method for each mnemonic (generated by the build script from the
+//! instruction table), which checks that the instruction can be encoded before appending it:
+//!
+//! ```
+//! use ras_x86::builder::{label, mem, CodeBuilder, Scale};
+//! use ras_x86::{EAX, RAX, RBX, RCX};
+//! # fn main() -> ras_x86::RasResult<()> {
+//! let mut asm = CodeBuilder::long_mode();
+//! asm.label("loop")
+//!     .mov(RAX, 103)?
+//!     .add(mem(RBX + RCX * Scale::Double + 8), EAX)?
+//!     .jne(label("loop"))?
+//!     .ret()?;
+//!
+//! let text = asm.into_assembler().dump_text()?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The operands are in Intel order (the destination first). Depending on the operands the
+//! instruction accepts, each operand is a register (e.g. `RAX`), an immediate (e.g. `103`), a
+//! memory operand (`mem(...)`), or any of these (including a `label(...)`).
+//!
+//! The mnemonics that are Rust keywords have a trailing underscore (`in_` and `loop_`). If the
+//! instructions of a mnemonic have a different number of operands, the methods that take more
+//! operands than the shortest form have the number of operands as a suffix (e.g. `imul`, `imul_2`
+//! and `imul_3`).
+
+use crate::assembler::{Assembler, Item};
+use crate::encoder::Encoder;
+use crate::instruction::Instruction;
+use crate::operand::{Memory, MemoryRel, Operand};
+use crate::symbol::SymbolId;
+use crate::{CpuFeatures, Mode, RasResult};
+
+pub use crate::operand::Scale;
+
+include!(concat!(env!("OUT_DIR"), "/builder.rs"));
+
+/// Builds a list of instructions and labels, which can then be assembled with an [`Assembler`].
+pub struct CodeBuilder {
+    mode: Mode,
+    features: CpuFeatures,
+    items: Vec<Item>,
+}
+
+impl CodeBuilder {
+    pub fn long_mode() -> Self {
+        Self::new(Mode::Long)
+    }
+
+    pub fn protected_mode() -> Self {
+        Self::new(Mode::Protected)
+    }
+
+    pub fn real_mode() -> Self {
+        Self::new(Mode::Real)
+    }
+
+    fn new(mode: Mode) -> Self {
+        Self {
+            mode,
+            features: CpuFeatures::all(),
+            items: Default::default(),
+        }
+    }
+
+    /// Only allow the instructions from the specified CPU features (all the known features are
+    /// enabled by default).
+    pub fn cpu_features(mut self, features: CpuFeatures) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Define a label at the current position.
+    pub fn label(&mut self, name: impl Into<SymbolId>) -> &mut Self {
+        self.items.push(Item::Label(name.into()));
+        self
+    }
+
+    /// Append an instruction, if it can be encoded.
+    pub fn instruction(&mut self, inst: Instruction) -> RasResult<&mut Self> {
+        let mut enc = Encoder::new(self.mode);
+        enc.features = self.features;
+        inst.encode(&mut enc)?;
+
+        self.items.push(Item::Instruction(inst));
+        Ok(self)
+    }
+
+    /// The instructions and labels appended so far.
+    pub fn items(&self) -> &[Item] {
+        &self.items
+    }
+
+    pub fn into_items(self) -> Vec<Item> {
+        self.items
+    }
+
+    /// Create an [`Assembler`] for the instructions and labels appended so far.
+    pub fn into_assembler(self) -> Assembler {
+        Assembler::new(self.mode)
+            .cpu_features(self.features)
+            .items(self.items)
+    }
+}
+
+/// A memory operand (e.g. `mem(RBX + RCX * Scale::Double + 8)`).
+pub fn mem(mem: impl Into<Memory>) -> Memory {
+    mem.into()
+}
+
+/// A reference to a label (e.g. the target of a jump).
+pub fn label(name: impl Into<SymbolId>) -> Operand {
+    Operand::Memory(Memory::Relative(MemoryRel::Label(name.into())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{label, mem, CodeBuilder, Scale};
+    use crate::error::RasError;
+    use crate::mnemonic::Mnemonic;
+    use crate::operand::Immediate;
+    use crate::{AL, AX, EAX, ECX, RAX, RBX, RCX, XMM0, XMM1};
+
+    fn assemble(asm: CodeBuilder) -> Vec<u8> {
+        asm.into_assembler().dump_text().unwrap()
+    }
+
+    #[test]
+    fn mov_add_jne() {
+        let mut asm = CodeBuilder::long_mode();
+        asm.label("loop")
+            .mov(RAX, 103)
+            .unwrap()
+            .add(mem(RBX + RCX * Scale::Double + 8), EAX)
+            .unwrap()
+            .jne(label("loop"))
+            .unwrap()
+            .ret()
+            .unwrap();
+
+        assert_eq!(
+            assemble(asm),
+            [
+                0x48, 0xc7, 0xc0, 0x67, 0x00, 0x00, 0x00, // mov $103, %rax
+                0x01, 0x44, 0x8b, 0x08, // add %eax, 8(%rbx,%rcx,4)
+                0x0f, 0x85, 0xef, 0xff, 0xff, 0xff, // jne loop
+                0xc3, // ret
+            ]
+        );
+    }
+
+    #[test]
+    fn operand_conversions() {
+        let mut asm = CodeBuilder::long_mode();
+        asm.mov(mem(RBX), 1i8)
+            .unwrap()
+            .mov(ECX, mem(RAX * Scale::Quad - 16))
+            .unwrap()
+            .imul_3(EAX, ECX, Immediate::Imm32(1000))
+            .unwrap()
+            .addps(XMM0, XMM1)
+            .unwrap()
+            .in_(AL, 0x60)
+            .unwrap();
+
+        assert_eq!(
+            assemble(asm),
+            [
+                0xc6, 0x03, 0x01, // movb $1, (%rbx)
+                0x8b, 0x0c, 0xc5, 0xf0, 0xff, 0xff, 0xff, // mov -16(,%rax,8), %ecx
+                0x69, 0xc1, 0xe8, 0x03, 0x00, 0x00, // imul $1000, %ecx, %eax
+                0x0f, 0x58, 0xc1, // addps %xmm1, %xmm0
+                0xe4, 0x60, // in $0x60, %al
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_operands() {
+        let mut asm = CodeBuilder::long_mode();
+        let err = asm.mov(AX, EAX).err().unwrap();
+
+        assert_eq!(err, RasError::MissingInstructionRepr(Mnemonic::MOV));
+        // The instruction isn't appended.
+        assert!(asm.items().is_empty());
+    }
+}
@@ -0,0 +1,130 @@
+//! Assembler directives, which emit data (e.g. `.quad label`) or control where the following
+//! items are assembled (e.g. `.org 510` or `.data`).
+
+use crate::assembled::{align_up, SectionKind};
+use crate::encoder::{fits_in, Encoder};
+use crate::patch;
+use crate::symbol::{SymbolId, SymbolOffset};
+use crate::{RasError, RasResult};
+
+/// The length of the longest NOP used for padding (like GNU as).
+const MAX_NOP_LEN: usize = 11;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Directive {
+    /// `.org offset`: pad the section with zeros up to the specified offset (relative to the
+    /// start of the section).
+    Org(SymbolOffset),
+    /// `.skip`, `.space` or `.zero size`: emit `size` zero bytes.
+    Skip(u64),
+    /// `.align`, `.balign` or `.p2align`: pad the section up to the next multiple of the
+    /// alignment (in bytes), with NOPs in the code sections and with zeros in the other sections.
+    /// The section itself is aligned to (at least) the same alignment.
+    Align(u64),
+    /// `.text`, `.data`, `.bss` or `.section name`: assemble the following items into the
+    /// specified section (which is created the first time it's used).
+    Section(String),
+    /// `.byte`, `.word`, `.long` or `.quad`: emit each value as a `size`-byte integer.
+    Data { size: usize, values: Vec<DataValue> },
+}
+
+/// A value emitted by a data directive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataValue {
+    Int(i64),
+    /// The address of a symbol.
+    Symbol(SymbolId),
+}
+
+impl Directive {
+    /// Returns the size of the values emitted by the data directive with the specified name
+    /// (e.g. 8 for `.quad`).
+    pub fn data_size(name: &str) -> Option<usize> {
+        match name {
+            ".byte" => Some(1),
+            ".word" | ".short" | ".value" => Some(2),
+            ".long" | ".int" => Some(4),
+            ".quad" => Some(8),
+            _ => None,
+        }
+    }
+
+    /// Emit the directive into a section of the specified kind.
+    pub(crate) fn emit(&self, enc: &mut Encoder, kind: SectionKind) -> RasResult<()> {
+        match self {
+            Directive::Org(offset) => {
+                let current = enc.current_offset();
+                if *offset < current {
+                    return Err(RasError::Encoding(format!(
+                        ".org {:#x} would move backwards from {:#x}",
+                        offset, current
+                    )));
+                }
+                enc.out.resize(*offset as usize, 0);
+            }
+            Directive::Skip(size) => {
+                let end = enc.out.len() + *size as usize;
+                enc.out.resize(end, 0);
+            }
+            Directive::Align(align) => {
+                enc.align = enc.align.max(*align);
+                let current = enc.current_offset();
+                let padding = (align_up(current, *align) - current) as usize;
+                if kind == SectionKind::Text {
+                    emit_nops(enc, padding)?;
+                } else {
+                    enc.out.resize(enc.out.len() + padding, 0);
+                }
+            }
+            // The assembler switches to the encoder of the section.
+            Directive::Section(_) => {}
+            Directive::Data { size, values } => {
+                for value in values {
+                    match value {
+                        DataValue::Int(value) if fits_in(*value, *size as u64) => {
+                            enc.out.extend(&value.to_le_bytes()[..*size]);
+                        }
+                        DataValue::Int(value) => {
+                            return Err(RasError::Encoding(format!(
+                                "{} doesn't fit in {} bytes",
+                                value, size
+                            )));
+                        }
+                        DataValue::Symbol(symbol_id) => {
+                            enc.emit_symbol_address(symbol_id, *size, false)
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Emit `len` bytes of NOPs, using the longest NOPs first.
+///
+/// Like GNU as, this uses NOPs of up to 11 bytes (the 10 and 11-byte NOPs are the 8-byte NOP
+/// preceded by a `cs` segment override and one or two operand-size prefixes). There are no
+/// multi-byte NOPs in real mode, so the padding is made of 1-byte NOPs.
+fn emit_nops(enc: &mut Encoder, mut len: usize) -> RasResult<()> {
+    while len > 0 {
+        let nop_len = len.min(MAX_NOP_LEN);
+        let (prefixes, base_len): (&[u8], _) = match nop_len {
+            10 => (&[0x66, 0x2e], 8),
+            11 => (&[0x66, 0x66, 0x2e], 8),
+            _ => (&[], nop_len),
+        };
+
+        match patch::nop(enc.mode, base_len) {
+            Some(nop) => {
+                enc.out.extend(prefixes);
+                nop.encode(enc)?;
+            }
+            None => enc.out.resize(enc.out.len() + nop_len, 0x90),
+        }
+        len -= nop_len;
+    }
+
+    Ok(())
+}
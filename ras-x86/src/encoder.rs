@@ -1,34 +1,91 @@
+mod modrm;
+
+use self::modrm::{
+    address_size, default_address_size, modrm, DisplacementSize, MemoryAddressing, MOD_REG,
+};
+use crate::assembled::RelocationKind;
 use crate::assembler::{SymbolId, SymbolOffset};
 use crate::error::RasError;
+use crate::incremental::Label;
+use crate::instruction::{PseudoPrefix, PseudoPrefixes};
 use crate::operand::{
-    Immediate, ImmediateSize, Memory, MemoryRel, Operand, Register, RegisterNum, Scale,
+    Decorators, FarPointer, Immediate, ImmediateSize, Memory, MemoryRel, Operand, Register,
+    RegisterNum, Rounding,
+};
+use crate::repr::prefix::{VexMap, VexOperand, VexW};
+use crate::repr::{
+    CpuFeatures, EncodingBytecode, EvexPrefix, InstructionRepr, OperandKind, Prefix, RexPrefix,
+    VexPrefix,
 };
-use crate::repr::{EncodingBytecode, InstructionRepr, OperandRepr, Prefix};
 use crate::symbol::Symbol;
 use crate::Mode;
 
 use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Range;
 
-const SIB_INDEX_NONE: u8 = 0b100;
+/// The first byte of the 2-byte VEX prefix.
+const VEX_2_BYTE: u8 = 0xc5;
+/// The first byte of the 3-byte VEX prefix.
+const VEX_3_BYTE: u8 = 0xc4;
+/// The first byte of the EVEX prefix.
+const EVEX: u8 = 0x62;
 
 /// A range of bytes to patch: `[offset, offset + size)`
-struct Fixup {
-    offset: SymbolOffset,
-    size: u64,
+///
+/// The bytes hold either the offset of a symbol, relative to the end of the range (e.g. the
+/// `rel32` of a `call`), or the address of a symbol (e.g. the `imm32` of `mov $label, %eax`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fixup {
+    pub offset: SymbolOffset,
+    pub size: u64,
+    /// Whether the value is sign-extended by the CPU, so it must fit in `size` bytes as a signed
+    /// integer. The relative offsets always are, and so are the addresses that are extended to
+    /// a wider operand size (e.g. the `imm32` of `mov $label, %rax`).
+    pub signed: bool,
+}
+
+impl Fixup {
+    /// Returns the kind of the relocation that patches the address of a symbol into this
+    /// range.
+    pub(crate) fn address_kind(&self) -> Option<RelocationKind> {
+        match (self.size, self.signed) {
+            (4, true) => Some(RelocationKind::Absolute32Signed),
+            (size, _) => RelocationKind::absolute(size),
+        }
+    }
 }
 
 /// The instruction encoder.
-#[derive(Default)]
 pub(crate) struct Encoder {
     /// The output buffer where assembled instructions are written.
     pub out: Vec<u8>,
     /// The `Mode` to assemble instructions in.
     pub mode: Mode,
+    /// The CPU features the assembled instructions are allowed to use.
+    pub features: CpuFeatures,
+    /// The alignment of the section the code is assembled into (in bytes), which is raised by the
+    /// alignment directives (1 by default, like GNU as).
+    pub align: u64,
     /// A mapping from symbol -> its occurrences in the code.
     ///
     /// Each non-extern symbol occurrence needs to be patched up with a concrete value by the
     /// assembler.
     rel_jmp_fixups: HashMap<SymbolId, Vec<Fixup>>,
+    /// A mapping from symbol -> the occurrences of its address in the code.
+    ///
+    /// The addresses can only be patched once the address the code is loaded at is known.
+    abs_fixups: HashMap<SymbolId, Vec<Fixup>>,
+    /// A mapping from label -> its occurrences in the code (the labels of an
+    /// [`IncrementalAssembler`](crate::incremental::IncrementalAssembler)).
+    label_fixups: HashMap<Label, Vec<Fixup>>,
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Self::new(Default::default())
+    }
 }
 
 impl Encoder {
@@ -36,7 +93,11 @@ impl Encoder {
         Self {
             out: Default::default(),
             mode,
+            features: CpuFeatures::all(),
+            align: 1,
             rel_jmp_fixups: Default::default(),
+            abs_fixups: Default::default(),
+            label_fixups: Default::default(),
         }
     }
 
@@ -46,54 +107,225 @@ impl Encoder {
     }
 
     /// Returns `true` if the specified instruction can be encoded in the current mode.
+    ///
+    /// Far pointer operands don't have an intrinsic size, so far jumps and calls always use the
+    /// default operand size of the current mode (like GNU as does for `ljmp` and `lcall`).
     pub(crate) fn is_encodable(&self, repr: &InstructionRepr) -> bool {
         repr.is_valid_in_mode(&self.mode)
+            && repr
+                .operands
+                .iter()
+                .filter(|op| is_far_pointer_kind(op.kind))
+                .all(|op| op.size() == self.default_operand_size())
+    }
+
+    /// Returns `true` if all the CPU features required by the specified instruction are enabled.
+    pub(crate) fn has_features(&self, repr: &InstructionRepr) -> bool {
+        self.features.missing(&repr.features).next().is_none()
+    }
+
+    /// Returns the operand size used by instructions that don't have an operand-size prefix.
+    pub(crate) fn default_operand_size(&self) -> u32 {
+        match self.mode {
+            Mode::Real => 16,
+            Mode::Protected | Mode::Long => 32,
+        }
     }
 
     pub(crate) fn encode(
         &mut self,
         repr: &InstructionRepr,
         operands: &[Operand],
+        decorators: Decorators,
+        pseudo_prefixes: PseudoPrefixes,
+        explicit_size: Option<u32>,
+    ) -> Result<(), RasError> {
+        let operands = EncodingOperands::new(repr, operands, explicit_size)?;
+        let mut enc = InstructionEncoder::new(self, repr, operands, decorators, pseudo_prefixes)?;
+        enc.encode_address_size_prefix()?;
+
+        for code in repr.encoding.bytecode.iter() {
+            enc.handle_opcode(code)?;
+        }
+
+        enc.check_operands_encoded()
+    }
+
+    /// Returns the length (in bytes) of the encoding of `operands` using the specified `repr`.
+    ///
+    /// The instruction is encoded into a scratch buffer, so the output of this encoder isn't
+    /// modified.
+    pub(crate) fn encoded_len(
+        &self,
+        repr: &InstructionRepr,
+        operands: &[Operand],
+        decorators: Decorators,
+        pseudo_prefixes: PseudoPrefixes,
+        explicit_size: Option<u32>,
+    ) -> Result<usize, RasError> {
+        let mut scratch = Encoder::new(self.mode);
+        scratch.features = self.features;
+        scratch.encode(repr, operands, decorators, pseudo_prefixes, explicit_size)?;
+
+        Ok(scratch.out.len())
+    }
+
+    /// Patch the references to a symbol, which is `offset` bytes away from the start of the text
+    /// section (e.g. an external symbol, or a label that was bound after it was referenced).
+    ///
+    /// Returns an error (without patching anything) if the relative offset of the symbol doesn't
+    /// fit in any of its references.
+    pub(crate) fn resolve_symbol(&mut self, symbol_id: &str, offset: i64) -> Result<(), RasError> {
+        if let Some(fixups) = self.rel_jmp_fixups.get(symbol_id) {
+            patch_relative(&mut self.out, symbol_id, fixups, offset)?;
+            self.rel_jmp_fixups.remove(symbol_id);
+        }
+
+        Ok(())
+    }
+
+    /// Patch the references to a label, which is `offset` bytes away from the start of the text
+    /// section (see [`Encoder::resolve_symbol`]).
+    pub(crate) fn resolve_label(&mut self, label: Label, offset: i64) -> Result<(), RasError> {
+        if let Some(fixups) = self.label_fixups.get(&label) {
+            patch_relative(&mut self.out, label, fixups, offset)?;
+            self.label_fixups.remove(&label);
+        }
+
+        Ok(())
+    }
+
+    /// Emit a `size`-byte placeholder for the address of a symbol, which is patched when the
+    /// address of the symbol is known (see [`Encoder::resolve_symbol_address`]).
+    ///
+    /// `signed` is `true` if the CPU sign-extends the address (see [`Fixup::signed`]).
+    pub(crate) fn emit_symbol_address(&mut self, symbol_id: &str, size: usize, signed: bool) {
+        let fixup = Fixup {
+            offset: self.current_offset(),
+            size: size as u64,
+            signed,
+        };
+
+        self.out.extend(vec![0; size]);
+        self.abs_fixups
+            .entry(symbol_id.to_string())
+            .or_default()
+            .push(fixup);
+    }
+
+    /// Patch the references to the address of a symbol.
+    ///
+    /// Returns an error (without patching anything) if the address doesn't fit in any of its
+    /// references.
+    pub(crate) fn resolve_symbol_address(
+        &mut self,
+        symbol_id: &str,
+        address: u64,
     ) -> Result<(), RasError> {
-        match operands.len() {
-            0 => self.encode_no_operands(repr),
-            1 => self.encode_1_operand(repr, &operands[0]),
-            2 => self.encode_2_operands(repr, &operands[0], &operands[1]),
-            n => unimplemented!("{} operands", n),
+        let fixups = match self.abs_fixups.get(symbol_id) {
+            Some(fixups) => fixups,
+            None => return Ok(()),
+        };
+
+        if let Some(fixup) = fixups.iter().find(|fixup| {
+            !fixup
+                .address_kind()
+                .is_some_and(|kind| kind.fits(address as i64))
+        }) {
+            return Err(RasError::Encoding(format!(
+                "the address of {} ({:#x}) doesn't fit in the {}-byte reference at {:#x}",
+                symbol_id, address, fixup.size, fixup.offset
+            )));
         }
+
+        for fixup in self.abs_fixups.remove(symbol_id).unwrap_or_default() {
+            let start = fixup.offset as usize;
+            let bytes = &address.to_le_bytes()[..fixup.size as usize];
+            self.out[start..start + bytes.len()].copy_from_slice(bytes);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the first unresolved reference to a symbol that starts in the specified range of
+    /// offsets.
+    pub(crate) fn fixup_in(&self, range: Range<SymbolOffset>) -> Option<Fixup> {
+        self.rel_jmp_fixups
+            .values()
+            .chain(self.label_fixups.values())
+            .flatten()
+            .filter(|fixup| range.contains(&fixup.offset))
+            .min_by_key(|fixup| fixup.offset)
+            .copied()
+    }
+
+    /// Returns the relative references to symbols that haven't been resolved yet (e.g. the
+    /// references to external symbols).
+    pub(crate) fn unresolved_fixups(&self) -> impl Iterator<Item = (&SymbolId, &Fixup)> {
+        self.rel_jmp_fixups
+            .iter()
+            .flat_map(|(symbol_id, fixups)| fixups.iter().map(move |fixup| (symbol_id, fixup)))
+    }
+
+    /// Returns the references to the addresses of symbols that haven't been resolved yet.
+    pub(crate) fn unresolved_address_fixups(&self) -> impl Iterator<Item = (&SymbolId, &Fixup)> {
+        self.abs_fixups
+            .iter()
+            .flat_map(|(symbol_id, fixups)| fixups.iter().map(move |fixup| (symbol_id, fixup)))
+    }
+
+    /// Returns the symbols that are referenced but haven't been resolved yet.
+    pub(crate) fn unresolved_symbols(&self) -> Vec<SymbolId> {
+        let mut symbols = self
+            .rel_jmp_fixups
+            .keys()
+            .chain(self.abs_fixups.keys())
+            .cloned()
+            .collect::<Vec<_>>();
+        symbols.sort();
+        symbols.dedup();
+        symbols
+    }
+
+    /// Returns the labels that are referenced but haven't been resolved yet.
+    pub(crate) fn unresolved_labels(&self) -> Vec<Label> {
+        let mut labels = self.label_fixups.keys().copied().collect::<Vec<_>>();
+        labels.sort();
+        labels
     }
 
     pub(crate) fn fixup_symbol_references(
         &mut self,
         sym_tab: &HashMap<SymbolId, Symbol>,
+        section: usize,
     ) -> Result<(), RasError> {
         for (symbol_id, symbol) in sym_tab {
+            // The references to the symbols of the other sections are left as relocations.
+            if symbol.section != section {
+                continue;
+            }
+
+            // Patch any symbolic references (e.g. jmp label)
             if let Some(offset) = symbol.offset {
-                // Patch any symbolic references (e.g. jmp label)
-                if let Some(fixups) = self.rel_jmp_fixups.remove(symbol_id) {
-                    for fixup in fixups {
-                        let start = fixup.offset as usize;
-                        let end = (fixup.offset + fixup.size) as usize;
-                        let offset =
-                            (offset as i32 - (fixup.offset + fixup.size) as i32).to_le_bytes();
-
-                        self.out.splice(start..end, offset.iter().cloned());
-                    }
-                }
+                self.resolve_symbol(symbol_id, offset as i64)?;
             }
         }
         // If there are still some unresolved symbols, return an error if any of them are not
-        // marked as external:
+        // marked as external (the references to the addresses of the defined symbols are
+        // resolved once the address of the code is known):
         let undefined_symbols: Vec<String> = self
             .rel_jmp_fixups
             .keys()
+            .chain(self.abs_fixups.keys())
             .map(|symbol_id| symbol_id.to_string())
             .filter(|symbol_id| {
                 !sym_tab
                     .get(symbol_id)
-                    .map(|sym| sym.is_global())
+                    .map(|sym| sym.is_global() || sym.is_defined())
                     .unwrap_or_default()
             })
+            // The labels of an incremental assembler can't be bound here.
+            .chain(self.unresolved_labels().iter().map(Label::to_string))
             .collect();
 
         if !undefined_symbols.is_empty() {
@@ -101,264 +333,977 @@ impl Encoder {
         }
         Ok(())
     }
+}
 
-    /// Encode an instruction with no operands.
-    fn encode_no_operands(&mut self, inst_repr: &InstructionRepr) -> Result<(), RasError> {
-        for code in &inst_repr.encoding.bytecode {
-            match code {
-                EncodingBytecode::Rex(rex_prefix) => {
-                    self.out.push((*rex_prefix).into());
-                }
-                EncodingBytecode::Opcode(opcode) => self.out.push(*opcode),
-                _ => unimplemented!("encoding: {:?}", code),
+/// Patch the relative references to a symbol (or label) which is `offset` bytes away from the
+/// start of `out`.
+///
+/// Returns an error (without patching anything) if the relative offset doesn't fit in any of the
+/// references.
+fn patch_relative(
+    out: &mut [u8],
+    name: impl fmt::Display,
+    fixups: &[Fixup],
+    offset: i64,
+) -> Result<(), RasError> {
+    let patches = fixups
+        .iter()
+        .map(|fixup| {
+            let rel_offset = offset.wrapping_sub((fixup.offset + fixup.size) as i64);
+            let fits = match fixup.size {
+                1 => i8::try_from(rel_offset).is_ok(),
+                2 => i16::try_from(rel_offset).is_ok(),
+                4 => i32::try_from(rel_offset).is_ok(),
+                _ => false,
+            };
+
+            if fits {
+                let bytes = rel_offset.to_le_bytes()[..fixup.size as usize].to_vec();
+                Ok((fixup.offset as usize, bytes))
+            } else {
+                Err(RasError::Encoding(format!(
+                    "{} is out of range of the {}-byte relative offset at {:#x}",
+                    name, fixup.size, fixup.offset
+                )))
             }
-        }
-        Ok(())
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for (start, bytes) in patches {
+        out[start..start + bytes.len()].copy_from_slice(&bytes);
     }
 
-    /// Encode a single operand instruction.
-    fn encode_1_operand(
-        &mut self,
-        inst_repr: &InstructionRepr,
-        operand: &Operand,
-    ) -> Result<(), RasError> {
-        let (reg_op, reg_memory_op, imm_op) = match operand {
-            Operand::Register(reg) => (Some(reg), None, None),
-            Operand::Memory(_) => (None, Some(operand), None),
-            Operand::Immediate(imm) => (None, None, Some(imm)),
+    Ok(())
+}
+
+/// The operands of an instruction, classified by how they are encoded.
+#[derive(Default)]
+struct EncodingOperands<'o> {
+    /// The register encoded in the ModRM.reg field, or in the opcode byte (`+rb`/`+rw`/`+rd`/`+i`).
+    reg: Option<&'o Register>,
+    /// The register or memory operand encoded in the ModRM.rm field (and in the SIB byte), or
+    /// the relative offset/far pointer that follows the opcode.
+    rm: Option<&'o Operand>,
+    /// The register encoded in the VEX.vvvv (or EVEX.vvvv) field.
+    vvvv: Option<&'o Register>,
+    /// The register encoded in the upper 4 bits of an 8-bit immediate (`/is4`).
+    is4: Option<&'o Register>,
+    /// The immediate operands (or symbol addresses), in the order in which they are encoded.
+    imms: Vec<&'o Operand>,
+    /// A memory operand implied by the opcode (see [`implicit_memory_bases`]), which only
+    /// determines the address size of the instruction.
+    implicit_memory: Option<&'o Memory>,
+    /// The operand size of the instruction.
+    size: u32,
+    /// The index of the operand encoded in `reg`.
+    reg_index: Option<usize>,
+    /// The index of the operand encoded in `rm`.
+    rm_index: Option<usize>,
+}
+
+impl<'o> EncodingOperands<'o> {
+    fn new(
+        repr: &InstructionRepr,
+        operands: &'o [Operand],
+        explicit_size: Option<u32>,
+    ) -> Result<Self, RasError> {
+        let mut enc_operands = Self {
+            size: operand_size(repr, operands, explicit_size),
+            ..Default::default()
         };
 
-        let mut enc = InstructionEncoder::from(self);
+        // VEX.vvvv encodes the destination operand (NDD), or the first source operand (NDS and
+        // DDS), which is always the second operand of the instruction.
+        let vvvv_index = match repr.encoding.vvvv_operand() {
+            VexOperand::Ndd => Some(0),
+            VexOperand::Nds | VexOperand::Dds => Some(1),
+            VexOperand::None => None,
+        };
 
-        for code in &inst_repr.encoding.bytecode {
-            enc.handle_opcode(
-                code,
-                inst_repr,
-                reg_op,
-                reg_memory_op,
-                imm_op,
-                operand.size(),
-            )?;
+        // The last operand of the instructions with a /is4 immediate (e.g. VBLENDVPS xmm1, xmm2,
+        // xmm3/m128, xmm4) is encoded in the immediate.
+        let is4_index = repr
+            .encoding
+            .bytecode
+            .iter()
+            .any(|code| matches!(code, EncodingBytecode::Is4))
+            .then(|| operands.len().saturating_sub(1));
+
+        let mut implicit_bases = implicit_memory_bases(repr).iter();
+
+        // The register operands that could be encoded either in ModRM.reg or in ModRM.rm (e.g.
+        // the operands of MOVSD xmm1, xmm2).
+        let mut regs = vec![];
+        for (i, (op, op_repr)) in operands.iter().zip(repr.operands.iter()).enumerate() {
+            if let Operand::Memory(m) = op {
+                if let Some(base) = implicit_bases.next() {
+                    check_implicit_memory(m, *base)?;
+                    enc_operands.implicit_memory = Some(m);
+                    continue;
+                }
+            }
+
+            if let (Some(vvvv_index), Operand::Register(reg)) = (vvvv_index, op) {
+                if i == vvvv_index {
+                    enc_operands.vvvv = Some(reg);
+                    continue;
+                }
+            }
+
+            if let (Some(is4_index), Operand::Register(reg)) = (is4_index, op) {
+                if i == is4_index {
+                    enc_operands.is4 = Some(reg);
+                    continue;
+                }
+            }
+
+            match operand_role(op_repr.kind) {
+                OperandRole::Implicit => {}
+                OperandRole::Imm => enc_operands.imms.push(op),
+                OperandRole::Reg => regs.push((i, op)),
+                OperandRole::Rm => {
+                    enc_operands.rm = Some(op);
+                    enc_operands.rm_index = Some(i);
+                }
+                OperandRole::ModRmReg => {
+                    if let Operand::Register(reg) = op {
+                        enc_operands.reg = Some(reg);
+                        enc_operands.reg_index = Some(i);
+                    }
+                }
+            }
         }
 
-        Ok(())
+        // If the ModRM.reg field is an opcode extension (/digit), the register has to be encoded
+        // in ModRM.rm.
+        let has_opcode_ext = repr
+            .encoding
+            .bytecode
+            .iter()
+            .any(|code| matches!(code, EncodingBytecode::ModRmWithReg(_)));
+
+        for (i, op) in regs {
+            match op {
+                Operand::Register(reg) if enc_operands.reg.is_none() && !has_opcode_ext => {
+                    enc_operands.reg = Some(reg);
+                    enc_operands.reg_index = Some(i);
+                }
+                op if enc_operands.rm.is_none() => {
+                    enc_operands.rm = Some(op);
+                    enc_operands.rm_index = Some(i);
+                }
+                op => {
+                    return Err(RasError::Encoding(format!(
+                        "no space left in the ModRM byte for {:?}",
+                        op
+                    )))
+                }
+            }
+        }
+
+        Ok(enc_operands)
     }
 
-    /// Encode a two-operand instruction.
-    fn encode_2_operands(
-        &mut self,
-        inst_repr: &InstructionRepr,
-        op1: &Operand,
-        op2: &Operand,
-    ) -> Result<(), RasError> {
-        let op_size = if op1.is_memory() {
-            op2.size()
-        } else if op2.is_memory() {
-            op1.size()
-        } else {
-            std::cmp::max(op1.size(), op2.size())
-        };
+    /// Returns the memory operand of the instruction, if it has one.
+    fn memory(&self) -> Option<&'o Memory> {
+        self.rm.and_then(Operand::memory)
+    }
 
-        let (reg_op, reg_memory_op, imm_op) = match (op2, op1) {
-            (Operand::Register(reg), Operand::Memory(_)) => (Some(reg), Some(op1), None),
-            (Operand::Register(reg), Operand::Register(_)) => (Some(reg), Some(op1), None),
-            (Operand::Register(reg), Operand::Immediate(imm)) => (Some(reg), None, Some(imm)),
-            (Operand::Memory(_), Operand::Register(reg)) => (Some(reg), Some(op2), None),
-            (Operand::Memory(_), Operand::Immediate(imm)) => (None, Some(op2), Some(imm)),
-            (Operand::Memory(_), _) => (None, Some(op2), None),
-            (Operand::Immediate(imm), Operand::Memory(_)) => (None, Some(op1), Some(imm)),
-            (Operand::Immediate(imm), Operand::Register(reg)) => (Some(reg), None, Some(imm)),
-            _ => unreachable!(
-                "instruction repr has ModRM byte but none of the operands are register/memory"
-            ),
-        };
+    /// Returns the direction of the instruction: `{load}` if the destination operand is encoded
+    /// in ModRM.reg and a source operand in ModRM.rm, or `{store}` if it's the other way around.
+    fn direction(&self) -> Option<PseudoPrefix> {
+        match (self.reg_index?, self.rm_index?) {
+            (0, _) => Some(PseudoPrefix::Load),
+            (_, 0) => Some(PseudoPrefix::Store),
+            _ => None,
+        }
+    }
+}
+
+/// Where an operand is encoded.
+enum OperandRole {
+    /// The operand is implied by the opcode (e.g. AL in `XOR AL, imm8`).
+    Implicit,
+    /// The operand is an immediate that follows the opcode.
+    Imm,
+    /// The operand is a register that's encoded in ModRM.reg or in the opcode byte. If there is
+    /// no other operand that needs to be encoded in ModRM.rm, it may be encoded in ModRM.rm.
+    Reg,
+    /// The operand is encoded in ModRM.rm, or it's a relative offset or a far pointer.
+    Rm,
+    /// The operand is a register that's always encoded in ModRM.reg (e.g. the control register
+    /// of `MOV r64, CR0-CR7`, whose general-purpose register is encoded in ModRM.rm).
+    ModRmReg,
+}
+
+fn operand_role(kind: OperandKind) -> OperandRole {
+    use OperandKind::*;
 
-        let mut enc = InstructionEncoder::from(self);
+    match kind {
+        // CR8 is encoded as REX.R + /0
+        Al | Cl | Dx | Zero | One | Three | Cs | Ds | Es | Fs | Gs | Ss | St0 | Cr8 => {
+            OperandRole::Implicit
+        }
+        Imm => OperandRole::Imm,
+        Cr | Dr => OperandRole::ModRmReg,
+        Reg | ModRmReg | OpcodeRd | Sreg | Mm | Mm1 | Mm2 | Xmm | Ymm | Zmm | K | Sti => {
+            OperandRole::Reg
+        }
+        _ => OperandRole::Rm,
+    }
+}
 
-        for code in &inst_repr.encoding.bytecode {
-            enc.handle_opcode(code, inst_repr, reg_op, reg_memory_op, imm_op, op_size)?;
+/// Returns the operand size of the instruction, which determines whether the instruction needs
+/// an operand-size prefix.
+///
+/// `explicit_size` is the size of the memory operands if it was specified explicitly, or the
+/// operand size implied by the mnemonic of an instruction without operands (e.g. `CBW`).
+fn operand_size(repr: &InstructionRepr, operands: &[Operand], explicit_size: Option<u32>) -> u32 {
+    if let [op] = operands {
+        // The size of a far pointer is determined by the selected instruction repr.
+        if op.is_far_pointer() {
+            return repr.operands[0].size();
         }
+    }
 
-        Ok(())
+    if repr.encoding.has_rm_operand_size() {
+        if let Some(op) = repr
+            .operands
+            .iter()
+            .find(|op| op.kind == OperandKind::ModRmRegMem)
+        {
+            return op.size();
+        }
+    }
+
+    // The operands whose size is implied by the opcode don't determine the operand size (e.g. the
+    // count of `SHL r/m32, CL`, the port of `IN AL, DX` or the selector of `PUSH FS`).
+    let operands = operands
+        .iter()
+        .zip(repr.operands.iter())
+        .filter(|(_, op_repr)| !has_implied_size(op_repr.kind))
+        .collect::<Vec<_>>();
+
+    // The size of a memory operand is determined by the size of the other operands. The size of
+    // an immediate is the size of the operand it's encoded as (e.g. `$128` is encoded as the
+    // imm32 operand of `PUSH imm32`).
+    let size = operands
+        .iter()
+        .filter(|(op, _)| !op.is_memory())
+        .map(|(op, op_repr)| match op {
+            Operand::Immediate(_) => op_repr.size(),
+            _ => op.size(),
+        })
+        .max();
+
+    match (size, operands.first()) {
+        (Some(size), _) => size,
+        // If all the operands are memory operands, the size of the memory operands can be
+        // specified explicitly (e.g. to select `NOP r/m16` rather than `NOP r/m32`).
+        (None, Some((op, _))) => explicit_size.unwrap_or_else(|| op.size()),
+        (None, None) => explicit_size.unwrap_or_default(),
     }
 }
 
-pub(crate) struct InstructionEncoder<'a> {
+/// Returns the base registers of the memory operands implied by the opcode of a string instruction
+/// or of `XLAT`, in the order of the operands (e.g. `MOVS m8, m8` copies the byte at `(%rsi)` to
+/// `(%rdi)`).
+fn implicit_memory_bases(repr: &InstructionRepr) -> &'static [RegisterNum] {
+    use EncodingBytecode::*;
+    use RegisterNum::*;
+
+    let opcode = match repr.encoding.bytecode[..] {
+        [Opcode(opcode)] | [Rex(_), Opcode(opcode)] => opcode,
+        _ => return &[],
+    };
+
+    match opcode {
+        0xa4 | 0xa5 => &[Rdi, Rsi],
+        0xa6 | 0xa7 => &[Rsi, Rdi],
+        0x6c | 0x6d | 0xaa | 0xab | 0xae | 0xaf => &[Rdi],
+        0x6e | 0x6f | 0xac | 0xad => &[Rsi],
+        0xd7 => &[Rbx],
+        _ => &[],
+    }
+}
+
+/// Check that an implicit memory operand is addressed by the register implied by the opcode (e.g.
+/// `lods (%rsi)`), since its address can't be encoded.
+fn check_implicit_memory(m: &Memory, base: RegisterNum) -> Result<(), RasError> {
+    match m {
+        Memory::Sib {
+            base: Some(reg),
+            index: None,
+            displacement: None,
+            ..
+        } if reg.gpr() == Some(base) => Ok(()),
+        _ => Err(RasError::Encoding(format!(
+            "the address of {:?} is implied by the opcode, and must be in {:?}",
+            m, base
+        ))),
+    }
+}
+
+/// Returns `true` if the size of an operand of this kind is the same for all the operand sizes
+/// of the instruction.
+fn has_implied_size(kind: OperandKind) -> bool {
+    use OperandKind::*;
+
+    matches!(kind, Cl | Dx | Sreg | Cs | Ds | Es | Fs | Gs | Ss)
+}
+
+pub(crate) struct InstructionEncoder<'a, 'o> {
     enc: &'a mut Encoder,
-    /// Whether the operand-size prefix was added to the output buffer.
+    repr: &'o InstructionRepr,
+    operands: EncodingOperands<'o>,
+    /// The AVX-512 decorators of the instruction.
+    decorators: Decorators,
+    /// The pseudo-prefixes that request a specific encoding of the instruction.
+    pseudo_prefixes: PseudoPrefixes,
+    /// The REX prefix of the instruction, if it needs one.
+    rex: Option<u8>,
+    /// Whether the operand-size prefix was added to the output buffer (if needed).
     has_operand_size_prefix: bool,
+    /// Whether the operand-size and REX prefixes were added to the output buffer.
+    has_prefixes: bool,
+    /// The index of the next immediate to encode.
+    next_imm: usize,
 }
 
-impl<'a> From<&'a mut Encoder> for InstructionEncoder<'a> {
-    fn from(enc: &'a mut Encoder) -> Self {
-        Self {
+impl<'a, 'o> InstructionEncoder<'a, 'o> {
+    fn new(
+        enc: &'a mut Encoder,
+        repr: &'o InstructionRepr,
+        operands: EncodingOperands<'o>,
+        decorators: Decorators,
+        pseudo_prefixes: PseudoPrefixes,
+    ) -> Result<Self, RasError> {
+        let mut enc = Self {
             enc,
+            repr,
+            operands,
+            decorators,
+            pseudo_prefixes,
+            rex: None,
             has_operand_size_prefix: false,
+            has_prefixes: false,
+            next_imm: 0,
+        };
+        enc.check_pseudo_prefixes()?;
+        enc.rex = enc.rex_prefix()?;
+
+        Ok(enc)
+    }
+
+    /// Check if the instruction can be encoded the way its pseudo-prefixes request.
+    ///
+    /// `{rex}` and the size of the displacement are checked when the REX prefix and the
+    /// displacement are encoded.
+    fn check_pseudo_prefixes(&self) -> Result<(), RasError> {
+        if let Some(direction) = self.pseudo_prefixes.direction() {
+            if self.operands.direction() != Some(direction) {
+                return Err(RasError::InvalidPseudoPrefix(
+                    direction,
+                    format!(
+                        "{:?} doesn't have a ModRM.reg and a ModRM.rm operand in this direction",
+                        self.repr.encoding.bytecode
+                    ),
+                ));
+            }
         }
+
+        let encoding = self.pseudo_prefixes.encoding();
+        let is_vex = self.repr.encoding.vex_prefix().is_some();
+        let is_evex = self.repr.encoding.evex_prefix().is_some();
+        match encoding {
+            Some(PseudoPrefix::Vex) if !is_vex => {
+                return Err(RasError::InvalidPseudoPrefix(
+                    PseudoPrefix::Vex,
+                    "the instruction isn't VEX-encoded".into(),
+                ));
+            }
+            Some(PseudoPrefix::Evex) if !is_evex => {
+                return Err(RasError::InvalidPseudoPrefix(
+                    PseudoPrefix::Evex,
+                    "the instruction isn't EVEX-encoded".into(),
+                ));
+            }
+            _ => {}
+        }
+
+        if let Some(displacement) = self.pseudo_prefixes.displacement() {
+            let has_displacement = match self.operands.rm {
+                Some(Operand::Memory(mem)) => mem.is_sib() || mem.is_relative(),
+                _ => false,
+            };
+            if !has_displacement {
+                return Err(RasError::InvalidPseudoPrefix(
+                    displacement,
+                    "the instruction doesn't have a displacement or a relative offset".into(),
+                ));
+            }
+        }
+
+        Ok(())
     }
-}
 
-impl<'a> InstructionEncoder<'a> {
-    fn handle_opcode(
-        &mut self,
-        code: &EncodingBytecode,
-        inst_repr: &InstructionRepr,
-        reg_op: Option<&Register>,
-        reg_memory_op: Option<&Operand>,
-        imm_op: Option<&Immediate>,
-        op_size: u32,
-    ) -> Result<(), RasError> {
+    fn handle_opcode(&mut self, code: &EncodingBytecode) -> Result<(), RasError> {
         match code {
-            EncodingBytecode::Rex(rex_prefix) => self.enc.out.push((*rex_prefix).into()),
-            EncodingBytecode::Prefix(prefix) => self.enc.out.push(*prefix),
+            // The REX prefix is emitted right before the opcode (see `encode_prefixes`).
+            EncodingBytecode::Rex(_) => {}
+            EncodingBytecode::Vex(prefix) => self.encode_vex_prefix(prefix)?,
+            EncodingBytecode::Evex(prefix) => self.encode_evex_prefix(prefix)?,
+            EncodingBytecode::Prefix(prefix) => {
+                // The operand-size prefix precedes the mandatory prefixes (e.g. `66 F3 0F B8` is
+                // `POPCNT r16, r/m16`).
+                self.encode_operand_size_prefix();
+                self.enc.out.push(*prefix)
+            }
             EncodingBytecode::Opcode(opcode) => {
-                if self.needs_operand_size_prefix(inst_repr, op_size) {
-                    self.encode_operand_size_prefix();
-                }
+                self.encode_prefixes();
                 self.enc.out.push(*opcode)
             }
             EncodingBytecode::OpcodeRw(opcode)
             | EncodingBytecode::OpcodeRb(opcode)
-            | EncodingBytecode::OpcodeRd(opcode) => {
-                if self.needs_operand_size_prefix(inst_repr, op_size) {
-                    self.encode_operand_size_prefix();
-                }
-                match reg_op {
+            | EncodingBytecode::OpcodeRd(opcode)
+            | EncodingBytecode::OpcodeRo(opcode)
+            | EncodingBytecode::OpcodeI(opcode) => {
+                self.encode_prefixes();
+                match self.operands.reg {
                     Some(reg_op) => self.encode_reg_in_opcode(*opcode, reg_op),
-                    None => unreachable!("invalid operand"),
+                    None => return Err(missing_operand(code)),
                 }
             }
             EncodingBytecode::ModRm => {
-                self.encode_modrm_sib_bytes(reg_op, reg_memory_op, None)?;
+                let modrm_reg = self.operands.reg.map(Register::num).unwrap_or_default();
+                self.encode_modrm_sib_bytes(modrm_reg)?;
             }
             EncodingBytecode::ModRmWithReg(modrm_reg) => {
-                self.encode_modrm_sib_bytes(reg_op, reg_memory_op, Some(*modrm_reg))?;
-            }
-            EncodingBytecode::Cd => {
-                if let Some(Operand::Memory(Memory::Relative(rel))) = reg_memory_op {
-                    let operand_repr = inst_repr.operands[0];
-                    self.encode_rel_memory_offset(rel, operand_repr);
-                }
-            }
-            EncodingBytecode::Ib => {
-                if let Some(imm) = imm_op {
-                    self.encode_imm(*imm)
-                } else {
-                    unreachable!("missing immediate operand for opcode {:?}", code);
-                }
+                self.encode_modrm_sib_bytes(*modrm_reg)?;
             }
-            EncodingBytecode::Iw => {
-                if let Some(imm) = imm_op {
-                    self.encode_imm(imm.sign_extend(ImmediateSize::Imm16)?)
-                } else {
-                    unreachable!("missing immediate operand for opcode {:?}", code);
+            EncodingBytecode::Cb => self.encode_code_offset(code, 1)?,
+            EncodingBytecode::Cw => self.encode_code_offset(code, 2)?,
+            EncodingBytecode::Cd => self.encode_code_offset(code, 4)?,
+            EncodingBytecode::Cp => self.encode_code_offset(code, 6)?,
+            EncodingBytecode::Co => self.encode_code_offset(code, 8)?,
+            EncodingBytecode::Ct => self.encode_code_offset(code, 10)?,
+            EncodingBytecode::Ib => match self.next_imm(code)? {
+                Imm::Value(imm) => self.encode_imm(imm.to_unsigned_imm8().unwrap_or(imm)),
+                Imm::Symbol(symbol_id) => self.encode_symbol_address(symbol_id, 1)?,
+            },
+            EncodingBytecode::Iw => match self.next_imm(code)? {
+                Imm::Value(imm) => self.encode_imm(imm.sign_extend(ImmediateSize::Imm16)?),
+                Imm::Symbol(symbol_id) => self.encode_symbol_address(symbol_id, 2)?,
+            },
+            EncodingBytecode::Id => match self.next_imm(code)? {
+                Imm::Value(imm) => self.encode_imm(imm.sign_extend(ImmediateSize::Imm32)?),
+                Imm::Symbol(symbol_id) => self.encode_symbol_address(symbol_id, 4)?,
+            },
+            EncodingBytecode::Io => match self.next_imm(code)? {
+                Imm::Value(imm) => self.enc.out.extend(imm.value().to_le_bytes()),
+                Imm::Symbol(symbol_id) => self.encode_symbol_address(symbol_id, 8)?,
+            },
+            // The register is encoded in bits 7:4 of the immediate.
+            EncodingBytecode::Is4 => match self.operands.is4 {
+                Some(reg) => self.enc.out.push((reg.num() & 0b1111) << 4),
+                None => return Err(missing_operand(code)),
+            },
+        }
+
+        Ok(())
+    }
+
+    /// Check that all the operands were encoded by the bytecode of the instruction.
+    ///
+    /// Otherwise, an operand the encoding has no room for (e.g. the r/m8 operand of a repr without
+    /// a ModRM byte) would be silently dropped.
+    fn check_operands_encoded(&self) -> Result<(), RasError> {
+        use EncodingBytecode::*;
+
+        let bytecode = &self.repr.encoding.bytecode;
+        let encodes = |f: fn(&EncodingBytecode) -> bool| bytecode.iter().any(f);
+        let reg_encoded = encodes(|code| {
+            matches!(
+                code,
+                ModRm | OpcodeRb(_) | OpcodeRw(_) | OpcodeRd(_) | OpcodeRo(_) | OpcodeI(_)
+            )
+        });
+        let rm_encoded =
+            encodes(|code| matches!(code, ModRm | ModRmWithReg(_) | Cb | Cw | Cd | Cp | Co | Ct));
+
+        let unencoded = match (self.operands.reg, self.operands.rm) {
+            (Some(reg), _) if !reg_encoded => Some(Operand::Register(*reg)),
+            (_, Some(op)) if !rm_encoded => Some(op.clone()),
+            _ => self
+                .operands
+                .imms
+                .get(self.next_imm)
+                .map(|op| (*op).clone()),
+        };
+
+        match unencoded {
+            Some(op) => Err(RasError::Encoding(format!(
+                "{:?} has no room for the operand {:?}",
+                bytecode, op
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the next immediate operand to encode.
+    fn next_imm(&mut self, code: &EncodingBytecode) -> Result<Imm<'o>, RasError> {
+        let imm = match self.operands.imms.get(self.next_imm) {
+            Some(Operand::Immediate(imm)) => Imm::Value(*imm),
+            Some(Operand::Symbol(symbol_id)) => Imm::Symbol(symbol_id),
+            _ => return Err(missing_operand(code)),
+        };
+        self.next_imm += 1;
+
+        Ok(imm)
+    }
+
+    /// Returns `true` if the CPU sign-extends the `size`-byte immediate of the instruction to its
+    /// operand size: the immediates of the instructions with a REX.W prefix, and the `imm32` of
+    /// `PUSH imm32`, which pushes 64 bits in long mode.
+    fn is_imm_sign_extended(&self, size: usize) -> bool {
+        use EncodingBytecode::*;
+
+        let bytecode = &self.repr.encoding.bytecode;
+        let has_rex_w = bytecode.contains(&Rex(RexPrefix::W));
+        let is_push = matches!(bytecode[..], [Opcode(0x68), Id]) && self.enc.mode == Mode::Long;
+
+        (has_rex_w || is_push) && size < 8
+    }
+
+    /// Encode the address of a symbol as a `size`-byte immediate.
+    fn encode_symbol_address(&mut self, symbol_id: &str, size: usize) -> Result<(), RasError> {
+        // Like GNU as, use the default operand size for addresses (e.g. `push $label` is encoded
+        // as `PUSH imm32` in long mode).
+        let address_size = self.enc.default_operand_size() as usize / 8;
+        if size != address_size {
+            return Err(RasError::Encoding(format!(
+                "the address of {} must be a {}-bit immediate",
+                symbol_id,
+                address_size * 8
+            )));
+        }
+
+        let signed = self.is_imm_sign_extended(size);
+        self.enc.emit_symbol_address(symbol_id, size, signed);
+        Ok(())
+    }
+
+    /// Encode the `size`-byte value that follows the opcode (`cb`, `cw`, `cd`, `cp`, `co` or
+    /// `ct`): either a relative offset or a far pointer (an offset followed by a 16-bit segment
+    /// selector).
+    fn encode_code_offset(&mut self, code: &EncodingBytecode, size: usize) -> Result<(), RasError> {
+        match self.operands.rm {
+            Some(Operand::Memory(Memory::Relative(rel))) => {
+                if let Some(prefix) = self.pseudo_prefixes.displacement() {
+                    let expected_size = if prefix == PseudoPrefix::Disp8 { 1 } else { 4 };
+                    if size != expected_size {
+                        return Err(RasError::InvalidPseudoPrefix(
+                            prefix,
+                            format!("the relative offset is {} bits wide", size * 8),
+                        ));
+                    }
                 }
+
+                self.encode_rel_memory_offset(rel, size)?
             }
-            EncodingBytecode::Id => {
-                if let Some(imm) = imm_op {
-                    self.encode_imm(imm.sign_extend(ImmediateSize::Imm32)?)
-                } else {
-                    unreachable!("missing immediate operand for opcode {:?}", code);
+            Some(Operand::FarPointer(FarPointer::Direct { segment, offset })) => {
+                let offset_size = size.saturating_sub(2);
+                let offset = u64::from(*offset);
+                if offset_size < 8 && offset >> (offset_size * 8) != 0 {
+                    return Err(RasError::Encoding(format!(
+                        "far pointer offset {:#x} does not fit in {} bits",
+                        offset,
+                        offset_size * 8
+                    )));
                 }
+
+                self.enc
+                    .out
+                    .extend(&offset.to_le_bytes()[..offset_size.min(8)]);
+                self.enc.out.extend(segment.to_le_bytes());
             }
-            _ => unimplemented!("encoding: {:?}", code),
+            _ => return Err(missing_operand(code)),
         }
 
         Ok(())
     }
 
-    fn encode_modrm_sib_bytes(
-        &mut self,
-        reg_op: Option<&Register>,
-        reg_memory_op: Option<&Operand>,
-        modrm_reg: Option<u8>,
-    ) -> Result<(), RasError> {
-        let (modrm_reg, rm) = if let Some(modrm_reg) = modrm_reg {
-            (modrm_reg, reg_op.map(|reg| **reg as u8).unwrap_or_default())
-        } else {
-            (
-                reg_op.map(|reg| **reg as u8).unwrap_or_default(),
-                reg_memory_op.map(|reg| reg.reg_num()).unwrap_or_default(),
-            )
-        };
+    /// Encode the operand-size and REX prefixes (if needed).
+    ///
+    /// The REX prefix must immediately precede the opcode (it comes after any mandatory or
+    /// operand-size prefixes).
+    fn encode_prefixes(&mut self) {
+        // There's no need to add the prefixes more than once.
+        if self.has_prefixes {
+            return;
+        }
 
-        if let Some(Operand::Memory(Memory::Sib {
-            displacement,
-            base,
-            index,
-            scale,
-            ..
-        })) = reg_memory_op
-        {
-            let (modifier, displacement) = match (base.is_none(), displacement) {
-                // In GNU as, expressions with missing base and index registers with no
-                // displacement are the same as a 32-bit displacement of 0 (e.g. movb $0x2,(,2)
-                // is the same as movb $0x2, 0)
-                (true, v) => (0b00, Some((v.unwrap_or(0) as i32).to_le_bytes().to_vec())),
-                // disp8
-                (false, Some(v)) if *v < i8::MAX as i64 => {
-                    (0b01, Some((*v as u8).to_le_bytes().to_vec()))
-                }
-                // disp32
-                (false, Some(v)) => (0b10, Some(v.to_le_bytes().to_vec())),
-                (false, None) => (0b00, None),
+        self.encode_operand_size_prefix();
+
+        if let Some(rex) = self.rex {
+            self.enc.out.push(rex);
+        }
+
+        self.has_prefixes = true;
+    }
+
+    /// Encode the address-size prefix (if needed).
+    ///
+    /// Like GNU as, the address-size prefix precedes all the other prefixes (including the
+    /// operand-size, mandatory, REX, VEX and EVEX prefixes).
+    fn encode_address_size_prefix(&mut self) -> Result<(), RasError> {
+        if self.needs_address_size_prefix()? {
+            self.enc.out.push(Prefix::AddressSize.into());
+        }
+
+        Ok(())
+    }
+
+    /// Encode the operand-size prefix (if needed).
+    fn encode_operand_size_prefix(&mut self) {
+        if self.has_operand_size_prefix {
+            return;
+        }
+
+        if self.needs_operand_size_prefix() {
+            self.enc.out.push(Prefix::OperandSize.into());
+        }
+
+        self.has_operand_size_prefix = true;
+    }
+
+    /// Compute the REX prefix of the instruction.
+    ///
+    /// The instruction needs a REX prefix if its encoding has one (e.g. REX.W), or if any of its
+    /// register operands can only be encoded with a REX prefix. VEX and EVEX-encoded instructions
+    /// never have a REX prefix (the REX bits are part of the VEX/EVEX prefix).
+    fn rex_prefix(&self) -> Result<Option<u8>, RasError> {
+        fn set(rex: &mut Option<u8>, prefix: RexPrefix) {
+            *rex.get_or_insert(0) |= u8::from(prefix);
+        }
+
+        if self.repr.encoding.vex_prefix().is_some() || self.repr.encoding.evex_prefix().is_some() {
+            if self.pseudo_prefixes.rex() {
+                return Err(RasError::InvalidPseudoPrefix(
+                    PseudoPrefix::Rex,
+                    "VEX and EVEX-encoded instructions can't have a REX prefix".into(),
+                ));
+            }
+            return Ok(None);
+        }
+
+        let mut rex = None;
+
+        if self.pseudo_prefixes.rex() {
+            if self.enc.mode != Mode::Long {
+                return Err(RasError::InvalidPseudoPrefix(
+                    PseudoPrefix::Rex,
+                    "the REX prefix is only available in long mode".into(),
+                ));
+            }
+            set(&mut rex, RexPrefix::None);
+        }
+
+        for code in self.repr.encoding.bytecode.iter() {
+            if let EncodingBytecode::Rex(prefix) = code {
+                set(&mut rex, *prefix);
+            }
+        }
+
+        for prefix in self.register_extensions() {
+            set(&mut rex, prefix);
+        }
+
+        if let Some(reg) = self.operands.reg {
+            if reg.needs_rex() {
+                set(&mut rex, RexPrefix::None);
+            }
+        }
+
+        if let Some(Operand::Register(reg)) = self.operands.rm {
+            if reg.needs_rex() {
+                set(&mut rex, RexPrefix::None);
+            }
+        }
+
+        if rex.is_some() {
+            let rm_reg = match self.operands.rm {
+                Some(Operand::Register(reg)) => Some(reg),
+                _ => None,
             };
+            if let Some(reg) = self
+                .operands
+                .reg
+                .iter()
+                .chain(rm_reg.iter())
+                .find(|reg| reg.is_rex_incompatible())
+            {
+                return Err(RasError::Encoding(format!(
+                    "{:?} can't be encoded in an instruction with a REX prefix",
+                    reg
+                )));
+            }
+
+            if self.enc.mode != Mode::Long {
+                return Err(RasError::Encoding(format!(
+                    "REX prefix required by {:?} is only available in long mode",
+                    self.repr.encoding.bytecode
+                )));
+            }
+        }
+
+        Ok(rex)
+    }
 
-            let sib = maybe_sib(base.as_ref(), index.as_ref(), *scale, modifier)?;
+    /// Returns the REX bits (R, X and B) needed to extend the register numbers that don't fit in
+    /// 3 bits.
+    fn register_extensions(&self) -> Vec<RexPrefix> {
+        let is_extended = |reg: &Register| reg.num() & 0b1000 != 0;
+        let mut extensions = vec![];
 
-            let rm = if sib.is_some() {
-                0b100
+        let is_reg_in_opcode = self.repr.encoding.bytecode.iter().any(|code| {
+            matches!(
+                code,
+                EncodingBytecode::OpcodeRb(_)
+                    | EncodingBytecode::OpcodeRw(_)
+                    | EncodingBytecode::OpcodeRd(_)
+            )
+        });
+
+        if self.operands.reg.map(is_extended).unwrap_or_default() {
+            // The register in the opcode byte is extended by REX.B
+            extensions.push(if is_reg_in_opcode {
+                RexPrefix::B
             } else {
-                base.map(|base| *base as u8).unwrap_or(0)
-            };
+                RexPrefix::R
+            });
+        }
 
-            self.enc.out.push(modrm(modifier, modrm_reg, rm));
+        if let Some(Operand::Register(reg)) = self.operands.rm {
+            if is_extended(reg) {
+                extensions.push(RexPrefix::B);
+            }
+        }
 
-            if let Some(sib) = sib {
-                self.enc.out.push(sib);
+        if let Some(Memory::Sib { base, index, .. }) = self.operands.memory() {
+            if base.as_ref().map(is_extended).unwrap_or_default() {
+                extensions.push(RexPrefix::B);
+            }
+            if index.as_ref().map(is_extended).unwrap_or_default() {
+                extensions.push(RexPrefix::X);
             }
+        }
+
+        extensions
+    }
+
+    /// Encode the VEX prefix of the instruction.
+    ///
+    /// Like GNU as, the 2-byte form is used whenever possible, i.e. if the instruction doesn't
+    /// need VEX.X, VEX.B, VEX.W or an opcode map other than 0F.
+    fn encode_vex_prefix(&mut self, vex: &VexPrefix) -> Result<(), RasError> {
+        let extensions = self.register_extensions();
+
+        if !extensions.is_empty() && self.enc.mode != Mode::Long {
+            return Err(RasError::Encoding(format!(
+                "registers 8-15 required by {:?} are only available in long mode",
+                self.repr.encoding.bytecode
+            )));
+        }
+
+        // The R, X, B and vvvv fields are stored in inverted (1's complement) form.
+        let inverted = |prefix| u8::from(!extensions.contains(&prefix));
+        let (r, x, b) = (
+            inverted(RexPrefix::R),
+            inverted(RexPrefix::X),
+            inverted(RexPrefix::B),
+        );
+        let vvvv = !self.operands.vvvv.map(Register::num).unwrap_or_default() & 0b1111;
+        let l = u8::from(vex.l);
+        let pp = u8::from(vex.pp);
+        let w = u8::from(vex.w);
+
+        if vex.mmmmm == VexMap::M0F && vex.w != VexW::W1 && x == 1 && b == 1 {
+            self.enc.out.push(VEX_2_BYTE);
+            self.enc.out.push((r << 7) | (vvvv << 3) | (l << 2) | pp);
+        } else {
+            self.enc.out.push(VEX_3_BYTE);
+            self.enc
+                .out
+                .push((r << 7) | (x << 6) | (b << 5) | u8::from(vex.mmmmm));
+            self.enc.out.push((w << 7) | (vvvv << 3) | (l << 2) | pp);
+        }
+
+        // There is no need for the REX or legacy prefixes, as they're included in the VEX prefix.
+        self.has_prefixes = true;
+
+        Ok(())
+    }
+
+    /// Encode the EVEX prefix of the instruction.
+    ///
+    /// The fifth bit of the register numbers is encoded in EVEX.R' (ModRM.reg), EVEX.X (ModRM.rm)
+    /// and EVEX.V' (EVEX.vvvv).
+    fn encode_evex_prefix(&mut self, evex: &EvexPrefix) -> Result<(), RasError> {
+        let is_high = |reg: &Register| reg.num() & 0b1_0000 != 0;
+        let extensions = self.register_extensions();
+        let reg_high = self.operands.reg.map(is_high).unwrap_or_default();
+        let rm_high = matches!(self.operands.rm, Some(Operand::Register(reg)) if is_high(reg));
+        let vvvv_high = self.operands.vvvv.map(is_high).unwrap_or_default();
+
+        if (!extensions.is_empty() || reg_high || rm_high || vvvv_high)
+            && self.enc.mode != Mode::Long
+        {
+            return Err(RasError::Encoding(format!(
+                "registers 8-31 required by {:?} are only available in long mode",
+                self.repr.encoding.bytecode
+            )));
+        }
+
+        // The R, X, B, R', vvvv and V' fields are stored in inverted (1's complement) form.
+        let inverted = |is_set: bool| u8::from(!is_set);
+        let r = inverted(extensions.contains(&RexPrefix::R));
+        let x = inverted(extensions.contains(&RexPrefix::X) || rm_high);
+        let b = inverted(extensions.contains(&RexPrefix::B));
+        let r_high = inverted(reg_high);
+        let vvvv = !self.operands.vvvv.map(Register::num).unwrap_or_default() & 0b1111;
+        let v_high = inverted(vvvv_high);
+
+        // With static rounding, EVEX.L'L is the rounding mode (the vector length is implied to
+        // be 512 bits).
+        let (l, b_bit) = match self.decorators.rounding {
+            Some(Rounding::Sae) => (0b00, 1),
+            Some(rounding) => (rounding as u8, 1),
+            None => (
+                u8::from(evex.l),
+                u8::from(self.decorators.broadcast.is_some()),
+            ),
+        };
+        let z = u8::from(self.decorators.zeroing);
+        let aaa = self.decorators.mask.map(|k| k.num()).unwrap_or_default();
+
+        self.enc.out.extend([
+            EVEX,
+            (r << 7) | (x << 6) | (b << 5) | (r_high << 4) | u8::from(evex.mm),
+            (u8::from(evex.w) << 7) | (vvvv << 3) | 0b100 | u8::from(evex.pp),
+            (z << 7) | (l << 5) | (b_bit << 4) | (v_high << 3) | (aaa & 0b111),
+        ]);
 
-            // Encode the displacement if needed
-            if let Some(displacement) = displacement {
-                self.enc.out.extend(displacement);
+        // There is no need for the REX or legacy prefixes, as they're included in the EVEX prefix.
+        self.has_prefixes = true;
+
+        Ok(())
+    }
+
+    /// Returns the 8-bit displacement to encode instead of `displacement`, if there is one.
+    ///
+    /// EVEX-encoded instructions use a compressed displacement (disp8*N), where N depends on the
+    /// tuple type of the instruction, the vector length, and the size of the memory operand. A
+    /// displacement that isn't a multiple of N needs a 32-bit displacement.
+    fn disp8(&self, displacement: i64) -> Option<i8> {
+        let scale = match self.repr.encoding.evex_prefix() {
+            Some(evex) => {
+                let mem_size = self
+                    .repr
+                    .operands
+                    .iter()
+                    .find_map(|op| memory_operand_size(op.kind))
+                    .unwrap_or_else(|| evex.vector_len());
+                i64::from(evex.disp8_scale(mem_size, self.decorators.broadcast.is_some())?)
             }
+            None => 1,
+        };
+
+        if displacement % scale != 0 {
+            return None;
+        }
+
+        i8::try_from(displacement / scale).ok()
+    }
+
+    /// Returns the size of the displacement requested with `{disp8}` or `{disp32}`, if any.
+    fn displacement_size(&self) -> Option<DisplacementSize> {
+        match self.pseudo_prefixes.displacement()? {
+            PseudoPrefix::Disp8 => Some(DisplacementSize::Disp8),
+            _ => Some(DisplacementSize::Disp32),
+        }
+    }
+
+    fn encode_modrm_sib_bytes(&mut self, modrm_reg: u8) -> Result<(), RasError> {
+        if let Some(Memory::Sib {
+            displacement,
+            base,
+            index,
+            scale,
+            ..
+        }) = self.operands.memory()
+        {
+            let addressing = MemoryAddressing::new(
+                self.enc.mode,
+                base.as_ref(),
+                index.as_ref(),
+                *scale,
+                *displacement,
+                self.displacement_size(),
+                |displacement| self.disp8(displacement),
+            )?;
+            addressing.encode(modrm_reg, &mut self.enc.out);
         } else {
-            self.enc.out.push(modrm(0b11, modrm_reg, rm))
+            let rm = self.operands.rm.map(Operand::reg_num).unwrap_or_default();
+            self.enc.out.push(modrm(MOD_REG, modrm_reg, rm))
         }
         Ok(())
     }
 
-    fn encode_rel_memory_offset(&mut self, rel: &MemoryRel, operand_repr: OperandRepr) {
+    fn encode_rel_memory_offset(&mut self, rel: &MemoryRel, size: usize) -> Result<(), RasError> {
         match rel {
-            MemoryRel::Absolute(_imm) => unimplemented!("imm memory offset"),
+            MemoryRel::Absolute(imm) => {
+                return Err(RasError::Encoding(format!(
+                    "absolute jump targets are not supported: {:?}",
+                    imm
+                )))
+            }
             MemoryRel::Label(symbol_id) => {
-                let size = (operand_repr.size() / 8) as usize;
-                let fixup = Fixup {
-                    offset: self.enc.current_offset(),
-                    size: size as u64,
-                };
-
-                // Store some zeroes...
-                self.enc.out.extend(vec![0; size]);
-                // ...and remember that we need to fix-up this location when we resolve the label:
+                let fixup = self.emit_rel_placeholder(size);
+                // Remember that we need to fix-up this location when we resolve the label:
                 self.enc
                     .rel_jmp_fixups
                     .entry(symbol_id.to_string())
                     .or_default()
                     .push(fixup);
             }
+            MemoryRel::Local(label) => {
+                let fixup = self.emit_rel_placeholder(size);
+                self.enc.label_fixups.entry(*label).or_default().push(fixup);
+            }
         }
+
+        Ok(())
     }
 
-    fn encode_operand_size_prefix(&mut self) {
-        self.enc.out.push(Prefix::OperandSize.into());
-        self.has_operand_size_prefix = true;
+    /// Store `size` zeroes in place of a relative offset, and return the fixup that patches
+    /// them.
+    fn emit_rel_placeholder(&mut self, size: usize) -> Fixup {
+        let fixup = Fixup {
+            offset: self.enc.current_offset(),
+            size: size as u64,
+            signed: true,
+        };
+        self.enc.out.extend(vec![0; size]);
+
+        fixup
     }
 
     /// Check if the current instruction needs an operand-size prefix.
     ///
     /// An operand-size prefix overrides the default operand-size for a particular instruction. In
-    /// 64-bit (long) mode, the default operand size is 32 bits.
+    /// 64-bit (long) and 32-bit (protected) mode, the default operand size is 32 bits. In 16-bit
+    /// (real) mode, the default operand size is 16 bits.
     ///
     /// According the to Intel manual, this is how the effective operand size is affected by the
     /// REX.W and operand-size prefixes:
@@ -366,11 +1311,9 @@ impl<'a> InstructionEncoder<'a> {
     /// REX.W Prefix            |0  |0  |0  |0  |1  |1  |1  |1
     /// Operand-Size Prefix     |N  |N  |Y  |Y  |N  |N  |Y  |Y
     /// Effective Operand Size  |32 |32 |16 |16 |64 |64 |64 |64
-    fn needs_operand_size_prefix(&mut self, inst_repr: &InstructionRepr, size: u32) -> bool {
-        // There's no need to add the operand-size prefix more than once.
-        if self.has_operand_size_prefix {
-            return false;
-        }
+    fn needs_operand_size_prefix(&self) -> bool {
+        let inst_repr = self.repr;
+        let size = self.operands.size;
 
         // The REX.W prefix takes precedence over the operand-size prefix, so if the instruction
         // already has a REX.W prefix, there's no need to add the operand-size prefix.
@@ -380,25 +1323,37 @@ impl<'a> InstructionEncoder<'a> {
             .iter()
             .any(|code| matches!(code, EncodingBytecode::Rex(_)))
             || !inst_repr.is_full_sized()
+            || inst_repr.encoding.has_fixed_operand_size()
         {
             return false;
         }
 
         match self.enc.mode {
-            Mode::Long => size == 16,
-            mode => unimplemented!("mode={:?}", mode),
+            Mode::Long | Mode::Protected => size == 16,
+            Mode::Real => size == 32,
         }
     }
 
-    #[allow(unused)]
-    fn needs_address_size_prefix(&mut self, size: u32) -> bool {
-        unimplemented!()
+    /// Check if the current instruction needs an address-size prefix.
+    ///
+    /// An address-size prefix overrides the default address size, i.e. the size of the
+    /// registers of the memory operands. In 64-bit (long) mode, the default address size is 64
+    /// bits (and it can be overridden to 32 bits). In 32-bit (protected) and 16-bit (real) mode,
+    /// the default address size is 32 and 16 bits respectively (and the prefix switches between
+    /// them).
+    fn needs_address_size_prefix(&self) -> Result<bool, RasError> {
+        match self.operands.memory().or(self.operands.implicit_memory) {
+            Some(Memory::Sib { base, index, .. }) => {
+                let mode = self.enc.mode;
+                let size = address_size(mode, base.as_ref(), index.as_ref())?;
+                Ok(size != default_address_size(mode))
+            }
+            _ => Ok(false),
+        }
     }
 
     /// Encode an immediate.
     fn encode_imm(&mut self, imm: Immediate) {
-        // XXX: MOV r64, imm64 supports 64-bit immediates
-        // Can't use more than 32 bits for the immediate.
         match imm {
             Immediate::Imm8(imm) => self.enc.out.extend(imm.to_le_bytes().to_vec()),
             Immediate::Imm16(imm) => self.enc.out.extend(imm.to_le_bytes().to_vec()),
@@ -408,66 +1363,60 @@ impl<'a> InstructionEncoder<'a> {
 
     /// Encode the register number the least significant 3 bits of the opcode byte.
     fn encode_reg_in_opcode(&mut self, opcode: u8, reg: &Register) {
-        let opcode = opcode + (0b00000111 & **reg as u8);
+        let opcode = opcode + (0b00000111 & reg.num());
 
         self.enc.out.push(opcode);
     }
 }
 
-/// The value of the ModR/M byte.
-fn modrm(modifier: u8, reg: u8, rm: u8) -> u8 {
-    ((modifier & 0b11) << 6) + ((reg & 0b111) << 3) + rm
+/// An immediate operand.
+enum Imm<'o> {
+    Value(Immediate),
+    /// The address of a symbol.
+    Symbol(&'o str),
 }
 
-/// Return the SIB byte for the base-index-scale addressing mode.
-///
-/// See Table 2-2. 32-Bit Addressing Forms with the ModR/M Byte.
-fn maybe_sib(
-    base: Option<&Register>,
-    index: Option<&Register>,
-    scale: Scale,
-    modifier: u8,
-) -> Result<Option<u8>, RasError> {
-    let sib = match (base, index, modifier) {
-        (Some(base), None, 0b00) if matches!(**base, RegisterNum::Rsp | RegisterNum::Rbp) => {
-            Some(sib(scale as u8, SIB_INDEX_NONE, sib_base(*base)))
-        }
-        (Some(base), None, 0b01) | (Some(base), None, 0b10)
-            if matches!(**base, RegisterNum::Rsp) =>
-        {
-            Some(sib(scale as u8, SIB_INDEX_NONE, sib_base(*base)))
-        }
-        (Some(_), None, _) => None,
-        (None, Some(index), _) if modifier == 0b00 => Some(sib(scale as u8, **index as u8, 0b101)),
-        (Some(base), Some(index), _) => Some(sib(scale as u8, **index as u8, sib_base(*base))),
-        _ => return Err(RasError::Encoding("invalid SIB expression".into())),
-    };
+/// Returns `true` if `value` fits in `size` bytes (as a signed or as an unsigned integer).
+pub(crate) fn fits_in(value: i64, size: u64) -> bool {
+    match size {
+        1 => i8::try_from(value).is_ok() || u8::try_from(value).is_ok(),
+        2 => i16::try_from(value).is_ok() || u16::try_from(value).is_ok(),
+        4 => i32::try_from(value).is_ok() || u32::try_from(value).is_ok(),
+        8 => true,
+        _ => false,
+    }
+}
 
-    Ok(sib)
+/// The error returned when the operand encoded by `code` is missing.
+fn missing_operand(code: &EncodingBytecode) -> RasError {
+    RasError::Encoding(format!("missing operand for {:?}", code))
 }
 
-/// Return the base field of the SIB byte for the specified base register.
-///
-/// See Table 2-3. 32-Bit Addressing Forms with the SIB Byte.
-fn sib_base(reg: Register) -> u8 {
-    match *reg {
-        RegisterNum::Rax
-        | RegisterNum::Rcx
-        | RegisterNum::Rdx
-        | RegisterNum::Rbx
-        | RegisterNum::Rsp
-        | RegisterNum::Rsi
-        | RegisterNum::Rdi => *reg as u8,
-        _ => 0b101,
+/// Returns the size of the memory operand (in bits), if `kind` is a memory operand of a known
+/// size.
+fn memory_operand_size(kind: OperandKind) -> Option<u32> {
+    use OperandKind::*;
+
+    match kind {
+        M8 => Some(8),
+        M16 => Some(16),
+        M32 | XmmM32 => Some(32),
+        M64 | XmmM64 => Some(64),
+        M128 | XmmM128 => Some(128),
+        M256 | YmmM256 => Some(256),
+        M512 | ZmmM512 => Some(512),
+        _ => None,
     }
 }
 
-/// The value of the SIB byte. From the Intel manual:
-///   * The scale field specifies the scale factor.
-///   * The index field specifies the register number of the index register.
-///   * The base field specifies the register number of the base register.
-///
-/// See Table 2-3. 32-Bit Addressing Forms with the SIB Byte
-fn sib(scale: u8, index: u8, base: u8) -> u8 {
-    ((scale & 0b11) << 6) + ((index & 0b111) << 3) + base
+/// Returns `true` if `kind` is one of the `ptr16:16/32` or `m16:16/32/64` operand kinds.
+fn is_far_pointer_kind(kind: OperandKind) -> bool {
+    matches!(
+        kind,
+        OperandKind::FarPointer16
+            | OperandKind::FarPointer32
+            | OperandKind::MemIndirectFarPointer16
+            | OperandKind::MemIndirectFarPointer32
+            | OperandKind::MemIndirectFarPointer64
+    )
 }
@@ -0,0 +1,609 @@
+//! The ModRM, SIB and displacement bytes of the memory operands.
+//!
+//! See Tables 2-1 "16-Bit Addressing Forms with the ModR/M Byte", 2-2 "32-Bit Addressing Forms
+//! with the ModR/M Byte" and 2-3 "32-Bit Addressing Forms with the SIB Byte" of the [Intel® 64
+//! and IA-32 architectures software developer's manual volume 2].
+//!
+//! [Intel® 64 and IA-32 architectures software developer's manual volume 2]: https://software.intel.com/content/www/us/en/develop/articles/intel-sdm.html
+
+use crate::error::RasError;
+use crate::instruction::PseudoPrefix;
+use crate::operand::{Register, RegisterNum, Scale};
+use crate::Mode;
+
+use std::convert::TryFrom;
+
+/// ModRM.mod: a memory operand with no displacement (or with a 32-bit displacement and no base).
+const MOD_NO_DISP: u8 = 0b00;
+/// ModRM.mod: a memory operand with an 8-bit displacement.
+const MOD_DISP8: u8 = 0b01;
+/// ModRM.mod: a memory operand with a 32-bit displacement.
+const MOD_DISP32: u8 = 0b10;
+/// ModRM.mod: a register operand.
+pub(super) const MOD_REG: u8 = 0b11;
+
+/// ModRM.rm: the memory operand is described by the SIB byte.
+const RM_SIB: u8 = 0b100;
+/// ModRM.rm: a 32-bit displacement (RIP-relative in long mode) if ModRM.mod is 00.
+const RM_DISP32: u8 = 0b101;
+/// SIB.index: no index register.
+const SIB_INDEX_NONE: u8 = 0b100;
+/// SIB.base: no base register (a 32-bit displacement) if ModRM.mod is 00.
+const SIB_BASE_NONE: u8 = 0b101;
+/// ModRM.rm: a 16-bit displacement if ModRM.mod is 00 (or [BP] otherwise), with 16-bit
+/// addressing.
+const RM16_DISP16: u8 = 0b110;
+
+/// The displacement of a memory operand.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Displacement {
+    None,
+    Disp8(i8),
+    Disp16(i16),
+    Disp32(i32),
+}
+
+/// The size of the displacement requested with a `{disp8}` or `{disp32}` pseudo-prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum DisplacementSize {
+    Disp8,
+    Disp32,
+}
+
+/// How a memory operand is encoded: the ModRM.mod and ModRM.rm fields, the SIB byte (if any), and
+/// the displacement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct MemoryAddressing {
+    modifier: u8,
+    rm: u8,
+    sib: Option<u8>,
+    displacement: Displacement,
+}
+
+/// Returns the address size (in bits) used by instructions that don't have an address-size
+/// prefix.
+pub(super) fn default_address_size(mode: Mode) -> u32 {
+    match mode {
+        Mode::Real => 16,
+        Mode::Protected => 32,
+        Mode::Long => 64,
+    }
+}
+
+/// Returns the size (in bits) of the address computed by the memory operand `(base, index)`:
+/// the size of its registers, or the default address size of `mode` if it doesn't have any.
+///
+/// Like GNU as, an address size other than the default one of `mode` is encoded with an
+/// address-size prefix, except for 16-bit addresses in long mode and 64-bit addresses outside
+/// long mode, which can't be encoded at all.
+pub(super) fn address_size(
+    mode: Mode,
+    base: Option<&Register>,
+    index: Option<&Register>,
+) -> Result<u32, RasError> {
+    let sizes = base
+        .iter()
+        .chain(index.iter())
+        .filter(|reg| reg.gpr().is_some())
+        .map(|reg| reg.size())
+        .collect::<Vec<_>>();
+
+    let size = match sizes.as_slice() {
+        [] => return Ok(default_address_size(mode)),
+        [size] => *size,
+        [base, index] if base == index => *base,
+        _ => {
+            return Err(RasError::Encoding(format!(
+                "the base register {:?} and the index register {:?} must have the same size",
+                base, index
+            )));
+        }
+    };
+
+    match (size, mode) {
+        (16, Mode::Real | Mode::Protected) | (32, _) | (64, Mode::Long) => Ok(size),
+        _ => Err(RasError::Encoding(format!(
+            "{}-bit addresses can't be used in {:?} mode",
+            size, mode
+        ))),
+    }
+}
+
+impl MemoryAddressing {
+    /// Pick the addressing form of the memory operand `displacement(base, index, scale)`.
+    ///
+    /// If `size` is specified, the displacement is always encoded with that size (even if it's
+    /// 0). `disp8` returns the 8-bit displacement to encode instead of the specified displacement,
+    /// if there is one (EVEX-encoded instructions use a compressed displacement).
+    ///
+    /// The addressing form depends on the address size (see [`address_size`]): 16-bit addresses
+    /// use the 16-bit addressing forms, which don't have a SIB byte.
+    pub(super) fn new(
+        mode: Mode,
+        base: Option<&Register>,
+        index: Option<&Register>,
+        scale: Scale,
+        displacement: Option<i64>,
+        size: Option<DisplacementSize>,
+        disp8: impl Fn(i64) -> Option<i8>,
+    ) -> Result<Self, RasError> {
+        if address_size(mode, base, index)? == 16 {
+            return Self::new16(base, index, scale, displacement, size, disp8);
+        }
+
+        let displacement = displacement.unwrap_or_default();
+        let disp32 = i32::try_from(displacement).map_err(|_| {
+            RasError::Encoding(format!(
+                "displacement {:#x} doesn't fit in 32 bits",
+                displacement
+            ))
+        })?;
+
+        if let Some(index) = index {
+            if index.gpr() == Some(RegisterNum::Rsp) {
+                return Err(RasError::Encoding(format!(
+                    "{:?} can't be used as an index register",
+                    index
+                )));
+            }
+        }
+
+        let base = match base {
+            Some(base) => base,
+            // There is no base register, so the displacement is always 32 bits wide.
+            None if size == Some(DisplacementSize::Disp8) => {
+                return Err(RasError::InvalidPseudoPrefix(
+                    PseudoPrefix::Disp8,
+                    "a memory operand without a base register needs a 32-bit displacement".into(),
+                ));
+            }
+            None => {
+                let (rm, sib) = match (index, mode) {
+                    (Some(index), _) => (RM_SIB, Some(sib(scale, index.num(), SIB_BASE_NONE))),
+                    // In long mode, ModRM.rm = 101 means RIP-relative addressing, so an absolute
+                    // address needs a SIB byte with no base and no index.
+                    (None, Mode::Long) => (
+                        RM_SIB,
+                        Some(sib(Scale::Byte, SIB_INDEX_NONE, SIB_BASE_NONE)),
+                    ),
+                    (None, _) => (RM_DISP32, None),
+                };
+
+                return Ok(Self {
+                    modifier: MOD_NO_DISP,
+                    rm,
+                    sib,
+                    displacement: Displacement::Disp32(disp32),
+                });
+            }
+        };
+
+        if base.gpr().is_none() {
+            return Err(RasError::Encoding(format!(
+                "{:?} can't be used as a base register",
+                base
+            )));
+        }
+
+        // ModRM.mod = 00 with a base of RBP/R13 means there's no base (ModRM.rm = 101 or
+        // SIB.base = 101), so a displacement of 0 needs to be encoded explicitly.
+        let needs_disp = base.num() & 0b111 == SIB_BASE_NONE;
+        let (modifier, displacement) = match (size, disp8(displacement)) {
+            (None, _) if displacement == 0 && !needs_disp => (MOD_NO_DISP, Displacement::None),
+            (Some(DisplacementSize::Disp32), _) => (MOD_DISP32, Displacement::Disp32(disp32)),
+            (_, Some(disp8)) => (MOD_DISP8, Displacement::Disp8(disp8)),
+            (Some(DisplacementSize::Disp8), None) => {
+                return Err(RasError::InvalidPseudoPrefix(
+                    PseudoPrefix::Disp8,
+                    format!("displacement {:#x} doesn't fit in 8 bits", displacement),
+                ));
+            }
+            (None, None) => (MOD_DISP32, Displacement::Disp32(disp32)),
+        };
+
+        // ModRM.rm = 100 means there's a SIB byte, so RSP/R12 can only be used as a base register
+        // through the SIB byte.
+        let (rm, sib) = match index {
+            Some(index) => (RM_SIB, Some(sib(scale, index.num(), base.num()))),
+            None if base.num() & 0b111 == RM_SIB => {
+                (RM_SIB, Some(sib(scale, SIB_INDEX_NONE, base.num())))
+            }
+            None => (base.num(), None),
+        };
+
+        Ok(Self {
+            modifier,
+            rm,
+            sib,
+            displacement,
+        })
+    }
+
+    /// Pick the 16-bit addressing form of the memory operand `displacement(base, index, scale)`.
+    ///
+    /// The base register is one of BX and BP, and the index register one of SI and DI, and there
+    /// is no scale. Any of them except for BP can be used on its own, as the base register.
+    fn new16(
+        base: Option<&Register>,
+        index: Option<&Register>,
+        scale: Scale,
+        displacement: Option<i64>,
+        size: Option<DisplacementSize>,
+        disp8: impl Fn(i64) -> Option<i8>,
+    ) -> Result<Self, RasError> {
+        use RegisterNum::*;
+
+        let invalid = || {
+            RasError::Encoding(format!(
+                "({:?}, {:?}, {:?}) isn't a valid 16-bit memory operand",
+                base, index, scale
+            ))
+        };
+        if scale != Scale::Byte {
+            return Err(invalid());
+        }
+        if size == Some(DisplacementSize::Disp32) {
+            return Err(RasError::InvalidPseudoPrefix(
+                PseudoPrefix::Disp32,
+                "16-bit memory operands have a 16-bit displacement".into(),
+            ));
+        }
+
+        let displacement = displacement.unwrap_or_default();
+        let disp16 = i16::try_from(displacement)
+            .or_else(|_| u16::try_from(displacement).map(|disp| disp as i16))
+            .map_err(|_| {
+                RasError::Encoding(format!(
+                    "displacement {:#x} doesn't fit in 16 bits",
+                    displacement
+                ))
+            })?;
+        // Like GNU as, pick the size of the displacement after wrapping it around to 16 bits
+        // (e.g. 0xffff is encoded as an 8-bit -1).
+        let displacement = i64::from(disp16);
+
+        let gpr = |reg: Option<&Register>| reg.map(|reg| reg.gpr().ok_or_else(invalid)).transpose();
+        let rm = match (gpr(base)?, gpr(index)?) {
+            (Some(Rbx), Some(Rsi)) => 0b000,
+            (Some(Rbx), Some(Rdi)) => 0b001,
+            (Some(Rbp), Some(Rsi)) => 0b010,
+            (Some(Rbp), Some(Rdi)) => 0b011,
+            (Some(Rsi), None) => 0b100,
+            (Some(Rdi), None) => 0b101,
+            (Some(Rbp), None) => RM16_DISP16,
+            (Some(Rbx), None) => 0b111,
+            (None, None) if size == Some(DisplacementSize::Disp8) => {
+                return Err(RasError::InvalidPseudoPrefix(
+                    PseudoPrefix::Disp8,
+                    "a memory operand without a base register needs a 16-bit displacement".into(),
+                ));
+            }
+            // There are no registers, so the displacement is always 16 bits wide.
+            (None, None) => {
+                return Ok(Self {
+                    modifier: MOD_NO_DISP,
+                    rm: RM16_DISP16,
+                    sib: None,
+                    displacement: Displacement::Disp16(disp16),
+                });
+            }
+            _ => return Err(invalid()),
+        };
+
+        // ModRM.mod = 00 with ModRM.rm = 110 means there's no base, so a displacement of 0 from
+        // BP needs to be encoded explicitly.
+        let needs_disp = rm == RM16_DISP16;
+        let (modifier, displacement) = match (size, disp8(displacement)) {
+            (None, _) if displacement == 0 && !needs_disp => (MOD_NO_DISP, Displacement::None),
+            (_, Some(disp8)) => (MOD_DISP8, Displacement::Disp8(disp8)),
+            (Some(_), None) => {
+                return Err(RasError::InvalidPseudoPrefix(
+                    PseudoPrefix::Disp8,
+                    format!("displacement {:#x} doesn't fit in 8 bits", displacement),
+                ));
+            }
+            // The 16-bit displacement is encoded with ModRM.mod = 10 (like a 32-bit one).
+            (None, None) => (MOD_DISP32, Displacement::Disp16(disp16)),
+        };
+
+        Ok(Self {
+            modifier,
+            rm,
+            sib: None,
+            displacement,
+        })
+    }
+
+    /// Append the ModRM byte (with the specified ModRM.reg field), the SIB byte and the
+    /// displacement to `out`.
+    pub(super) fn encode(&self, reg: u8, out: &mut Vec<u8>) {
+        out.push(modrm(self.modifier, reg, self.rm));
+        out.extend(self.sib);
+
+        match self.displacement {
+            Displacement::None => {}
+            Displacement::Disp8(disp) => out.extend(disp.to_le_bytes()),
+            Displacement::Disp16(disp) => out.extend(disp.to_le_bytes()),
+            Displacement::Disp32(disp) => out.extend(disp.to_le_bytes()),
+        }
+    }
+}
+
+/// The value of the ModR/M byte.
+pub(super) fn modrm(modifier: u8, reg: u8, rm: u8) -> u8 {
+    ((modifier & 0b11) << 6) + ((reg & 0b111) << 3) + (rm & 0b111)
+}
+
+/// The value of the SIB byte. From the Intel manual:
+///   * The scale field specifies the scale factor.
+///   * The index field specifies the register number of the index register.
+///   * The base field specifies the register number of the base register.
+fn sib(scale: Scale, index: u8, base: u8) -> u8 {
+    ((scale as u8 & 0b11) << 6) + ((index & 0b111) << 3) + (base & 0b111)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repr::Prefix;
+    use crate::{AX, BP, BX, DI, EAX, EBP, ECX, ESP, RAX, RBP, RBX, RCX, RSP, SI, XMM0};
+    use crate::{R12, R13, R13D, R8, R9};
+
+    /// The base, index, scale and displacement of a memory operand, and its expected encoding
+    /// (with ModRM.reg = 0), including the address-size prefix, if it needs one.
+    type TestCase<'a> = (
+        Option<&'a Register>,
+        Option<&'a Register>,
+        Scale,
+        Option<i64>,
+        &'a [u8],
+    );
+
+    /// A 1-byte displacement `disp8` function (i.e. one without displacement compression).
+    fn disp8(displacement: i64) -> Option<i8> {
+        i8::try_from(displacement).ok()
+    }
+
+    fn encode(
+        mode: Mode,
+        base: Option<&Register>,
+        index: Option<&Register>,
+        scale: Scale,
+        displacement: Option<i64>,
+    ) -> Result<Vec<u8>, RasError> {
+        let mut out = vec![];
+        if address_size(mode, base, index)? != default_address_size(mode) {
+            out.push(Prefix::AddressSize.into());
+        }
+        MemoryAddressing::new(mode, base, index, scale, displacement, None, disp8)?
+            .encode(0, &mut out);
+        Ok(out)
+    }
+
+    #[test]
+    fn addressing_forms() {
+        use Scale::*;
+
+        #[rustfmt::skip]
+        let cases: &[TestCase] = &[
+            // [base]
+            (Some(&RAX), None, Byte, None, &[0x00]),
+            (Some(&RBX), None, Byte, Some(0), &[0x03]),
+            // [base + disp8]
+            (Some(&RAX), None, Byte, Some(1), &[0x40, 0x01]),
+            (Some(&RAX), None, Byte, Some(127), &[0x40, 0x7f]),
+            (Some(&RAX), None, Byte, Some(-1), &[0x40, 0xff]),
+            (Some(&RAX), None, Byte, Some(-128), &[0x40, 0x80]),
+            // [base + disp32]
+            (Some(&RAX), None, Byte, Some(128), &[0x80, 0x80, 0x00, 0x00, 0x00]),
+            (Some(&RAX), None, Byte, Some(-129), &[0x80, 0x7f, 0xff, 0xff, 0xff]),
+            (Some(&RAX), None, Byte, Some(i32::MAX.into()), &[0x80, 0xff, 0xff, 0xff, 0x7f]),
+            (Some(&RAX), None, Byte, Some(i32::MIN.into()), &[0x80, 0x00, 0x00, 0x00, 0x80]),
+            // RSP as a base needs a SIB byte
+            (Some(&RSP), None, Byte, None, &[0x04, 0x24]),
+            (Some(&RSP), None, Byte, Some(8), &[0x44, 0x24, 0x08]),
+            (Some(&RSP), None, Byte, Some(256), &[0x84, 0x24, 0x00, 0x01, 0x00, 0x00]),
+            (Some(&ESP), None, Byte, None, &[0x67, 0x04, 0x24]),
+            // ...and so does R12 (REX.B + 100)
+            (Some(&R12), None, Byte, None, &[0x04, 0x24]),
+            (Some(&R12), None, Byte, Some(-8), &[0x44, 0x24, 0xf8]),
+            // RBP as a base needs a displacement
+            (Some(&RBP), None, Byte, None, &[0x45, 0x00]),
+            (Some(&RBP), None, Byte, Some(0), &[0x45, 0x00]),
+            (Some(&RBP), None, Byte, Some(-8), &[0x45, 0xf8]),
+            (Some(&RBP), None, Byte, Some(4096), &[0x85, 0x00, 0x10, 0x00, 0x00]),
+            (Some(&EBP), None, Byte, None, &[0x67, 0x45, 0x00]),
+            // ...and so does R13 (REX.B + 101)
+            (Some(&R13), None, Byte, None, &[0x45, 0x00]),
+            (Some(&R13), None, Byte, Some(4096), &[0x85, 0x00, 0x10, 0x00, 0x00]),
+            (Some(&R13D), None, Byte, None, &[0x67, 0x45, 0x00]),
+            // The other extended registers don't (REX.B + 000 is R8)
+            (Some(&R8), None, Byte, None, &[0x00]),
+            // [base + index * scale]
+            (Some(&RAX), Some(&RCX), Byte, None, &[0x04, 0x08]),
+            (Some(&RBX), Some(&RCX), Word, None, &[0x04, 0x4b]),
+            (Some(&RBX), Some(&RCX), Double, Some(16), &[0x44, 0x8b, 0x10]),
+            (Some(&RBX), Some(&RCX), Quad, Some(-4096), &[0x84, 0xcb, 0x00, 0xf0, 0xff, 0xff]),
+            (Some(&RSP), Some(&RCX), Byte, None, &[0x04, 0x0c]),
+            (Some(&RBP), Some(&RCX), Byte, None, &[0x44, 0x0d, 0x00]),
+            (Some(&RBP), Some(&RCX), Quad, Some(127), &[0x44, 0xcd, 0x7f]),
+            (Some(&RBP), Some(&RAX), Byte, Some(-129), &[0x84, 0x05, 0x7f, 0xff, 0xff, 0xff]),
+            // R12 can be an index register (REX.X + 100), unlike RSP
+            (Some(&RAX), Some(&R12), Byte, None, &[0x04, 0x20]),
+            (Some(&R13), Some(&R12), Quad, None, &[0x44, 0xe5, 0x00]),
+            (Some(&R12), Some(&R9), Double, Some(8), &[0x44, 0x8c, 0x08]),
+            // [index * scale + disp32]
+            (None, Some(&RCX), Quad, None, &[0x04, 0xcd, 0x00, 0x00, 0x00, 0x00]),
+            (None, Some(&RCX), Double, Some(8), &[0x04, 0x8d, 0x08, 0x00, 0x00, 0x00]),
+            (None, Some(&RBP), Byte, Some(-1), &[0x04, 0x2d, 0xff, 0xff, 0xff, 0xff]),
+            // [disp32]
+            (None, None, Byte, None, &[0x04, 0x25, 0x00, 0x00, 0x00, 0x00]),
+            (None, None, Byte, Some(4096), &[0x04, 0x25, 0x00, 0x10, 0x00, 0x00]),
+            // 32-bit addresses need an address-size prefix
+            (Some(&EAX), None, Byte, None, &[0x67, 0x00]),
+            (Some(&EAX), Some(&ECX), Double, None, &[0x67, 0x04, 0x88]),
+        ];
+
+        for (base, index, scale, displacement, expected) in cases {
+            assert_eq!(
+                encode(Mode::Long, *base, *index, *scale, *displacement).unwrap(),
+                *expected,
+                "incorrect encoding for {:?}({:?}, {:?}, {:?})",
+                displacement,
+                base,
+                index,
+                scale,
+            );
+        }
+    }
+
+    #[test]
+    fn absolute_address_outside_long_mode() {
+        // Outside long mode, ModRM.rm = 101 is a 32-bit displacement (rather than RIP-relative).
+        assert_eq!(
+            encode(Mode::Protected, None, None, Scale::Byte, Some(4096)).unwrap(),
+            [0x05, 0x00, 0x10, 0x00, 0x00]
+        );
+    }
+
+    #[test]
+    fn address_size_outside_long_mode() {
+        use Scale::*;
+
+        #[rustfmt::skip]
+        let cases: &[(Mode, TestCase)] = &[
+            (Mode::Protected, (Some(&EAX), None, Byte, None, &[0x00])),
+            (Mode::Protected, (Some(&BX), Some(&SI), Byte, None, &[0x67, 0x00])),
+            (Mode::Real, (Some(&EAX), None, Byte, None, &[0x67, 0x00])),
+            (Mode::Real, (Some(&EAX), Some(&ECX), Double, None, &[0x67, 0x04, 0x88])),
+            // [base + index]
+            (Mode::Real, (Some(&BX), Some(&SI), Byte, None, &[0x00])),
+            (Mode::Real, (Some(&BX), Some(&DI), Byte, Some(0x100), &[0x81, 0x00, 0x01])),
+            (Mode::Real, (Some(&BP), Some(&SI), Byte, Some(-1), &[0x42, 0xff])),
+            (Mode::Real, (Some(&BP), Some(&DI), Byte, None, &[0x03])),
+            // [base] or [index]
+            (Mode::Real, (Some(&SI), None, Byte, None, &[0x04])),
+            (Mode::Real, (Some(&DI), None, Byte, None, &[0x05])),
+            (Mode::Real, (Some(&BX), None, Byte, None, &[0x07])),
+            (Mode::Real, (Some(&BX), None, Byte, Some(0xffff), &[0x47, 0xff])),
+            (Mode::Real, (Some(&BX), None, Byte, Some(0x8000), &[0x87, 0x00, 0x80])),
+            // BP as a base needs a displacement
+            (Mode::Real, (Some(&BP), None, Byte, None, &[0x46, 0x00])),
+            (Mode::Real, (Some(&BP), None, Byte, Some(4), &[0x46, 0x04])),
+            // [disp16]
+            (Mode::Real, (None, None, Byte, Some(0x1000), &[0x06, 0x00, 0x10])),
+        ];
+
+        for (mode, (base, index, scale, displacement, expected)) in cases {
+            assert_eq!(
+                encode(*mode, *base, *index, *scale, *displacement).unwrap(),
+                *expected,
+                "incorrect encoding for {:?}({:?}, {:?}, {:?}) in {:?} mode",
+                displacement,
+                base,
+                index,
+                scale,
+                mode,
+            );
+        }
+    }
+
+    #[test]
+    fn compressed_displacement() {
+        let disp8 = |displacement: i64| {
+            if displacement % 64 != 0 {
+                return None;
+            }
+            i8::try_from(displacement / 64).ok()
+        };
+        let encode = |displacement| {
+            let mut out = vec![];
+            MemoryAddressing::new(
+                Mode::Long,
+                Some(&RAX),
+                None,
+                Scale::Byte,
+                displacement,
+                None,
+                disp8,
+            )
+            .unwrap()
+            .encode(0, &mut out);
+            out
+        };
+
+        assert_eq!(encode(Some(64)), [0x40, 0x01]);
+        assert_eq!(encode(Some(-8192)), [0x40, 0x80]);
+        assert_eq!(encode(Some(8192)), [0x80, 0x00, 0x20, 0x00, 0x00]);
+        assert_eq!(encode(Some(32)), [0x80, 0x20, 0x00, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn forced_displacement_size() {
+        let encode = |base, displacement, size| {
+            let mut out = vec![];
+            MemoryAddressing::new(
+                Mode::Long,
+                base,
+                None,
+                Scale::Byte,
+                displacement,
+                Some(size),
+                disp8,
+            )
+            .map(|addressing| addressing.encode(0, &mut out))
+            .map(|_| out)
+        };
+
+        use DisplacementSize::*;
+        assert_eq!(encode(Some(&RAX), None, Disp8), Ok(vec![0x40, 0x00]));
+        assert_eq!(encode(Some(&RAX), Some(-1), Disp8), Ok(vec![0x40, 0xff]));
+        assert_eq!(
+            encode(Some(&RAX), None, Disp32),
+            Ok(vec![0x80, 0x00, 0x00, 0x00, 0x00])
+        );
+        assert_eq!(
+            encode(Some(&RSP), Some(8), Disp32),
+            Ok(vec![0x84, 0x24, 0x08, 0x00, 0x00, 0x00])
+        );
+        assert_eq!(
+            encode(None, Some(8), Disp32),
+            Ok(vec![0x04, 0x25, 0x08, 0x00, 0x00, 0x00])
+        );
+        assert!(encode(Some(&RAX), Some(128), Disp8).is_err());
+        assert!(encode(None, Some(8), Disp8).is_err());
+    }
+
+    #[test]
+    fn invalid_addressing_forms() {
+        assert!(encode(Mode::Long, Some(&RAX), Some(&RSP), Scale::Byte, None).is_err());
+        assert!(encode(Mode::Long, Some(&RAX), None, Scale::Byte, Some(1 << 31)).is_err());
+        assert!(encode(
+            Mode::Long,
+            Some(&RAX),
+            None,
+            Scale::Byte,
+            Some(-(1 << 31) - 1)
+        )
+        .is_err());
+        assert!(encode(Mode::Long, Some(&XMM0), None, Scale::Byte, None).is_err());
+        assert_eq!(
+            encode(Mode::Long, Some(&EAX), None, Scale::Byte, None).unwrap(),
+            [0x67, 0x00]
+        );
+
+        // The address size must be supported by the mode.
+        assert!(encode(Mode::Long, Some(&BX), None, Scale::Byte, None).is_err());
+        assert!(encode(Mode::Protected, Some(&RAX), None, Scale::Byte, None).is_err());
+        assert!(encode(Mode::Real, Some(&RAX), None, Scale::Byte, None).is_err());
+        assert!(encode(Mode::Long, Some(&RAX), Some(&ECX), Scale::Byte, None).is_err());
+
+        // The 16-bit addressing forms have a few valid registers, and no scale.
+        assert!(encode(Mode::Real, Some(&AX), None, Scale::Byte, None).is_err());
+        assert!(encode(Mode::Real, Some(&SI), Some(&BX), Scale::Byte, None).is_err());
+        assert!(encode(Mode::Real, None, Some(&DI), Scale::Byte, None).is_err());
+        assert!(encode(Mode::Real, Some(&BX), Some(&SI), Scale::Word, None).is_err());
+        assert!(encode(Mode::Real, Some(&XMM0), None, Scale::Byte, None).is_err());
+        assert!(encode(Mode::Real, Some(&BX), None, Scale::Byte, Some(0x10000)).is_err());
+    }
+}
@@ -1,5 +1,8 @@
+use crate::instruction::PseudoPrefix;
 use crate::mnemonic::Mnemonic;
+use crate::repr::CpuFeature;
 use crate::symbol::SymbolId;
+use crate::Mode;
 
 use object::write;
 use std::cmp::PartialEq;
@@ -11,6 +14,13 @@ use std::num::ParseIntError;
 #[derive(Debug)]
 pub struct ParseErrorList(Vec<(usize, ParseError)>);
 
+impl ParseErrorList {
+    /// The errors, along with the (0-based) number of the line they were found on.
+    pub fn errors(&self) -> &[(usize, ParseError)] {
+        &self.0
+    }
+}
+
 impl Error for ParseErrorList {}
 
 impl Display for ParseErrorList {
@@ -37,6 +47,20 @@ pub enum RasError {
     DuplicateLabel(SymbolId),
     UndefinedSymbols(Vec<SymbolId>),
     MissingInstructionRepr(Mnemonic),
+    /// The instruction can't be encoded in the current mode (e.g. `SWAPGS` outside long mode).
+    InvalidMode(Mnemonic, Mode),
+    /// The instruction requires CPU features that aren't enabled (e.g. `LZCNT` when assembling
+    /// for `x86-64-v2`).
+    MissingCpuFeatures(Mnemonic, Vec<CpuFeature>),
+    /// The instruction can't be encoded the way a pseudo-prefix requested (e.g. `{store}` when
+    /// the destination is a register and the source is a memory operand).
+    InvalidPseudoPrefix(PseudoPrefix, String),
+    /// The code refers to external symbols, but the output format can't record relocations
+    /// (e.g. a flat binary).
+    UnresolvedRelocations(Vec<SymbolId>),
+    /// An object file can't be linked (e.g. it isn't a relocatable ELF object, or it has
+    /// relocations the linker doesn't support).
+    Link(String),
     Object(write::Error),
     Io(io::Error),
     SignExtend(String),
@@ -73,6 +97,30 @@ impl Display for RasError {
             MissingInstructionRepr(mnemonic) => {
                 write!(f, "failed to select instruction repr for {:?}", mnemonic)
             }
+            InvalidMode(mnemonic, mode) => {
+                write!(f, "{:?} is not valid in {:?} mode", mnemonic, mode)
+            }
+            MissingCpuFeatures(mnemonic, features) => {
+                let features = features
+                    .iter()
+                    .map(|feature| feature.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(
+                    f,
+                    "{:?} requires CPU features that aren't enabled: {}",
+                    mnemonic, features
+                )
+            }
+            InvalidPseudoPrefix(prefix, err) => {
+                write!(f, "can't encode the instruction with {}: {}", prefix, err)
+            }
+            UnresolvedRelocations(symbols) => write!(
+                f,
+                "the references to external symbols can't be relocated: {}",
+                symbols.join(", ")
+            ),
+            Link(err) => write!(f, "link error: {}", err),
             Object(err) => write!(f, "{}", err),
             Io(err) => write!(f, "{}", err),
             SignExtend(err) => write!(f, "sign extend error: {}", err),
@@ -91,6 +139,11 @@ impl PartialEq for RasError {
             (DuplicateLabel(s1), DuplicateLabel(s2)) => s1 == s2,
             (UndefinedSymbols(s1), UndefinedSymbols(s2)) => s1 == s2,
             (MissingInstructionRepr(s1), MissingInstructionRepr(s2)) => s1 == s2,
+            (InvalidMode(s1, m1), InvalidMode(s2, m2)) => s1 == s2 && m1 == m2,
+            (MissingCpuFeatures(s1, f1), MissingCpuFeatures(s2, f2)) => s1 == s2 && f1 == f2,
+            (InvalidPseudoPrefix(p1, s1), InvalidPseudoPrefix(p2, s2)) => p1 == p2 && s1 == s2,
+            (UnresolvedRelocations(s1), UnresolvedRelocations(s2)) => s1 == s2,
+            (Link(s1), Link(s2)) => s1 == s2,
             (Object(s1), Object(s2)) => s1 == s2,
             (SignExtend(z1), SignExtend(z2)) => z1 == z2,
             _ => false,
@@ -116,6 +169,12 @@ impl Display for ParseError {
             ParseErrorKind::JunkAfterExpression(s) => {
                 write!(f, "found junk after expression: {:?}", s)
             }
+            ParseErrorKind::InvalidFarPointer(err) => write!(f, "invalid far pointer: {}", err),
+            ParseErrorKind::InvalidDecorator(d) => write!(f, "invalid decorator '{}'", d),
+            ParseErrorKind::InvalidPseudoPrefix(p) => {
+                write!(f, "invalid pseudo-prefix '{{{}}}'", p)
+            }
+            ParseErrorKind::InvalidDirective(d) => write!(f, "unknown directive '{}'", d),
         }
     }
 }
@@ -171,4 +230,8 @@ pub enum ParseErrorKind {
     UnexpectedChar(char),
     ParseInt(ParseIntError),
     JunkAfterExpression(String),
+    InvalidFarPointer(String),
+    InvalidDecorator(String),
+    InvalidPseudoPrefix(String),
+    InvalidDirective(String),
 }
@@ -0,0 +1,304 @@
+//! Statically linked ELF executables (`ET_EXEC`), which can be run directly, without a linker or
+//! a dynamic loader.
+//!
+//! ```
+//! use ras_x86::assembler::Assembler;
+//! use ras_x86::executable::ExecutableWriter;
+//! use ras_x86::parser::parse_asm;
+//!
+//! let items = parse_asm("_start:\nmov $60, %eax\nmov $42, %edi\nsyscall").unwrap();
+//! let out = Assembler::long_mode().items(items).assemble().unwrap();
+//!
+//! let exe = ExecutableWriter::new().base_address(0x10000).write(&out).unwrap();
+//! assert_eq!(exe[..4], *b"\x7fELF");
+//! ```
+
+use object::elf;
+use object::endian::Endianness;
+use object::write::elf::{FileHeader, ProgramHeader, SectionHeader, SectionIndex, Sym, Writer};
+use object::write::StringId;
+
+use crate::assembled::{align_up, Assembled, SectionKind, SymbolBinding};
+use crate::symbol::SymbolId;
+use crate::{Mode, RasError, RasResult};
+
+/// The size of the pages the segments are mapped into.
+const PAGE_SIZE: u64 = 0x1000;
+
+/// Writes the assembled sections into a static ELF executable.
+///
+/// The sections are loaded in (at most) three segments, in this order:
+/// - the code and the read-only data, which are mapped with the headers of the file at the base
+///   address (readable and executable);
+/// - the initialized data (readable and writable);
+/// - the `.bss` sections, which don't take any space in the file (readable and writable).
+///
+/// The references to the addresses of the symbols are resolved to their final addresses, so the
+/// code can't refer to external symbols (see [`RasError::UnresolvedRelocations`]).
+#[derive(Debug, Clone)]
+pub struct ExecutableWriter {
+    base_address: u64,
+    entry: SymbolId,
+}
+
+impl Default for ExecutableWriter {
+    fn default() -> Self {
+        Self {
+            base_address: 0x400000,
+            entry: "_start".into(),
+        }
+    }
+}
+
+/// A section, laid out in the executable.
+struct Placement {
+    /// The index of the section in the assembled sections.
+    section: usize,
+    offset: u64,
+    address: u64,
+    name: StringId,
+    index: SectionIndex,
+}
+
+impl ExecutableWriter {
+    /// Create a writer that loads the executable at 0x400000 (like `ld`), and starts executing
+    /// it at `_start`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The address of the first segment, which must be aligned to a page.
+    pub fn base_address(mut self, address: u64) -> Self {
+        self.base_address = address;
+        self
+    }
+
+    /// The symbol the execution starts at.
+    pub fn entry(mut self, symbol: impl Into<SymbolId>) -> Self {
+        self.entry = symbol.into();
+        self
+    }
+
+    /// Lay out the assembled sections, and return the contents of the executable.
+    ///
+    /// Long mode code is written into an ELF64 executable, and protected mode code into an
+    /// ELF32 one (there are no ELF executables for real mode).
+    pub fn write(&self, out: &Assembled) -> RasResult<Vec<u8>> {
+        let (is_64, machine) = match out.mode() {
+            Mode::Long => (true, elf::EM_X86_64),
+            Mode::Protected => (false, elf::EM_386),
+            Mode::Real => {
+                return Err(RasError::Encoding(
+                    "real mode code can't be written into an ELF executable".into(),
+                ));
+            }
+        };
+        if !self.base_address.is_multiple_of(PAGE_SIZE) {
+            return Err(RasError::Encoding(format!(
+                "the base address {:#x} isn't aligned to a page",
+                self.base_address
+            )));
+        }
+
+        let sections = out.sections();
+        let in_segment = |segment: usize| {
+            (0..sections.len()).filter(move |&index| {
+                let kind = sections[index].kind;
+                let nonempty = !sections[index].data.is_empty();
+                nonempty
+                    && match segment {
+                        0 => matches!(kind, SectionKind::Text | SectionKind::ReadOnlyData),
+                        1 => kind == SectionKind::Data,
+                        _ => kind == SectionKind::Bss,
+                    }
+            })
+        };
+        let segment_count = 1
+            + (1..3)
+                .filter(|&seg| in_segment(seg).next().is_some())
+                .count();
+
+        let mut buffer = vec![];
+        let mut writer = Writer::new(Endianness::Little, is_64, &mut buffer);
+        writer.reserve_file_header();
+        writer.reserve_program_headers(segment_count as u32);
+        writer.reserve_null_section_index();
+
+        // Lay out the sections: the offset of each segment in the file is congruent to its
+        // address modulo the page size, so the file can be mapped page by page.
+        let mut placements = vec![];
+        let mut segments = vec![];
+        let mut end_address = self.base_address;
+        for segment in 0..3 {
+            let start = if segment == 0 {
+                0
+            } else {
+                writer.reserved_len() as u64
+            };
+            let start_address = if segment == 0 {
+                self.base_address
+            } else {
+                align_up(end_address, PAGE_SIZE) + start % PAGE_SIZE
+            };
+
+            let mut file_end = writer.reserved_len() as u64;
+            end_address = start_address + writer.reserved_len() as u64 - start;
+            for index in in_segment(segment) {
+                let section = &sections[index];
+                let address = align_up(end_address, section.align);
+                // The .bss sections don't take any space in the file.
+                let offset = if section.kind == SectionKind::Bss {
+                    start
+                } else {
+                    writer.reserve_until((start + address - start_address) as usize);
+                    let offset = writer.reserve(section.data.len(), 1) as u64;
+                    file_end = offset + section.data.len() as u64;
+                    offset
+                };
+                end_address = address + section.data.len() as u64;
+
+                placements.push(Placement {
+                    section: index,
+                    offset,
+                    address,
+                    name: writer.add_section_name(section.name.as_bytes()),
+                    index: writer.reserve_section_index(),
+                });
+            }
+
+            if segment == 0 || end_address > start_address {
+                let flags = if segment == 0 {
+                    elf::PF_R | elf::PF_X
+                } else {
+                    elf::PF_R | elf::PF_W
+                };
+                segments.push(ProgramHeader {
+                    p_type: elf::PT_LOAD,
+                    p_flags: flags,
+                    p_offset: start,
+                    p_vaddr: start_address,
+                    p_paddr: start_address,
+                    p_filesz: file_end - start,
+                    p_memsz: end_address - start_address,
+                    p_align: PAGE_SIZE,
+                });
+            }
+        }
+        if !is_64 && end_address > u64::from(u32::MAX) {
+            return Err(RasError::Encoding(format!(
+                "the end address {:#x} of the executable doesn't fit in 32 bits",
+                end_address
+            )));
+        }
+
+        let mut addresses = vec![0; sections.len()];
+        for placement in &placements {
+            addresses[placement.section] = placement.address;
+        }
+        let data = out.relocate(&addresses)?;
+        let entry = out
+            .symbol(&self.entry)
+            .map(|sym| addresses[sym.section] + sym.offset)
+            .ok_or_else(|| RasError::UndefinedSymbols(vec![self.entry.clone()]))?;
+
+        // The local symbols must come before the others in the symbol table. The symbols of the
+        // sections that aren't loaded (the empty ones) are left out.
+        let mut symbols = out
+            .symbols()
+            .iter()
+            .filter_map(|sym| {
+                let placement = placements.iter().find(|p| p.section == sym.section)?;
+                Some((sym, placement.index, addresses[sym.section] + sym.offset))
+            })
+            .collect::<Vec<_>>();
+        symbols.sort_by_key(|(sym, _, _)| sym.binding != SymbolBinding::Local);
+        let local_count = symbols
+            .iter()
+            .filter(|(sym, _, _)| sym.binding == SymbolBinding::Local)
+            .count();
+
+        writer.reserve_null_symbol_index();
+        let symbol_names = symbols
+            .iter()
+            .map(|(sym, section, _)| {
+                let name = writer.add_string(sym.name.as_bytes());
+                writer.reserve_symbol_index(Some(*section));
+                name
+            })
+            .collect::<Vec<_>>();
+        writer.reserve_symtab_section_index();
+        writer.reserve_strtab_section_index();
+        writer.reserve_shstrtab_section_index();
+        writer.reserve_symtab();
+        writer.reserve_strtab();
+        writer.reserve_shstrtab();
+        writer.reserve_section_headers();
+
+        writer.write_file_header(&FileHeader {
+            os_abi: elf::ELFOSABI_NONE,
+            abi_version: 0,
+            e_type: elf::ET_EXEC,
+            e_machine: machine,
+            e_entry: entry,
+            e_flags: 0,
+        })?;
+        writer.write_align_program_headers();
+        for segment in &segments {
+            writer.write_program_header(segment);
+        }
+        for placement in &placements {
+            if sections[placement.section].kind != SectionKind::Bss {
+                writer.pad_until(placement.offset as usize);
+                writer.write(&data[placement.section]);
+            }
+        }
+
+        writer.write_null_symbol();
+        for ((sym, section, address), name) in symbols.iter().zip(symbol_names) {
+            let bind = match sym.binding {
+                SymbolBinding::Local => elf::STB_LOCAL,
+                SymbolBinding::Global => elf::STB_GLOBAL,
+                SymbolBinding::Weak => elf::STB_WEAK,
+            };
+            writer.write_symbol(&Sym {
+                name: Some(name),
+                section: Some(*section),
+                st_info: (bind << 4) | elf::STT_NOTYPE,
+                st_other: elf::STV_DEFAULT,
+                st_shndx: 0,
+                st_value: *address,
+                st_size: 0,
+            });
+        }
+        writer.write_strtab();
+        writer.write_shstrtab();
+
+        writer.write_null_section_header();
+        for placement in &placements {
+            let section = &sections[placement.section];
+            let (sh_type, sh_flags) = match section.kind {
+                SectionKind::Text => (elf::SHT_PROGBITS, elf::SHF_ALLOC | elf::SHF_EXECINSTR),
+                SectionKind::ReadOnlyData => (elf::SHT_PROGBITS, elf::SHF_ALLOC),
+                SectionKind::Data => (elf::SHT_PROGBITS, elf::SHF_ALLOC | elf::SHF_WRITE),
+                SectionKind::Bss => (elf::SHT_NOBITS, elf::SHF_ALLOC | elf::SHF_WRITE),
+            };
+            writer.write_section_header(&SectionHeader {
+                name: Some(placement.name),
+                sh_type,
+                sh_flags: sh_flags.into(),
+                sh_addr: placement.address,
+                sh_offset: placement.offset,
+                sh_size: section.data.len() as u64,
+                sh_link: 0,
+                sh_info: 0,
+                sh_addralign: section.align,
+                sh_entsize: 0,
+            });
+        }
+        writer.write_symtab_section_header(local_count as u32 + 1);
+        writer.write_strtab_section_header();
+        writer.write_shstrtab_section_header();
+
+        Ok(buffer)
+    }
+}
@@ -0,0 +1,412 @@
+//! An assembler that encodes one instruction at a time.
+//!
+//! Unlike [`Assembler`](crate::assembler::Assembler), which assembles a list of items all at
+//! once, an [`IncrementalAssembler`] encodes each instruction as soon as it's emitted, and can
+//! keep emitting instructions after the code was finalized:
+//!
+//! ```
+//! use ras_x86::incremental::IncrementalAssembler;
+//! use ras_x86::instruction::Instruction;
+//! use ras_x86::operand::Operand;
+//! use ras_x86::{Mnemonic, ECX};
+//!
+//! let mut asm = IncrementalAssembler::long_mode();
+//! let top = asm.new_label();
+//! asm.bind(top).unwrap();
+//! asm.emit(Instruction::new(Mnemonic::DEC, vec![Operand::Register(*ECX)])).unwrap();
+//! asm.emit(Instruction::new(Mnemonic::JNE, vec![top.into()])).unwrap();
+//!
+//! let code = asm.finalize().unwrap();
+//! assert_eq!(code, [0xff, 0xc9, 0x0f, 0x85, 0xf8, 0xff, 0xff, 0xff]);
+//! ```
+
+use crate::encoder::Encoder;
+use crate::instruction::Instruction;
+use crate::operand::{Memory, MemoryRel, Operand};
+use crate::patch::{self, PatchPoint};
+use crate::symbol::SymbolOffset;
+use crate::{CpuFeatures, Mnemonic, Mode, RasError, RasResult};
+
+use std::fmt;
+use std::slice;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+/// The ID of the next [`IncrementalAssembler`] (which tags the labels it allocates).
+static NEXT_ASSEMBLER_ID: AtomicU32 = AtomicU32::new(0);
+
+/// A label allocated by an [`IncrementalAssembler`], which can be bound to an offset later.
+///
+/// A label can be used as the operand of an instruction (e.g. the target of a jump) before it's
+/// bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Label {
+    /// The ID of the assembler that allocated the label.
+    assembler: u32,
+    /// The number of the label in its assembler.
+    index: u32,
+}
+
+impl fmt::Display for Label {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "label {}", self.index)
+    }
+}
+
+impl From<Label> for Operand {
+    fn from(label: Label) -> Operand {
+        Operand::Memory(Memory::Relative(MemoryRel::Local(label)))
+    }
+}
+
+/// An assembler that encodes instructions as they're emitted.
+pub struct IncrementalAssembler {
+    encoder: Encoder,
+    /// The ID of the assembler, which tells its labels apart from the labels of the other
+    /// assemblers.
+    id: u32,
+    /// The offsets the labels are bound to (indexed by the number of the label).
+    labels: Vec<Option<SymbolOffset>>,
+}
+
+impl IncrementalAssembler {
+    pub fn long_mode() -> Self {
+        Self::new(Mode::Long)
+    }
+
+    pub fn protected_mode() -> Self {
+        Self::new(Mode::Protected)
+    }
+
+    pub fn real_mode() -> Self {
+        Self::new(Mode::Real)
+    }
+
+    fn new(mode: Mode) -> Self {
+        Self {
+            encoder: Encoder::new(mode),
+            id: NEXT_ASSEMBLER_ID.fetch_add(1, Ordering::Relaxed),
+            labels: Default::default(),
+        }
+    }
+
+    /// Only allow the instructions from the specified CPU features (all the known features are
+    /// enabled by default).
+    pub fn cpu_features(mut self, features: CpuFeatures) -> Self {
+        self.encoder.features = features;
+        self
+    }
+
+    /// Allocate a new (unbound) label.
+    pub fn new_label(&mut self) -> Label {
+        self.labels.push(None);
+        Label {
+            assembler: self.id,
+            index: self.labels.len() as u32 - 1,
+        }
+    }
+
+    /// Bind the label to the current offset, and patch the instructions that already referred to
+    /// it.
+    ///
+    /// Binding a label twice, or a label allocated by another assembler, is an error.
+    pub fn bind(&mut self, label: Label) -> RasResult<()> {
+        self.check_label(label)?;
+        let offset = self.offset();
+        let binding = &mut self.labels[label.index as usize];
+        if binding.is_some() {
+            return Err(RasError::DuplicateLabel(label.to_string()));
+        }
+
+        *binding = Some(offset);
+        self.encoder.resolve_label(label, offset as i64)
+    }
+
+    /// The offset the label is bound to, if it's bound (and was allocated by this assembler).
+    pub fn label_offset(&self, label: Label) -> Option<SymbolOffset> {
+        if label.assembler != self.id {
+            return None;
+        }
+
+        self.labels[label.index as usize]
+    }
+
+    /// Returns an error if the label was allocated by another assembler.
+    fn check_label(&self, label: Label) -> RasResult<()> {
+        if label.assembler != self.id {
+            return Err(RasError::UndefinedSymbols(vec![label.to_string()]));
+        }
+
+        Ok(())
+    }
+
+    /// The offset of the next instruction.
+    pub fn offset(&self) -> SymbolOffset {
+        self.encoder.current_offset()
+    }
+
+    /// Encode an instruction.
+    ///
+    /// If the instruction refers to labels that are already bound, their offsets are patched
+    /// immediately. The references to the other labels are patched when they're bound.
+    pub fn emit(&mut self, inst: Instruction) -> RasResult<()> {
+        self.encode(&inst).map(|_| ())
+    }
+
+    /// Encode an instruction, and return its [`PatchPoint`] (so it can be rewritten later).
+    ///
+    /// The instruction is preceded by NOPs if needed, so that it fits in an 8-byte word (or, if
+    /// it's longer than 8 bytes, so that its relative target does), and can be patched
+    /// atomically. The words are aligned relative to the start of the code, so the code must be
+    /// patched at an 8-byte aligned address.
+    ///
+    /// Returns an error if the instruction is longer than 8 bytes and doesn't have a relative
+    /// target (it can't be patched atomically).
+    pub fn emit_patchable(&mut self, inst: Instruction) -> RasResult<PatchPoint> {
+        let mut scratch = Encoder::new(self.encoder.mode);
+        scratch.features = self.encoder.features;
+        inst.encode(&mut scratch)?;
+        let len = scratch.out.len();
+        let padding = match scratch.fixup_in(0..len as SymbolOffset) {
+            _ if len <= patch::WORD_SIZE => patch::padding(self.offset(), 0, len),
+            Some(fixup) => {
+                patch::padding(self.offset(), fixup.offset as usize, fixup.size as usize)
+            }
+            None => {
+                return Err(RasError::Encoding(format!(
+                    "a {}-byte instruction without a relative target can't be patched atomically",
+                    len
+                )))
+            }
+        };
+        self.emit_padding(padding)?;
+
+        self.encode(&inst)
+    }
+
+    /// Emit a NOP of the specified length (between 1 and 8 bytes), which can be replaced with
+    /// another instruction of the same length later (e.g. a 5-byte NOP can be replaced with a
+    /// `jmp rel32`).
+    pub fn emit_nop_slot(&mut self, len: usize) -> RasResult<PatchPoint> {
+        if len > patch::WORD_SIZE {
+            return Err(RasError::Encoding(format!(
+                "a {}-byte NOP can't be patched atomically",
+                len
+            )));
+        }
+
+        let nop = patch::nop(self.encoder.mode, len).ok_or_else(|| {
+            RasError::Encoding(format!(
+                "there is no {}-byte NOP in {:?} mode",
+                len, self.encoder.mode
+            ))
+        })?;
+        self.emit_patchable(nop)
+    }
+
+    /// Emit `len` bytes of NOPs.
+    fn emit_padding(&mut self, len: usize) -> RasResult<()> {
+        match patch::nop(self.encoder.mode, len) {
+            Some(nop) => self.emit(nop),
+            None => (0..len).try_for_each(|_| self.emit(Instruction::new(Mnemonic::NOP, vec![]))),
+        }
+    }
+
+    /// Encode an instruction, and return its [`PatchPoint`].
+    fn encode(&mut self, inst: &Instruction) -> RasResult<PatchPoint> {
+        let labels = inst
+            .operands()
+            .iter()
+            .filter_map(|operand| match operand {
+                Operand::Memory(Memory::Relative(MemoryRel::Local(label))) => Some(*label),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        labels
+            .iter()
+            .try_for_each(|label| self.check_label(*label))?;
+
+        let offset = self.offset();
+        inst.encode(&mut self.encoder)?;
+        // Look for the relative offset of the target before it's resolved.
+        let target = self.encoder.fixup_in(offset..self.offset());
+
+        for label in labels {
+            if let Some(offset) = self.label_offset(label) {
+                self.encoder.resolve_label(label, offset as i64)?;
+            }
+        }
+
+        Ok(PatchPoint {
+            offset,
+            len: (self.offset() - offset) as usize,
+            target,
+        })
+    }
+
+    /// The code encoded so far (the references to unbound labels aren't patched yet).
+    pub fn code(&self) -> &[u8] {
+        &self.encoder.out
+    }
+
+    /// Patch the code encoded so far (e.g. with [`PatchPoint::rewrite`]).
+    ///
+    /// The code passed to `patch` is a copy which starts at an 8-byte aligned address (like the
+    /// words the patch points are aligned to), and is padded to a multiple of 8 bytes. The
+    /// patched code is copied back afterwards (even if `patch` fails).
+    pub fn patch<T>(&mut self, patch: impl FnOnce(&mut [u8]) -> RasResult<T>) -> RasResult<T> {
+        let len = self.encoder.out.len();
+        let mut words = vec![0u64; len.div_ceil(patch::WORD_SIZE)];
+        // SAFETY: the words are at least `len` bytes long (rounded up to a multiple of 8), and
+        // they aren't used while `code` is alive.
+        let code = unsafe {
+            slice::from_raw_parts_mut(
+                words.as_mut_ptr() as *mut u8,
+                words.len() * patch::WORD_SIZE,
+            )
+        };
+        code[..len].copy_from_slice(&self.encoder.out);
+
+        let res = patch(code);
+        self.encoder.out.copy_from_slice(&code[..len]);
+        res
+    }
+
+    /// Check that all the referenced labels are bound, and return the code encoded so far.
+    ///
+    /// More instructions can be emitted after the code is finalized (and they can refer to the
+    /// labels that were bound before).
+    pub fn finalize(&mut self) -> RasResult<&[u8]> {
+        let unresolved = self
+            .encoder
+            .unresolved_labels()
+            .iter()
+            .map(Label::to_string)
+            .chain(self.encoder.unresolved_symbols())
+            .collect::<Vec<_>>();
+        if !unresolved.is_empty() {
+            return Err(RasError::UndefinedSymbols(unresolved));
+        }
+
+        Ok(&self.encoder.out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IncrementalAssembler;
+    use crate::error::RasError;
+    use crate::instruction::Instruction;
+    use crate::operand::{Immediate, Operand};
+    use crate::{label, Mnemonic, EAX, ECX};
+
+    fn jmp(target: impl Into<Operand>) -> Instruction {
+        Instruction::new(Mnemonic::JMP, vec![target.into()])
+    }
+
+    fn inc_eax() -> Instruction {
+        Instruction::new(Mnemonic::INC, vec![Operand::Register(*EAX)])
+    }
+
+    #[test]
+    fn forward_and_backward_references() {
+        let mut asm = IncrementalAssembler::long_mode();
+        let top = asm.new_label();
+        let end = asm.new_label();
+
+        asm.bind(top).unwrap();
+        asm.emit(inc_eax()).unwrap();
+        asm.emit(jmp(end)).unwrap();
+        assert_eq!(asm.offset(), 7);
+        // The forward reference isn't patched until the label is bound.
+        assert_eq!(asm.code()[3..], [0, 0, 0, 0]);
+        asm.emit(jmp(top)).unwrap();
+        asm.bind(end).unwrap();
+
+        assert_eq!(asm.label_offset(top), Some(0));
+        assert_eq!(asm.label_offset(end), Some(12));
+        assert_eq!(
+            asm.finalize().unwrap(),
+            [
+                0xff, 0xc0, // inc %eax
+                0xe9, 0x05, 0x00, 0x00, 0x00, // jmp end
+                0xe9, 0xf4, 0xff, 0xff, 0xff, // jmp top
+            ]
+        );
+    }
+
+    #[test]
+    fn append_after_finalize() {
+        let mut asm = IncrementalAssembler::long_mode();
+        let top = asm.new_label();
+        asm.bind(top).unwrap();
+        asm.emit(inc_eax()).unwrap();
+        let len = asm.finalize().unwrap().len();
+
+        asm.emit(Instruction::new(
+            Mnemonic::MOV,
+            vec![
+                Operand::Register(*ECX),
+                Operand::Immediate(Immediate::Imm32(1)),
+            ],
+        ))
+        .unwrap();
+        // The label was bound before the code was finalized.
+        asm.emit(jmp(top)).unwrap();
+
+        let code = asm.finalize().unwrap();
+        assert_eq!(
+            code[len..],
+            [
+                0xb9, 0x01, 0x00, 0x00, 0x00, // mov $1, %ecx
+                0xe9, 0xf4, 0xff, 0xff, 0xff, // jmp top
+            ]
+        );
+    }
+
+    #[test]
+    fn label_errors() {
+        let mut asm = IncrementalAssembler::long_mode();
+        let top = asm.new_label();
+        asm.bind(top).unwrap();
+        assert_eq!(
+            asm.bind(top),
+            Err(RasError::DuplicateLabel("label 0".into()))
+        );
+
+        // A label allocated by another assembler.
+        let foreign = {
+            let mut other = IncrementalAssembler::long_mode();
+            other.new_label();
+            other.new_label()
+        };
+        assert_eq!(asm.label_offset(foreign), None);
+        assert_eq!(
+            asm.bind(foreign),
+            Err(RasError::UndefinedSymbols(vec!["label 1".into()]))
+        );
+
+        // A foreign label with the same number as a label of this assembler.
+        let foreign = IncrementalAssembler::long_mode().new_label();
+        assert_eq!(asm.label_offset(foreign), None);
+        assert_eq!(
+            asm.bind(foreign),
+            Err(RasError::UndefinedSymbols(vec!["label 0".into()]))
+        );
+        assert_eq!(
+            asm.emit(jmp(foreign)),
+            Err(RasError::UndefinedSymbols(vec!["label 0".into()]))
+        );
+        assert_eq!(asm.offset(), 0);
+
+        let end = asm.new_label();
+        asm.emit(jmp(end)).unwrap();
+        asm.emit(jmp(label!("foo".into()))).unwrap();
+        assert_eq!(
+            asm.finalize(),
+            Err(RasError::UndefinedSymbols(vec![
+                "label 1".into(),
+                "foo".into()
+            ]))
+        );
+    }
+}
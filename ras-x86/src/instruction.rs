@@ -1,63 +1,181 @@
-use crate::assembler::SymbolTable;
 use crate::encoder::Encoder;
 use crate::mnemonic::Mnemonic;
-use crate::operand::Operand;
+use crate::operand::{Decorators, Memory, Operand};
 use crate::repr::instruction::InstructionRepr;
+use crate::repr::operand::{OperandKind, OperandRepr};
 use crate::{RasError, RasResult};
-use std::str::FromStr;
 
-use lazy_static::lazy_static;
-use std::collections::HashMap;
-use std::fs;
-use std::path::Path;
+pub use inst_table::{instruction_reprs, instruction_table};
+pub use pseudo_prefix::{PseudoPrefix, PseudoPrefixes};
 
-const MAX_OPERAND_COUNT: usize = 4;
-const INST_MAP: &str = "inst_map.json";
+mod pseudo_prefix;
 
-lazy_static! {
-    pub static ref INSTR_REPRS: HashMap<Mnemonic, Vec<InstructionRepr>> = {
-        let inst_map = fs::read(Path::new(env!("CARGO_MANIFEST_DIR")).join(INST_MAP)).unwrap();
-        let map: Vec<(String, Vec<InstructionRepr>)> = serde_json::from_slice(&inst_map).unwrap();
+const MAX_OPERAND_COUNT: usize = 4;
 
-        map.into_iter()
-            .map(|(mnemonic, repr)| (Mnemonic::from_str(&mnemonic).unwrap(), repr))
-            .collect()
-    };
+/// The instruction reprs, generated from the x86-csv by the build script.
+mod inst_table {
+    include!(concat!(env!("OUT_DIR"), "/inst_table.rs"));
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct Instruction {
     mnemonic: Mnemonic,
     operands: Vec<Operand>,
+    decorators: Decorators,
+    /// The size of the memory operand (in bits), if it was specified explicitly.
+    memory_size: Option<u32>,
+    /// The pseudo-prefixes that request a specific encoding (e.g. `{load}` or `{disp32}`).
+    pseudo_prefixes: PseudoPrefixes,
 }
 
 impl Instruction {
     pub fn new(mnemonic: Mnemonic, operands: Vec<Operand>) -> Self {
         assert!(operands.len() <= MAX_OPERAND_COUNT);
 
-        Self { mnemonic, operands }
+        Self {
+            mnemonic,
+            operands,
+            decorators: Default::default(),
+            memory_size: None,
+            pseudo_prefixes: Default::default(),
+        }
+    }
+
+    pub fn mnemonic(&self) -> Mnemonic {
+        self.mnemonic
+    }
+
+    /// The operands of the instruction, in Intel order (the destination first).
+    pub fn operands(&self) -> &[Operand] {
+        &self.operands
+    }
+
+    pub fn operands_mut(&mut self) -> &mut [Operand] {
+        &mut self.operands
+    }
+
+    /// Add AVX-512 decorators (masking, broadcast or rounding control) to the instruction.
+    pub fn with_decorators(mut self, decorators: Decorators) -> Self {
+        self.decorators = decorators;
+        self
+    }
+
+    /// Specify the size of the memory operand (in bits).
+    ///
+    /// This is needed to disambiguate between instructions that only differ in the size of their
+    /// memory operand (e.g. `FLD m32fp` and `FLD m64fp`). If the size isn't specified, the
+    /// smallest memory operand is used.
+    pub fn with_memory_size(mut self, size: u32) -> Self {
+        self.memory_size = Some(size);
+        self
+    }
+
+    /// Request a specific encoding of the instruction using pseudo-prefixes (e.g. `{load}` or
+    /// `{disp32}`), instead of the shortest one.
+    pub fn with_pseudo_prefixes(mut self, pseudo_prefixes: PseudoPrefixes) -> Self {
+        self.pseudo_prefixes = pseudo_prefixes;
+        self
     }
 
-    pub(crate) fn encode(&self, enc: &mut Encoder, _sym_tab: &SymbolTable) -> RasResult<()> {
-        let variants = (*INSTR_REPRS).get(&self.mnemonic).unwrap();
+    pub(crate) fn encode(&self, enc: &mut Encoder) -> RasResult<()> {
+        let variants = instruction_reprs(self.mnemonic);
 
         // Find the best instruction encoding (always choose the encoding with the smallest operand
         // sizes).
-        let mut instructions = variants
+        let mut candidates = variants
             .iter()
             .filter(|variant| enc.is_encodable(variant) && self.encodable_with(variant))
             .collect::<Vec<_>>();
 
-        // Sort the instructions by their estimated encoding length:
-        instructions.sort();
+        // If none of the operands determines the operand size (e.g. `push $128`), don't use an
+        // operand-size prefix just to encode a smaller immediate.
+        if candidates
+            .iter()
+            .any(|variant| self.has_default_operand_size(enc, variant))
+        {
+            candidates.retain(|variant| self.has_default_operand_size(enc, variant));
+        }
+
+        // Sort the instructions by their operand sizes, and then by their estimated encoding
+        // length:
+        candidates.sort_by_key(|variant| {
+            let operand_sizes = variant.operands.iter().map(|op| op.size()).sum::<u32>();
+            (operand_sizes, variant.encoding.estimated_len())
+        });
+
+        let instructions = candidates
+            .iter()
+            .filter(|variant| enc.has_features(variant))
+            .collect::<Vec<_>>();
+
+        // Pick the encoding that is actually the shortest (e.g. the sign-extended imm8 forms can
+        // be shorter than the accumulator forms, or the other way around, depending on the
+        // operands). If several encodings have the same length, the first one is used: the
+        // candidates are sorted by operand size, so like GNU as, this prefers the forms with the
+        // smallest immediate (e.g. `83 /6 ib` over `35 iw` for `xor $2, %ax`).
+        let mut shortest = None;
+        let mut first_err = None;
+        for variant in &instructions {
+            match enc.encoded_len(
+                variant,
+                &self.operands,
+                self.decorators,
+                self.pseudo_prefixes,
+                self.explicit_size(),
+            ) {
+                Ok(len) if shortest.is_none_or(|(_, shortest_len)| len < shortest_len) => {
+                    shortest = Some((variant, len));
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    first_err.get_or_insert(e);
+                }
+            }
+        }
+
+        // None of the encodings could be used for these operands.
+        if let (None, Some(e)) = (shortest, first_err) {
+            return Err(e);
+        }
+
+        let (shortest_repr, _) = shortest.ok_or_else(|| {
+            // If the operands can be encoded, but only by reprs that need features which aren't
+            // enabled, report the features required by the best of those reprs.
+            if let Some(candidate) = candidates.first() {
+                let missing = enc.features.missing(&candidate.features).collect();
+                return RasError::MissingCpuFeatures(self.mnemonic, missing);
+            }
 
-        // Pick the best encoding:
-        // TODO: use sym_tab to determine the operand size for relative offset operands
-        let shortest_repr = instructions
-            .first()
-            .ok_or(RasError::MissingInstructionRepr(self.mnemonic))?;
+            // If the operands can be encoded, but not by any of the reprs that are valid in the
+            // current mode, the instruction isn't available in this mode.
+            let has_repr_in_mode = variants
+                .iter()
+                .any(|variant| variant.is_valid_in_mode(&enc.mode) && self.encodable_with(variant));
+            if !has_repr_in_mode && variants.iter().any(|variant| self.encodable_with(variant)) {
+                RasError::InvalidMode(self.mnemonic, enc.mode)
+            } else {
+                RasError::MissingInstructionRepr(self.mnemonic)
+            }
+        })?;
 
-        enc.encode(shortest_repr, &self.operands)
+        enc.encode(
+            shortest_repr,
+            &self.operands,
+            self.decorators,
+            self.pseudo_prefixes,
+            self.explicit_size(),
+        )
+    }
+
+    /// Returns the operand size (in bits) that was specified explicitly, if any: the size of the
+    /// memory operand, or the operand size implied by the mnemonic of an instruction without
+    /// operands (e.g. `cbw`).
+    fn explicit_size(&self) -> Option<u32> {
+        self.memory_size.or_else(|| {
+            self.mnemonic
+                .implied_operand_size()
+                .filter(|_| self.operands.is_empty())
+        })
     }
 
     /// Check if the operands of this instruction can be encoded by the specified `InstructionRepr`.
@@ -69,14 +187,95 @@ impl Instruction {
         self.operands
             .iter()
             .zip(repr.operands.iter())
-            .all(|(op, op_enc)| op.can_encode(op_enc))
+            .all(|(op, op_enc)| {
+                (op.can_encode(op_enc)
+                    || Self::is_unsigned_imm8(repr, op, op_enc)
+                    || self.is_rel8_target(op, op_enc))
+                    && self.has_memory_size(op, op_enc.size())
+            })
+            && self.is_evex_compatible(repr)
+    }
+
+    /// Check if `op` is an immediate that can be encoded as the unsigned imm8 operand `op_enc`.
+    ///
+    /// Like GNU as, the imm8 operands accept any value that fits in a byte (e.g. `add $128, %al`
+    /// or `shl $255, %eax`), unless the CPU sign-extends them to the operand size (e.g.
+    /// `add $128, %eax` can't be encoded as `83 /0 ib`).
+    fn is_unsigned_imm8(repr: &InstructionRepr, op: &Operand, op_enc: &OperandRepr) -> bool {
+        op_enc.kind == OperandKind::Imm
+            && op_enc.size() == 8
+            && !repr.encoding.has_sign_extended_imm8()
+            && op
+                .immediate()
+                .is_some_and(|imm| imm.to_unsigned_imm8().is_some())
+    }
+
+    /// Check if `op` is a branch target that can be encoded as the rel8 operand `op_enc`.
+    ///
+    /// The offset of a label isn't known until it's bound, so the branches use a rel32 operand,
+    /// unless `{disp8}` requests a rel8 operand (the offset is range-checked when the label is
+    /// resolved).
+    fn is_rel8_target(&self, op: &Operand, op_enc: &OperandRepr) -> bool {
+        op_enc.kind == OperandKind::Rel8
+            && self.pseudo_prefixes.displacement() == Some(PseudoPrefix::Disp8)
+            && op.memory().is_some_and(Memory::is_relative)
+    }
+
+    /// Check if `repr` uses the default operand size of the current mode, unless the operand
+    /// size is determined by a register operand, or by the explicitly specified memory size.
+    ///
+    /// The size of an immediate operand doesn't determine the operand size: like GNU as,
+    /// `push $128` uses `PUSH imm32` rather than `PUSH imm16` (with an operand-size prefix).
+    fn has_default_operand_size(&self, enc: &Encoder, repr: &InstructionRepr) -> bool {
+        if self.memory_size.is_some() || self.operands.iter().any(Operand::is_register) {
+            return true;
+        }
+
+        repr.encoding.has_fixed_operand_size()
+            || repr
+                .operands
+                .iter()
+                .filter(|op| op.kind == OperandKind::Imm && op.size() > 8)
+                .all(|op| op.size() == enc.default_operand_size())
+    }
+
+    /// Check if `op` has the explicitly specified memory size (if any), given the `size` of the
+    /// operand it's going to be encoded as.
+    fn has_memory_size(&self, op: &Operand, size: u32) -> bool {
+        match self.memory_size {
+            Some(memory_size) if op.is_memory() => memory_size == size,
+            _ => true,
+        }
+    }
+
+    /// Check if the instruction can be encoded with (or without) an EVEX prefix.
+    ///
+    /// The AVX-512 decorators and registers (ZMM0-ZMM31, XMM16-XMM31 and YMM16-YMM31) can only
+    /// be encoded by the EVEX-encoded instructions.
+    fn is_evex_compatible(&self, repr: &InstructionRepr) -> bool {
+        match repr.encoding.evex_prefix() {
+            Some(evex) => {
+                let has_memory = self.operands.iter().any(|op| op.memory().is_some());
+                self.decorators.can_encode(evex, has_memory)
+            }
+            None => {
+                self.decorators.is_empty()
+                    && !self
+                        .operands
+                        .iter()
+                        .any(|op| matches!(op, Operand::Register(reg) if reg.needs_evex()))
+            }
+        }
     }
 }
 
 #[cfg(test)]
 pub mod tests {
     use super::*;
-    use crate::{RAX, RBX, RCX};
+    use crate::operand::{FarPointer, Immediate, Memory, MemoryRel, Moffs, Register, Scale};
+    use crate::{AL, AX, CL, DX, EAX, EBX, RAX, RBX, RCX};
+
+    use std::panic;
 
     macro_rules! encode_instr {
         ($opcode:ident, $($operands:expr),*) => {{
@@ -85,7 +284,7 @@ pub mod tests {
             Instruction::new(
                 Mnemonic::$opcode,
                 vec![$($operands,)*]
-            ).encode(&mut enc, &Default::default()).unwrap();
+            ).encode(&mut enc).unwrap();
 
             enc.out
         }}
@@ -103,4 +302,100 @@ pub mod tests {
             vec![0x48, 0x01, 0xd8]
         );
     }
+
+    /// A sample of operands of every kind, used to exercise all the instruction reprs.
+    fn sample_operands() -> Vec<Operand> {
+        let sib = Memory::sib(None, Some(*RBX), None, Scale::Byte, Some(8));
+        let mut operands = vec![
+            Operand::Register(*AL),
+            Operand::Register(*CL),
+            Operand::Register(*AX),
+            Operand::Register(*DX),
+            Operand::Register(*EAX),
+            Operand::Register(*EBX),
+            Operand::Register(*RAX),
+            Operand::Register(*RCX),
+            Operand::Immediate(Immediate::Imm8(1)),
+            Operand::Immediate(Immediate::Imm16(0x100)),
+            Operand::Immediate(Immediate::Imm32(0x10000)),
+            Operand::Memory(sib.clone()),
+            Operand::Memory(Memory::relative(MemoryRel::Label("foo".into()))),
+            Operand::Memory(Memory::moffs(Moffs::Moffs32(0x1000))),
+            Operand::FarPointer(FarPointer::direct(0x10, 0x1000)),
+            Operand::FarPointer(FarPointer::indirect(sib)),
+        ];
+
+        for num in 0..8 {
+            operands.extend(
+                [
+                    Register::Xmm(num),
+                    Register::Ymm(num),
+                    Register::Zmm(num),
+                    Register::Opmask(num),
+                    Register::St(num),
+                    Register::Mmx(num),
+                    Register::Cr(num),
+                    Register::Dr(num),
+                ]
+                .iter()
+                .map(|reg| Operand::Register(*reg)),
+            );
+        }
+        operands.push(Operand::Register(Register::Cr(8)));
+        operands.extend((0..6).map(|num| Operand::Register(Register::Segment(num))));
+
+        operands
+    }
+
+    /// Encode every instruction repr the operands of which can be picked from
+    /// `sample_operands`, in every mode in which it's valid. The encoding may fail, but it must
+    /// never panic.
+    #[test]
+    fn encode_every_instruction_repr() {
+        let samples = sample_operands();
+        let mut panicked = vec![];
+
+        for (mnemonic, reprs) in instruction_table() {
+            for repr in reprs.iter() {
+                let operands = repr
+                    .operands
+                    .iter()
+                    .map(|op_repr| {
+                        samples
+                            .iter()
+                            .find(|op| op.can_encode(op_repr) && op.is_exact_match(op_repr))
+                            .or_else(|| samples.iter().find(|op| op.can_encode(op_repr)))
+                            .cloned()
+                    })
+                    .collect::<Option<Vec<_>>>();
+
+                let operands = match operands {
+                    Some(operands) => operands,
+                    None => continue,
+                };
+
+                for mode in repr.modes.iter() {
+                    let result = panic::catch_unwind(|| {
+                        Encoder::new(*mode).encode(
+                            repr,
+                            &operands,
+                            Decorators::default(),
+                            PseudoPrefixes::default(),
+                            None,
+                        )
+                    });
+
+                    if result.is_err() {
+                        panicked.push(format!("{:?} {:?} ({:?})", mnemonic, operands, mode));
+                    }
+                }
+            }
+        }
+
+        assert!(
+            panicked.is_empty(),
+            "failed to encode:\n{}",
+            panicked.join("\n")
+        );
+    }
 }
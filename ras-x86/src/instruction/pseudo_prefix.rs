@@ -0,0 +1,143 @@
+use crate::error::{ParseError, ParseErrorKind};
+
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+/// A pseudo-prefix (e.g. `{load}` or `{disp32}`), which requests a specific encoding of an
+/// instruction instead of the shortest one.
+///
+/// Unlike GNU as, which ignores the pseudo-prefixes it can't honor, an instruction that can't be
+/// encoded as requested is an error.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PseudoPrefix {
+    /// Use the encoding where the destination operand is in ModRM.reg (e.g. `8B /r` instead of
+    /// `89 /r` for `mov %eax, %ecx`).
+    Load,
+    /// Use the encoding where the destination operand is in ModRM.rm.
+    Store,
+    /// Use an 8-bit displacement (or relative offset), even if the displacement is 0.
+    Disp8,
+    /// Use a 32-bit displacement (or relative offset), even if a shorter one would do.
+    Disp32,
+    /// Use a VEX-encoded instruction.
+    Vex,
+    /// Use an EVEX-encoded instruction.
+    Evex,
+    /// Emit a REX prefix, even if the instruction doesn't need one.
+    Rex,
+}
+
+impl PseudoPrefix {
+    pub const ALL: &'static [PseudoPrefix] = &[
+        PseudoPrefix::Load,
+        PseudoPrefix::Store,
+        PseudoPrefix::Disp8,
+        PseudoPrefix::Disp32,
+        PseudoPrefix::Vex,
+        PseudoPrefix::Evex,
+        PseudoPrefix::Rex,
+    ];
+
+    /// The name of the pseudo-prefix, without the braces.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PseudoPrefix::Load => "load",
+            PseudoPrefix::Store => "store",
+            PseudoPrefix::Disp8 => "disp8",
+            PseudoPrefix::Disp32 => "disp32",
+            PseudoPrefix::Vex => "vex",
+            PseudoPrefix::Evex => "evex",
+            PseudoPrefix::Rex => "rex",
+        }
+    }
+}
+
+impl Display for PseudoPrefix {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{{{}}}", self.name())
+    }
+}
+
+impl FromStr for PseudoPrefix {
+    type Err = ParseError;
+
+    /// Parse the name of a pseudo-prefix (without the braces).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PseudoPrefix::ALL
+            .iter()
+            .find(|prefix| prefix.name().eq_ignore_ascii_case(s))
+            .copied()
+            .ok_or_else(|| ParseError::new(ParseErrorKind::InvalidPseudoPrefix(s.into())))
+    }
+}
+
+/// The pseudo-prefixes of an instruction.
+///
+/// An instruction can have at most one pseudo-prefix of each kind: `{load}` or `{store}`,
+/// `{disp8}` or `{disp32}`, `{vex}` or `{evex}`, and `{rex}`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct PseudoPrefixes {
+    direction: Option<PseudoPrefix>,
+    displacement: Option<PseudoPrefix>,
+    encoding: Option<PseudoPrefix>,
+    rex: bool,
+}
+
+impl PseudoPrefixes {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Add a pseudo-prefix.
+    ///
+    /// If a different pseudo-prefix of the same kind was already added (e.g. `{store}` when
+    /// adding `{load}`), it's returned as an error.
+    pub fn insert(&mut self, prefix: PseudoPrefix) -> Result<(), PseudoPrefix> {
+        let slot = match prefix {
+            PseudoPrefix::Load | PseudoPrefix::Store => &mut self.direction,
+            PseudoPrefix::Disp8 | PseudoPrefix::Disp32 => &mut self.displacement,
+            PseudoPrefix::Vex | PseudoPrefix::Evex => &mut self.encoding,
+            PseudoPrefix::Rex => {
+                self.rex = true;
+                return Ok(());
+            }
+        };
+
+        match slot {
+            Some(other) if *other != prefix => Err(*other),
+            _ => {
+                *slot = Some(prefix);
+                Ok(())
+            }
+        }
+    }
+
+    /// `{load}` or `{store}`, if specified.
+    pub fn direction(&self) -> Option<PseudoPrefix> {
+        self.direction
+    }
+
+    /// `{disp8}` or `{disp32}`, if specified.
+    pub fn displacement(&self) -> Option<PseudoPrefix> {
+        self.displacement
+    }
+
+    /// `{vex}` or `{evex}`, if specified.
+    pub fn encoding(&self) -> Option<PseudoPrefix> {
+        self.encoding
+    }
+
+    /// Whether `{rex}` was specified.
+    pub fn rex(&self) -> bool {
+        self.rex
+    }
+}
+
+impl From<PseudoPrefix> for PseudoPrefixes {
+    fn from(prefix: PseudoPrefix) -> Self {
+        let mut prefixes = Self::default();
+        // A single prefix can't conflict with anything.
+        let _ = prefixes.insert(prefix);
+        prefixes
+    }
+}
@@ -0,0 +1,384 @@
+//! Assemble instructions into executable memory, and run them in the current process.
+//!
+//! ```
+//! use ras_x86::jit::Jit;
+//! use ras_x86::parser::parse_asm;
+//!
+//! let items = parse_asm("add_one:\nlea 1(%rdi), %rax\nret").unwrap();
+//! let code = Jit::new().items(items).finalize().unwrap();
+//!
+//! let add_one: extern "C" fn(u64) -> u64 = unsafe { code.function("add_one") }.unwrap();
+//! assert_eq!(add_one(41), 42);
+//! ```
+
+use crate::assembler::{Assembler, Item};
+use crate::symbol::{Symbol, SymbolAttribute, SymbolId, SymbolOffset, SymbolType};
+use crate::{CpuFeatures, RasError, RasResult};
+
+use std::collections::HashMap;
+use std::{io, mem, ptr, slice};
+
+/// `jmp *0(%rip)`, which jumps to the absolute address that follows it.
+const ABSOLUTE_JMP: [u8; 6] = [0xff, 0x25, 0x00, 0x00, 0x00, 0x00];
+/// The size of a stub that jumps to an external symbol (the `jmp` and the address).
+const STUB_SIZE: usize = ABSOLUTE_JMP.len() + mem::size_of::<u64>();
+
+/// Assembles instructions (in long mode) into executable memory.
+///
+/// The references to external symbols are resolved to the addresses specified with
+/// [`Jit::external`]. If an external symbol is too far away from the code to be reached with a
+/// 32-bit relative offset, its references are redirected to a stub that jumps to its absolute
+/// address, so the external symbols that aren't within 2GB of the code can only be called or
+/// jumped to.
+pub struct Jit {
+    features: CpuFeatures,
+    items: Vec<Item>,
+    externals: Vec<(SymbolId, u64)>,
+}
+
+impl Jit {
+    pub fn new() -> Self {
+        Self {
+            features: CpuFeatures::all(),
+            items: Default::default(),
+            externals: Default::default(),
+        }
+    }
+
+    /// Only allow the instructions from the specified CPU features (all the known features are
+    /// enabled by default).
+    pub fn cpu_features(mut self, features: CpuFeatures) -> Self {
+        self.features = features;
+        self
+    }
+
+    pub fn items<I: Into<Item>>(mut self, items: Vec<I>) -> Self {
+        self.items.extend(items.into_iter().map(Into::into));
+        self
+    }
+
+    /// Resolve the references to the external symbol `name` to the specified absolute address.
+    pub fn external(mut self, name: impl Into<SymbolId>, addr: *const u8) -> Self {
+        self.externals.push((name.into(), addr as u64));
+        self
+    }
+
+    /// Assemble the instructions into executable memory.
+    pub fn finalize(self) -> RasResult<JitCode> {
+        let externals = self
+            .externals
+            .iter()
+            .map(|(name, _)| {
+                let sym = Symbol::new_decl(SymbolType::Quad, SymbolAttribute::Global as u8);
+                (name.clone(), sym)
+            })
+            .collect::<Vec<_>>();
+
+        let (mut enc, sym_tab) = Assembler::long_mode()
+            .cpu_features(self.features)
+            .items(self.items)
+            .symbols(&externals)
+            .assemble_parts()?;
+
+        let code_len = enc.out.len();
+        let mut mem = ExecutableMemory::new(code_len + self.externals.len() * STUB_SIZE)?;
+        let base = mem.as_ptr() as u64;
+
+        for (i, (name, addr)) in self.externals.iter().enumerate() {
+            // A label with the same name takes precedence.
+            if sym_tab
+                .get(name)
+                .map(Symbol::is_defined)
+                .unwrap_or_default()
+            {
+                continue;
+            }
+
+            enc.resolve_symbol_address(name, *addr)?;
+
+            let stub_offset = code_len + i * STUB_SIZE;
+            let direct = enc.resolve_symbol(name, addr.wrapping_sub(base) as i64);
+            if direct.is_err() {
+                enc.resolve_symbol(name, stub_offset as i64)?;
+            }
+
+            let stub = &mut mem.as_mut_slice()[stub_offset..stub_offset + STUB_SIZE];
+            stub[..ABSOLUTE_JMP.len()].copy_from_slice(&ABSOLUTE_JMP);
+            stub[ABSOLUTE_JMP.len()..].copy_from_slice(&addr.to_le_bytes());
+        }
+
+        let labels = sym_tab
+            .into_iter()
+            .filter_map(|(name, sym)| sym.offset.map(|offset| (name, offset)))
+            .collect::<HashMap<_, _>>();
+
+        // The addresses of the labels (e.g. `mov $label, %eax` or `.quad label`) are only known
+        // now that the code has been mapped.
+        for (name, offset) in &labels {
+            enc.resolve_symbol_address(name, base + offset)?;
+        }
+
+        mem.as_mut_slice()[..code_len].copy_from_slice(&enc.out);
+        mem.make_executable()?;
+
+        Ok(JitCode { mem, labels })
+    }
+}
+
+impl Default for Jit {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Executable code, along with the offsets of its labels.
+pub struct JitCode {
+    mem: ExecutableMemory,
+    labels: HashMap<SymbolId, SymbolOffset>,
+}
+
+impl JitCode {
+    /// The address of the first instruction.
+    pub fn as_ptr(&self) -> *const u8 {
+        self.mem.as_ptr()
+    }
+
+    /// The size of the code (including the stubs of the external symbols).
+    pub fn len(&self) -> usize {
+        self.mem.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mem.len == 0
+    }
+
+    pub fn label_address(&self, label: &str) -> Option<*const u8> {
+        let offset = *self.labels.get(label)?;
+        Some(self.as_ptr().wrapping_add(offset as usize))
+    }
+
+    /// Returns the code at the specified label as a function pointer of type `F` (e.g. `extern
+    /// "C" fn(u64) -> u64`).
+    ///
+    /// # Safety
+    ///
+    /// `F` must be a function pointer type that matches the code at the label, and it must not
+    /// be called after this `JitCode` is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `F` isn't pointer-sized.
+    pub unsafe fn function<F: Copy>(&self, label: &str) -> Option<F> {
+        assert_eq!(
+            mem::size_of::<F>(),
+            mem::size_of::<*const u8>(),
+            "the type of a function pointer must be pointer-sized"
+        );
+
+        let addr = self.label_address(label)?;
+        Some(mem::transmute_copy(&addr))
+    }
+
+    /// Patch the code in place (e.g. with [`PatchPoint::rewrite`](crate::patch::PatchPoint::rewrite)).
+    ///
+    /// The memory is made writable (and no longer executable) while `patch` runs, and executable
+    /// again afterwards, so it's never writable and executable at the same time. The other
+    /// threads must not run the code while it's being patched (they would fault). The code
+    /// passed to `patch` is padded to a multiple of 8 bytes.
+    pub fn patch<T>(&mut self, patch: impl FnOnce(&mut [u8]) -> RasResult<T>) -> RasResult<T> {
+        self.mem.protect(libc::PROT_READ | libc::PROT_WRITE)?;
+        let res = patch(self.mem.as_mut_words());
+        self.mem.make_executable()?;
+        res
+    }
+}
+
+/// An anonymous memory mapping, which is writable until it's made executable.
+struct ExecutableMemory {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl ExecutableMemory {
+    /// Map `len` bytes of readable and writable memory.
+    fn new(len: usize) -> RasResult<Self> {
+        // Mapping 0 bytes is an error, but the code can be empty.
+        let map_len = len.max(1);
+        // SAFETY: this creates a new private mapping, so no existing memory is affected.
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                map_len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+
+        if ptr == libc::MAP_FAILED {
+            return Err(RasError::Io(io::Error::last_os_error()));
+        }
+
+        Ok(Self {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+
+    fn as_ptr(&self) -> *const u8 {
+        self.ptr
+    }
+
+    fn as_mut_slice(&mut self) -> &mut [u8] {
+        // SAFETY: the mapping is at least `len` bytes long. This is only called while the memory
+        // is writable.
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+
+    /// The memory, padded to a multiple of 8 bytes (so that the end of the code can be patched
+    /// atomically too).
+    fn as_mut_words(&mut self) -> &mut [u8] {
+        // SAFETY: the mapping is page-aligned and made of whole pages, so it's at least `len`
+        // (rounded up to a multiple of 8) bytes long. This is only called while the memory is
+        // writable.
+        unsafe { slice::from_raw_parts_mut(self.ptr, (self.len + 7) & !7) }
+    }
+
+    /// Make the memory readable and executable (and no longer writable).
+    fn make_executable(&mut self) -> RasResult<()> {
+        self.protect(libc::PROT_READ | libc::PROT_EXEC)
+    }
+
+    /// Change the protection of the memory (`PROT_*`).
+    fn protect(&mut self, prot: libc::c_int) -> RasResult<()> {
+        // SAFETY: `ptr` is the (page-aligned) start of a mapping of at least `len` bytes.
+        let res = unsafe { libc::mprotect(self.ptr as *mut libc::c_void, self.len.max(1), prot) };
+
+        if res != 0 {
+            return Err(RasError::Io(io::Error::last_os_error()));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for ExecutableMemory {
+    fn drop(&mut self) {
+        // SAFETY: the mapping was created in `ExecutableMemory::new`, and it isn't used after
+        // this.
+        unsafe {
+            libc::munmap(self.ptr as *mut libc::c_void, self.len.max(1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Jit;
+    use crate::instruction::Instruction;
+    use crate::operand::{Immediate, Operand};
+    use crate::parser::parse_asm;
+    use crate::patch::PatchPoint;
+    use crate::{Mnemonic, Mode, EAX};
+
+    extern "C" fn double(x: u64) -> u64 {
+        x * 2
+    }
+
+    #[test]
+    fn call_labels() {
+        let items = parse_asm(
+            "
+            add:
+            lea (%rdi,%rsi), %rax
+            ret
+            max:
+            mov %rdi, %rax
+            cmp %rsi, %rdi
+            jae done
+            mov %rsi, %rax
+            done:
+            ret
+            ",
+        )
+        .unwrap();
+        let code = Jit::new().items(items).finalize().unwrap();
+
+        let add: extern "C" fn(u64, u64) -> u64 = unsafe { code.function("add") }.unwrap();
+        let max: extern "C" fn(u64, u64) -> u64 = unsafe { code.function("max") }.unwrap();
+        assert_eq!(add(2, 3), 5);
+        assert_eq!(max(2, 3), 3);
+        assert_eq!(max(7, 3), 7);
+        assert_eq!(code.label_address("add"), Some(code.as_ptr()));
+        assert!(code.label_address("min").is_none());
+    }
+
+    #[test]
+    fn external_symbols() {
+        let items = parse_asm(
+            "
+            quadruple:
+            sub $8, %rsp
+            call double
+            mov %rax, %rdi
+            add $8, %rsp
+            jmp double
+            ",
+        )
+        .unwrap();
+        let code = Jit::new()
+            .items(items)
+            .external("double", double as *const u8)
+            .finalize()
+            .unwrap();
+
+        let quadruple: extern "C" fn(u64) -> u64 = unsafe { code.function("quadruple") }.unwrap();
+        assert_eq!(quadruple(5), 20);
+    }
+
+    #[test]
+    fn symbol_addresses() {
+        let items = parse_asm("target:\nret\ntable:\n.quad target, double").unwrap();
+        let code = Jit::new()
+            .items(items)
+            .external("double", double as *const u8)
+            .finalize()
+            .unwrap();
+
+        let table = code.label_address("table").unwrap() as *const u64;
+        // SAFETY: the table holds two addresses, and the code is readable.
+        let entries = unsafe { [table.read_unaligned(), table.add(1).read_unaligned()] };
+        assert_eq!(entries, [code.as_ptr() as u64, double as *const u8 as u64]);
+    }
+
+    #[test]
+    fn patch_code() {
+        let items = parse_asm("answer:\nmov $1, %eax\nret").unwrap();
+        let mut code = Jit::new().items(items).finalize().unwrap();
+        let answer: extern "C" fn() -> u32 = unsafe { code.function("answer") }.unwrap();
+        assert_eq!(answer(), 1);
+
+        let point = PatchPoint {
+            offset: 0,
+            len: 5,
+            target: None,
+        };
+        let mov = Instruction::new(
+            Mnemonic::MOV,
+            vec![
+                Operand::Register(*EAX),
+                Operand::Immediate(Immediate::Imm8(42)),
+            ],
+        );
+        code.patch(|code| point.rewrite(code, Mode::Long, &mov, |_| None))
+            .unwrap();
+        assert_eq!(answer(), 42);
+    }
+
+    #[test]
+    fn undefined_symbols() {
+        let items = parse_asm("call double").unwrap();
+        assert!(Jit::new().items(items).finalize().is_err());
+    }
+}
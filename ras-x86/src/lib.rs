@@ -1,19 +1,28 @@
+pub mod assembled;
 pub mod assembler;
+pub mod builder;
+pub mod directive;
 pub mod encoder;
 pub mod error;
+pub mod executable;
+pub mod incremental;
 pub mod instruction;
+#[cfg(all(unix, target_arch = "x86_64"))]
+pub mod jit;
+pub mod linker;
 mod macros;
 pub mod mnemonic;
 mod object;
 pub mod operand;
 pub mod parser;
+pub mod patch;
 pub mod symbol;
 
 pub use crate::operand::register::reg_defs::*;
 pub use error::{ParseError, RasError};
 pub use mnemonic::Mnemonic;
 pub use ras_x86_repr as repr;
-pub use repr::Mode;
+pub use repr::{CpuFeature, CpuFeatures, Mode};
 
 pub type RasResult<T> = Result<T, RasError>;
 pub type ParseResult<T> = Result<T, ParseError>;
@@ -22,10 +31,15 @@ pub type ParseResult<T> = Result<T, ParseError>;
 #[cfg(test)]
 mod tests {
     use crate::assembler::{Assembler, Item};
-    use crate::operand::Scale;
+    use crate::instruction::{Instruction, PseudoPrefix, PseudoPrefixes};
+    use crate::operand::{Decorators, FarPointer, Operand, Rounding, Scale};
     use crate::symbol::{Symbol, SymbolAttribute, SymbolType};
-    use crate::{i, imm16, imm32, imm8, label, reg, sib, RasError};
-    use crate::{AL, AX, BX, CX, EAX, EBX, EDX, RAX, RBP, RBX, RCX, RDX, RSP};
+    use crate::{i, imm16, imm32, imm8, label, reg, sib, Mnemonic, Mode, RasError};
+    use crate::{CpuFeature, CpuFeatures};
+    use crate::{AH, AL, AX, BP, BX, CL, CX, EAX, EBX, ECX, EDX, RAX, RBP, RBX, RCX, RDX, RSP};
+    use crate::{CR0, CR3, CR8, DR7, MM0, MM1, MM7, ST0, ST1, ST3, ZMM0, ZMM1, ZMM2, ZMM31};
+    use crate::{DS, ES, ESP, FS, R12, R13, R15, R8, R8D, SI};
+    use crate::{K1, XMM0, XMM1, XMM15, XMM18, XMM2, XMM3, XMM8, YMM0, YMM1, YMM10, YMM2};
 
     macro_rules! assert_encoding_eq {
         ([$($expected:expr),*], $($inst:expr),*) => {{
@@ -64,18 +78,13 @@ mod tests {
 
     #[test]
     fn xor_ax_imm8() {
-        assert_encoding_eq!(
-            // XXX: This could be encoded more efficiently by using the XOR AX, imm16 variant of
-            // the instruction instead
-            //[0x66, 0x35, 0x02, 0x00],
-            [0x66, 0x83, 0b11110000, 0x2],
-            i!(XOR, reg!(AX), imm8!(2))
-        );
+        // XOR AX, imm16 (66 35 02 00) is just as long, but GNU as prefers the sign-extended imm8
+        assert_encoding_eq!([0x66, 0x83, 0b11110000, 0x2], i!(XOR, reg!(AX), imm8!(2)));
     }
 
     #[test]
     fn add_imm_rax() {
-        //assert_encoding_eq!([0x66, 0x05, 0x00, 0x01], i!(ADD, reg!(AX), imm16!(0x100)));
+        assert_encoding_eq!([0x66, 0x05, 0x00, 0x01], i!(ADD, reg!(AX), imm16!(0x100)));
         assert_encoding_eq!(
             [0x05, 0x00, 0x01, 0x00, 0x00],
             i!(ADD, reg!(EAX), imm16!(0x100))
@@ -103,13 +112,17 @@ mod tests {
 
     #[test]
     fn mov_imm8_memory_indirect() {
-        // XXX use the REX.X prefix to encode r15
-        //   42 c6 04 3b 00          movb   $0x0,(%rbx,%r15,1)
-        //   c6 04 2b 02             movb   $0x2,(%rbx,%rbp,1)
+        // c6 04 2b 02             movb   $0x2,(%rbx,%rbp,1)
         assert_encoding_eq!(
             [0xc6, 0b00_000_100, 0b00_101_011, 2],
             i!(MOV, sib!(; ; (RBX, RBP,)), imm8!(2))
         );
+        // The fourth bit of the index register is encoded in REX.X:
+        //   42 c6 04 3b 00          movb   $0x0,(%rbx,%r15,1)
+        assert_encoding_eq!(
+            [0x42, 0xc6, 0b00_000_100, 0b00_111_011, 0],
+            i!(MOV, sib!(; ; (RBX, R15,)), imm8!(0))
+        );
     }
 
     #[test]
@@ -122,9 +135,6 @@ mod tests {
 
     #[test]
     fn mov_imm8_memory_indirect_with_displacement() {
-        // XXX use the REX.X prefix to encode r15
-        //   42 c6 04 3b 00          movb   $0x0,(%rbx,%r15,1)
-        //   c6 04 2b 02             movb   $0x2,(%rbx,%rbp,1)
         assert_encoding_eq!(
             [0xc6, 0b01_000_100, 0b01_101_011, 5, 2],
             //  c6 44 2b 05 02          movb   $0x2,0x5(%rbx,%rbp,1)
@@ -195,6 +205,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn jmp_disp8() {
+        let jmp = |mnemonic| {
+            Item::Instruction(
+                Instruction::new(mnemonic, vec![label!("test_label".to_string())])
+                    .with_pseudo_prefixes(PseudoPrefixes::from(PseudoPrefix::Disp8)),
+            )
+        };
+        let nops = |count| (0..count).map(|_| Item::Instruction(i!(NOP)));
+
+        assert_encoding_eq!(
+            // nop, followed by the rel8 version of the JMP (0xfd = -3)
+            [0x90, 0xeb, 0xfd],
+            Item::Label("test_label".to_string()),
+            Item::Instruction(i!(NOP)),
+            jmp(Mnemonic::JMP)
+        );
+        assert_encoding_eq!(
+            [0x75, 0x00],
+            jmp(Mnemonic::JNE),
+            Item::Label("test_label".to_string())
+        );
+
+        let mut items = vec![jmp(Mnemonic::JMP)];
+        items.extend(nops(127));
+        items.push(Item::Label("test_label".to_string()));
+        let asm = Assembler::long_mode().items(items).dump_text().unwrap();
+        assert_eq!(&[0xeb, 0x7f], &asm[..2]);
+
+        // The label is out of range of the rel8 offset
+        let mut items = vec![jmp(Mnemonic::JMP)];
+        items.extend(nops(128));
+        items.push(Item::Label("test_label".to_string()));
+        assert!(Assembler::long_mode().items(items).dump_text().is_err());
+    }
+
     #[test]
     fn jmp_undefined_static_symbol() {
         assert_encoding_eq!(
@@ -219,6 +265,639 @@ mod tests {
         assert_eq!(&[0xe9, 0, 0, 0, 0], &asm[..]);
     }
 
+    #[test]
+    fn indirect_jmp_call() {
+        assert_encoding_eq!([0xff, 0xe0], i!(JMP, reg!(RAX)));
+        assert_encoding_eq!([0xff, 0xd3], i!(CALL, reg!(RBX)));
+        assert_encoding_eq!([0xff, 0x53, 0x08], i!(CALL, sib!(; 8; (RBX,,))));
+    }
+
+    #[test]
+    fn far_jmp_call_memory_indirect() {
+        let far_ptr = |mem| match mem {
+            Operand::Memory(mem) => Operand::FarPointer(FarPointer::indirect(mem)),
+            _ => unreachable!(),
+        };
+
+        assert_encoding_eq!([0xff, 0x28], i!(JMP, far_ptr(sib!(; ; (RAX,,)))));
+        assert_encoding_eq!([0xff, 0x5b, 0x08], i!(CALL, far_ptr(sib!(; 8; (RBX,,)))));
+    }
+
+    #[test]
+    fn far_jmp_direct() {
+        let far_jmp = || i!(JMP, Operand::FarPointer(FarPointer::direct(0x10, 0x1000)));
+
+        let asm = Assembler::protected_mode()
+            .items(vec![far_jmp()])
+            .dump_text()
+            .unwrap();
+        assert_eq!(&[0xea, 0x00, 0x10, 0x00, 0x00, 0x10, 0x00], &asm[..]);
+
+        let asm = Assembler::real_mode()
+            .items(vec![far_jmp()])
+            .dump_text()
+            .unwrap();
+        assert_eq!(&[0xea, 0x00, 0x10, 0x10, 0x00], &asm[..]);
+
+        // There is no direct far jump in long mode:
+        assert_encoding_eq!(RasError::InvalidMode(Mnemonic::JMP, Mode::Long), far_jmp());
+    }
+
+    #[test]
+    fn sse_reg_reg() {
+        assert_encoding_eq!([0xf2, 0x0f, 0x58, 0xc1], i!(ADDSD, reg!(XMM0), reg!(XMM1)));
+        assert_encoding_eq!([0x66, 0x0f, 0xef, 0xc0], i!(PXOR, reg!(XMM0), reg!(XMM0)));
+        assert_encoding_eq!([0x0f, 0x28, 0xca], i!(MOVAPS, reg!(XMM1), reg!(XMM2)));
+    }
+
+    #[test]
+    fn sse_extended_regs() {
+        assert_encoding_eq!(
+            [0xf2, 0x41, 0x0f, 0x58, 0xc0],
+            i!(ADDSD, reg!(XMM0), reg!(XMM8))
+        );
+        assert_encoding_eq!(
+            [0xf2, 0x44, 0x0f, 0x58, 0xf8],
+            i!(ADDSD, reg!(XMM15), reg!(XMM0))
+        );
+    }
+
+    #[test]
+    fn sse_memory() {
+        assert_encoding_eq!(
+            [0xf2, 0x0f, 0x10, 0x43, 0x08],
+            i!(MOVSD, reg!(XMM0), sib!(; 8; (RBX,,)))
+        );
+        assert_encoding_eq!(
+            [0xf2, 0x44, 0x0f, 0x11, 0x03],
+            i!(MOVSD, sib!(; ; (RBX,,)), reg!(XMM8))
+        );
+    }
+
+    #[test]
+    fn sse_gpr_operands() {
+        assert_encoding_eq!(
+            [0xf2, 0x0f, 0x2a, 0xc0],
+            i!(CVTSI2SD, reg!(XMM0), reg!(EAX))
+        );
+        assert_encoding_eq!(
+            [0xf2, 0x48, 0x0f, 0x2a, 0xc8],
+            i!(CVTSI2SD, reg!(XMM1), reg!(RAX))
+        );
+        assert_encoding_eq!(
+            [0x66, 0x48, 0x0f, 0x6e, 0xc0],
+            i!(MOVQ, reg!(XMM0), reg!(RAX))
+        );
+    }
+
+    #[test]
+    fn vex_2_byte() {
+        assert_encoding_eq!(
+            [0xc5, 0xf0, 0x58, 0xc2],
+            i!(VADDPS, reg!(XMM0), reg!(XMM1), reg!(XMM2))
+        );
+        assert_encoding_eq!(
+            [0xc5, 0xf4, 0x58, 0xc2],
+            i!(VADDPS, reg!(YMM0), reg!(YMM1), reg!(YMM2))
+        );
+        // VEX.R can be encoded in the 2-byte form
+        assert_encoding_eq!(
+            [0xc5, 0x70, 0x58, 0xc2],
+            i!(VADDPS, reg!(XMM8), reg!(XMM1), reg!(XMM2))
+        );
+        assert_encoding_eq!([0xc5, 0xf8, 0x77], i!(VZEROUPPER));
+    }
+
+    #[test]
+    fn vex_3_byte() {
+        // VEX.B
+        assert_encoding_eq!(
+            [0xc4, 0xc1, 0x74, 0x58, 0xc2],
+            i!(VADDPS, reg!(YMM0), reg!(YMM1), reg!(YMM10))
+        );
+        // VEX.W
+        assert_encoding_eq!(
+            [0xc4, 0xe1, 0xf9, 0x6e, 0xc0],
+            i!(VMOVQ, reg!(XMM0), reg!(RAX))
+        );
+        // 0F38 opcode map
+        assert_encoding_eq!(
+            [0xc4, 0xe2, 0x75, 0xb8, 0xc2],
+            i!(VFMADD231PS, reg!(YMM0), reg!(YMM1), reg!(YMM2))
+        );
+    }
+
+    #[test]
+    fn vex_ndd() {
+        // VEX.vvvv encodes the destination, ModRM.reg is an opcode extension
+        assert_encoding_eq!(
+            [0xc5, 0xed, 0x72, 0xf1, 0x04],
+            i!(VPSLLD, reg!(YMM2), reg!(YMM1), imm8!(4))
+        );
+    }
+
+    #[test]
+    fn vex_extended_regs_outside_long_mode() {
+        let err = Assembler::protected_mode()
+            .items(vec![i!(VADDPS, reg!(XMM8), reg!(XMM1), reg!(XMM2))])
+            .dump_text()
+            .unwrap_err();
+        assert!(matches!(err, RasError::Encoding(_)));
+    }
+
+    #[test]
+    fn evex_registers() {
+        assert_encoding_eq!(
+            [0x62, 0xb1, 0x74, 0x08, 0x58, 0xc2],
+            i!(VADDPS, reg!(XMM0), reg!(XMM1), reg!(XMM18))
+        );
+        assert_encoding_eq!(
+            [0x62, 0x61, 0x74, 0x48, 0x58, 0xfa],
+            i!(VADDPS, reg!(ZMM31), reg!(ZMM1), reg!(ZMM2))
+        );
+    }
+
+    #[test]
+    fn evex_masking() {
+        // The same instruction is VEX-encoded unless it needs an EVEX feature
+        assert_encoding_eq!(
+            [0xc5, 0xf0, 0x58, 0xc2],
+            i!(VADDPS, reg!(XMM0), reg!(XMM1), reg!(XMM2))
+        );
+        assert_encoding_eq!(
+            [0x62, 0xf1, 0x74, 0x09, 0x58, 0xc2],
+            i!(VADDPS, reg!(XMM0), reg!(XMM1), reg!(XMM2))
+                .with_decorators(Decorators::default().mask(*K1))
+        );
+        assert_encoding_eq!(
+            [0x62, 0xf1, 0x74, 0xc9, 0x58, 0xc2],
+            i!(VADDPS, reg!(ZMM0), reg!(ZMM1), reg!(ZMM2))
+                .with_decorators(Decorators::default().mask(*K1).zeroing())
+        );
+    }
+
+    #[test]
+    fn evex_broadcast_and_rounding() {
+        assert_encoding_eq!(
+            [0x62, 0xf1, 0x74, 0x58, 0x58, 0x40, 0x01],
+            i!(VADDPS, reg!(ZMM0), reg!(ZMM1), sib!(; 4; (RAX,,)))
+                .with_decorators(Decorators::default().broadcast(16))
+        );
+        assert_encoding_eq!(
+            [0x62, 0xf1, 0x74, 0x78, 0x58, 0xc2],
+            i!(VADDPS, reg!(ZMM0), reg!(ZMM1), reg!(ZMM2))
+                .with_decorators(Decorators::default().rounding(Rounding::Zero))
+        );
+    }
+
+    #[test]
+    fn evex_compressed_displacement() {
+        let vaddps = |disp| i!(VADDPS, reg!(ZMM0), reg!(ZMM1), sib!(; disp; (RAX,,)));
+        // disp8*N, where N is the size of the memory operand (64 bytes)
+        assert_encoding_eq!([0x62, 0xf1, 0x74, 0x48, 0x58, 0x40, 0x01], vaddps(64));
+        assert_encoding_eq!([0x62, 0xf1, 0x74, 0x48, 0x58, 0x40, 0x7f], vaddps(8128));
+        assert_encoding_eq!([0x62, 0xf1, 0x74, 0x48, 0x58, 0x40, 0x80], vaddps(-8192));
+        // Displacements that aren't a multiple of N, or are out of range, need a disp32
+        assert_encoding_eq!(
+            [0x62, 0xf1, 0x74, 0x48, 0x58, 0x80, 0x64, 0x00, 0x00, 0x00],
+            vaddps(100)
+        );
+        assert_encoding_eq!(
+            [0x62, 0xf1, 0x74, 0x48, 0x58, 0x80, 0x00, 0x20, 0x00, 0x00],
+            vaddps(8192)
+        );
+    }
+
+    #[test]
+    fn evex_invalid_decorators() {
+        // VUCOMISS can't be masked
+        assert_encoding_eq!(
+            RasError::MissingInstructionRepr(Mnemonic::VUCOMISS),
+            i!(VUCOMISS, reg!(XMM0), reg!(XMM1)).with_decorators(Decorators::default().mask(*K1))
+        );
+        // The broadcast must fill the whole vector
+        assert_encoding_eq!(
+            RasError::MissingInstructionRepr(Mnemonic::VADDPS),
+            i!(VADDPS, reg!(ZMM0), reg!(ZMM1), sib!(; ; (RAX,,)))
+                .with_decorators(Decorators::default().broadcast(8))
+        );
+        // Static rounding is only allowed for register operands
+        assert_encoding_eq!(
+            RasError::MissingInstructionRepr(Mnemonic::VADDPS),
+            i!(VADDPS, reg!(ZMM0), reg!(ZMM1), sib!(; ; (RAX,,)))
+                .with_decorators(Decorators::default().rounding(Rounding::Nearest))
+        );
+    }
+
+    #[test]
+    fn x87_stack_registers() {
+        assert_encoding_eq!([0xd8, 0xc1], i!(FADD, reg!(ST0), reg!(ST1)));
+        assert_encoding_eq!([0xdc, 0xc3], i!(FADD, reg!(ST3), reg!(ST0)));
+        assert_encoding_eq!([0xde, 0xc1], i!(FADDP, reg!(ST1), reg!(ST0)));
+        assert_encoding_eq!([0xd9, 0xc3], i!(FLD, reg!(ST3)));
+        // The size of the operands of x87 instructions is implied by the opcode
+        assert_encoding_eq!([0xdf, 0xe0], i!(FNSTSW, reg!(AX)));
+    }
+
+    #[test]
+    fn x87_memory_operands() {
+        let fld = || i!(FLD, sib!(; 4; (RAX,,)));
+        // The smallest memory operand is used by default (like GNU as does)
+        assert_encoding_eq!([0xd9, 0x40, 0x04], fld());
+        assert_encoding_eq!([0xd9, 0x40, 0x04], fld().with_memory_size(32));
+        assert_encoding_eq!([0xdd, 0x40, 0x04], fld().with_memory_size(64));
+        assert_encoding_eq!([0xdb, 0x68, 0x04], fld().with_memory_size(80));
+        assert_encoding_eq!(
+            [0xdf, 0x28],
+            i!(FILD, sib!(; ; (RAX,,))).with_memory_size(64)
+        );
+        assert_encoding_eq!(
+            RasError::MissingInstructionRepr(Mnemonic::FILD),
+            i!(FILD, sib!(; ; (RAX,,))).with_memory_size(80)
+        );
+    }
+
+    #[test]
+    fn mmx_registers() {
+        assert_encoding_eq!([0x0f, 0xfe, 0xc1], i!(PADDD, reg!(MM0), reg!(MM1)));
+        assert_encoding_eq!([0x66, 0x0f, 0xfe, 0xc1], i!(PADDD, reg!(XMM0), reg!(XMM1)));
+        assert_encoding_eq!([0x0f, 0x6e, 0xf8], i!(MOVD, reg!(MM7), reg!(EAX)));
+        assert_encoding_eq!([0x48, 0x0f, 0x7e, 0xc8], i!(MOVQ, reg!(RAX), reg!(MM1)));
+        assert_encoding_eq!([0x0f, 0x71, 0xf0, 0x04], i!(PSLLW, reg!(MM0), imm8!(4)));
+        // MMX registers can't be used as general-purpose registers
+        assert_encoding_eq!(
+            RasError::MissingInstructionRepr(Mnemonic::ADD),
+            i!(ADD, reg!(RAX), reg!(MM0))
+        );
+    }
+
+    #[test]
+    fn control_and_debug_registers() {
+        assert_encoding_eq!([0x0f, 0x20, 0xd8], i!(MOV, reg!(RAX), reg!(CR3)));
+        assert_encoding_eq!([0x0f, 0x22, 0xd8], i!(MOV, reg!(CR3), reg!(RAX)));
+        // CR8 needs REX.R
+        assert_encoding_eq!([0x44, 0x0f, 0x22, 0xc0], i!(MOV, reg!(CR8), reg!(RAX)));
+        assert_encoding_eq!([0x44, 0x0f, 0x20, 0xc2], i!(MOV, reg!(RDX), reg!(CR8)));
+        assert_encoding_eq!([0x0f, 0x21, 0xf9], i!(MOV, reg!(RCX), reg!(DR7)));
+
+        let asm = Assembler::protected_mode()
+            .items(vec![i!(MOV, reg!(EAX), reg!(CR0))])
+            .dump_text()
+            .unwrap();
+        assert_eq!(&[0x0f, 0x20, 0xc0], &asm[..]);
+    }
+
+    #[test]
+    fn system_instructions() {
+        assert_encoding_eq!([0x0f, 0x01, 0x10], i!(LGDT, sib!(; ; (RAX,,))));
+        assert_encoding_eq!([0x0f, 0x01, 0x5b, 0x08], i!(LIDT, sib!(; 8; (RBX,,))));
+        // LTR always loads a 16-bit selector, so it doesn't need an operand-size prefix
+        assert_encoding_eq!([0x0f, 0x00, 0xd8], i!(LTR, reg!(AX)));
+        assert_encoding_eq!([0x0f, 0x01, 0xf8], i!(SWAPGS));
+        assert_encoding_eq!([0x0f, 0x07], i!(SYSRET));
+        assert_encoding_eq!([0x48, 0x0f, 0x07], i!(SYSRETQ));
+        assert_encoding_eq!([0x48, 0x0f, 0x35], i!(SYSEXITQ));
+        assert_encoding_eq!([0x48, 0xcf], i!(IRETQ));
+        assert_encoding_eq!([0x0f, 0x30], i!(WRMSR));
+    }
+
+    #[test]
+    fn address_size() {
+        let encode = |asm: Assembler, inst| asm.items(vec![inst]).dump_text();
+
+        // mov (%eax), %ecx
+        assert_encoding_eq!([0x67, 0x8b, 0x08], i!(MOV, reg!(ECX), sib!(; ; (EAX,,))));
+        // lea (%eax,%ecx,4), %edx
+        assert_encoding_eq!(
+            [0x67, 0x8d, 0x14, 0x88],
+            i!(LEA, reg!(EDX), sib!(; ; (EAX, ECX, Scale::Double)))
+        );
+        // mov (%esp), %eax
+        assert_encoding_eq!(
+            [0x67, 0x8b, 0x04, 0x24],
+            i!(MOV, reg!(EAX), sib!(; ; (ESP,,)))
+        );
+        // mov (%bx), %ax
+        assert!(encode(Assembler::long_mode(), i!(MOV, reg!(AX), sib!(; ; (BX,,)))).is_err());
+
+        // mov (%eax), %ecx
+        assert_eq!(
+            encode(
+                Assembler::protected_mode(),
+                i!(MOV, reg!(ECX), sib!(; ; (EAX,,)))
+            ),
+            Ok(vec![0x8b, 0x08])
+        );
+        // mov (%bx,%si), %ecx
+        assert_eq!(
+            encode(
+                Assembler::protected_mode(),
+                i!(MOV, reg!(ECX), sib!(; ; (BX, SI,)))
+            ),
+            Ok(vec![0x67, 0x8b, 0x08])
+        );
+        // mov (%rax), %ecx
+        assert!(encode(
+            Assembler::protected_mode(),
+            i!(MOV, reg!(ECX), sib!(; ; (RAX,,)))
+        )
+        .is_err());
+
+        // mov (%eax), %ecx
+        assert_eq!(
+            encode(
+                Assembler::real_mode(),
+                i!(MOV, reg!(ECX), sib!(; ; (EAX,,)))
+            ),
+            Ok(vec![0x67, 0x66, 0x8b, 0x08])
+        );
+        // mov 4(%bp), %cx
+        assert_eq!(
+            encode(Assembler::real_mode(), i!(MOV, reg!(CX), sib!(; 4; (BP,,)))),
+            Ok(vec![0x8b, 0x4e, 0x04])
+        );
+        // mov 0x1000, %cx
+        assert_eq!(
+            encode(
+                Assembler::real_mode(),
+                i!(MOV, reg!(CX), sib!(; 0x1000; (,,)))
+            ),
+            Ok(vec![0x8b, 0x0e, 0x00, 0x10])
+        );
+    }
+
+    #[test]
+    fn invalid_mode() {
+        let protected_mode_err = |inst| {
+            Assembler::protected_mode()
+                .items(vec![inst])
+                .dump_text()
+                .unwrap_err()
+        };
+
+        assert_eq!(
+            protected_mode_err(i!(SWAPGS)),
+            RasError::InvalidMode(Mnemonic::SWAPGS, Mode::Protected)
+        );
+        assert_eq!(
+            protected_mode_err(i!(IRETQ)),
+            RasError::InvalidMode(Mnemonic::IRETQ, Mode::Protected)
+        );
+        assert_eq!(
+            protected_mode_err(i!(MOV, reg!(CR8), reg!(RAX))),
+            RasError::InvalidMode(Mnemonic::MOV, Mode::Protected)
+        );
+        // The control registers are 64 bits wide in long mode
+        assert_encoding_eq!(
+            RasError::InvalidMode(Mnemonic::MOV, Mode::Long),
+            i!(MOV, reg!(EAX), reg!(CR0))
+        );
+    }
+
+    #[test]
+    fn extended_registers() {
+        // inc %r8
+        assert_encoding_eq!([0x49, 0xff, 0xc0], i!(INC, reg!(R8)));
+        // mov (%r13), %eax
+        assert_encoding_eq!(
+            [0x41, 0x8b, 0x45, 0x00],
+            i!(MOV, reg!(EAX), sib!(; ; (R13, ,)))
+        );
+        // mov (%r12), %eax
+        assert_encoding_eq!(
+            [0x41, 0x8b, 0x04, 0x24],
+            i!(MOV, reg!(EAX), sib!(; ; (R12, ,)))
+        );
+        // mov (%rax,%r12,1), %eax
+        assert_encoding_eq!(
+            [0x42, 0x8b, 0x04, 0x20],
+            i!(MOV, reg!(EAX), sib!(; ; (RAX, R12,)))
+        );
+
+        // R8-R15 can only be encoded with a REX prefix, which doesn't exist outside long mode.
+        assert!(Assembler::protected_mode()
+            .items(vec![i!(INC, reg!(R8D))])
+            .dump_text()
+            .is_err());
+    }
+
+    #[test]
+    fn implicit_operands() {
+        let encode = |asm: Assembler, inst| asm.items(vec![inst]).dump_text();
+
+        // The mnemonic determines the operand size of the instructions without operands
+        assert_encoding_eq!([0x66, 0x98], i!(CBW));
+        assert_encoding_eq!([0x98], i!(CWDE));
+        assert_encoding_eq!([0x66, 0xad], i!(LODSW));
+        assert_eq!(encode(Assembler::real_mode(), i!(CBW)), Ok(vec![0x98]));
+        assert_eq!(
+            encode(Assembler::real_mode(), i!(CWDE)),
+            Ok(vec![0x66, 0x98])
+        );
+
+        // The segment registers don't determine the operand size
+        assert_encoding_eq!([0x0f, 0xa0], i!(PUSH, reg!(FS)));
+        assert_encoding_eq!([0x66, 0x8c, 0xd9], i!(MOV, reg!(CX), reg!(DS)));
+        assert_encoding_eq!([0x8c, 0x03], i!(MOV, sib!(; ; (RBX,,)), reg!(ES)));
+        assert_encoding_eq!([0x8e, 0xd9], i!(MOV, reg!(DS), reg!(CX)));
+
+        // xlat (%rbx), xlat (%ebx)
+        assert_encoding_eq!([0xd7], i!(XLAT, sib!(; ; (RBX,,))));
+        assert_encoding_eq!([0x67, 0xd7], i!(XLAT, sib!(; ; (EBX,,))));
+        // The address of the memory operand of XLAT is implied by the opcode
+        assert!(encode(Assembler::long_mode(), i!(XLAT, sib!(; 8; (RBX,,)))).is_err());
+        assert!(encode(Assembler::long_mode(), i!(XLAT, sib!(; ; (RAX,,)))).is_err());
+    }
+
+    #[test]
+    fn feature_flagged_instructions() {
+        // popcnt %ax, %bx
+        assert_encoding_eq!(
+            [0x66, 0xf3, 0x0f, 0xb8, 0xd8],
+            i!(POPCNT, reg!(BX), reg!(AX))
+        );
+        // lzcnt %rax, %rcx
+        assert_encoding_eq!(
+            [0xf3, 0x48, 0x0f, 0xbd, 0xc8],
+            i!(LZCNT, reg!(RCX), reg!(RAX))
+        );
+        // andn %ecx, %ebx, %eax
+        assert_encoding_eq!(
+            [0xc4, 0xe2, 0x60, 0xf2, 0xc1],
+            i!(ANDN, reg!(EAX), reg!(EBX), reg!(ECX))
+        );
+        // blsr %rcx, %rax
+        assert_encoding_eq!(
+            [0xc4, 0xe2, 0xf8, 0xf3, 0xc9],
+            i!(BLSR, reg!(RAX), reg!(RCX))
+        );
+        // aesenc %xmm1, %xmm0
+        assert_encoding_eq!(
+            [0x66, 0x0f, 0x38, 0xdc, 0xc1],
+            i!(AESENC, reg!(XMM0), reg!(XMM1))
+        );
+    }
+
+    #[test]
+    fn cpu_features() {
+        let assemble = |features: &str, inst| {
+            Assembler::long_mode()
+                .cpu_features(features.parse().unwrap())
+                .items(vec![inst])
+                .dump_text()
+        };
+
+        assert_eq!(
+            assemble("x86-64-v2", i!(POPCNT, reg!(EBX), reg!(EAX))),
+            Ok(vec![0xf3, 0x0f, 0xb8, 0xd8])
+        );
+        assert_eq!(
+            assemble("x86-64-v2", i!(LZCNT, reg!(EBX), reg!(EAX))),
+            Err(RasError::MissingCpuFeatures(
+                Mnemonic::LZCNT,
+                vec![CpuFeature::Lzcnt]
+            ))
+        );
+        assert_eq!(
+            assemble("x86-64-v3", i!(LZCNT, reg!(EBX), reg!(EAX))),
+            Ok(vec![0xf3, 0x0f, 0xbd, 0xd8])
+        );
+        assert_eq!(
+            assemble("x86-64", i!(POPCNT, reg!(EBX), reg!(EAX))),
+            Err(RasError::MissingCpuFeatures(
+                Mnemonic::POPCNT,
+                vec![CpuFeature::Popcnt]
+            ))
+        );
+        // The extensions that aren't part of any microarchitecture level have to be enabled
+        // explicitly
+        assert_eq!(
+            assemble("x86-64-v4", i!(AESENC, reg!(XMM0), reg!(XMM1))),
+            Err(RasError::MissingCpuFeatures(
+                Mnemonic::AESENC,
+                vec![CpuFeature::Aes]
+            ))
+        );
+        assert_eq!(
+            assemble(
+                "x86-64-v2, aes",
+                i!(VAESENC, reg!(XMM0), reg!(XMM1), reg!(XMM2))
+            ),
+            Err(RasError::MissingCpuFeatures(
+                Mnemonic::VAESENC,
+                vec![CpuFeature::Avx]
+            ))
+        );
+        // The VEX-encoded form is used if AVX-512 isn't enabled
+        assert_eq!(
+            assemble("x86-64-v3", i!(VADDPS, reg!(YMM0), reg!(YMM1), reg!(YMM2))),
+            Ok(vec![0xc5, 0xf4, 0x58, 0xc2])
+        );
+        assert_eq!(
+            assemble("x86-64-v3", i!(VADDPS, reg!(ZMM0), reg!(ZMM1), reg!(ZMM2))),
+            Err(RasError::MissingCpuFeatures(
+                Mnemonic::VADDPS,
+                vec![CpuFeature::Avx512f]
+            ))
+        );
+        // The instructions without feature flags are always available
+        assert_eq!(
+            assemble("", i!(ADD, reg!(EAX), reg!(EBX))),
+            Ok(vec![0x01, 0xd8])
+        );
+        assert!("x86-64-v5".parse::<CpuFeatures>().is_err());
+    }
+
+    #[test]
+    fn pseudo_prefixes() {
+        let with = |inst: Instruction, prefix: PseudoPrefix| {
+            inst.with_pseudo_prefixes(PseudoPrefixes::from(prefix))
+        };
+        let assemble_err =
+            |inst: Instruction| match Assembler::long_mode().items(vec![inst]).dump_text() {
+                Err(RasError::InvalidPseudoPrefix(prefix, _)) => Some(prefix),
+                _ => None,
+            };
+
+        // {load} and {store}
+        assert_encoding_eq!([0x89, 0xc1], mov_reg_reg());
+        assert_encoding_eq!([0x8b, 0xc8], with(mov_reg_reg(), PseudoPrefix::Load));
+        assert_encoding_eq!([0x89, 0xc1], with(mov_reg_reg(), PseudoPrefix::Store));
+        assert_encoding_eq!(
+            [0xc5, 0xf8, 0x29, 0xca],
+            with(i!(VMOVAPS, reg!(XMM2), reg!(XMM1)), PseudoPrefix::Store)
+        );
+        assert_eq!(
+            assemble_err(with(
+                i!(MOV, reg!(ECX), sib!(; ; (RAX,,))),
+                PseudoPrefix::Store
+            )),
+            Some(PseudoPrefix::Store)
+        );
+        assert_eq!(
+            assemble_err(with(i!(ADD, reg!(EAX), imm8!(1)), PseudoPrefix::Load)),
+            Some(PseudoPrefix::Load)
+        );
+
+        // {disp8} and {disp32}
+        let mov = |disp| i!(MOV, reg!(ECX), sib!(; disp; (RAX,,)));
+        assert_encoding_eq!([0x8b, 0x48, 0x00], with(mov(0), PseudoPrefix::Disp8));
+        assert_encoding_eq!(
+            [0x8b, 0x88, 0x08, 0x00, 0x00, 0x00],
+            with(mov(8), PseudoPrefix::Disp32)
+        );
+        assert_eq!(
+            assemble_err(with(mov(1000), PseudoPrefix::Disp8)),
+            Some(PseudoPrefix::Disp8)
+        );
+        assert_eq!(
+            assemble_err(with(mov_reg_reg(), PseudoPrefix::Disp32)),
+            Some(PseudoPrefix::Disp32)
+        );
+
+        // {vex} and {evex}
+        let vaddps = || i!(VADDPS, reg!(XMM3), reg!(XMM2), reg!(XMM1));
+        assert_encoding_eq!([0xc5, 0xe8, 0x58, 0xd9], with(vaddps(), PseudoPrefix::Vex));
+        assert_encoding_eq!(
+            [0x62, 0xf1, 0x6c, 0x08, 0x58, 0xd9],
+            with(vaddps(), PseudoPrefix::Evex)
+        );
+        assert_eq!(
+            assemble_err(with(
+                i!(VADDPS, reg!(ZMM0), reg!(ZMM1), reg!(ZMM2)),
+                PseudoPrefix::Vex
+            )),
+            Some(PseudoPrefix::Vex)
+        );
+        assert_eq!(
+            assemble_err(with(i!(NOP), PseudoPrefix::Evex)),
+            Some(PseudoPrefix::Evex)
+        );
+
+        // {rex}
+        assert_encoding_eq!([0x40, 0x90], with(i!(NOP), PseudoPrefix::Rex));
+        assert_encoding_eq!([0x40, 0x89, 0xc1], with(mov_reg_reg(), PseudoPrefix::Rex));
+        assert_eq!(
+            assemble_err(with(vaddps(), PseudoPrefix::Rex)),
+            Some(PseudoPrefix::Rex)
+        );
+        assert!(Assembler::long_mode()
+            .items(vec![with(i!(MOV, reg!(CL), reg!(AH)), PseudoPrefix::Rex)])
+            .dump_text()
+            .is_err());
+        assert_eq!(
+            Assembler::protected_mode()
+                .items(vec![with(i!(NOP), PseudoPrefix::Rex)])
+                .dump_text()
+                .map_err(|err| matches!(err, RasError::InvalidPseudoPrefix(PseudoPrefix::Rex, _))),
+            Err(true)
+        );
+    }
+
+    fn mov_reg_reg() -> Instruction {
+        i!(MOV, reg!(ECX), reg!(EAX))
+    }
+
     //   XXX
     //   33 54 24 10             xor    0x10(%rsp),%edx
     //   48 8d 5c 03 01          lea    0x1(%rbx,%rax,1),%rbx
@@ -0,0 +1,587 @@
+//! A minimal static linker, which links relocatable ELF objects (like the ones written by
+//! [`Assembler::write_obj`](crate::assembler::Assembler::write_obj) or GNU as) into
+//! [`Assembled`] code, which can be written as a flat binary (see [`Assembled::flat_binary`]) or
+//! as a static executable (see [`ExecutableWriter`](crate::executable::ExecutableWriter)).
+//!
+//! ```
+//! use ras_x86::assembler::Assembler;
+//! use ras_x86::linker::Linker;
+//! use ras_x86::parser::parse_asm;
+//! use ras_x86::symbol::{Symbol, SymbolAttribute, SymbolType};
+//!
+//! let exit = Symbol::new_decl(SymbolType::Quad, SymbolAttribute::Global as u8);
+//! let object = |asm| {
+//!     let mut obj = vec![];
+//!     Assembler::long_mode()
+//!         .items(parse_asm(asm).unwrap())
+//!         .symbols(&[("exit".into(), exit.clone())])
+//!         .write_obj(&mut obj)
+//!         .unwrap();
+//!     obj
+//! };
+//!
+//! let out = Linker::new()
+//!     .object(object("_start:\ncall exit"))
+//!     .object(object("exit:\nmov $60, %eax\nsyscall"))
+//!     .link()
+//!     .unwrap();
+//! assert_eq!(
+//!     out.flat_binary(0x1000).unwrap(),
+//!     [0xe8, 0x00, 0x00, 0x00, 0x00, 0xb8, 0x3c, 0x00, 0x00, 0x00, 0x0f, 0x05]
+//! );
+//! ```
+
+use object::read::{File, Object, ObjectSection, ObjectSymbol, RelocationTarget, SectionIndex};
+use object::{Architecture, BinaryFormat, ObjectKind, RelocationEncoding, SymbolKind};
+
+use crate::assembled::{
+    align_up, Assembled, Relocation, RelocationKind, ResolvedSymbol, Section, SectionKind,
+    SymbolBinding, TEXT_SECTION,
+};
+use crate::symbol::{SymbolId, SymbolOffset};
+use crate::{Mode, RasError, RasResult};
+
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Display;
+
+/// Links relocatable ELF objects.
+///
+/// The sections with the same name are merged (in the order the objects were added), and the
+/// references to the symbols are resolved like `ld` does:
+/// - a global symbol can only be defined once;
+/// - a global definition overrides the weak ones, and the first weak definition overrides the
+///   other weak ones;
+/// - the undefined weak symbols are 0;
+/// - the absolute symbols (e.g. `foo = 42` in GNU as) are defined outside of the sections (see
+///   [`Assembled::absolute_symbols`]).
+#[derive(Debug, Clone, Default)]
+pub struct Linker {
+    objects: Vec<Vec<u8>>,
+}
+
+/// The definition of a global (or weak) symbol.
+struct Definition {
+    /// The merged section the symbol is defined in (`None` for an absolute symbol, whose offset
+    /// is its address).
+    section: Option<usize>,
+    offset: SymbolOffset,
+    weak: bool,
+}
+
+impl Linker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a relocatable ELF object to link.
+    pub fn object(mut self, data: impl Into<Vec<u8>>) -> Self {
+        self.objects.push(data.into());
+        self
+    }
+
+    /// Link the objects, and return the merged sections, their symbols, and the references that
+    /// are resolved once the addresses of the sections are known.
+    ///
+    /// The references to the local symbols are relative to the start of their (merged) section,
+    /// which is defined as a local symbol named after the section.
+    pub fn link(&self) -> RasResult<Assembled> {
+        let mut files = vec![];
+        let mut mode = None;
+        for (index, data) in self.objects.iter().enumerate() {
+            let file = File::parse(&**data).map_err(|err| error(index, err))?;
+            if file.format() != BinaryFormat::Elf || file.kind() != ObjectKind::Relocatable {
+                return Err(error(index, "not a relocatable ELF object"));
+            }
+
+            let file_mode = match file.architecture() {
+                Architecture::X86_64 => Mode::Long,
+                Architecture::I386 => Mode::Protected,
+                arch => return Err(error(index, format!("unsupported architecture {:?}", arch))),
+            };
+            if *mode.get_or_insert(file_mode) != file_mode {
+                return Err(error(
+                    index,
+                    "can't link 32-bit and 64-bit objects together",
+                ));
+            }
+            files.push(file);
+        }
+
+        let (sections, placements) = merge_sections(&files)?;
+        let globals = resolve_globals(&files, &placements)?;
+
+        let mut symbols = globals
+            .iter()
+            .filter_map(|(name, def)| {
+                Some(ResolvedSymbol {
+                    name: name.to_string(),
+                    section: def.section?,
+                    offset: def.offset,
+                    size: 0,
+                    binding: if def.weak {
+                        SymbolBinding::Weak
+                    } else {
+                        SymbolBinding::Global
+                    },
+                })
+            })
+            .collect::<Vec<_>>();
+        for (index, file) in files.iter().enumerate() {
+            let locals = file
+                .symbols()
+                .filter(|sym| sym.is_local() && sym.kind() != SymbolKind::Section);
+            for sym in locals {
+                let name = sym.name().map_err(|err| error(index, err))?;
+                let location = sym
+                    .section()
+                    .index()
+                    .and_then(|section| placements.get(&(index, section)));
+                if let (false, Some(&(section, base))) = (name.is_empty(), location) {
+                    symbols.push(ResolvedSymbol {
+                        name: name.into(),
+                        section,
+                        offset: base + sym.address(),
+                        size: 0,
+                        binding: SymbolBinding::Local,
+                    });
+                }
+            }
+        }
+
+        let mut relocator = Relocator {
+            sections,
+            globals,
+            relocations: vec![],
+            section_symbols: Default::default(),
+            undefined: vec![],
+            undefined_weak: Default::default(),
+        };
+        for (index, file) in files.iter().enumerate() {
+            for section in file.sections() {
+                if let Some(&(out_section, base)) = placements.get(&(index, section.index())) {
+                    for (offset, reloc) in section.relocations() {
+                        relocator.relocate(
+                            index,
+                            file,
+                            &placements,
+                            (out_section, base + offset),
+                            &reloc,
+                        )?;
+                    }
+                }
+            }
+        }
+
+        if !relocator.undefined.is_empty() {
+            relocator.undefined.sort();
+            relocator.undefined.dedup();
+            return Err(RasError::UndefinedSymbols(relocator.undefined));
+        }
+
+        symbols.extend(
+            relocator
+                .section_symbols
+                .iter()
+                .map(|&section| ResolvedSymbol {
+                    name: relocator.sections[section].name.clone(),
+                    section,
+                    offset: 0,
+                    size: 0,
+                    binding: SymbolBinding::Local,
+                }),
+        );
+
+        let absolute_symbols = relocator
+            .globals
+            .iter()
+            .filter(|(_, def)| def.section.is_none())
+            .map(|(name, def)| (name.to_string(), def.offset))
+            .chain(relocator.undefined_weak.into_iter().map(|name| (name, 0)))
+            .collect();
+
+        Ok(Assembled::from_parts(
+            mode.unwrap_or(Mode::Long),
+            relocator.sections,
+            symbols,
+            absolute_symbols,
+            relocator.relocations,
+            vec![],
+        ))
+    }
+}
+
+/// The merged section and the offset each section of each object was placed at (indexed by the
+/// index of the object and the index of the section).
+type Placements = HashMap<(usize, SectionIndex), (usize, SymbolOffset)>;
+
+/// Merge the sections that are loaded (the code and the data) by name. The `.text` section is
+/// always the first one.
+fn merge_sections(files: &[File]) -> RasResult<(Vec<Section>, Placements)> {
+    let mut sections = vec![Section {
+        name: TEXT_SECTION.into(),
+        kind: SectionKind::Text,
+        data: vec![],
+        align: 1,
+    }];
+    let mut placements = HashMap::new();
+    for (index, file) in files.iter().enumerate() {
+        for section in file.sections() {
+            let kind = match section.kind() {
+                object::SectionKind::Text => SectionKind::Text,
+                object::SectionKind::Data => SectionKind::Data,
+                object::SectionKind::ReadOnlyData | object::SectionKind::ReadOnlyString => {
+                    SectionKind::ReadOnlyData
+                }
+                object::SectionKind::UninitializedData => SectionKind::Bss,
+                // The other sections (e.g. the debug info) aren't loaded.
+                _ => continue,
+            };
+            let name = section.name().map_err(|err| error(index, err))?;
+            let out_index = match sections.iter().position(|out| out.name == name) {
+                Some(out_index) => out_index,
+                None => {
+                    sections.push(Section {
+                        name: name.into(),
+                        kind,
+                        data: vec![],
+                        align: 1,
+                    });
+                    sections.len() - 1
+                }
+            };
+
+            let out = &mut sections[out_index];
+            let align = section.align().max(1);
+            out.align = out.align.max(align);
+            let offset = align_up(out.data.len() as u64, align);
+            out.data.resize(offset as usize, 0);
+            if kind == SectionKind::Bss {
+                out.data.resize((offset + section.size()) as usize, 0);
+            } else {
+                out.data
+                    .extend(section.data().map_err(|err| error(index, err))?);
+            }
+            placements.insert((index, section.index()), (out_index, offset));
+        }
+    }
+
+    Ok((sections, placements))
+}
+
+/// Find the definitions of the global and weak symbols.
+fn resolve_globals<'f>(
+    files: &[File<'f>],
+    placements: &Placements,
+) -> RasResult<HashMap<&'f str, Definition>> {
+    let mut globals = HashMap::new();
+    for (index, file) in files.iter().enumerate() {
+        for sym in file.symbols().filter(|sym| !sym.is_local()) {
+            let name = sym.name().map_err(|err| error(index, err))?;
+            let (section, offset) = match sym.section() {
+                object::SymbolSection::Section(section) => {
+                    match placements.get(&(index, section)) {
+                        Some(&(section, base)) => (Some(section), base + sym.address()),
+                        None => continue,
+                    }
+                }
+                object::SymbolSection::Absolute => (None, sym.address()),
+                object::SymbolSection::Common => {
+                    return Err(error(
+                        index,
+                        format!("the common symbol {} isn't supported", name),
+                    ));
+                }
+                // The undefined symbols don't define anything.
+                _ => continue,
+            };
+
+            let def = Definition {
+                section,
+                offset,
+                weak: sym.is_weak(),
+            };
+            match globals.entry(name) {
+                Entry::Vacant(entry) => {
+                    entry.insert(def);
+                }
+                Entry::Occupied(entry) if !entry.get().weak && !def.weak => {
+                    return Err(RasError::DuplicateLabel(name.into()));
+                }
+                Entry::Occupied(mut entry) => {
+                    if entry.get().weak && !def.weak {
+                        entry.insert(def);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(globals)
+}
+
+/// Collects the relocations of the merged sections.
+struct Relocator<'f> {
+    sections: Vec<Section>,
+    globals: HashMap<&'f str, Definition>,
+    relocations: Vec<Relocation>,
+    /// The sections the references to the local symbols are relative to.
+    section_symbols: BTreeSet<usize>,
+    /// The undefined symbols that are referenced.
+    undefined: Vec<SymbolId>,
+    /// The undefined weak symbols that are referenced (which are absolute symbols at 0).
+    undefined_weak: BTreeSet<SymbolId>,
+}
+
+impl<'f> Relocator<'f> {
+    /// Translate the relocation at the specified offset of a merged section.
+    fn relocate(
+        &mut self,
+        index: usize,
+        file: &File<'f>,
+        placements: &Placements,
+        (section, offset): (usize, SymbolOffset),
+        reloc: &object::Relocation,
+    ) -> RasResult<()> {
+        use object::RelocationKind::*;
+
+        let kind = match (reloc.kind(), reloc.encoding(), reloc.size()) {
+            (Relative | PltRelative, _, size) => RelocationKind::relative(u64::from(size) / 8),
+            (Absolute, RelocationEncoding::X86Signed, 32) => Some(RelocationKind::Absolute32Signed),
+            (Absolute, RelocationEncoding::Generic, size) => {
+                RelocationKind::absolute(u64::from(size) / 8)
+            }
+            _ => None,
+        }
+        .ok_or_else(|| {
+            let name = &self.sections[section].name;
+            error(
+                index,
+                format!(
+                    "unsupported relocation {:?} at {}+{:#x}",
+                    reloc.kind(),
+                    name,
+                    offset
+                ),
+            )
+        })?;
+
+        let start = offset as usize;
+        let mut addend = reloc.addend();
+        if reloc.has_implicit_addend() {
+            // Sign-extend the addend stored in the bytes to patch.
+            let size = kind.size();
+            let mut bytes = [0; 8];
+            bytes[..size].copy_from_slice(&self.sections[section].data[start..start + size]);
+            let shift = 64 - 8 * size as u32;
+            addend += (i64::from_le_bytes(bytes) << shift) >> shift;
+        }
+
+        let sym = match reloc.target() {
+            RelocationTarget::Symbol(sym) => {
+                file.symbol_by_index(sym).map_err(|err| error(index, err))?
+            }
+            target => {
+                return Err(error(
+                    index,
+                    format!("unsupported relocation target {:?}", target),
+                ));
+            }
+        };
+
+        let symbol = if sym.is_local() {
+            // Refer to the local symbol through its section, since the local symbols of the
+            // objects can have the same names.
+            let (target, base) = sym
+                .section()
+                .index()
+                .and_then(|sym_section| placements.get(&(index, sym_section)))
+                .copied()
+                .ok_or_else(|| {
+                    error(
+                        index,
+                        format!(
+                            "the relocation at {}+{:#x} refers to a section that isn't linked",
+                            self.sections[section].name, offset
+                        ),
+                    )
+                })?;
+            self.section_symbols.insert(target);
+            addend += (base + sym.address()) as i64;
+            self.sections[target].name.clone()
+        } else {
+            let name = sym.name().map_err(|err| error(index, err))?;
+            if !self.globals.contains_key(name) {
+                if !sym.is_weak() {
+                    self.undefined.push(name.into());
+                    return Ok(());
+                }
+
+                // The address of an undefined weak symbol is 0, but the relative references to it
+                // depend on the address of the code.
+                self.undefined_weak.insert(name.into());
+            }
+            name.into()
+        };
+
+        self.relocations.push(Relocation {
+            section,
+            offset,
+            kind,
+            symbol,
+            addend,
+        });
+        Ok(())
+    }
+}
+
+fn error(object: usize, err: impl Display) -> RasError {
+    RasError::Link(format!("object #{}: {}", object, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Linker;
+    use crate::assembled::SymbolBinding;
+    use crate::assembler::Assembler;
+    use crate::parser::parse_asm;
+    use crate::symbol::{Symbol, SymbolAttribute, SymbolType};
+    use crate::RasError;
+
+    fn object(asm: &str, symbols: &[(&str, u8)]) -> Vec<u8> {
+        let symbols = symbols
+            .iter()
+            .map(|(name, attrs)| (name.to_string(), Symbol::new_decl(SymbolType::Quad, *attrs)))
+            .collect::<Vec<_>>();
+        let mut obj = vec![];
+        Assembler::long_mode()
+            .items(parse_asm(asm).unwrap())
+            .symbols(&symbols)
+            .write_obj(&mut obj)
+            .unwrap();
+        obj
+    }
+
+    #[test]
+    fn symbol_resolution() {
+        let global = SymbolAttribute::Global as u8;
+        let weak = global | SymbolAttribute::Weak as u8;
+        let main = object(
+            "_start:\nmov $foo, %esi\nmov $bar, %edi\ncall baz\n.data\nptr:\n.quad ptr",
+            &[("foo", global), ("bar", weak), ("baz", global)],
+        );
+        let weak_foo = object("foo:\nnop", &[("foo", weak)]);
+        let strong_foo = object("foo:\nret\nbaz:\nret", &[("foo", global), ("baz", global)]);
+        let out = Linker::new()
+            .object(main)
+            .object(weak_foo)
+            .object(strong_foo)
+            .link()
+            .unwrap();
+
+        // The global definition of foo overrides the weak one, and the undefined weak bar is 0.
+        assert_eq!(
+            out.flat_binary(0x1000).unwrap(),
+            [
+                0xbe, 0x10, 0x10, 0x00, 0x00, // mov $foo, %esi
+                0xbf, 0x00, 0x00, 0x00, 0x00, // mov $bar, %edi
+                0xe8, 0x02, 0x00, 0x00, 0x00, // call baz
+                0x90, // nop (the weak foo)
+                0xc3, // foo: ret
+                0xc3, // baz: ret
+                0x12, 0x10, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // ptr: .quad ptr
+            ]
+        );
+        let foo = out.symbol("foo").unwrap();
+        assert_eq!((foo.offset, foo.binding), (16, SymbolBinding::Global));
+    }
+
+    #[test]
+    fn absolute_symbols() {
+        use object::write::{Object, Symbol, SymbolSection};
+        use object::{
+            Architecture, BinaryFormat, Endianness, SymbolFlags, SymbolKind, SymbolScope,
+        };
+
+        let global = SymbolAttribute::Global as u8;
+        let weak = global | SymbolAttribute::Weak as u8;
+        let main = object(
+            "_start:\ncall bar\nmov $abs, %eax\ncall abs",
+            &[("abs", global), ("bar", weak)],
+        );
+        // GNU as defines `abs = 0x2000` as an absolute symbol.
+        let mut abs = Object::new(BinaryFormat::Elf, Architecture::X86_64, Endianness::Little);
+        abs.add_symbol(Symbol {
+            name: b"abs".to_vec(),
+            value: 0x2000,
+            size: 0,
+            kind: SymbolKind::Data,
+            scope: SymbolScope::Linkage,
+            weak: false,
+            section: SymbolSection::Absolute,
+            flags: SymbolFlags::None,
+        });
+        let out = Linker::new()
+            .object(main)
+            .object(abs.write().unwrap())
+            .link()
+            .unwrap();
+
+        // The relative reference to the undefined weak bar is 0 - P.
+        assert_eq!(
+            out.flat_binary(0x1000).unwrap(),
+            [
+                0xe8, 0xfb, 0xef, 0xff, 0xff, // call bar
+                0xb8, 0x00, 0x20, 0x00, 0x00, // mov $abs, %eax
+                0xe8, 0xf1, 0x0f, 0x00, 0x00, // call abs
+            ]
+        );
+        assert_eq!(
+            out.absolute_symbols(),
+            [("abs".to_string(), 0x2000), ("bar".to_string(), 0)]
+        );
+        assert!(out.symbol("abs").is_none());
+    }
+
+    #[test]
+    fn local_symbols() {
+        // Both objects define a local val in their .data section.
+        let first = object("mov $val, %eax\n.data\nval:\n.long 1", &[]);
+        let second = object("mov $val, %eax\n.data\nval:\n.long 2", &[]);
+        let out = Linker::new().object(first).object(second).link().unwrap();
+
+        assert_eq!(
+            out.flat_binary(0x1000).unwrap(),
+            [
+                0xb8, 0x0a, 0x10, 0x00, 0x00, // mov $val, %eax
+                0xb8, 0x0e, 0x10, 0x00, 0x00, // mov $val, %eax
+                0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00,
+            ]
+        );
+    }
+
+    #[test]
+    fn link_errors() {
+        let global = SymbolAttribute::Global as u8;
+        let foo = || object("foo:\nret", &[("foo", global)]);
+        assert_eq!(
+            Linker::new()
+                .object(foo())
+                .object(foo())
+                .link()
+                .unwrap_err(),
+            RasError::DuplicateLabel("foo".into())
+        );
+
+        let call = object("call foo\ncall bar", &[("foo", global), ("bar", global)]);
+        assert_eq!(
+            Linker::new().object(call).object(foo()).link().unwrap_err(),
+            RasError::UndefinedSymbols(vec!["bar".into()])
+        );
+
+        assert!(matches!(
+            Linker::new().object(vec![0x7f, b'E']).link(),
+            Err(RasError::Link(_))
+        ));
+    }
+}
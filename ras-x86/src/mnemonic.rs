@@ -1,797 +1,22 @@
-#![doc = r" This file was autogenerated by build.rs."]
-use crate::error::{ParseError, ParseErrorKind};
-use serde::{Deserialize, Serialize};
-use std::hash::Hash;
-use std::str::FromStr;
-#[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd, Hash, Serialize, Deserialize)]
-pub enum Mnemonic {
-    AAA,
-    AAD,
-    AAM,
-    AAS,
-    ADC,
-    ADD,
-    AND,
-    ARPL,
-    BOUND,
-    BSF,
-    BSR,
-    BSWAP,
-    BT,
-    BTC,
-    BTR,
-    BTS,
-    CALL,
-    CBW,
-    CDQ,
-    CDQE,
-    CLC,
-    CLD,
-    CLFLUSH,
-    CLFLUSHOPT,
-    CLI,
-    CLTS,
-    CMC,
-    CMOVA,
-    CMOVAE,
-    CMOVB,
-    CMOVBE,
-    CMOVC,
-    CMOVE,
-    CMOVG,
-    CMOVGE,
-    CMOVL,
-    CMOVLE,
-    CMOVNA,
-    CMOVNAE,
-    CMOVNB,
-    CMOVNBE,
-    CMOVNC,
-    CMOVNE,
-    CMOVNG,
-    CMOVNGE,
-    CMOVNL,
-    CMOVNLE,
-    CMOVNO,
-    CMOVNP,
-    CMOVNS,
-    CMOVNZ,
-    CMOVO,
-    CMOVP,
-    CMOVPE,
-    CMP,
-    CMPS,
-    CMPSB,
-    CMPSD,
-    CMPSQ,
-    CMPSW,
-    CMPXCHG,
-    CMPXCHG16B,
-    CMPXCHG8B,
-    CPUID,
-    CQO,
-    CRC32,
-    CVTPD2PI,
-    CVTPI2PD,
-    CVTPI2PS,
-    CVTPS2PI,
-    CVTTPD2PI,
-    CVTTPS2PI,
-    CWD,
-    CWDE,
-    DAA,
-    DAS,
-    DEC,
-    DIV,
-    EMMS,
-    ENTER,
-    F2XM1,
-    FABS,
-    FADD,
-    FADDP,
-    FBLD,
-    FBSTP,
-    FCHS,
-    FCLEX,
-    FCMOVB,
-    FCMOVBE,
-    FCMOVE,
-    FCMOVNB,
-    FCMOVNBE,
-    FCMOVNE,
-    FCMOVNU,
-    FCMOVU,
-    FCOM,
-    FCOMI,
-    FCOMIP,
-    FCOMP,
-    FCOMPP,
-    FCOS,
-    FDECSTP,
-    FDIV,
-    FDIVP,
-    FDIVR,
-    FDIVRP,
-    FFREE,
-    FIADD,
-    FICOM,
-    FICOMP,
-    FIDIV,
-    FIDIVR,
-    FILD,
-    FIMUL,
-    FINCSTP,
-    FINIT,
-    FIST,
-    FISTP,
-    FISTTP,
-    FISUB,
-    FISUBR,
-    FLD,
-    FLD1,
-    FLDCW,
-    FLDENV,
-    FLDL2E,
-    FLDL2T,
-    FLDLG2,
-    FLDLN2,
-    FLDPI,
-    FLDZ,
-    FMUL,
-    FMULP,
-    FNCLEX,
-    FNINIT,
-    FNOP,
-    FNSAVE,
-    FNSTCW,
-    FNSTENV,
-    FNSTSW,
-    FPATAN,
-    FPREM,
-    FPREM1,
-    FPTAN,
-    FRNDINT,
-    FRSTOR,
-    FSAVE,
-    FSCALE,
-    FSIN,
-    FSINCOS,
-    FSQRT,
-    FST,
-    FSTCW,
-    FSTENV,
-    FSTP,
-    FSTSW,
-    FSUB,
-    FSUBP,
-    FSUBR,
-    FSUBRP,
-    FTST,
-    FUCOM,
-    FUCOMI,
-    FUCOMIP,
-    FUCOMP,
-    FUCOMPP,
-    FWAIT,
-    FXAM,
-    FXCH,
-    FXRSTOR,
-    FXRSTOR64,
-    FXSAVE,
-    FXSAVE64,
-    FXTRACT,
-    FYL2X,
-    FYL2XP1,
-    HLT,
-    IDIV,
-    IMUL,
-    IN,
-    INC,
-    INS,
-    INSB,
-    INSD,
-    INSW,
-    INT,
-    INTO,
-    INVD,
-    INVLPG,
-    IRET,
-    IRETD,
-    IRETQ,
-    JA,
-    JAE,
-    JB,
-    JBE,
-    JC,
-    JCXZ,
-    JE,
-    JECXZ,
-    JG,
-    JGE,
-    JL,
-    JLE,
-    JMP,
-    JNA,
-    JNAE,
-    JNB,
-    JNBE,
-    JNC,
-    JNE,
-    JNG,
-    JNGE,
-    JNL,
-    JNLE,
-    JNO,
-    JNP,
-    JNS,
-    JNZ,
-    JO,
-    JP,
-    JPE,
-    JPO,
-    JRCXZ,
-    JS,
-    JZ,
-    LAHF,
-    LAR,
-    LDS,
-    LEA,
-    LEAVE,
-    LES,
-    LFENCE,
-    LFS,
-    LGDT,
-    LGS,
-    LIDT,
-    LLDT,
-    LMSW,
-    LOCK,
-    LODS,
-    LODSB,
-    LODSD,
-    LODSQ,
-    LODSW,
-    LOOP,
-    LOOPE,
-    LOOPNE,
-    LSL,
-    LSS,
-    LTR,
-    MASKMOVQ,
-    MFENCE,
-    MONITOR,
-    MOV,
-    MOVBE,
-    MOVDQ2Q,
-    MOVNTI,
-    MOVNTQ,
-    MOVQ2DQ,
-    MOVS,
-    MOVSB,
-    MOVSD,
-    MOVSQ,
-    MOVSW,
-    MOVSX,
-    MOVSXD,
-    MOVZX,
-    MUL,
-    MWAIT,
-    NEG,
-    NOP,
-    NOT,
-    OR,
-    OUT,
-    OUTS,
-    OUTSB,
-    OUTSD,
-    OUTSW,
-    PAUSE,
-    POP,
-    POPA,
-    POPAD,
-    POPCNT,
-    POPF,
-    POPFD,
-    POPFQ,
-    PREFETCHNTA,
-    PREFETCHT0,
-    PREFETCHT1,
-    PREFETCHT2,
-    PSHUFW,
-    PTWRITE,
-    PUSH,
-    PUSHA,
-    PUSHAD,
-    PUSHF,
-    PUSHFD,
-    PUSHFQ,
-    RCL,
-    RCR,
-    RDMSR,
-    RDPMC,
-    RDTSC,
-    RDTSCP,
-    RET,
-    ROL,
-    ROR,
-    RSM,
-    SAHF,
-    SAL,
-    SAR,
-    SBB,
-    SCAS,
-    SCASB,
-    SCASD,
-    SCASQ,
-    SCASW,
-    SETA,
-    SETAE,
-    SETB,
-    SETBE,
-    SETC,
-    SETE,
-    SETG,
-    SETGE,
-    SETL,
-    SETLE,
-    SETNA,
-    SETNAE,
-    SETNB,
-    SETNBE,
-    SETNC,
-    SETNE,
-    SETNG,
-    SETNGE,
-    SETNL,
-    SETNLE,
-    SFENCE,
-    SGDT,
-    SHL,
-    SHLD,
-    SHR,
-    SHRD,
-    SIDT,
-    SLDT,
-    SMSW,
-    STC,
-    STD,
-    STI,
-    STOS,
-    STOSB,
-    STOSD,
-    STOSQ,
-    STOSW,
-    STR,
-    SUB,
-    SWAPGS,
-    SYSCALL,
-    SYSENTER,
-    SYSEXIT,
-    SYSRET,
-    TEST,
-    UD0,
-    UD1,
-    UD2,
-    VERR,
-    VERW,
-    WAIT,
-    WBINVD,
-    WRMSR,
-    XADD,
-    XCHG,
-    XGETBV,
-    XLAT,
-    XLATB,
-    XOR,
-    XRSTOR,
-    XRSTOR64,
-    XRSTORS,
-    XRSTORS64,
-    XSAVE,
-    XSAVE64,
-    XSAVEC,
-    XSAVEC64,
-    XSAVES,
-    XSAVES64,
-    XSETBV,
-}
-impl FromStr for Mnemonic {
-    type Err = ParseError;
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let upper = s.to_ascii_uppercase();
-        match upper.as_str() {
-            "AAA" => Ok(Mnemonic::AAA),
-            "AAD" => Ok(Mnemonic::AAD),
-            "AAM" => Ok(Mnemonic::AAM),
-            "AAS" => Ok(Mnemonic::AAS),
-            "ADC" => Ok(Mnemonic::ADC),
-            "ADD" => Ok(Mnemonic::ADD),
-            "AND" => Ok(Mnemonic::AND),
-            "ARPL" => Ok(Mnemonic::ARPL),
-            "BOUND" => Ok(Mnemonic::BOUND),
-            "BSF" => Ok(Mnemonic::BSF),
-            "BSR" => Ok(Mnemonic::BSR),
-            "BSWAP" => Ok(Mnemonic::BSWAP),
-            "BT" => Ok(Mnemonic::BT),
-            "BTC" => Ok(Mnemonic::BTC),
-            "BTR" => Ok(Mnemonic::BTR),
-            "BTS" => Ok(Mnemonic::BTS),
-            "CALL" => Ok(Mnemonic::CALL),
-            "CBW" => Ok(Mnemonic::CBW),
-            "CDQ" => Ok(Mnemonic::CDQ),
-            "CDQE" => Ok(Mnemonic::CDQE),
-            "CLC" => Ok(Mnemonic::CLC),
-            "CLD" => Ok(Mnemonic::CLD),
-            "CLFLUSH" => Ok(Mnemonic::CLFLUSH),
-            "CLFLUSHOPT" => Ok(Mnemonic::CLFLUSHOPT),
-            "CLI" => Ok(Mnemonic::CLI),
-            "CLTS" => Ok(Mnemonic::CLTS),
-            "CMC" => Ok(Mnemonic::CMC),
-            "CMOVA" => Ok(Mnemonic::CMOVA),
-            "CMOVAE" => Ok(Mnemonic::CMOVAE),
-            "CMOVB" => Ok(Mnemonic::CMOVB),
-            "CMOVBE" => Ok(Mnemonic::CMOVBE),
-            "CMOVC" => Ok(Mnemonic::CMOVC),
-            "CMOVE" => Ok(Mnemonic::CMOVE),
-            "CMOVG" => Ok(Mnemonic::CMOVG),
-            "CMOVGE" => Ok(Mnemonic::CMOVGE),
-            "CMOVL" => Ok(Mnemonic::CMOVL),
-            "CMOVLE" => Ok(Mnemonic::CMOVLE),
-            "CMOVNA" => Ok(Mnemonic::CMOVNA),
-            "CMOVNAE" => Ok(Mnemonic::CMOVNAE),
-            "CMOVNB" => Ok(Mnemonic::CMOVNB),
-            "CMOVNBE" => Ok(Mnemonic::CMOVNBE),
-            "CMOVNC" => Ok(Mnemonic::CMOVNC),
-            "CMOVNE" => Ok(Mnemonic::CMOVNE),
-            "CMOVNG" => Ok(Mnemonic::CMOVNG),
-            "CMOVNGE" => Ok(Mnemonic::CMOVNGE),
-            "CMOVNL" => Ok(Mnemonic::CMOVNL),
-            "CMOVNLE" => Ok(Mnemonic::CMOVNLE),
-            "CMOVNO" => Ok(Mnemonic::CMOVNO),
-            "CMOVNP" => Ok(Mnemonic::CMOVNP),
-            "CMOVNS" => Ok(Mnemonic::CMOVNS),
-            "CMOVNZ" => Ok(Mnemonic::CMOVNZ),
-            "CMOVO" => Ok(Mnemonic::CMOVO),
-            "CMOVP" => Ok(Mnemonic::CMOVP),
-            "CMOVPE" => Ok(Mnemonic::CMOVPE),
-            "CMP" => Ok(Mnemonic::CMP),
-            "CMPS" => Ok(Mnemonic::CMPS),
-            "CMPSB" => Ok(Mnemonic::CMPSB),
-            "CMPSD" => Ok(Mnemonic::CMPSD),
-            "CMPSQ" => Ok(Mnemonic::CMPSQ),
-            "CMPSW" => Ok(Mnemonic::CMPSW),
-            "CMPXCHG" => Ok(Mnemonic::CMPXCHG),
-            "CMPXCHG16B" => Ok(Mnemonic::CMPXCHG16B),
-            "CMPXCHG8B" => Ok(Mnemonic::CMPXCHG8B),
-            "CPUID" => Ok(Mnemonic::CPUID),
-            "CQO" => Ok(Mnemonic::CQO),
-            "CRC32" => Ok(Mnemonic::CRC32),
-            "CVTPD2PI" => Ok(Mnemonic::CVTPD2PI),
-            "CVTPI2PD" => Ok(Mnemonic::CVTPI2PD),
-            "CVTPI2PS" => Ok(Mnemonic::CVTPI2PS),
-            "CVTPS2PI" => Ok(Mnemonic::CVTPS2PI),
-            "CVTTPD2PI" => Ok(Mnemonic::CVTTPD2PI),
-            "CVTTPS2PI" => Ok(Mnemonic::CVTTPS2PI),
-            "CWD" => Ok(Mnemonic::CWD),
-            "CWDE" => Ok(Mnemonic::CWDE),
-            "DAA" => Ok(Mnemonic::DAA),
-            "DAS" => Ok(Mnemonic::DAS),
-            "DEC" => Ok(Mnemonic::DEC),
-            "DIV" => Ok(Mnemonic::DIV),
-            "EMMS" => Ok(Mnemonic::EMMS),
-            "ENTER" => Ok(Mnemonic::ENTER),
-            "F2XM1" => Ok(Mnemonic::F2XM1),
-            "FABS" => Ok(Mnemonic::FABS),
-            "FADD" => Ok(Mnemonic::FADD),
-            "FADDP" => Ok(Mnemonic::FADDP),
-            "FBLD" => Ok(Mnemonic::FBLD),
-            "FBSTP" => Ok(Mnemonic::FBSTP),
-            "FCHS" => Ok(Mnemonic::FCHS),
-            "FCLEX" => Ok(Mnemonic::FCLEX),
-            "FCMOVB" => Ok(Mnemonic::FCMOVB),
-            "FCMOVBE" => Ok(Mnemonic::FCMOVBE),
-            "FCMOVE" => Ok(Mnemonic::FCMOVE),
-            "FCMOVNB" => Ok(Mnemonic::FCMOVNB),
-            "FCMOVNBE" => Ok(Mnemonic::FCMOVNBE),
-            "FCMOVNE" => Ok(Mnemonic::FCMOVNE),
-            "FCMOVNU" => Ok(Mnemonic::FCMOVNU),
-            "FCMOVU" => Ok(Mnemonic::FCMOVU),
-            "FCOM" => Ok(Mnemonic::FCOM),
-            "FCOMI" => Ok(Mnemonic::FCOMI),
-            "FCOMIP" => Ok(Mnemonic::FCOMIP),
-            "FCOMP" => Ok(Mnemonic::FCOMP),
-            "FCOMPP" => Ok(Mnemonic::FCOMPP),
-            "FCOS" => Ok(Mnemonic::FCOS),
-            "FDECSTP" => Ok(Mnemonic::FDECSTP),
-            "FDIV" => Ok(Mnemonic::FDIV),
-            "FDIVP" => Ok(Mnemonic::FDIVP),
-            "FDIVR" => Ok(Mnemonic::FDIVR),
-            "FDIVRP" => Ok(Mnemonic::FDIVRP),
-            "FFREE" => Ok(Mnemonic::FFREE),
-            "FIADD" => Ok(Mnemonic::FIADD),
-            "FICOM" => Ok(Mnemonic::FICOM),
-            "FICOMP" => Ok(Mnemonic::FICOMP),
-            "FIDIV" => Ok(Mnemonic::FIDIV),
-            "FIDIVR" => Ok(Mnemonic::FIDIVR),
-            "FILD" => Ok(Mnemonic::FILD),
-            "FIMUL" => Ok(Mnemonic::FIMUL),
-            "FINCSTP" => Ok(Mnemonic::FINCSTP),
-            "FINIT" => Ok(Mnemonic::FINIT),
-            "FIST" => Ok(Mnemonic::FIST),
-            "FISTP" => Ok(Mnemonic::FISTP),
-            "FISTTP" => Ok(Mnemonic::FISTTP),
-            "FISUB" => Ok(Mnemonic::FISUB),
-            "FISUBR" => Ok(Mnemonic::FISUBR),
-            "FLD" => Ok(Mnemonic::FLD),
-            "FLD1" => Ok(Mnemonic::FLD1),
-            "FLDCW" => Ok(Mnemonic::FLDCW),
-            "FLDENV" => Ok(Mnemonic::FLDENV),
-            "FLDL2E" => Ok(Mnemonic::FLDL2E),
-            "FLDL2T" => Ok(Mnemonic::FLDL2T),
-            "FLDLG2" => Ok(Mnemonic::FLDLG2),
-            "FLDLN2" => Ok(Mnemonic::FLDLN2),
-            "FLDPI" => Ok(Mnemonic::FLDPI),
-            "FLDZ" => Ok(Mnemonic::FLDZ),
-            "FMUL" => Ok(Mnemonic::FMUL),
-            "FMULP" => Ok(Mnemonic::FMULP),
-            "FNCLEX" => Ok(Mnemonic::FNCLEX),
-            "FNINIT" => Ok(Mnemonic::FNINIT),
-            "FNOP" => Ok(Mnemonic::FNOP),
-            "FNSAVE" => Ok(Mnemonic::FNSAVE),
-            "FNSTCW" => Ok(Mnemonic::FNSTCW),
-            "FNSTENV" => Ok(Mnemonic::FNSTENV),
-            "FNSTSW" => Ok(Mnemonic::FNSTSW),
-            "FPATAN" => Ok(Mnemonic::FPATAN),
-            "FPREM" => Ok(Mnemonic::FPREM),
-            "FPREM1" => Ok(Mnemonic::FPREM1),
-            "FPTAN" => Ok(Mnemonic::FPTAN),
-            "FRNDINT" => Ok(Mnemonic::FRNDINT),
-            "FRSTOR" => Ok(Mnemonic::FRSTOR),
-            "FSAVE" => Ok(Mnemonic::FSAVE),
-            "FSCALE" => Ok(Mnemonic::FSCALE),
-            "FSIN" => Ok(Mnemonic::FSIN),
-            "FSINCOS" => Ok(Mnemonic::FSINCOS),
-            "FSQRT" => Ok(Mnemonic::FSQRT),
-            "FST" => Ok(Mnemonic::FST),
-            "FSTCW" => Ok(Mnemonic::FSTCW),
-            "FSTENV" => Ok(Mnemonic::FSTENV),
-            "FSTP" => Ok(Mnemonic::FSTP),
-            "FSTSW" => Ok(Mnemonic::FSTSW),
-            "FSUB" => Ok(Mnemonic::FSUB),
-            "FSUBP" => Ok(Mnemonic::FSUBP),
-            "FSUBR" => Ok(Mnemonic::FSUBR),
-            "FSUBRP" => Ok(Mnemonic::FSUBRP),
-            "FTST" => Ok(Mnemonic::FTST),
-            "FUCOM" => Ok(Mnemonic::FUCOM),
-            "FUCOMI" => Ok(Mnemonic::FUCOMI),
-            "FUCOMIP" => Ok(Mnemonic::FUCOMIP),
-            "FUCOMP" => Ok(Mnemonic::FUCOMP),
-            "FUCOMPP" => Ok(Mnemonic::FUCOMPP),
-            "FWAIT" => Ok(Mnemonic::FWAIT),
-            "FXAM" => Ok(Mnemonic::FXAM),
-            "FXCH" => Ok(Mnemonic::FXCH),
-            "FXRSTOR" => Ok(Mnemonic::FXRSTOR),
-            "FXRSTOR64" => Ok(Mnemonic::FXRSTOR64),
-            "FXSAVE" => Ok(Mnemonic::FXSAVE),
-            "FXSAVE64" => Ok(Mnemonic::FXSAVE64),
-            "FXTRACT" => Ok(Mnemonic::FXTRACT),
-            "FYL2X" => Ok(Mnemonic::FYL2X),
-            "FYL2XP1" => Ok(Mnemonic::FYL2XP1),
-            "HLT" => Ok(Mnemonic::HLT),
-            "IDIV" => Ok(Mnemonic::IDIV),
-            "IMUL" => Ok(Mnemonic::IMUL),
-            "IN" => Ok(Mnemonic::IN),
-            "INC" => Ok(Mnemonic::INC),
-            "INS" => Ok(Mnemonic::INS),
-            "INSB" => Ok(Mnemonic::INSB),
-            "INSD" => Ok(Mnemonic::INSD),
-            "INSW" => Ok(Mnemonic::INSW),
-            "INT" => Ok(Mnemonic::INT),
-            "INTO" => Ok(Mnemonic::INTO),
-            "INVD" => Ok(Mnemonic::INVD),
-            "INVLPG" => Ok(Mnemonic::INVLPG),
-            "IRET" => Ok(Mnemonic::IRET),
-            "IRETD" => Ok(Mnemonic::IRETD),
-            "IRETQ" => Ok(Mnemonic::IRETQ),
-            "JA" => Ok(Mnemonic::JA),
-            "JAE" => Ok(Mnemonic::JAE),
-            "JB" => Ok(Mnemonic::JB),
-            "JBE" => Ok(Mnemonic::JBE),
-            "JC" => Ok(Mnemonic::JC),
-            "JCXZ" => Ok(Mnemonic::JCXZ),
-            "JE" => Ok(Mnemonic::JE),
-            "JECXZ" => Ok(Mnemonic::JECXZ),
-            "JG" => Ok(Mnemonic::JG),
-            "JGE" => Ok(Mnemonic::JGE),
-            "JL" => Ok(Mnemonic::JL),
-            "JLE" => Ok(Mnemonic::JLE),
-            "JMP" => Ok(Mnemonic::JMP),
-            "JNA" => Ok(Mnemonic::JNA),
-            "JNAE" => Ok(Mnemonic::JNAE),
-            "JNB" => Ok(Mnemonic::JNB),
-            "JNBE" => Ok(Mnemonic::JNBE),
-            "JNC" => Ok(Mnemonic::JNC),
-            "JNE" => Ok(Mnemonic::JNE),
-            "JNG" => Ok(Mnemonic::JNG),
-            "JNGE" => Ok(Mnemonic::JNGE),
-            "JNL" => Ok(Mnemonic::JNL),
-            "JNLE" => Ok(Mnemonic::JNLE),
-            "JNO" => Ok(Mnemonic::JNO),
-            "JNP" => Ok(Mnemonic::JNP),
-            "JNS" => Ok(Mnemonic::JNS),
-            "JNZ" => Ok(Mnemonic::JNZ),
-            "JO" => Ok(Mnemonic::JO),
-            "JP" => Ok(Mnemonic::JP),
-            "JPE" => Ok(Mnemonic::JPE),
-            "JPO" => Ok(Mnemonic::JPO),
-            "JRCXZ" => Ok(Mnemonic::JRCXZ),
-            "JS" => Ok(Mnemonic::JS),
-            "JZ" => Ok(Mnemonic::JZ),
-            "LAHF" => Ok(Mnemonic::LAHF),
-            "LAR" => Ok(Mnemonic::LAR),
-            "LDS" => Ok(Mnemonic::LDS),
-            "LEA" => Ok(Mnemonic::LEA),
-            "LEAVE" => Ok(Mnemonic::LEAVE),
-            "LES" => Ok(Mnemonic::LES),
-            "LFENCE" => Ok(Mnemonic::LFENCE),
-            "LFS" => Ok(Mnemonic::LFS),
-            "LGDT" => Ok(Mnemonic::LGDT),
-            "LGS" => Ok(Mnemonic::LGS),
-            "LIDT" => Ok(Mnemonic::LIDT),
-            "LLDT" => Ok(Mnemonic::LLDT),
-            "LMSW" => Ok(Mnemonic::LMSW),
-            "LOCK" => Ok(Mnemonic::LOCK),
-            "LODS" => Ok(Mnemonic::LODS),
-            "LODSB" => Ok(Mnemonic::LODSB),
-            "LODSD" => Ok(Mnemonic::LODSD),
-            "LODSQ" => Ok(Mnemonic::LODSQ),
-            "LODSW" => Ok(Mnemonic::LODSW),
-            "LOOP" => Ok(Mnemonic::LOOP),
-            "LOOPE" => Ok(Mnemonic::LOOPE),
-            "LOOPNE" => Ok(Mnemonic::LOOPNE),
-            "LSL" => Ok(Mnemonic::LSL),
-            "LSS" => Ok(Mnemonic::LSS),
-            "LTR" => Ok(Mnemonic::LTR),
-            "MASKMOVQ" => Ok(Mnemonic::MASKMOVQ),
-            "MFENCE" => Ok(Mnemonic::MFENCE),
-            "MONITOR" => Ok(Mnemonic::MONITOR),
-            "MOV" => Ok(Mnemonic::MOV),
-            "MOVBE" => Ok(Mnemonic::MOVBE),
-            "MOVDQ2Q" => Ok(Mnemonic::MOVDQ2Q),
-            "MOVNTI" => Ok(Mnemonic::MOVNTI),
-            "MOVNTQ" => Ok(Mnemonic::MOVNTQ),
-            "MOVQ2DQ" => Ok(Mnemonic::MOVQ2DQ),
-            "MOVS" => Ok(Mnemonic::MOVS),
-            "MOVSB" => Ok(Mnemonic::MOVSB),
-            "MOVSD" => Ok(Mnemonic::MOVSD),
-            "MOVSQ" => Ok(Mnemonic::MOVSQ),
-            "MOVSW" => Ok(Mnemonic::MOVSW),
-            "MOVSX" => Ok(Mnemonic::MOVSX),
-            "MOVSXD" => Ok(Mnemonic::MOVSXD),
-            "MOVZX" => Ok(Mnemonic::MOVZX),
-            "MUL" => Ok(Mnemonic::MUL),
-            "MWAIT" => Ok(Mnemonic::MWAIT),
-            "NEG" => Ok(Mnemonic::NEG),
-            "NOP" => Ok(Mnemonic::NOP),
-            "NOT" => Ok(Mnemonic::NOT),
-            "OR" => Ok(Mnemonic::OR),
-            "OUT" => Ok(Mnemonic::OUT),
-            "OUTS" => Ok(Mnemonic::OUTS),
-            "OUTSB" => Ok(Mnemonic::OUTSB),
-            "OUTSD" => Ok(Mnemonic::OUTSD),
-            "OUTSW" => Ok(Mnemonic::OUTSW),
-            "PAUSE" => Ok(Mnemonic::PAUSE),
-            "POP" => Ok(Mnemonic::POP),
-            "POPA" => Ok(Mnemonic::POPA),
-            "POPAD" => Ok(Mnemonic::POPAD),
-            "POPCNT" => Ok(Mnemonic::POPCNT),
-            "POPF" => Ok(Mnemonic::POPF),
-            "POPFD" => Ok(Mnemonic::POPFD),
-            "POPFQ" => Ok(Mnemonic::POPFQ),
-            "PREFETCHNTA" => Ok(Mnemonic::PREFETCHNTA),
-            "PREFETCHT0" => Ok(Mnemonic::PREFETCHT0),
-            "PREFETCHT1" => Ok(Mnemonic::PREFETCHT1),
-            "PREFETCHT2" => Ok(Mnemonic::PREFETCHT2),
-            "PSHUFW" => Ok(Mnemonic::PSHUFW),
-            "PTWRITE" => Ok(Mnemonic::PTWRITE),
-            "PUSH" => Ok(Mnemonic::PUSH),
-            "PUSHA" => Ok(Mnemonic::PUSHA),
-            "PUSHAD" => Ok(Mnemonic::PUSHAD),
-            "PUSHF" => Ok(Mnemonic::PUSHF),
-            "PUSHFD" => Ok(Mnemonic::PUSHFD),
-            "PUSHFQ" => Ok(Mnemonic::PUSHFQ),
-            "RCL" => Ok(Mnemonic::RCL),
-            "RCR" => Ok(Mnemonic::RCR),
-            "RDMSR" => Ok(Mnemonic::RDMSR),
-            "RDPMC" => Ok(Mnemonic::RDPMC),
-            "RDTSC" => Ok(Mnemonic::RDTSC),
-            "RDTSCP" => Ok(Mnemonic::RDTSCP),
-            "RET" => Ok(Mnemonic::RET),
-            "ROL" => Ok(Mnemonic::ROL),
-            "ROR" => Ok(Mnemonic::ROR),
-            "RSM" => Ok(Mnemonic::RSM),
-            "SAHF" => Ok(Mnemonic::SAHF),
-            "SAL" => Ok(Mnemonic::SAL),
-            "SAR" => Ok(Mnemonic::SAR),
-            "SBB" => Ok(Mnemonic::SBB),
-            "SCAS" => Ok(Mnemonic::SCAS),
-            "SCASB" => Ok(Mnemonic::SCASB),
-            "SCASD" => Ok(Mnemonic::SCASD),
-            "SCASQ" => Ok(Mnemonic::SCASQ),
-            "SCASW" => Ok(Mnemonic::SCASW),
-            "SETA" => Ok(Mnemonic::SETA),
-            "SETAE" => Ok(Mnemonic::SETAE),
-            "SETB" => Ok(Mnemonic::SETB),
-            "SETBE" => Ok(Mnemonic::SETBE),
-            "SETC" => Ok(Mnemonic::SETC),
-            "SETE" => Ok(Mnemonic::SETE),
-            "SETG" => Ok(Mnemonic::SETG),
-            "SETGE" => Ok(Mnemonic::SETGE),
-            "SETL" => Ok(Mnemonic::SETL),
-            "SETLE" => Ok(Mnemonic::SETLE),
-            "SETNA" => Ok(Mnemonic::SETNA),
-            "SETNAE" => Ok(Mnemonic::SETNAE),
-            "SETNB" => Ok(Mnemonic::SETNB),
-            "SETNBE" => Ok(Mnemonic::SETNBE),
-            "SETNC" => Ok(Mnemonic::SETNC),
-            "SETNE" => Ok(Mnemonic::SETNE),
-            "SETNG" => Ok(Mnemonic::SETNG),
-            "SETNGE" => Ok(Mnemonic::SETNGE),
-            "SETNL" => Ok(Mnemonic::SETNL),
-            "SETNLE" => Ok(Mnemonic::SETNLE),
-            "SFENCE" => Ok(Mnemonic::SFENCE),
-            "SGDT" => Ok(Mnemonic::SGDT),
-            "SHL" => Ok(Mnemonic::SHL),
-            "SHLD" => Ok(Mnemonic::SHLD),
-            "SHR" => Ok(Mnemonic::SHR),
-            "SHRD" => Ok(Mnemonic::SHRD),
-            "SIDT" => Ok(Mnemonic::SIDT),
-            "SLDT" => Ok(Mnemonic::SLDT),
-            "SMSW" => Ok(Mnemonic::SMSW),
-            "STC" => Ok(Mnemonic::STC),
-            "STD" => Ok(Mnemonic::STD),
-            "STI" => Ok(Mnemonic::STI),
-            "STOS" => Ok(Mnemonic::STOS),
-            "STOSB" => Ok(Mnemonic::STOSB),
-            "STOSD" => Ok(Mnemonic::STOSD),
-            "STOSQ" => Ok(Mnemonic::STOSQ),
-            "STOSW" => Ok(Mnemonic::STOSW),
-            "STR" => Ok(Mnemonic::STR),
-            "SUB" => Ok(Mnemonic::SUB),
-            "SWAPGS" => Ok(Mnemonic::SWAPGS),
-            "SYSCALL" => Ok(Mnemonic::SYSCALL),
-            "SYSENTER" => Ok(Mnemonic::SYSENTER),
-            "SYSEXIT" => Ok(Mnemonic::SYSEXIT),
-            "SYSRET" => Ok(Mnemonic::SYSRET),
-            "TEST" => Ok(Mnemonic::TEST),
-            "UD0" => Ok(Mnemonic::UD0),
-            "UD1" => Ok(Mnemonic::UD1),
-            "UD2" => Ok(Mnemonic::UD2),
-            "VERR" => Ok(Mnemonic::VERR),
-            "VERW" => Ok(Mnemonic::VERW),
-            "WAIT" => Ok(Mnemonic::WAIT),
-            "WBINVD" => Ok(Mnemonic::WBINVD),
-            "WRMSR" => Ok(Mnemonic::WRMSR),
-            "XADD" => Ok(Mnemonic::XADD),
-            "XCHG" => Ok(Mnemonic::XCHG),
-            "XGETBV" => Ok(Mnemonic::XGETBV),
-            "XLAT" => Ok(Mnemonic::XLAT),
-            "XLATB" => Ok(Mnemonic::XLATB),
-            "XOR" => Ok(Mnemonic::XOR),
-            "XRSTOR" => Ok(Mnemonic::XRSTOR),
-            "XRSTOR64" => Ok(Mnemonic::XRSTOR64),
-            "XRSTORS" => Ok(Mnemonic::XRSTORS),
-            "XRSTORS64" => Ok(Mnemonic::XRSTORS64),
-            "XSAVE" => Ok(Mnemonic::XSAVE),
-            "XSAVE64" => Ok(Mnemonic::XSAVE64),
-            "XSAVEC" => Ok(Mnemonic::XSAVEC),
-            "XSAVEC64" => Ok(Mnemonic::XSAVEC64),
-            "XSAVES" => Ok(Mnemonic::XSAVES),
-            "XSAVES64" => Ok(Mnemonic::XSAVES64),
-            "XSETBV" => Ok(Mnemonic::XSETBV),
-            _ => Err(ParseError::new(ParseErrorKind::InvalidMnemonic(
-                s.to_string(),
-            ))),
+//! The x86 mnemonics.
+//!
+//! The `Mnemonic` enum is generated by the build script from the [x86-csv].
+//!
+//! [x86-csv]: https://github.com/GregoryComer/x86-csv
+
+include!(concat!(env!("OUT_DIR"), "/mnemonic.rs"));
+
+impl Mnemonic {
+    /// Returns the operand size (in bits) implied by the mnemonic of an instruction without
+    /// operands, if it shares its opcode with the other operand sizes (e.g. `CBW` and `CWDE` are
+    /// both `98`, and `CBW` needs an operand-size prefix outside of real mode).
+    pub(crate) fn implied_operand_size(self) -> Option<u32> {
+        use Mnemonic::*;
+
+        match self {
+            CBW | CWD | CMPSW | INSW | LODSW | MOVSW | OUTSW | SCASW | STOSW => Some(16),
+            CWDE | CDQ | CMPSD | INSD | LODSD | MOVSD | OUTSD | SCASD | STOSD => Some(32),
+            _ => None,
         }
     }
 }
@@ -1,50 +1,210 @@
 use object::endian::Endianness;
-use object::write::{Object, SectionId, StandardSection, Symbol as ObjSymbol, SymbolSection};
-use object::{Architecture, BinaryFormat, SymbolFlags, SymbolKind, SymbolScope};
+use object::write::{
+    Object, Relocation as ObjRelocation, SectionId, StandardSection, Symbol as ObjSymbol,
+    SymbolId as ObjSymbolId, SymbolSection,
+};
+use object::{
+    Architecture, BinaryFormat, RelocationEncoding, SymbolFlags, SymbolKind, SymbolScope,
+};
 
-use crate::symbol::Symbol;
-use crate::{Mode, RasResult};
+use crate::assembled::{
+    Relocation, RelocationKind, ResolvedSymbol, Section, SectionKind, SymbolBinding,
+};
+use crate::symbol::SymbolId;
+use crate::{Mode, RasError, RasResult};
+
+use std::collections::HashMap;
+
+/// The alignment of the sections of COFF objects (the default alignment used by MSVC and by
+/// GNU as for x86-64 Windows).
+const COFF_SECTION_ALIGN: u64 = 16;
+
+/// The format of the object files written by
+/// [`Assembler::write_obj`](crate::assembler::Assembler::write_obj).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ObjectFormat {
+    /// ELF, for Linux and most Unix-like systems.
+    #[default]
+    Elf,
+    /// COFF, for Windows.
+    ///
+    /// The names of the global symbols are mangled like the C compilers for Windows do (a `_`
+    /// is prepended to them in 32-bit objects), so the same code can refer to the same C
+    /// functions on Linux and on Windows.
+    Coff,
+}
 
 pub struct ObjectWriter<'o> {
     obj: Object<'o>,
-    text_section_id: SectionId,
+    format: ObjectFormat,
+    mode: Mode,
+    /// The kinds of the sections added to the object file.
+    section_kinds: HashMap<SectionId, SectionKind>,
+    /// The symbols added to the object file, and whether they're local.
+    symbols: HashMap<SymbolId, (ObjSymbolId, bool)>,
 }
 
 impl<'o> ObjectWriter<'o> {
-    pub fn new(mode: Mode) -> Self {
-        let mut obj = Object::new(BinaryFormat::Elf, Self::arch(mode), Endianness::Little);
-        let text_section_id = obj.section_id(StandardSection::Text);
+    pub fn new(format: ObjectFormat, mode: Mode) -> Self {
+        let binary_format = match format {
+            ObjectFormat::Elf => BinaryFormat::Elf,
+            ObjectFormat::Coff => BinaryFormat::Coff,
+        };
 
         Self {
-            obj,
-            text_section_id,
+            obj: Object::new(binary_format, Self::arch(mode), Endianness::Little),
+            format,
+            mode,
+            section_kinds: Default::default(),
+            symbols: Default::default(),
         }
     }
 
-    pub fn append_text_section(&mut self, text_section: &[u8]) {
-        const ALIGN: u64 = 8;
+    /// Append the contents of the section to the section of the object file with the same
+    /// name, and return the ID of the section.
+    pub fn append_section(&mut self, section: &Section) -> SectionId {
+        let standard = match section.name.as_str() {
+            ".text" => Some(StandardSection::Text),
+            ".data" => Some(StandardSection::Data),
+            ".rodata" => Some(StandardSection::ReadOnlyData),
+            ".bss" => Some(StandardSection::UninitializedData),
+            _ => None,
+        };
+        let section_id = match standard {
+            Some(standard) => self.obj.section_id(standard),
+            None => {
+                let kind = match section.kind {
+                    SectionKind::Text => object::SectionKind::Text,
+                    SectionKind::Data => object::SectionKind::Data,
+                    SectionKind::ReadOnlyData => object::SectionKind::ReadOnlyData,
+                    SectionKind::Bss => object::SectionKind::UninitializedData,
+                };
+                self.obj
+                    .add_section(vec![], section.name.as_bytes().to_vec(), kind)
+            }
+        };
 
-        self.obj
-            .append_section_data(self.text_section_id, text_section, ALIGN);
+        let align = match self.format {
+            ObjectFormat::Elf => section.align,
+            ObjectFormat::Coff => section.align.max(COFF_SECTION_ALIGN),
+        };
+        if section.kind == SectionKind::Bss {
+            self.obj
+                .append_section_bss(section_id, section.data.len() as u64, align);
+        } else {
+            self.obj
+                .append_section_data(section_id, &section.data, align);
+        }
+        self.section_kinds.insert(section_id, section.kind);
+        section_id
     }
 
-    pub fn add_text_symbol(&mut self, sym_id: &str, sym: &Symbol) {
-        // If the symbol is defined in this compilation unit...
-        if let Some(offset) = sym.offset {
-            let sym = ObjSymbol {
-                name: sym_id.as_bytes().to_vec(),
-                // If the symbol defined in a section, then this is the section offset of the
-                // symbol.
-                value: offset,
-                kind: SymbolKind::Label,
-                scope: Self::sym_scope(sym),
-                weak: sym.is_weak(),
-                section: SymbolSection::Section(self.text_section_id),
-                flags: SymbolFlags::None,
-                size: 0,
-            };
-            self.obj.add_symbol(sym);
+    pub fn add_symbol(&mut self, sym: &ResolvedSymbol, section_id: SectionId) -> RasResult<()> {
+        // The COFF weak externals are aliases of other symbols, rather than weak definitions.
+        if self.format == ObjectFormat::Coff && sym.binding == SymbolBinding::Weak {
+            return Err(RasError::Encoding(format!(
+                "the weak symbol {} can't be defined in a COFF object",
+                sym.name
+            )));
         }
+
+        // The COFF labels are only visible in their object file (and only the symbols of the
+        // functions and of the data are mangled).
+        let kind = match (self.format, self.section_kinds.get(&section_id)) {
+            (ObjectFormat::Elf, _) => SymbolKind::Label,
+            (ObjectFormat::Coff, Some(SectionKind::Text)) => SymbolKind::Text,
+            (ObjectFormat::Coff, _) => SymbolKind::Data,
+        };
+        let is_local = sym.binding == SymbolBinding::Local;
+        let name = sym.name.clone();
+        let sym = ObjSymbol {
+            name: sym.name.as_bytes().to_vec(),
+            // If the symbol defined in a section, then this is the section offset of the
+            // symbol.
+            value: sym.offset,
+            kind,
+            scope: Self::sym_scope(sym),
+            weak: sym.binding == SymbolBinding::Weak,
+            section: SymbolSection::Section(section_id),
+            flags: SymbolFlags::None,
+            size: 0,
+        };
+        let symbol_id = self.obj.add_symbol(sym);
+        self.symbols.insert(name, (symbol_id, is_local));
+        Ok(())
+    }
+
+    /// Add a relocation to the section. The symbols that weren't added to the object file are
+    /// added as undefined symbols (which are weak if `weak` is `true`).
+    pub fn add_relocation(
+        &mut self,
+        section_id: SectionId,
+        reloc: &Relocation,
+        weak: bool,
+    ) -> RasResult<()> {
+        let (symbol, addend, is_local) = match self.symbols.get(&reloc.symbol) {
+            // Like GNU as, refer to the local symbols through the symbol of their section.
+            Some(&(symbol_id, true)) => {
+                let sym = self.obj.symbol(symbol_id);
+                let (section, value) = (sym.section.id(), sym.value);
+                let section = section.expect("the local symbols are defined");
+                let addend = reloc.addend + value as i64;
+                (self.obj.section_symbol(section), addend, true)
+            }
+            Some(&(symbol_id, false)) => (symbol_id, reloc.addend, false),
+            None => {
+                // The COFF weak externals need a default definition (another symbol), which
+                // can't be specified.
+                if self.format == ObjectFormat::Coff && weak {
+                    return Err(RasError::Encoding(format!(
+                        "the undefined weak symbol {} can't be referenced in a COFF object",
+                        reloc.symbol
+                    )));
+                }
+
+                let symbol_id = self.obj.add_symbol(ObjSymbol {
+                    name: reloc.symbol.as_bytes().to_vec(),
+                    value: 0,
+                    // Like GNU as, leave the type of the undefined symbols unspecified.
+                    kind: match self.format {
+                        ObjectFormat::Elf => SymbolKind::Unknown,
+                        ObjectFormat::Coff => SymbolKind::Data,
+                    },
+                    scope: SymbolScope::Dynamic,
+                    weak,
+                    section: SymbolSection::Undefined,
+                    flags: SymbolFlags::None,
+                    size: 0,
+                });
+                self.symbols
+                    .insert(reloc.symbol.clone(), (symbol_id, false));
+                (symbol_id, reloc.addend, false)
+            }
+        };
+
+        // GNU as refers to the branch targets that may be preempted through the PLT in long
+        // mode (COFF has no PLT, so they're plain relative references in COFF objects).
+        let kind = if !reloc.kind.is_relative() {
+            object::RelocationKind::Absolute
+        } else if self.format == ObjectFormat::Elf && self.mode == Mode::Long && !is_local {
+            object::RelocationKind::PltRelative
+        } else {
+            object::RelocationKind::Relative
+        };
+        let encoding = if reloc.kind == RelocationKind::Absolute32Signed {
+            RelocationEncoding::X86Signed
+        } else {
+            RelocationEncoding::Generic
+        };
+        let reloc = ObjRelocation {
+            offset: reloc.offset,
+            size: reloc.kind.size() as u8 * 8,
+            kind,
+            encoding,
+            symbol,
+            addend,
+        };
+        Ok(self.obj.add_relocation(section_id, reloc)?)
     }
 
     pub fn write(&mut self) -> RasResult<Vec<u8>> {
@@ -59,8 +219,8 @@ impl<'o> ObjectWriter<'o> {
         }
     }
 
-    fn sym_scope(sym: &Symbol) -> SymbolScope {
-        if sym.is_global() {
+    fn sym_scope(sym: &ResolvedSymbol) -> SymbolScope {
+        if sym.binding != SymbolBinding::Local {
             SymbolScope::Dynamic
         } else {
             SymbolScope::Compilation
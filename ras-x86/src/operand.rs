@@ -1,20 +1,71 @@
+mod decorator;
+mod far_pointer;
 mod immediate;
 mod memory;
 pub(crate) mod register;
 
 use crate::repr::operand::{OperandKind, OperandRepr};
+use crate::symbol::SymbolId;
 
+pub use decorator::{Decorators, Rounding};
+pub use far_pointer::FarPointer;
 pub use immediate::{Immediate, ImmediateSize};
 pub use memory::{Memory, MemoryRel, Moffs, Scale};
 pub use register::{Register, RegisterNum};
 
+use register::reg_defs::{CL, CS, DS, DX, ES, FS, GS, SS};
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Operand {
     Register(Register),
     Immediate(Immediate),
     Memory(Memory),
+    FarPointer(FarPointer),
+    /// The address of a symbol, used as an immediate (e.g. `$label`).
+    ///
+    /// The address is as wide as the default operand size of the current mode (32 bits in long
+    /// and protected mode, 16 bits in real mode).
+    Symbol(SymbolId),
 }
 
+impl From<Register> for Operand {
+    fn from(reg: Register) -> Self {
+        Operand::Register(reg)
+    }
+}
+
+impl From<Immediate> for Operand {
+    fn from(imm: Immediate) -> Self {
+        Operand::Immediate(imm)
+    }
+}
+
+impl From<Memory> for Operand {
+    fn from(mem: Memory) -> Self {
+        Operand::Memory(mem)
+    }
+}
+
+impl From<FarPointer> for Operand {
+    fn from(ptr: FarPointer) -> Self {
+        Operand::FarPointer(ptr)
+    }
+}
+
+macro_rules! impl_from_int_for_operand {
+    ($($int:ty),*) => {
+        $(
+            impl From<$int> for Operand {
+                fn from(imm: $int) -> Self {
+                    Operand::Immediate(imm.into())
+                }
+            }
+        )*
+    };
+}
+
+impl_from_int_for_operand!(i8, i16, i32);
+
 impl Operand {
     pub fn is_register(&self) -> bool {
         matches!(self, Operand::Register(_))
@@ -28,13 +79,25 @@ impl Operand {
         matches!(self, Operand::Memory(_))
     }
 
+    pub fn is_far_pointer(&self) -> bool {
+        matches!(self, Operand::FarPointer(_))
+    }
+
     pub fn reg_num(&self) -> u8 {
         match self {
-            Operand::Register(reg) => **reg as u8,
+            Operand::Register(reg) => reg.num(),
             _ => 0,
         }
     }
 
+    /// Returns the memory operand that needs to be encoded in the ModRM/SIB bytes, if any.
+    pub fn memory(&self) -> Option<&Memory> {
+        match self {
+            Operand::Memory(mem) | Operand::FarPointer(FarPointer::Indirect(mem)) => Some(mem),
+            _ => None,
+        }
+    }
+
     pub fn immediate(&self) -> Option<Immediate> {
         match self {
             Operand::Immediate(imm) => Some(*imm),
@@ -47,6 +110,12 @@ impl Operand {
             Operand::Register(reg) => reg.size(),
             Operand::Immediate(imm) => imm.size(),
             Operand::Memory(_) => 64, // XXX
+            // The size of a far pointer depends on the operand size of the instruction, which is
+            // picked by the encoder.
+            Operand::FarPointer(_) => 0,
+            // The size of the address of a symbol depends on the current mode, which is checked
+            // by the encoder.
+            Operand::Symbol(_) => 0,
         }
     }
 
@@ -56,6 +125,69 @@ impl Operand {
 
     /// Check if an operand is compatible with a particular operand encoding.
     pub fn can_encode(&self, op: &OperandRepr) -> bool {
+        match (self, op.kind) {
+            (
+                Operand::Register(reg),
+                OperandKind::Xmm | OperandKind::XmmM32 | OperandKind::XmmM64 | OperandKind::XmmM128,
+            ) => return reg.is_xmm(),
+            (Operand::Register(reg), OperandKind::Ymm | OperandKind::YmmM256) => {
+                return reg.is_ymm()
+            }
+            (Operand::Register(reg), OperandKind::Zmm | OperandKind::ZmmM512) => {
+                return reg.is_zmm()
+            }
+            (Operand::Register(reg), OperandKind::K) => return reg.is_opmask(),
+            (
+                Operand::Register(reg),
+                OperandKind::Mm
+                | OperandKind::Mm1
+                | OperandKind::Mm2
+                | OperandKind::MmM32
+                | OperandKind::MmM64
+                | OperandKind::Mm2M64,
+            ) => return reg.is_mmx(),
+            (Operand::Register(reg), OperandKind::St0) => return *reg == Register::St(0),
+            (Operand::Register(reg), OperandKind::Sti) => return reg.is_st(),
+            (Operand::Register(reg), OperandKind::Cr) => return reg.is_cr() && reg.num() < 8,
+            (Operand::Register(reg), OperandKind::Cr8) => return *reg == Register::Cr(8),
+            (Operand::Register(reg), OperandKind::Dr) => return reg.is_dr(),
+            (Operand::Register(reg), OperandKind::Sreg) => return reg.is_segment(),
+            (Operand::Register(reg), OperandKind::Es) => return *reg == *ES,
+            (Operand::Register(reg), OperandKind::Cs) => return *reg == *CS,
+            (Operand::Register(reg), OperandKind::Ss) => return *reg == *SS,
+            (Operand::Register(reg), OperandKind::Ds) => return *reg == *DS,
+            (Operand::Register(reg), OperandKind::Fs) => return *reg == *FS,
+            (Operand::Register(reg), OperandKind::Gs) => return *reg == *GS,
+            (Operand::Register(reg), OperandKind::Cl) => return *reg == *CL,
+            (Operand::Register(reg), OperandKind::Dx) => return *reg == *DX,
+            (
+                Operand::Memory(m),
+                OperandKind::XmmM32
+                | OperandKind::XmmM64
+                | OperandKind::XmmM128
+                | OperandKind::YmmM256
+                | OperandKind::ZmmM512
+                | OperandKind::MmM32
+                | OperandKind::MmM64
+                | OperandKind::Mm2M64,
+            ) => return m.is_sib(),
+            // The operands implied by the opcode (e.g. the count of `SHL r/m32, 1`, or the
+            // interrupt number of `INT 3`).
+            (Operand::Immediate(imm), OperandKind::Zero) => return imm.value() == 0,
+            (Operand::Immediate(imm), OperandKind::One) => return imm.value() == 1,
+            (Operand::Immediate(imm), OperandKind::Three) => return imm.value() == 3,
+            // A 32 or 64-bit general-purpose register.
+            (Operand::Register(reg), OperandKind::Reg) => {
+                return reg.gpr().is_some() && reg.size() >= 32
+            }
+            // A 32 or 64-bit general-purpose register, or a 16-bit memory operand.
+            (Operand::Register(reg), OperandKind::R32M16 | OperandKind::R64M16) => {
+                return reg.gpr().is_some() && reg.size() == op.size()
+            }
+            (Operand::Memory(m), OperandKind::R32M16 | OperandKind::R64M16) => return m.is_sib(),
+            _ => {}
+        }
+
         if !self.is_memory() && self.size() > op.size() {
             return false;
         }
@@ -67,20 +199,55 @@ impl Operand {
         // RAX/EAX/AX/AH/AL
         if op.kind == OperandKind::Al {
             if let Operand::Register(reg) = self {
-                return **reg == RegisterNum::Rax;
+                return reg.gpr() == Some(RegisterNum::Rax);
             }
         }
 
         match (self, op.kind) {
-            (Operand::Register(_), OperandKind::ModRmRegMem)
-            | (Operand::Register(_), OperandKind::ModRmReg)
-            | (Operand::Immediate(_), OperandKind::Imm) => true,
+            (Operand::Register(reg), OperandKind::ModRmRegMem | OperandKind::ModRmReg) => {
+                reg.gpr().is_some()
+            }
+            (Operand::Immediate(_) | Operand::Symbol(_), OperandKind::Imm) => true,
             (Operand::Memory(m), OperandKind::ModRmRegMem) if m.is_sib() => true,
+            (
+                Operand::Memory(m),
+                OperandKind::M8
+                | OperandKind::M16
+                | OperandKind::M32
+                | OperandKind::M64
+                | OperandKind::M128
+                | OperandKind::M256
+                | OperandKind::M512
+                | OperandKind::M32Fp
+                | OperandKind::M64Fp
+                | OperandKind::M80Fp
+                | OperandKind::M16Int
+                | OperandKind::M32Int
+                | OperandKind::M64Int
+                | OperandKind::M80Bcd
+                | OperandKind::M2Byte
+                | OperandKind::M14M28Byte
+                | OperandKind::M94M108Byte
+                | OperandKind::M512Byte
+                | OperandKind::M16And16
+                | OperandKind::M16And32
+                | OperandKind::M32And32
+                | OperandKind::M16And64,
+            ) if m.is_sib() => true,
             (Operand::Memory(m), OperandKind::Moffs) if m.is_moffs() => true,
             (Operand::Memory(_), OperandKind::M) => true,
             // Be pessimistic and always use the largest (rel32) encoding for jump/call
             // instructions:
             (Operand::Memory(m), OperandKind::Rel32) if m.is_relative() => true,
+            (Operand::FarPointer(ptr), OperandKind::FarPointer16 | OperandKind::FarPointer32) => {
+                ptr.is_direct()
+            }
+            (
+                Operand::FarPointer(FarPointer::Indirect(m)),
+                OperandKind::MemIndirectFarPointer16
+                | OperandKind::MemIndirectFarPointer32
+                | OperandKind::MemIndirectFarPointer64,
+            ) => m.is_sib(),
             _ => false,
         }
     }
@@ -0,0 +1,90 @@
+use crate::operand::Register;
+use crate::repr::prefix::{EvexMasking, EvexPrefix, EvexRounding};
+
+/// The AVX-512 decorators of an instruction (e.g. `{%k1}{z}`, `{1to16}` or `{rn-sae}`).
+///
+/// Decorators can only be encoded by instructions that have an EVEX prefix.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Decorators {
+    /// The opmask register that masks the destination operand (`{%k1}`).
+    pub mask: Option<Register>,
+    /// Whether the masked out elements of the destination are zeroed (`{z}`) instead of being
+    /// left unchanged.
+    pub zeroing: bool,
+    /// The number of times the memory operand is broadcast (`{1toN}`).
+    pub broadcast: Option<u8>,
+    /// The static rounding mode (`{rn-sae}`, `{rd-sae}`, `{ru-sae}` or `{rz-sae}`), or `{sae}`.
+    pub rounding: Option<Rounding>,
+}
+
+/// The rounding control of an EVEX-encoded instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    /// Round to nearest (`{rn-sae}`).
+    Nearest = 0b00,
+    /// Round down (`{rd-sae}`).
+    Down = 0b01,
+    /// Round up (`{ru-sae}`).
+    Up = 0b10,
+    /// Round toward zero (`{rz-sae}`).
+    Zero = 0b11,
+    /// Suppress all exceptions without changing the rounding mode (`{sae}`).
+    Sae,
+}
+
+impl Decorators {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub fn mask(mut self, mask: Register) -> Self {
+        self.mask = Some(mask);
+        self
+    }
+
+    pub fn zeroing(mut self) -> Self {
+        self.zeroing = true;
+        self
+    }
+
+    pub fn broadcast(mut self, count: u8) -> Self {
+        self.broadcast = Some(count);
+        self
+    }
+
+    pub fn rounding(mut self, rounding: Rounding) -> Self {
+        self.rounding = Some(rounding);
+        self
+    }
+
+    /// Check if the decorators can be encoded by an instruction with the specified EVEX prefix.
+    ///
+    /// Embedded broadcast requires a memory operand, and static rounding (or `{sae}`) requires
+    /// all the operands to be registers.
+    pub(crate) fn can_encode(&self, evex: &EvexPrefix, has_memory: bool) -> bool {
+        let can_mask = match (self.mask, evex.masking) {
+            (None, _) => !self.zeroing,
+            (Some(_), EvexMasking::None) => false,
+            (Some(_), EvexMasking::Merge) => !self.zeroing,
+            (Some(_), EvexMasking::MergeOrZero) => true,
+        };
+
+        let can_broadcast = match (self.broadcast, evex.broadcast) {
+            (None, _) => true,
+            (Some(count), Some(elem_size)) => {
+                has_memory && u32::from(count) * elem_size == evex.vector_len()
+            }
+            (Some(_), None) => false,
+        };
+
+        let can_round = match (self.rounding, evex.rounding) {
+            (None, _) => true,
+            (Some(Rounding::Sae), EvexRounding::Sae) => !has_memory,
+            (Some(Rounding::Sae), _) => false,
+            (Some(_), EvexRounding::Er) => !has_memory,
+            (Some(_), _) => false,
+        };
+
+        can_mask && can_broadcast && can_round
+    }
+}
@@ -0,0 +1,33 @@
+use crate::operand::Memory;
+
+/// The target of a far jump or call (`ljmp`/`lcall`), which also loads a new code segment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FarPointer {
+    /// An absolute `segment:offset` pair encoded in the instruction (`ljmp $segment, $offset`).
+    ///
+    /// This is the `ptr16:16` or `ptr16:32` operand, which isn't available in long mode.
+    Direct { segment: u16, offset: u32 },
+    /// A memory location which stores the offset, followed by the 16-bit segment selector
+    /// (`ljmp *mem`).
+    ///
+    /// This is the `m16:16`, `m16:32` or `m16:64` operand.
+    Indirect(Memory),
+}
+
+impl FarPointer {
+    pub fn direct(segment: u16, offset: u32) -> Self {
+        Self::Direct { segment, offset }
+    }
+
+    pub fn indirect(mem: Memory) -> Self {
+        Self::Indirect(mem)
+    }
+
+    pub fn is_direct(&self) -> bool {
+        matches!(self, FarPointer::Direct { .. })
+    }
+
+    pub fn is_indirect(&self) -> bool {
+        matches!(self, FarPointer::Indirect(_))
+    }
+}
@@ -26,6 +26,15 @@ impl Immediate {
         }
     }
 
+    /// Returns the value of the immediate.
+    pub fn value(&self) -> i64 {
+        match *self {
+            Self::Imm8(imm) => imm.into(),
+            Self::Imm16(imm) => imm.into(),
+            Self::Imm32(imm) => imm.into(),
+        }
+    }
+
     pub fn sign_extend(self, size: ImmediateSize) -> RasResult<Self> {
         use Immediate::*;
         let imm = match (self, size) {
@@ -45,6 +54,44 @@ impl Immediate {
 
         Ok(imm)
     }
+
+    /// Reinterpret an immediate in the range 128-255 as an unsigned 8-bit immediate (e.g. the
+    /// count of `SHL r/m32, imm8`, or the nesting level of `ENTER`, which are never
+    /// sign-extended).
+    pub fn to_unsigned_imm8(self) -> Option<Self> {
+        match self {
+            Immediate::Imm8(_) => Some(self),
+            imm => u8::try_from(imm.value())
+                .ok()
+                .map(|imm| Immediate::Imm8(imm as i8)),
+        }
+    }
+}
+
+impl From<i8> for Immediate {
+    fn from(imm: i8) -> Self {
+        Immediate::Imm8(imm)
+    }
+}
+
+/// Use the smallest immediate that can hold the value.
+impl From<i16> for Immediate {
+    fn from(imm: i16) -> Self {
+        match i8::try_from(imm) {
+            Ok(imm) => Immediate::Imm8(imm),
+            Err(_) => Immediate::Imm16(imm),
+        }
+    }
+}
+
+/// Use the smallest immediate that can hold the value.
+impl From<i32> for Immediate {
+    fn from(imm: i32) -> Self {
+        match i16::try_from(imm) {
+            Ok(imm) => imm.into(),
+            Err(_) => Immediate::Imm32(imm),
+        }
+    }
 }
 
 impl TryFrom<&[u8]> for Immediate {
@@ -1,7 +1,9 @@
 use crate::error::{ParseError, ParseErrorKind};
+use crate::incremental::Label;
 use crate::operand::{Immediate, Register};
 use crate::symbol::SymbolId;
 use std::convert::TryFrom;
+use std::ops::{Add, Sub};
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Memory {
@@ -24,24 +26,21 @@ pub enum Memory {
 }
 
 /// The scale used in a SIB expression.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub enum Scale {
+    #[default]
     Byte = 0,
     Word = 0b01,
     Double = 0b10,
     Quad = 0b11,
 }
 
-impl Default for Scale {
-    fn default() -> Self {
-        Scale::Byte
-    }
-}
-
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MemoryRel {
     Absolute(Immediate),
     Label(SymbolId),
+    /// A label allocated by an [`IncrementalAssembler`](crate::incremental::IncrementalAssembler).
+    Local(Label),
 }
 
 impl Memory {
@@ -80,6 +79,58 @@ impl Memory {
     pub fn is_moffs(&self) -> bool {
         matches!(&self, Memory::Moffs(_))
     }
+
+    /// Set the base register of a SIB memory operand (e.g. `RBX + RCX * Scale::Double`).
+    ///
+    /// # Panics
+    ///
+    /// Panics if this isn't a SIB memory operand, or if it already has a base register.
+    pub fn with_base(mut self, reg: Register) -> Self {
+        match &mut self {
+            Memory::Sib {
+                base: base @ None, ..
+            } => *base = Some(reg),
+            mem => panic!("can't use {:?} as the base register of {:?}", reg, mem),
+        }
+        self
+    }
+}
+
+/// Add a displacement to a SIB memory operand (e.g. `RBX + RCX * Scale::Double + 8`).
+impl Add<i32> for Memory {
+    type Output = Memory;
+
+    fn add(mut self, disp: i32) -> Memory {
+        match &mut self {
+            Memory::Sib { displacement, .. } => {
+                *displacement = Some(displacement.unwrap_or_default() + i64::from(disp))
+            }
+            mem => panic!("{:?} can't have a displacement", mem),
+        }
+        self
+    }
+}
+
+impl Sub<i32> for Memory {
+    type Output = Memory;
+
+    fn sub(self, disp: i32) -> Memory {
+        self + -disp
+    }
+}
+
+impl TryFrom<u8> for Scale {
+    type Error = String;
+
+    fn try_from(scale: u8) -> Result<Self, Self::Error> {
+        match scale {
+            1 => Ok(Scale::Byte),
+            2 => Ok(Scale::Word),
+            4 => Ok(Scale::Double),
+            8 => Ok(Scale::Quad),
+            _ => Err(format!("invalid scale {}: expected 1, 2, 4 or 8", scale)),
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -1,25 +1,113 @@
 use std::convert::TryFrom;
-use std::ops::Deref;
 
 use crate::error::{ParseError, ParseErrorKind};
 
+/// Declare a register.
+///
+/// Each register is a unit struct that dereferences to its [`Register`], so it can be passed by
+/// value wherever a register operand is expected (e.g. `asm.mov(RAX, 103)`), or dereferenced to
+/// get the `Register` itself (`*RAX`).
+macro_rules! decl_reg_struct {
+    ($name:ident = $reg:expr) => {
+        #[allow(non_camel_case_types)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub struct $name;
+
+        impl std::ops::Deref for $name {
+            type Target = Register;
+
+            fn deref(&self) -> &Register {
+                const REG: Register = $reg;
+                &REG
+            }
+        }
+
+        impl From<$name> for Register {
+            fn from(reg: $name) -> Register {
+                *reg
+            }
+        }
+
+        impl From<$name> for Operand {
+            fn from(reg: $name) -> Operand {
+                Operand::Register(*reg)
+            }
+        }
+    };
+}
+
+/// Declare a general-purpose register, which can also be used to build memory operands (e.g.
+/// `RBX + RCX * Scale::Double + 8`).
+macro_rules! decl_gpr_struct {
+    ($name:ident = $reg:expr) => {
+        decl_reg_struct!($name = $reg);
+
+        /// `(reg)`
+        impl From<$name> for Memory {
+            fn from(reg: $name) -> Memory {
+                Memory::sib(None, Some(*reg), None, Scale::Byte, None)
+            }
+        }
+
+        /// `disp(reg)`
+        impl std::ops::Add<i32> for $name {
+            type Output = Memory;
+
+            fn add(self, displacement: i32) -> Memory {
+                Memory::from(self) + displacement
+            }
+        }
+
+        /// `-disp(reg)`
+        impl std::ops::Sub<i32> for $name {
+            type Output = Memory;
+
+            fn sub(self, displacement: i32) -> Memory {
+                Memory::from(self) - displacement
+            }
+        }
+
+        /// `(, reg, scale)`
+        impl std::ops::Mul<Scale> for $name {
+            type Output = Memory;
+
+            fn mul(self, scale: Scale) -> Memory {
+                Memory::sib(None, None, Some(*self), scale, None)
+            }
+        }
+
+        /// `(reg, index, scale)`
+        impl std::ops::Add<Memory> for $name {
+            type Output = Memory;
+
+            fn add(self, index: Memory) -> Memory {
+                index.with_base(*self)
+            }
+        }
+    };
+}
+
 macro_rules! decl_reg {
     ($name64:ident, $name32:ident, $name16:ident $(, $name8lo:ident $(, $name8hi:ident)?)? - $reg_name:ident) => {
-        lazy_static! {
-            pub static ref $name64: Register = Register::Register64(RegisterNum::$reg_name);
-            pub static ref $name32: Register = Register::Register32(RegisterNum::$reg_name);
-            pub static ref $name16: Register = Register::Register16(RegisterNum::$reg_name);
-            $(
-                pub static ref $name8lo: Register = Register::Register8Lo(RegisterNum::$reg_name);
-                $(pub static ref $name8hi: Register = Register::Register8Hi(RegisterNum::$reg_name);)?
-            )?
-        }
+        decl_gpr_struct!($name64 = Register::Register64(RegisterNum::$reg_name));
+        decl_gpr_struct!($name32 = Register::Register32(RegisterNum::$reg_name));
+        decl_reg_struct!($name16 = Register::Register16(RegisterNum::$reg_name));
+        $(
+            decl_reg_struct!($name8lo = Register::Register8Lo(RegisterNum::$reg_name));
+            $(decl_reg_struct!($name8hi = Register::Register8Hi(RegisterNum::$reg_name));)?
+        )?
+    }
+}
+
+macro_rules! decl_vec_reg {
+    ($kind:ident: $($name:ident - $num:expr),*) => {
+        $(decl_reg_struct!($name = Register::$kind($num));)*
     }
 }
 
 pub(crate) mod reg_defs {
     use super::*;
-    use lazy_static::lazy_static;
+    use crate::operand::{Memory, Operand, Scale};
 
     decl_reg!(RAX, EAX, AX, AL, AH - Rax);
     decl_reg!(RBX, EBX, BX, BL, BH - Rbx);
@@ -29,8 +117,210 @@ pub(crate) mod reg_defs {
     decl_reg!(RSI, ESI, SI, SIL - Rsi);
     decl_reg!(RBP, EBP, BP, BPL - Rbp);
     decl_reg!(RSP, ESP, SP - Rsp);
+    decl_reg!(R8, R8D, R8W, R8B - R8);
+    decl_reg!(R9, R9D, R9W, R9B - R9);
+    decl_reg!(R10, R10D, R10W, R10B - R10);
+    decl_reg!(R11, R11D, R11W, R11B - R11);
+    decl_reg!(R12, R12D, R12W, R12B - R12);
+    decl_reg!(R13, R13D, R13W, R13B - R13);
+    decl_reg!(R14, R14D, R14W, R14B - R14);
+    decl_reg!(R15, R15D, R15W, R15B - R15);
+
+    decl_vec_reg!(
+        Xmm:
+        XMM0 - 0,
+        XMM1 - 1,
+        XMM2 - 2,
+        XMM3 - 3,
+        XMM4 - 4,
+        XMM5 - 5,
+        XMM6 - 6,
+        XMM7 - 7,
+        XMM8 - 8,
+        XMM9 - 9,
+        XMM10 - 10,
+        XMM11 - 11,
+        XMM12 - 12,
+        XMM13 - 13,
+        XMM14 - 14,
+        XMM15 - 15,
+        XMM16 - 16,
+        XMM17 - 17,
+        XMM18 - 18,
+        XMM19 - 19,
+        XMM20 - 20,
+        XMM21 - 21,
+        XMM22 - 22,
+        XMM23 - 23,
+        XMM24 - 24,
+        XMM25 - 25,
+        XMM26 - 26,
+        XMM27 - 27,
+        XMM28 - 28,
+        XMM29 - 29,
+        XMM30 - 30,
+        XMM31 - 31
+    );
+
+    decl_vec_reg!(
+        Ymm:
+        YMM0 - 0,
+        YMM1 - 1,
+        YMM2 - 2,
+        YMM3 - 3,
+        YMM4 - 4,
+        YMM5 - 5,
+        YMM6 - 6,
+        YMM7 - 7,
+        YMM8 - 8,
+        YMM9 - 9,
+        YMM10 - 10,
+        YMM11 - 11,
+        YMM12 - 12,
+        YMM13 - 13,
+        YMM14 - 14,
+        YMM15 - 15,
+        YMM16 - 16,
+        YMM17 - 17,
+        YMM18 - 18,
+        YMM19 - 19,
+        YMM20 - 20,
+        YMM21 - 21,
+        YMM22 - 22,
+        YMM23 - 23,
+        YMM24 - 24,
+        YMM25 - 25,
+        YMM26 - 26,
+        YMM27 - 27,
+        YMM28 - 28,
+        YMM29 - 29,
+        YMM30 - 30,
+        YMM31 - 31
+    );
+
+    decl_vec_reg!(
+        Zmm:
+        ZMM0 - 0,
+        ZMM1 - 1,
+        ZMM2 - 2,
+        ZMM3 - 3,
+        ZMM4 - 4,
+        ZMM5 - 5,
+        ZMM6 - 6,
+        ZMM7 - 7,
+        ZMM8 - 8,
+        ZMM9 - 9,
+        ZMM10 - 10,
+        ZMM11 - 11,
+        ZMM12 - 12,
+        ZMM13 - 13,
+        ZMM14 - 14,
+        ZMM15 - 15,
+        ZMM16 - 16,
+        ZMM17 - 17,
+        ZMM18 - 18,
+        ZMM19 - 19,
+        ZMM20 - 20,
+        ZMM21 - 21,
+        ZMM22 - 22,
+        ZMM23 - 23,
+        ZMM24 - 24,
+        ZMM25 - 25,
+        ZMM26 - 26,
+        ZMM27 - 27,
+        ZMM28 - 28,
+        ZMM29 - 29,
+        ZMM30 - 30,
+        ZMM31 - 31
+    );
+
+    decl_vec_reg!(
+        St:
+        ST0 - 0,
+        ST1 - 1,
+        ST2 - 2,
+        ST3 - 3,
+        ST4 - 4,
+        ST5 - 5,
+        ST6 - 6,
+        ST7 - 7
+    );
+
+    decl_vec_reg!(
+        Mmx:
+        MM0 - 0,
+        MM1 - 1,
+        MM2 - 2,
+        MM3 - 3,
+        MM4 - 4,
+        MM5 - 5,
+        MM6 - 6,
+        MM7 - 7
+    );
+
+    decl_vec_reg!(
+        Cr:
+        CR0 - 0,
+        CR1 - 1,
+        CR2 - 2,
+        CR3 - 3,
+        CR4 - 4,
+        CR5 - 5,
+        CR6 - 6,
+        CR7 - 7,
+        CR8 - 8
+    );
+
+    decl_vec_reg!(
+        Dr:
+        DR0 - 0,
+        DR1 - 1,
+        DR2 - 2,
+        DR3 - 3,
+        DR4 - 4,
+        DR5 - 5,
+        DR6 - 6,
+        DR7 - 7
+    );
+
+    decl_vec_reg!(
+        Segment:
+        ES - 0,
+        CS - 1,
+        SS - 2,
+        DS - 3,
+        FS - 4,
+        GS - 5
+    );
+
+    decl_vec_reg!(
+        Opmask:
+        K0 - 0,
+        K1 - 1,
+        K2 - 2,
+        K3 - 3,
+        K4 - 4,
+        K5 - 5,
+        K6 - 6,
+        K7 - 7
+    );
 }
 
+/// The number of general-purpose registers available in long mode (RAX-R15).
+const GPR_COUNT: u8 = 16;
+/// The number of XMM (and YMM/ZMM) registers available in long mode (with AVX-512).
+const XMM_REG_COUNT: u8 = 32;
+/// The number of opmask registers.
+const OPMASK_REG_COUNT: u8 = 8;
+/// The number of x87 FPU stack registers.
+const ST_REG_COUNT: u8 = 8;
+/// The number of MMX registers.
+const MMX_REG_COUNT: u8 = 8;
+/// The number of control registers (CR0-CR8).
+const CR_REG_COUNT: u8 = 9;
+/// The number of debug registers.
+const DR_REG_COUNT: u8 = 8;
+
 use reg_defs::*;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -40,6 +330,24 @@ pub enum Register {
     Register16(RegisterNum),
     Register32(RegisterNum),
     Register64(RegisterNum),
+    /// An SSE register (XMM0-XMM31).
+    Xmm(u8),
+    /// An AVX register (YMM0-YMM31).
+    Ymm(u8),
+    /// An AVX-512 register (ZMM0-ZMM31).
+    Zmm(u8),
+    /// An AVX-512 opmask register (K0-K7).
+    Opmask(u8),
+    /// An x87 FPU stack register (ST(0)-ST(7)).
+    St(u8),
+    /// An MMX register (MM0-MM7).
+    Mmx(u8),
+    /// A control register (CR0-CR8).
+    Cr(u8),
+    /// A debug register (DR0-DR7).
+    Dr(u8),
+    /// A segment register (ES, CS, SS, DS, FS or GS, in the order of their numbers).
+    Segment(u8),
 }
 
 impl Register {
@@ -48,23 +356,111 @@ impl Register {
 
         match *self {
             Register8Hi(_) | Register8Lo(_) => 8,
-            Register16(_) => 16,
+            Register16(_) | Segment(_) => 16,
             Register32(_) => 32,
             Register64(_) => 64,
+            Xmm(_) => 128,
+            Ymm(_) => 256,
+            Zmm(_) => 512,
+            // The control and debug registers are 32 bits wide outside of long mode, but their
+            // size never affects the encoding.
+            Opmask(_) | Mmx(_) | Cr(_) | Dr(_) => 64,
+            St(_) => 80,
         }
     }
-}
 
-impl Deref for Register {
-    type Target = RegisterNum;
+    /// The register number, as encoded in the ModRM and SIB bytes, or in the opcode byte.
+    ///
+    /// The fourth bit of the register number (if any) is encoded in the REX prefix.
+    pub fn num(&self) -> u8 {
+        use Register::*;
+
+        match *self {
+            // AH, CH, DH and BH are encoded as SPL, BPL, SIL and DIL respectively, which is why
+            // they can't be used in instructions that have a REX prefix.
+            Register8Hi(r) => r as u8 + 4,
+            Register8Lo(r) | Register16(r) | Register32(r) | Register64(r) => r as u8,
+            Xmm(n) | Ymm(n) | Zmm(n) | Opmask(n) | St(n) | Mmx(n) | Cr(n) | Dr(n) | Segment(n) => n,
+        }
+    }
 
-    fn deref(&self) -> &Self::Target {
+    /// Returns the number of the general-purpose register, or `None` if this isn't a
+    /// general-purpose register.
+    pub fn gpr(&self) -> Option<RegisterNum> {
         use Register::*;
 
-        match self {
-            Register8Hi(r) | Register8Lo(r) | Register16(r) | Register32(r) | Register64(r) => r,
+        match *self {
+            Register8Hi(r) | Register8Lo(r) | Register16(r) | Register32(r) | Register64(r) => {
+                Some(r)
+            }
+            Xmm(_) | Ymm(_) | Zmm(_) | Opmask(_) | St(_) | Mmx(_) | Cr(_) | Dr(_) | Segment(_) => {
+                None
+            }
+        }
+    }
+
+    pub fn is_xmm(&self) -> bool {
+        matches!(self, Register::Xmm(_))
+    }
+
+    pub fn is_ymm(&self) -> bool {
+        matches!(self, Register::Ymm(_))
+    }
+
+    pub fn is_zmm(&self) -> bool {
+        matches!(self, Register::Zmm(_))
+    }
+
+    pub fn is_opmask(&self) -> bool {
+        matches!(self, Register::Opmask(_))
+    }
+
+    pub fn is_st(&self) -> bool {
+        matches!(self, Register::St(_))
+    }
+
+    pub fn is_mmx(&self) -> bool {
+        matches!(self, Register::Mmx(_))
+    }
+
+    pub fn is_cr(&self) -> bool {
+        matches!(self, Register::Cr(_))
+    }
+
+    pub fn is_dr(&self) -> bool {
+        matches!(self, Register::Dr(_))
+    }
+
+    pub fn is_segment(&self) -> bool {
+        matches!(self, Register::Segment(_))
+    }
+
+    /// Returns `true` if the register can only be encoded in an instruction with an EVEX prefix.
+    ///
+    /// This is the case for the ZMM registers, and for XMM16-XMM31 and YMM16-YMM31.
+    pub fn needs_evex(&self) -> bool {
+        match *self {
+            Register::Xmm(n) | Register::Ymm(n) => n > 0b1111,
+            Register::Zmm(_) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the register can only be encoded in an instruction with a REX prefix.
+    ///
+    /// This is the case for registers whose number doesn't fit in 3 bits, and for the low byte of
+    /// SP, BP, SI and DI (which would otherwise be interpreted as AH, CH, DH and BH).
+    pub fn needs_rex(&self) -> bool {
+        match *self {
+            Register::Register8Lo(r) => r as u8 >= 4,
+            _ => self.num() > 0b111,
         }
     }
+
+    /// Returns `true` if the register can't be encoded in an instruction with a REX prefix.
+    pub fn is_rex_incompatible(&self) -> bool {
+        matches!(self, Register::Register8Hi(_))
+    }
 }
 
 impl TryFrom<&[u8]> for Register {
@@ -107,10 +503,44 @@ impl TryFrom<&[u8]> for Register {
             b"rsp" => *RSP,
             b"esp" => *ESP,
             b"sp" => *SP,
+            b"es" => *ES,
+            b"cs" => *CS,
+            b"ss" => *SS,
+            b"ds" => *DS,
+            b"fs" => *FS,
+            b"gs" => *GS,
+            // gas accepts both %st and %st(0) for the top of the FPU stack
+            b"st" => *ST0,
             s => {
-                return Err(ParseError::new(ParseErrorKind::InvalidRegister(
-                    String::from_utf8_lossy(s).into(),
-                )))
+                if let Some(reg) = parse_extended_gpr(s) {
+                    reg
+                } else if let Some(n) = parse_reg_num(s, b"xmm", XMM_REG_COUNT) {
+                    Register::Xmm(n)
+                } else if let Some(n) = parse_reg_num(s, b"ymm", XMM_REG_COUNT) {
+                    Register::Ymm(n)
+                } else if let Some(n) = parse_reg_num(s, b"zmm", XMM_REG_COUNT) {
+                    Register::Zmm(n)
+                } else if let Some(n) = parse_reg_num(s, b"k", OPMASK_REG_COUNT) {
+                    Register::Opmask(n)
+                } else if let Some(n) = parse_reg_num(s, b"mm", MMX_REG_COUNT) {
+                    Register::Mmx(n)
+                } else if let Some(n) = parse_reg_num(s, b"cr", CR_REG_COUNT) {
+                    Register::Cr(n)
+                } else if let Some(n) = parse_reg_num(s, b"dr", DR_REG_COUNT)
+                    // gas also accepts %db0-%db7
+                    .or_else(|| parse_reg_num(s, b"db", DR_REG_COUNT))
+                {
+                    Register::Dr(n)
+                } else if let Some(n) = s
+                    .strip_suffix(b")")
+                    .and_then(|s| parse_reg_num(s, b"st(", ST_REG_COUNT))
+                {
+                    Register::St(n)
+                } else {
+                    return Err(ParseError::new(ParseErrorKind::InvalidRegister(
+                        String::from_utf8_lossy(s).into(),
+                    )));
+                }
             }
         };
 
@@ -118,6 +548,37 @@ impl TryFrom<&[u8]> for Register {
     }
 }
 
+/// Parse the name of one of the general-purpose registers R8-R15, which are only available in
+/// long mode (`r8` is 64 bits wide, and `r8d`, `r8w` and `r8b` are its low 32, 16 and 8 bits).
+fn parse_extended_gpr(s: &[u8]) -> Option<Register> {
+    let (name, size): (_, fn(RegisterNum) -> Register) = match s.split_last()? {
+        (b'd', name) => (name, Register::Register32),
+        (b'w', name) => (name, Register::Register16),
+        (b'b', name) => (name, Register::Register8Lo),
+        _ => (s, Register::Register64),
+    };
+    let num = parse_reg_num(name, b"r", GPR_COUNT)?;
+    let reg = *EXTENDED_GPRS.get(usize::from(num.checked_sub(8)?))?;
+
+    Some(size(reg))
+}
+
+/// Parse the name of a register from a numbered register file (e.g. `xmm15`).
+fn parse_reg_num(s: &[u8], prefix: &[u8], count: u8) -> Option<u8> {
+    let num = s.strip_prefix(prefix)?;
+    // Don't allow signs or leading zeroes (e.g. `xmm+1` or `xmm01`)
+    if num.is_empty() || !num.iter().all(u8::is_ascii_digit) || (num.len() > 1 && num[0] == b'0') {
+        return None;
+    }
+    let num = std::str::from_utf8(num).ok()?.parse::<u8>().ok()?;
+
+    if num < count {
+        Some(num)
+    } else {
+        None
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RegisterNum {
     Rax = 0,
@@ -128,13 +589,26 @@ pub enum RegisterNum {
     Rbp = 5,
     Rsi = 6,
     Rdi = 7,
-    // XXX
-    //R8 = 0,
-    //R9 = 1,
-    //R10 = 2,
-    //R11 = 3,
-    //R12 = 4,
-    //R13 = 5,
-    //R14 = 6,
-    //R15 = 7,
+    // The fourth bit of the number of R8-R15 is encoded in the REX prefix (REX.R, REX.X or
+    // REX.B, depending on where the register is encoded).
+    R8 = 8,
+    R9 = 9,
+    R10 = 10,
+    R11 = 11,
+    R12 = 12,
+    R13 = 13,
+    R14 = 14,
+    R15 = 15,
 }
+
+/// The general-purpose registers that are only available in long mode, in order.
+const EXTENDED_GPRS: [RegisterNum; 8] = [
+    RegisterNum::R8,
+    RegisterNum::R9,
+    RegisterNum::R10,
+    RegisterNum::R11,
+    RegisterNum::R12,
+    RegisterNum::R13,
+    RegisterNum::R14,
+    RegisterNum::R15,
+];
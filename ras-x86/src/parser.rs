@@ -1,11 +1,15 @@
 use crate::assembler::Item;
+use crate::directive::{DataValue, Directive};
 use crate::error::{ParseError, ParseErrorKind, ParseErrorList};
-use crate::instruction::Instruction;
-use crate::operand::{Immediate, Memory, MemoryRel, Moffs, Operand, Register, Scale};
+use crate::instruction::{Instruction, PseudoPrefix, PseudoPrefixes};
+use crate::operand::{
+    Decorators, FarPointer, Immediate, Memory, MemoryRel, Moffs, Operand, Register, Rounding, Scale,
+};
 use crate::Mnemonic;
 use crate::ParseResult;
+use crate::ST0;
 
-use std::convert::TryFrom;
+use std::convert::{TryFrom, TryInto};
 use std::str::FromStr;
 
 /// Parse assembly code written in AT&T syntax.
@@ -39,27 +43,277 @@ pub fn parse_asm(input: &str) -> Result<Vec<Item>, ParseErrorList> {
 fn parse_line(input: &str) -> ParseResult<Item> {
     if let Some(label) = input.strip_suffix(':') {
         Ok(Item::Label(label.into()))
+    } else if input.starts_with('.') {
+        parse_directive(input).map(Item::Directive)
     } else {
         parse_instruction(input)
     }
 }
 
+/// Parse a directive (e.g. `.quad 1, label` or `.org 0x1fe`).
+fn parse_directive(input: &str) -> ParseResult<Directive> {
+    let (name, args) = input
+        .split_once(|c: char| c.is_ascii_whitespace())
+        .unwrap_or((input, ""));
+    let name = name.to_ascii_lowercase();
+    let args = args.trim();
+
+    match name.as_str() {
+        ".org" => return parse_offset(".org", args).map(Directive::Org),
+        ".skip" | ".space" | ".zero" => return parse_offset(&name, args).map(Directive::Skip),
+        ".align" | ".balign" | ".p2align" => {
+            let align = parse_offset(&name, args)?;
+            // The argument of `.p2align` is the log2 of the alignment.
+            let align = match name.as_str() {
+                ".p2align" => (align < 64).then(|| 1 << align),
+                _ => Some(align),
+            };
+            return match align {
+                Some(align) if align.is_power_of_two() => Ok(Directive::Align(align)),
+                _ => Err(ParseError::with_context(
+                    ParseErrorKind::InvalidImmediate(args.into()),
+                    format!("the alignment of {} must be a power of 2", name),
+                )),
+            };
+        }
+        ".text" | ".data" | ".bss" => return Ok(Directive::Section(name)),
+        ".section" => {
+            // The flags and the type of the section (e.g. `.section .rodata, "a"`) are implied
+            // by its name.
+            let section = args.split(',').next().unwrap_or_default().trim();
+            if section.is_empty() {
+                return Err(ParseError::new(ParseErrorKind::UnexpectedEof));
+            }
+            return Ok(Directive::Section(section.into()));
+        }
+        _ => {}
+    }
+
+    let size = Directive::data_size(&name)
+        .ok_or_else(|| ParseError::new(ParseErrorKind::InvalidDirective(name.clone())))?;
+    let values = if args.is_empty() {
+        vec![]
+    } else {
+        args.split(',')
+            .map(|value| {
+                let value = value.trim();
+                match value.as_bytes().first() {
+                    Some(b'a'..=b'z' | b'A'..=b'Z' | b'.' | b'_') => {
+                        Ok(DataValue::Symbol(value.into()))
+                    }
+                    _ => parse_integer(value).map(DataValue::Int),
+                }
+            })
+            .collect::<ParseResult<_>>()?
+    };
+
+    Ok(Directive::Data { size, values })
+}
+
+/// Parse the non-negative offset (or size) passed to a directive.
+fn parse_offset(directive: &str, input: &str) -> ParseResult<u64> {
+    let offset = parse_integer(input)?;
+    u64::try_from(offset).map_err(|_| {
+        ParseError::with_context(
+            ParseErrorKind::InvalidImmediate(input.into()),
+            format!("the argument of {} must not be negative", directive),
+        )
+    })
+}
+
+/// Parse a decimal or a hexadecimal (`0x`) integer.
+///
+/// Like GNU as, this accepts values that only fit in 64 bits as unsigned integers (e.g.
+/// `0xffffffffffffffff`).
+fn parse_integer(input: &str) -> ParseResult<i64> {
+    let (negative, digits) = match input.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, input),
+    };
+    let value = match digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+    {
+        Some(hex) => u64::from_str_radix(hex, 16)?,
+        None => digits.parse::<u64>()?,
+    } as i64;
+
+    Ok(if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    })
+}
+
 fn parse_instruction(input: &str) -> ParseResult<Item> {
-    let (mnemonic, operands) = match input.split_once(' ') {
-        Some((mnemonic, operands)) => (
-            Mnemonic::from_str(mnemonic)?,
-            OperandParser::new(operands).parse()?,
-        ),
-        None => (Mnemonic::from_str(input)?, vec![]),
+    let (pseudo_prefixes, input) = parse_pseudo_prefixes(input)?;
+    let (mnemonic, (operands, decorators)) = match input.split_once(' ') {
+        Some((mnemonic, operands)) => (mnemonic, OperandParser::new(operands).parse()?),
+        None => (input, Default::default()),
+    };
+
+    let inst = if let Some(mnemonic) = parse_far_branch_mnemonic(mnemonic) {
+        Instruction::new(mnemonic, parse_far_branch_operands(operands)?)
+    } else if let Some((mnemonic, memory_size)) = parse_x87_mnemonic(mnemonic) {
+        let (mnemonic, operands) = parse_x87_operands(mnemonic, operands);
+        let inst = Instruction::new(mnemonic, operands);
+        match memory_size {
+            Some(size) => inst.with_memory_size(size),
+            None => inst,
+        }
+    } else {
+        Instruction::new(Mnemonic::from_str(mnemonic)?, operands)
+    };
+
+    Ok(Item::Instruction(
+        inst.with_decorators(decorators)
+            .with_pseudo_prefixes(pseudo_prefixes),
+    ))
+}
+
+/// Parse the pseudo-prefixes that precede the mnemonic of an instruction (e.g. `{load}` in
+/// `{load} mov %eax, %ecx`), and return them along with the rest of the instruction.
+fn parse_pseudo_prefixes(mut input: &str) -> ParseResult<(PseudoPrefixes, &str)> {
+    let mut prefixes = PseudoPrefixes::default();
+
+    while let Some(rest) = input.strip_prefix('{') {
+        let (name, rest) = rest
+            .split_once('}')
+            .ok_or_else(|| ParseError::new(ParseErrorKind::UnexpectedEof))?;
+        let prefix = PseudoPrefix::from_str(name.trim())?;
+        prefixes.insert(prefix).map_err(|other| {
+            ParseError::with_context(
+                ParseErrorKind::InvalidPseudoPrefix(name.into()),
+                format!("{} can't be used with {}", prefix, other),
+            )
+        })?;
+
+        input = rest.trim_start();
+    }
+
+    Ok((prefixes, input))
+}
+
+/// Far jumps and calls have their own mnemonics in AT&T syntax (`ljmp` and `lcall`), which are
+/// encoded as `JMP`/`CALL` with a far pointer operand.
+fn parse_far_branch_mnemonic(mnemonic: &str) -> Option<Mnemonic> {
+    match mnemonic.to_ascii_lowercase().as_str() {
+        "ljmp" => Some(Mnemonic::JMP),
+        "lcall" => Some(Mnemonic::CALL),
+        _ => None,
+    }
+}
+
+/// Convert the operands of a `ljmp`/`lcall` to a far pointer.
+///
+/// The target is either specified as `$segment, $offset`, or as `*mem`, where `mem` is the
+/// memory location of the far pointer.
+fn parse_far_branch_operands(operands: Vec<Operand>) -> ParseResult<Vec<Operand>> {
+    let far_ptr = match &operands[..] {
+        [Operand::Immediate(segment), Operand::Immediate(offset)] => {
+            let segment = segment.value().try_into().map_err(|_| {
+                ParseError::new(ParseErrorKind::InvalidFarPointer(format!(
+                    "segment selector {} does not fit in 16 bits",
+                    segment.value()
+                )))
+            })?;
+            let offset = offset.value().try_into().map_err(|_| {
+                ParseError::new(ParseErrorKind::InvalidFarPointer(format!(
+                    "invalid offset {}",
+                    offset.value()
+                )))
+            })?;
+            FarPointer::direct(segment, offset)
+        }
+        [Operand::Memory(mem)] if mem.is_sib() => FarPointer::indirect(mem.clone()),
+        _ => {
+            return Err(ParseError::new(ParseErrorKind::InvalidFarPointer(
+                "expected `$segment, $offset` or `*mem`".into(),
+            )))
+        }
     };
 
-    let inst = Instruction::new(mnemonic, operands);
-    Ok(Item::Instruction(inst))
+    Ok(vec![Operand::FarPointer(far_ptr)])
+}
+
+/// Parse the mnemonic of an x87 FPU instruction, and the size of its memory operand (if the
+/// mnemonic has a suffix).
+///
+/// In AT&T syntax, the suffix of a floating-point instruction is `s` (32 bits), `l` (64 bits) or
+/// `t` (80 bits), while the suffix of an integer instruction (e.g. `fild`) is `s` (16 bits), `l`
+/// (32 bits), or `ll`/`q` (64 bits).
+fn parse_x87_mnemonic(mnemonic: &str) -> Option<(Mnemonic, Option<u32>)> {
+    let mnemonic = mnemonic.to_ascii_lowercase();
+    if !mnemonic.starts_with('f') {
+        return None;
+    }
+
+    if let Ok(mnemonic) = Mnemonic::from_str(&mnemonic) {
+        return Some((mnemonic, None));
+    }
+
+    let suffixes: &[(&str, u32)] = if mnemonic.starts_with("fi") {
+        &[("ll", 64), ("q", 64), ("s", 16), ("l", 32)]
+    } else {
+        &[("s", 32), ("l", 64), ("t", 80)]
+    };
+
+    suffixes.iter().find_map(|(suffix, size)| {
+        let mnemonic = Mnemonic::from_str(mnemonic.strip_suffix(suffix)?).ok()?;
+        Some((mnemonic, Some(*size)))
+    })
+}
+
+/// Convert the operands of an x87 instruction to their Intel equivalent, like GNU as does.
+///
+/// The arithmetic instructions can have a single ST(i) operand: `fadd %st(i)` is the same as
+/// `fadd %st(i), %st`, and `faddp %st(i)` is the same as `faddp %st, %st(i)`.
+///
+/// For compatibility with the original UnixWare assembler, if the destination is ST(i), GNU as
+/// encodes `fsub` as `FSUBR` (and vice versa). The same goes for `fdiv`/`fdivr`, and for their
+/// popping variants (including the ones without operands).
+fn parse_x87_operands(mnemonic: Mnemonic, mut operands: Vec<Operand>) -> (Mnemonic, Vec<Operand>) {
+    use Mnemonic::*;
+
+    if let [Operand::Register(reg)] = operands[..] {
+        if reg.is_st() {
+            match mnemonic {
+                FADD | FSUB | FSUBR | FMUL | FDIV | FDIVR => {
+                    operands.insert(0, Operand::Register(*ST0))
+                }
+                FADDP | FSUBP | FSUBRP | FMULP | FDIVP | FDIVRP => {
+                    operands.push(Operand::Register(*ST0))
+                }
+                _ => {}
+            }
+        }
+    }
+
+    let is_reversed = match operands.first() {
+        Some(Operand::Register(Register::St(n))) => *n != 0 && operands.len() == 2,
+        None => matches!(mnemonic, FSUBP | FSUBRP | FDIVP | FDIVRP),
+        _ => false,
+    };
+
+    let mnemonic = match mnemonic {
+        FSUB if is_reversed => FSUBR,
+        FSUBR if is_reversed => FSUB,
+        FSUBP if is_reversed => FSUBRP,
+        FSUBRP if is_reversed => FSUBP,
+        FDIV if is_reversed => FDIVR,
+        FDIVR if is_reversed => FDIV,
+        FDIVP if is_reversed => FDIVRP,
+        FDIVRP if is_reversed => FDIVP,
+        mnemonic => mnemonic,
+    };
+
+    (mnemonic, operands)
 }
 
 struct OperandParser<'a> {
     input: &'a [u8],
     pos: usize,
+    decorators: Decorators,
 }
 
 impl<'a> OperandParser<'a> {
@@ -67,14 +321,21 @@ impl<'a> OperandParser<'a> {
         Self {
             input: input.as_bytes(),
             pos: 0,
+            decorators: Default::default(),
         }
     }
 
-    pub fn parse(mut self) -> ParseResult<Vec<Operand>> {
+    pub fn parse(mut self) -> ParseResult<(Vec<Operand>, Decorators)> {
         let mut operands = vec![];
         loop {
-            let operand = self.parse_single_operand()?;
-            operands.push(operand);
+            // The rounding control is specified as a separate operand (e.g. `{rn-sae}`)
+            if self.pos < self.input.len() && self.input[self.pos] == b'{' {
+                self.parse_rounding()?;
+            } else {
+                let operand = self.parse_single_operand()?;
+                operands.push(operand);
+                self.parse_decorators()?;
+            }
 
             if !self.skip_until_next_operand() {
                 break;
@@ -91,10 +352,78 @@ impl<'a> OperandParser<'a> {
             ));
         }
 
-        // AT&T syntax reverses the order of the operands:
-        operands.reverse();
+        // AT&T syntax reverses the order of the operands, except for the instructions with two
+        // immediate operands (like GNU as, e.g. `enter $frame_size, $nesting_level`, or
+        // `ljmp $segment, $offset`):
+        if !matches!(operands[..], [Operand::Immediate(_), Operand::Immediate(_)]) {
+            operands.reverse();
+        }
+
+        Ok((operands, self.decorators))
+    }
+
+    /// Parse the AVX-512 decorators that follow an operand (e.g. `%zmm0{%k1}{z}` or
+    /// `(%rax){1to16}`).
+    fn parse_decorators(&mut self) -> ParseResult<()> {
+        self.skip_whitespace();
+        while self.pos < self.input.len() && self.input[self.pos] == b'{' {
+            let decorator = self.parse_braced()?;
+            let invalid_decorator =
+                || ParseError::new(ParseErrorKind::InvalidDecorator(decorator.clone()));
+
+            if decorator == "z" {
+                self.decorators.zeroing = true;
+            } else if let Some(reg) = decorator.strip_prefix('%') {
+                let reg = Register::try_from(reg.as_bytes())?;
+                // k0 can't be used as a write mask (an EVEX.aaa of 0 means "no masking").
+                if !reg.is_opmask() || reg.num() == 0 {
+                    return Err(invalid_decorator());
+                }
+                self.decorators.mask = Some(reg);
+            } else if let Some(count) = decorator.strip_prefix("1to") {
+                let count = count.parse::<u8>().map_err(|_| invalid_decorator())?;
+                self.decorators.broadcast = Some(count);
+            } else {
+                return Err(invalid_decorator());
+            }
+            self.skip_whitespace();
+        }
+
+        Ok(())
+    }
+
+    /// Parse a rounding control operand (e.g. `{rn-sae}`).
+    fn parse_rounding(&mut self) -> ParseResult<()> {
+        let rounding = self.parse_braced()?;
+        let rounding = match rounding.as_str() {
+            "rn-sae" => Rounding::Nearest,
+            "rd-sae" => Rounding::Down,
+            "ru-sae" => Rounding::Up,
+            "rz-sae" => Rounding::Zero,
+            "sae" => Rounding::Sae,
+            _ => return Err(ParseError::new(ParseErrorKind::InvalidDecorator(rounding))),
+        };
+        self.decorators.rounding = Some(rounding);
+        self.skip_whitespace();
 
-        Ok(operands)
+        Ok(())
+    }
+
+    /// Parse an expression enclosed in curly braces, and return the expression.
+    fn parse_braced(&mut self) -> ParseResult<String> {
+        self.consume_char(b'{')?;
+        let start = self.pos;
+        self.skip_while(|c| c != b'}');
+        if self.pos >= self.input.len() {
+            return Err(ParseError::with_context(
+                ParseErrorKind::UnexpectedEof,
+                "expected closing brace",
+            ));
+        }
+        let expr = String::from_utf8_lossy(&self.input[start..self.pos]).into();
+        self.pos += 1;
+
+        Ok(expr)
     }
 
     fn parse_single_operand(&mut self) -> ParseResult<Operand> {
@@ -106,8 +435,19 @@ impl<'a> OperandParser<'a> {
         }
         match self.input[self.pos] {
             b'%' => self.parse_register().map(Operand::Register),
+            // The address of a symbol (e.g. `$label`).
+            b'$' if matches!(
+                self.input.get(self.pos + 1),
+                Some(b'a'..=b'z' | b'A'..=b'Z' | b'.' | b'_')
+            ) =>
+            {
+                self.pos += 1;
+                Ok(Operand::Symbol(self.parse_symbol_name()))
+            }
             b'$' => self.parse_immediate().map(Operand::Immediate),
-            b'0'..=b'9' | b'(' => self.parse_memory().map(Operand::Memory),
+            // The target of an indirect jump or call is prefixed with a '*'.
+            b'*' => self.parse_indirect_branch_target(),
+            b'0'..=b'9' | b'-' | b'(' => self.parse_memory().map(Operand::Memory),
             // Symbol names begin with a letter or with one of '.', '_'.
             // TODO: gas alllows quoted symbol names too
             b'a'..=b'z' | b'A'..=b'Z' | b'.' | b'_' => self.parse_label().map(Operand::Memory),
@@ -118,10 +458,29 @@ impl<'a> OperandParser<'a> {
         }
     }
 
+    fn parse_indirect_branch_target(&mut self) -> ParseResult<Operand> {
+        self.advance_or_eof()?;
+        match self.input[self.pos] {
+            b'%' => self.parse_register().map(Operand::Register),
+            b'0'..=b'9' | b'-' | b'(' => self.parse_memory().map(Operand::Memory),
+            c => Err(ParseError::with_context(
+                ParseErrorKind::UnexpectedChar(c.into()),
+                "expected register or memory operand after '*'",
+            )),
+        }
+    }
+
     fn parse_register(&mut self) -> ParseResult<Register> {
         self.advance_or_eof()?;
         let start = self.pos;
-        self.skip_while_alpha();
+        self.skip_while_alnum();
+        // The FPU stack registers are written as `%st(i)`
+        if self.input[start..self.pos].eq_ignore_ascii_case(b"st")
+            && self.input.get(self.pos) == Some(&b'(')
+        {
+            self.skip_while(|c| c != b')');
+            self.pos = (self.pos + 1).min(self.input.len());
+        }
         Register::try_from(&self.input[start..self.pos])
     }
 
@@ -133,6 +492,10 @@ impl<'a> OperandParser<'a> {
     }
 
     fn parse_label(&mut self) -> ParseResult<Memory> {
+        Ok(Memory::Relative(MemoryRel::Label(self.parse_symbol_name())))
+    }
+
+    fn parse_symbol_name(&mut self) -> String {
         let start = self.pos;
         // Skip over the first char from the name (fewer restrictions apply to the rest of the
         // chars).
@@ -140,8 +503,7 @@ impl<'a> OperandParser<'a> {
         let is_symbol_name =
             |c: u8| c.is_ascii_alphanumeric() || c == b'.' || c == b'_' || c == b'$';
         self.skip_while(is_symbol_name);
-        let symbol = String::from_utf8(self.input[start..self.pos].to_vec()).unwrap();
-        Ok(Memory::Relative(MemoryRel::Label(symbol)))
+        String::from_utf8(self.input[start..self.pos].to_vec()).unwrap()
     }
 
     fn parse_memory(&mut self) -> ParseResult<Memory> {
@@ -182,7 +544,7 @@ impl<'a> OperandParser<'a> {
         // (%rax)
         //      ^
         if self.consume_char(b')').is_ok() {
-            return Ok(Memory::sib(None, base, None, Scale::Byte, None));
+            return Ok(Memory::sib(None, base, None, Scale::Byte, displacement));
         }
         let has_index_comma = self.consume_char(b',').is_ok();
         self.skip_whitespace();
@@ -196,7 +558,7 @@ impl<'a> OperandParser<'a> {
         // (%rax, )
         //        ^
         if self.consume_char(b')').is_ok() {
-            return Ok(Memory::sib(None, base, None, Scale::Byte, None));
+            return Ok(Memory::sib(None, base, None, Scale::Byte, displacement));
         }
 
         // Missing comma, e.g.:
@@ -294,8 +656,8 @@ impl<'a> OperandParser<'a> {
         }
     }
 
-    fn skip_while_alpha(&mut self) {
-        while self.pos < self.input.len() && self.input[self.pos].is_ascii_alphabetic() {
+    fn skip_while_alnum(&mut self) {
+        while self.pos < self.input.len() && self.input[self.pos].is_ascii_alphanumeric() {
             self.pos += 1;
         }
     }
@@ -331,7 +693,10 @@ impl<'a> OperandParser<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::{i, imm16, imm32, imm8, reg, RAX, RCX};
+    use crate::operand::{Decorators, Rounding};
+    use crate::{i, imm16, imm32, imm8, reg, sib, RAX, RBX, RCX, XMM0, XMM15, YMM0, YMM1, YMM15};
+    use crate::{AX, CR3, CR8, DR0, DR7, K1, K2, MM0, MM7, ST0, ST1, ST7, ZMM0, ZMM1, ZMM2, ZMM31};
+    use crate::{EAX, ECX, ESI, R10, R11B, R12, R15D, R8W};
 
     #[test]
     fn no_operands() {
@@ -352,6 +717,72 @@ mod tests {
         );
     }
 
+    #[test]
+    fn directives() {
+        assert_eq!(
+            parse_line(".quad 1, -0x10, label").unwrap(),
+            Item::Directive(Directive::Data {
+                size: 8,
+                values: vec![
+                    DataValue::Int(1),
+                    DataValue::Int(-16),
+                    DataValue::Symbol("label".into())
+                ]
+            })
+        );
+        assert_eq!(
+            parse_line(".word 0xaa55").unwrap(),
+            Item::Directive(Directive::Data {
+                size: 2,
+                values: vec![DataValue::Int(0xaa55)]
+            })
+        );
+        assert_eq!(
+            parse_line(".org 510").unwrap(),
+            Item::Directive(Directive::Org(510))
+        );
+        assert_eq!(
+            parse_line(".org -1").unwrap_err().kind(),
+            &ParseErrorKind::InvalidImmediate("-1".into())
+        );
+        assert_eq!(
+            parse_line(".skip 16").unwrap(),
+            Item::Directive(Directive::Skip(16))
+        );
+        assert_eq!(
+            parse_line(".balign 16").unwrap(),
+            Item::Directive(Directive::Align(16))
+        );
+        assert_eq!(
+            parse_line(".p2align 3").unwrap(),
+            Item::Directive(Directive::Align(8))
+        );
+        assert_eq!(
+            parse_line(".align 12").unwrap_err().kind(),
+            &ParseErrorKind::InvalidImmediate("12".into())
+        );
+        assert_eq!(
+            parse_line(".bss").unwrap(),
+            Item::Directive(Directive::Section(".bss".into()))
+        );
+        assert_eq!(
+            parse_line(".section .rodata, \"a\"").unwrap(),
+            Item::Directive(Directive::Section(".rodata".into()))
+        );
+        assert_eq!(
+            parse_line(".ascii \"x\"").unwrap_err().kind(),
+            &ParseErrorKind::InvalidDirective(".ascii".into())
+        );
+    }
+
+    #[test]
+    fn symbol_address() {
+        assert_eq!(
+            parse_line("mov $.Lmsg, %esi").unwrap(),
+            Item::Instruction(i!(MOV, reg!(ESI), Operand::Symbol(".Lmsg".into())))
+        );
+    }
+
     #[test]
     fn invalid_register() {
         assert_eq!(
@@ -362,6 +793,14 @@ mod tests {
             parse_line("pop %rex").unwrap_err().kind(),
             &ParseErrorKind::InvalidRegister("rex".into())
         );
+        assert_eq!(
+            parse_line("pop %r16").unwrap_err().kind(),
+            &ParseErrorKind::InvalidRegister("r16".into())
+        );
+        assert_eq!(
+            parse_line("inc %r8l").unwrap_err().kind(),
+            &ParseErrorKind::InvalidRegister("r8l".into())
+        );
     }
 
     #[test]
@@ -384,19 +823,341 @@ mod tests {
         );
     }
 
+    #[test]
+    fn extended_registers() {
+        assert_eq!(
+            parse_line("add %r10, %r15d").unwrap(),
+            Item::Instruction(i!(ADD, reg!(R15D), reg!(R10)))
+        );
+        assert_eq!(
+            parse_line("mov %R8W, (%rax,%r12,1)").unwrap(),
+            Item::Instruction(i!(MOV, sib!(; ; (RAX, R12,)), reg!(R8W)))
+        );
+        assert_eq!(
+            parse_line("inc %r11b").unwrap(),
+            Item::Instruction(i!(INC, reg!(R11B)))
+        );
+    }
+
+    #[test]
+    fn suffixed_system_instructions() {
+        assert_eq!(
+            parse_line("sysretq").unwrap(),
+            Item::Instruction(i!(SYSRETQ))
+        );
+        assert_eq!(
+            parse_line("sysexitq").unwrap(),
+            Item::Instruction(i!(SYSEXITQ))
+        );
+    }
+
     #[test]
     fn xor_imm() {
         assert_eq!(
-            parse_line("xor $127, $2").unwrap(),
-            Item::Instruction(i!(XOR, imm8!(2), imm8!(127)))
+            parse_line("xor $127, %eax").unwrap(),
+            Item::Instruction(i!(XOR, reg!(EAX), imm8!(127)))
+        );
+        assert_eq!(
+            parse_line("xor $128, %eax").unwrap(),
+            Item::Instruction(i!(XOR, reg!(EAX), imm16!(128)))
+        );
+        assert_eq!(
+            parse_line("xor $65536, %eax").unwrap(),
+            Item::Instruction(i!(XOR, reg!(EAX), imm32!(65536)))
+        );
+    }
+
+    #[test]
+    fn indirect_branch() {
+        assert_eq!(
+            parse_line("jmp *%rax").unwrap(),
+            Item::Instruction(i!(JMP, reg!(RAX)))
+        );
+        assert_eq!(
+            parse_line("call *8(%rbx)").unwrap(),
+            Item::Instruction(i!(CALL, sib!(; 8; (RBX,,))))
+        );
+        assert_eq!(
+            parse_line("call *foo").unwrap_err().kind(),
+            &ParseErrorKind::UnexpectedChar('f')
+        );
+    }
+
+    #[test]
+    fn two_immediates() {
+        // The immediates aren't reversed (the frame size comes first, like in Intel syntax).
+        assert_eq!(
+            parse_line("enter $8, $0").unwrap(),
+            Item::Instruction(i!(ENTER, imm8!(8), imm8!(0)))
+        );
+        assert_eq!(
+            parse_line("mov $8, %eax").unwrap(),
+            Item::Instruction(i!(MOV, reg!(EAX), imm8!(8)))
+        );
+    }
+
+    #[test]
+    fn far_branch() {
+        assert_eq!(
+            parse_line("ljmp $16, $4096").unwrap(),
+            Item::Instruction(i!(JMP, Operand::FarPointer(FarPointer::direct(16, 4096))))
+        );
+        assert_eq!(
+            parse_line("lcall *8(%rbx)").unwrap(),
+            Item::Instruction(i!(
+                CALL,
+                Operand::FarPointer(FarPointer::indirect(Memory::sib(
+                    None,
+                    Some(*RBX),
+                    None,
+                    Scale::Byte,
+                    Some(8)
+                )))
+            ))
+        );
+        assert!(matches!(
+            parse_line("ljmp $65536, $0").unwrap_err().kind(),
+            ParseErrorKind::InvalidFarPointer(_)
+        ));
+        assert!(matches!(
+            parse_line("ljmp %rax").unwrap_err().kind(),
+            ParseErrorKind::InvalidFarPointer(_)
+        ));
+    }
+
+    #[test]
+    fn xmm_registers() {
+        assert_eq!(
+            parse_line("addsd %xmm15, %xmm0").unwrap(),
+            Item::Instruction(i!(ADDSD, reg!(XMM0), reg!(XMM15)))
+        );
+        assert!(parse_line("addsd %xmm32, %xmm0").is_err());
+    }
+
+    #[test]
+    fn ymm_registers() {
+        assert_eq!(
+            parse_line("vaddps %ymm15, %ymm1, %ymm0").unwrap(),
+            Item::Instruction(i!(VADDPS, reg!(YMM0), reg!(YMM1), reg!(YMM15)))
+        );
+        assert!(parse_line("vaddps %ymm32, %ymm1, %ymm0").is_err());
+    }
+
+    #[test]
+    fn zmm_and_opmask_registers() {
+        assert_eq!(
+            parse_line("vaddps %zmm2, %zmm1, %zmm31").unwrap(),
+            Item::Instruction(i!(VADDPS, reg!(ZMM31), reg!(ZMM1), reg!(ZMM2)))
+        );
+        assert_eq!(
+            parse_line("vpcmpeqd %zmm2, %zmm1, %k1").unwrap(),
+            Item::Instruction(i!(VPCMPEQD, reg!(K1), reg!(ZMM1), reg!(ZMM2)))
+        );
+        assert!(parse_line("vaddps %zmm32, %zmm1, %zmm0").is_err());
+        assert!(parse_line("kmovw %k8, %k1").is_err());
+    }
+
+    #[test]
+    fn evex_decorators() {
+        assert_eq!(
+            parse_line("vaddps %zmm2, %zmm1, %zmm0{%k1}{z}").unwrap(),
+            Item::Instruction(
+                i!(VADDPS, reg!(ZMM0), reg!(ZMM1), reg!(ZMM2))
+                    .with_decorators(Decorators::default().mask(*K1).zeroing())
+            )
+        );
+        assert_eq!(
+            parse_line("vpcmpeqd (%rax){1to16}, %zmm1, %k1{%k2}").unwrap(),
+            Item::Instruction(
+                i!(VPCMPEQD, reg!(K1), reg!(ZMM1), sib!(; ; (RAX,,)))
+                    .with_decorators(Decorators::default().mask(*K2).broadcast(16))
+            )
+        );
+        assert_eq!(
+            parse_line("vaddps {rn-sae}, %zmm2, %zmm1, %zmm0").unwrap(),
+            Item::Instruction(
+                i!(VADDPS, reg!(ZMM0), reg!(ZMM1), reg!(ZMM2))
+                    .with_decorators(Decorators::default().rounding(Rounding::Nearest))
+            )
+        );
+    }
+
+    #[test]
+    fn invalid_evex_decorators() {
+        assert_eq!(
+            parse_line("vaddps %zmm2, %zmm1, %zmm0{%k0}")
+                .unwrap_err()
+                .kind(),
+            &ParseErrorKind::InvalidDecorator("%k0".into())
+        );
+        assert_eq!(
+            parse_line("vaddps %zmm2, %zmm1, %zmm0{%rax}")
+                .unwrap_err()
+                .kind(),
+            &ParseErrorKind::InvalidDecorator("%rax".into())
+        );
+        assert_eq!(
+            parse_line("vaddps (%rax){1tox}, %zmm1, %zmm0")
+                .unwrap_err()
+                .kind(),
+            &ParseErrorKind::InvalidDecorator("1tox".into())
+        );
+        assert_eq!(
+            parse_line("vaddps {foo}, %zmm2, %zmm1, %zmm0")
+                .unwrap_err()
+                .kind(),
+            &ParseErrorKind::InvalidDecorator("foo".into())
+        );
+    }
+
+    #[test]
+    fn pseudo_prefixes() {
+        assert_eq!(
+            parse_line("{load} mov %eax, %ecx").unwrap(),
+            Item::Instruction(
+                i!(MOV, reg!(ECX), reg!(EAX)).with_pseudo_prefixes(PseudoPrefix::Load.into())
+            )
+        );
+
+        let mut prefixes = PseudoPrefixes::default();
+        prefixes.insert(PseudoPrefix::Disp32).unwrap();
+        prefixes.insert(PseudoPrefix::Rex).unwrap();
+        assert_eq!(
+            parse_line("{disp32}{rex}  mov 8(%rax), %ecx").unwrap(),
+            Item::Instruction(
+                i!(MOV, reg!(ECX), sib!(; 8; (RAX,,))).with_pseudo_prefixes(prefixes)
+            )
+        );
+        assert_eq!(
+            parse_line("{rex} nop").unwrap(),
+            Item::Instruction(i!(NOP).with_pseudo_prefixes(PseudoPrefix::Rex.into()))
+        );
+    }
+
+    #[test]
+    fn invalid_pseudo_prefixes() {
+        assert_eq!(
+            parse_line("{foo} mov %eax, %ecx").unwrap_err().kind(),
+            &ParseErrorKind::InvalidPseudoPrefix("foo".into())
+        );
+
+        let err = parse_line("{load} {store} mov %eax, %ecx").unwrap_err();
+        assert_eq!(
+            err.kind(),
+            &ParseErrorKind::InvalidPseudoPrefix("store".into())
+        );
+        assert_eq!(err.ctx(), "{store} can't be used with {load}");
+
+        assert_eq!(
+            parse_line("{vex mov %eax, %ecx").unwrap_err().kind(),
+            &ParseErrorKind::UnexpectedEof
+        );
+    }
+
+    #[test]
+    fn x87_registers() {
+        assert_eq!(
+            parse_line("fadd %st(1), %st").unwrap(),
+            Item::Instruction(i!(FADD, reg!(ST0), reg!(ST1)))
+        );
+        assert_eq!(
+            parse_line("fxch %ST(7)").unwrap(),
+            Item::Instruction(i!(FXCH, reg!(ST7)))
+        );
+        assert_eq!(
+            parse_line("fnstsw %ax").unwrap(),
+            Item::Instruction(i!(FNSTSW, reg!(AX)))
+        );
+        assert_eq!(
+            parse_line("fld %st(8)").unwrap_err().kind(),
+            &ParseErrorKind::InvalidRegister("st(8)".into())
+        );
+        assert_eq!(
+            parse_line("fld %st(1").unwrap_err().kind(),
+            &ParseErrorKind::InvalidRegister("st(1".into())
+        );
+    }
+
+    #[test]
+    fn x87_mnemonic_suffix() {
+        assert_eq!(
+            parse_line("flds (%rax)").unwrap(),
+            Item::Instruction(i!(FLD, sib!(; ; (RAX,,))).with_memory_size(32))
+        );
+        assert_eq!(
+            parse_line("fldt (%rax)").unwrap(),
+            Item::Instruction(i!(FLD, sib!(; ; (RAX,,))).with_memory_size(80))
+        );
+        assert_eq!(
+            parse_line("filds (%rax)").unwrap(),
+            Item::Instruction(i!(FILD, sib!(; ; (RAX,,))).with_memory_size(16))
+        );
+        assert_eq!(
+            parse_line("fildll (%rax)").unwrap(),
+            Item::Instruction(i!(FILD, sib!(; ; (RAX,,))).with_memory_size(64))
+        );
+        assert_eq!(
+            parse_line("fldx (%rax)").unwrap_err().kind(),
+            &ParseErrorKind::InvalidMnemonic("fldx".into())
+        );
+    }
+
+    #[test]
+    fn x87_operand_order() {
+        // A single operand is the source of FADD, and the destination of FADDP
+        assert_eq!(
+            parse_line("fadd %st(1)").unwrap(),
+            Item::Instruction(i!(FADD, reg!(ST0), reg!(ST1)))
+        );
+        assert_eq!(
+            parse_line("faddp %st(1)").unwrap(),
+            Item::Instruction(i!(FADDP, reg!(ST1), reg!(ST0)))
+        );
+        // fsub/fsubr and fdiv/fdivr are swapped if the destination is ST(i)
+        assert_eq!(
+            parse_line("fsub %st(1), %st").unwrap(),
+            Item::Instruction(i!(FSUB, reg!(ST0), reg!(ST1)))
+        );
+        assert_eq!(
+            parse_line("fsub %st, %st(1)").unwrap(),
+            Item::Instruction(i!(FSUBR, reg!(ST1), reg!(ST0)))
+        );
+        assert_eq!(
+            parse_line("fdivrp %st, %st(1)").unwrap(),
+            Item::Instruction(i!(FDIVP, reg!(ST1), reg!(ST0)))
+        );
+        assert_eq!(parse_line("fsubp").unwrap(), Item::Instruction(i!(FSUBRP)));
+        assert_eq!(parse_line("faddp").unwrap(), Item::Instruction(i!(FADDP)));
+    }
+
+    #[test]
+    fn mmx_registers() {
+        assert_eq!(
+            parse_line("paddd %mm7, %mm0").unwrap(),
+            Item::Instruction(i!(PADDD, reg!(MM0), reg!(MM7)))
+        );
+        assert!(parse_line("paddd %mm8, %mm0").is_err());
+    }
+
+    #[test]
+    fn control_and_debug_registers() {
+        assert_eq!(
+            parse_line("mov %cr3, %rax").unwrap(),
+            Item::Instruction(i!(MOV, reg!(RAX), reg!(CR3)))
+        );
+        assert_eq!(
+            parse_line("mov %rax, %cr8").unwrap(),
+            Item::Instruction(i!(MOV, reg!(CR8), reg!(RAX)))
         );
         assert_eq!(
-            parse_line("xor $128, $2").unwrap(),
-            Item::Instruction(i!(XOR, imm8!(2), imm16!(128)))
+            parse_line("mov %dr7, %rax").unwrap(),
+            Item::Instruction(i!(MOV, reg!(RAX), reg!(DR7)))
         );
         assert_eq!(
-            parse_line("xor $65536, $2").unwrap(),
-            Item::Instruction(i!(XOR, imm8!(2), imm32!(65536)))
+            parse_line("mov %rax, %db0").unwrap(),
+            Item::Instruction(i!(MOV, reg!(DR0), reg!(RAX)))
         );
+        assert!(parse_line("mov %cr9, %rax").is_err());
+        assert!(parse_line("mov %dr8, %rax").is_err());
     }
 }
@@ -0,0 +1,377 @@
+//! Rewrite instructions in assembled code (e.g. to redirect a call, or to turn a NOP into a
+//! jump).
+//!
+//! The instructions that are going to be patched are emitted with
+//! [`IncrementalAssembler::emit_patchable`](crate::incremental::IncrementalAssembler::emit_patchable)
+//! or [`IncrementalAssembler::emit_nop_slot`](crate::incremental::IncrementalAssembler::emit_nop_slot),
+//! which return the [`PatchPoint`] of each instruction. The patches are written atomically, with
+//! a single store to the aligned word that contains them (which must be within the code):
+//!
+//! ```
+//! use ras_x86::incremental::IncrementalAssembler;
+//! use ras_x86::instruction::Instruction;
+//! use ras_x86::operand::Operand;
+//! use ras_x86::{label, Mnemonic, Mode, EAX};
+//!
+//! let mut asm = IncrementalAssembler::long_mode();
+//! let slot = asm.emit_nop_slot(5).unwrap();
+//! asm.emit(Instruction::new(Mnemonic::RET, vec![])).unwrap();
+//! let tracer = asm.offset() as i64;
+//! let eax = Operand::Register(*EAX);
+//! asm.emit(Instruction::new(Mnemonic::XOR, vec![eax.clone(), eax])).unwrap();
+//! asm.emit(Instruction::new(Mnemonic::RET, vec![])).unwrap();
+//!
+//! // Turn the NOP into a jump to the tracer.
+//! let jmp = Instruction::new(Mnemonic::JMP, vec![label!("tracer".into())]);
+//! let symbols = |symbol: &str| (symbol == "tracer").then(|| tracer);
+//! asm.patch(|code| slot.rewrite(code, Mode::Long, &jmp, symbols))
+//!     .unwrap();
+//! assert_eq!(asm.code()[..5], [0xe9, 0x01, 0x00, 0x00, 0x00]);
+//! ```
+
+use crate::encoder::{Encoder, Fixup};
+use crate::instruction::{Instruction, PseudoPrefix};
+use crate::operand::{Memory, Operand, Register, Scale};
+use crate::symbol::SymbolOffset;
+use crate::{Mnemonic, Mode, RasError, RasResult, AX, EAX, RAX};
+
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU16, AtomicU32, AtomicU64, AtomicU8, Ordering};
+
+/// The size of the largest store that's guaranteed to be atomic.
+pub(crate) const WORD_SIZE: usize = 8;
+
+/// The location of an instruction that can be rewritten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PatchPoint {
+    /// The offset of the instruction.
+    pub offset: SymbolOffset,
+    /// The length of the instruction (in bytes).
+    pub len: usize,
+    /// The relative offset of the target of the instruction (e.g. the `rel32` of a `call`), if
+    /// it refers to a symbol.
+    pub target: Option<Fixup>,
+}
+
+impl PatchPoint {
+    /// Redirect the instruction to the specified target.
+    ///
+    /// The target is an offset from the start of `code` (which may be negative, or outside the
+    /// code: the offset of an absolute address is `addr - code.as_ptr()`).
+    pub fn redirect(&self, code: &mut [u8], target: i64) -> RasResult<()> {
+        let fixup = self.target.ok_or_else(|| {
+            RasError::Encoding(format!(
+                "the instruction at {:#x} doesn't have a relative target",
+                self.offset
+            ))
+        })?;
+
+        let rel_offset = target.wrapping_sub((fixup.offset + fixup.size) as i64);
+        let fits = match fixup.size {
+            1 => i8::try_from(rel_offset).is_ok(),
+            2 => i16::try_from(rel_offset).is_ok(),
+            4 => i32::try_from(rel_offset).is_ok(),
+            _ => false,
+        };
+        if !fits {
+            return Err(RasError::Encoding(format!(
+                "the target is out of range of the {}-byte relative offset at {:#x}",
+                fixup.size, fixup.offset
+            )));
+        }
+
+        write_code(
+            code,
+            fixup.offset as usize,
+            &rel_offset.to_le_bytes()[..fixup.size as usize],
+        )
+    }
+
+    /// Replace the instruction with another instruction of the same length.
+    ///
+    /// The offsets of the symbols the new instruction refers to are looked up with `symbols`
+    /// (they're relative to the start of `code`, like the targets of [`PatchPoint::redirect`]).
+    ///
+    /// Returns the patch point of the new instruction.
+    pub fn rewrite(
+        &self,
+        code: &mut [u8],
+        mode: Mode,
+        inst: &Instruction,
+        symbols: impl Fn(&str) -> Option<i64>,
+    ) -> RasResult<PatchPoint> {
+        // The instruction is encoded at offset 0, so the offsets of the symbols are made relative
+        // to the patch point.
+        let mut enc = Encoder::new(mode);
+        inst.encode(&mut enc)?;
+        let target = enc.fixup_in(0..enc.current_offset());
+
+        for symbol in enc.unresolved_symbols() {
+            let offset =
+                symbols(&symbol).ok_or_else(|| RasError::UndefinedSymbols(vec![symbol.clone()]))?;
+            enc.resolve_symbol(&symbol, offset.wrapping_sub(self.offset as i64))?;
+        }
+
+        // The labels of an incremental assembler aren't symbols.
+        let labels = enc.unresolved_labels();
+        if !labels.is_empty() {
+            return Err(RasError::UndefinedSymbols(
+                labels.iter().map(ToString::to_string).collect(),
+            ));
+        }
+
+        // Only the offsets of the symbols are known, so their addresses can't be patched in.
+        if let Some(symbol) = enc.unresolved_symbols().pop() {
+            return Err(RasError::Encoding(format!(
+                "can't patch in the address of {}",
+                symbol
+            )));
+        }
+
+        if enc.out.len() != self.len {
+            return Err(RasError::Encoding(format!(
+                "can't replace a {}-byte instruction with a {}-byte instruction",
+                self.len,
+                enc.out.len()
+            )));
+        }
+
+        write_code(code, self.offset as usize, &enc.out)?;
+
+        Ok(PatchPoint {
+            target: target.map(|fixup| Fixup {
+                offset: self.offset + fixup.offset,
+                ..fixup
+            }),
+            ..*self
+        })
+    }
+}
+
+/// Returns the NOP instruction recommended by the Intel manual for the specified length (between
+/// 1 and 9 bytes), in long or protected mode (there are no multi-byte NOPs in real mode).
+///
+/// The longer NOPs use `NOP r/m` with a memory operand, e.g. the 5-byte NOP is
+/// `nopl 0(%rax,%rax,1)`.
+pub fn nop(mode: Mode, len: usize) -> Option<Instruction> {
+    let base = match mode {
+        Mode::Long => *RAX,
+        Mode::Protected => *EAX,
+        Mode::Real => return None,
+    };
+    // (has an index, the size of the displacement, the size of the memory operand)
+    let (index, disp, size) = match len {
+        1 => return Some(Instruction::new(Mnemonic::NOP, vec![])),
+        2 => {
+            let ax = Operand::Register(*AX);
+            return Some(Instruction::new(Mnemonic::XCHG, vec![ax.clone(), ax]));
+        }
+        3 => (false, None, 32),
+        4 => (false, Some(PseudoPrefix::Disp8), 32),
+        5 => (true, Some(PseudoPrefix::Disp8), 32),
+        6 => (true, Some(PseudoPrefix::Disp8), 16),
+        7 => (false, Some(PseudoPrefix::Disp32), 32),
+        8 => (true, Some(PseudoPrefix::Disp32), 32),
+        9 => (true, Some(PseudoPrefix::Disp32), 16),
+        _ => return None,
+    };
+
+    let index: Option<Register> = if index { Some(base) } else { None };
+    let mem = Memory::sib(None, Some(base), index, Scale::Byte, disp.map(|_| 0));
+    let inst = Instruction::new(Mnemonic::NOP, vec![Operand::Memory(mem)]).with_memory_size(size);
+
+    Some(match disp {
+        Some(disp) => inst.with_pseudo_prefixes(disp.into()),
+        None => inst,
+    })
+}
+
+/// Returns the number of bytes of padding needed before an instruction at `offset`, so that the
+/// `len` bytes at `start` in the instruction fit in an aligned 8-byte word (and can be patched
+/// atomically).
+pub(crate) fn padding(offset: SymbolOffset, start: usize, len: usize) -> usize {
+    let start = offset as usize + start;
+    if len > WORD_SIZE || start % WORD_SIZE + len <= WORD_SIZE {
+        return 0;
+    }
+
+    WORD_SIZE - start % WORD_SIZE
+}
+
+/// Write `bytes` into `code` at the specified offset.
+///
+/// The bytes are written with a single atomic store (of the smallest aligned 1, 2, 4 or 8-byte
+/// word that contains them), so a thread that executes the code never sees a partially written
+/// instruction. This fails if there is no such word within `code`, or if `code` doesn't start at
+/// an 8-byte aligned address (the patch points are aligned relative to the start of the code).
+fn write_code(code: &mut [u8], offset: usize, bytes: &[u8]) -> RasResult<()> {
+    let base = code.as_ptr() as usize;
+    if !base.is_multiple_of(WORD_SIZE) {
+        return Err(RasError::Encoding(format!(
+            "the code at {:#x} isn't {}-byte aligned, so it can't be patched atomically",
+            base, WORD_SIZE
+        )));
+    }
+
+    let start = base + offset;
+    let word_size = [1, 2, 4, WORD_SIZE]
+        .iter()
+        .copied()
+        .find(|size| start % size + bytes.len() <= *size)
+        .filter(|size| {
+            let word = start - start % size;
+            word >= base && word + size <= base + code.len()
+        })
+        .ok_or_else(|| {
+            RasError::Encoding(format!(
+                "the {} bytes at {:#x} don't fit in an aligned {}-byte word of the code, so \
+                 they can't be patched atomically",
+                bytes.len(),
+                offset,
+                WORD_SIZE
+            ))
+        })?;
+
+    let word_offset = offset - start % word_size;
+    let mut value = [0; WORD_SIZE];
+    value[..word_size].copy_from_slice(&code[word_offset..word_offset + word_size]);
+    value[start % word_size..start % word_size + bytes.len()].copy_from_slice(bytes);
+
+    // SAFETY: the word is aligned, and it's within `code` (which is borrowed mutably).
+    unsafe {
+        let word = code.as_mut_ptr().add(word_offset);
+        match word_size {
+            1 => (*(word as *const AtomicU8)).store(value[0], Ordering::SeqCst),
+            2 => (*(word as *const AtomicU16))
+                .store(u16::from_ne_bytes([value[0], value[1]]), Ordering::SeqCst),
+            4 => (*(word as *const AtomicU32)).store(
+                u32::from_ne_bytes([value[0], value[1], value[2], value[3]]),
+                Ordering::SeqCst,
+            ),
+            _ => (*(word as *const AtomicU64)).store(u64::from_ne_bytes(value), Ordering::SeqCst),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{nop, write_code};
+    use crate::encoder::Encoder;
+    use crate::incremental::IncrementalAssembler;
+    use crate::instruction::Instruction;
+    use crate::operand::{Immediate, Operand};
+    use crate::{Mnemonic, Mode};
+
+    use std::slice;
+
+    #[test]
+    fn multi_byte_nops() {
+        let expected: &[&[u8]] = &[
+            &[0x90],
+            &[0x66, 0x90],
+            &[0x0f, 0x1f, 0x00],
+            &[0x0f, 0x1f, 0x40, 0x00],
+            &[0x0f, 0x1f, 0x44, 0x00, 0x00],
+            &[0x66, 0x0f, 0x1f, 0x44, 0x00, 0x00],
+            &[0x0f, 0x1f, 0x80, 0x00, 0x00, 0x00, 0x00],
+            &[0x0f, 0x1f, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00],
+            &[0x66, 0x0f, 0x1f, 0x84, 0x00, 0x00, 0x00, 0x00, 0x00],
+        ];
+
+        for mode in [Mode::Long, Mode::Protected] {
+            for (len, expected) in (1..).zip(expected) {
+                let mut enc = Encoder::new(mode);
+                let inst = nop(mode, len).unwrap();
+                inst.encode(&mut enc).unwrap();
+                assert_eq!(enc.out, *expected, "{}-byte NOP in {:?} mode", len, mode);
+            }
+        }
+
+        assert!(nop(Mode::Long, 0).is_none());
+        assert!(nop(Mode::Long, 10).is_none());
+    }
+
+    #[test]
+    fn redirect_and_rewrite() {
+        let mut asm = IncrementalAssembler::long_mode();
+        let target = asm.new_label();
+        let call = asm
+            .emit_patchable(Instruction::new(Mnemonic::CALL, vec![target.into()]))
+            .unwrap();
+        let nop = asm
+            .emit_patchable(Instruction::new(Mnemonic::NOP, vec![]))
+            .unwrap();
+        assert!(nop.target.is_none());
+        asm.bind(target).unwrap();
+        asm.emit(Instruction::new(Mnemonic::RET, vec![])).unwrap();
+        // The patches are written atomically, so the code must cover the whole 8-byte word.
+        asm.emit(Instruction::new(Mnemonic::RET, vec![])).unwrap();
+
+        assert_eq!(asm.code()[..5], [0xe8, 0x01, 0x00, 0x00, 0x00]);
+        asm.patch(|code| call.redirect(code, 0)).unwrap();
+        assert_eq!(asm.code()[..5], [0xe8, 0xfb, 0xff, 0xff, 0xff]);
+        assert!(asm.patch(|code| call.redirect(code, 1 << 40)).is_err());
+        assert!(asm.patch(|code| nop.redirect(code, 0)).is_err());
+
+        // `clc` has the same length as `nop`, `ret $8` doesn't.
+        let clc = Instruction::new(Mnemonic::CLC, vec![]);
+        let ret = Instruction::new(Mnemonic::RET, vec![Operand::Immediate(Immediate::Imm16(8))]);
+        assert!(asm
+            .patch(|code| nop.rewrite(code, Mode::Long, &ret, |_| None))
+            .is_err());
+        assert_eq!(
+            asm.patch(|code| nop.rewrite(code, Mode::Long, &clc, |_| None)),
+            Ok(nop)
+        );
+        assert_eq!(asm.code()[5], 0xf8);
+    }
+
+    #[test]
+    fn aligned_slots() {
+        let mut asm = IncrementalAssembler::long_mode();
+        asm.emit(Instruction::new(Mnemonic::RET, vec![])).unwrap();
+        // The slot is moved to the next word, after a 7-byte NOP.
+        let slot = asm.emit_nop_slot(8).unwrap();
+        assert_eq!(slot.offset, 8);
+        assert_eq!(asm.code()[1..4], [0x0f, 0x1f, 0x80]);
+        // The slot fits in the rest of the word.
+        assert_eq!(asm.emit_nop_slot(5).unwrap().offset, 16);
+        assert_eq!(asm.emit_nop_slot(3).unwrap().offset, 21);
+
+        assert!(asm.emit_nop_slot(9).is_err());
+        // A long instruction without a relative target can't be patched atomically.
+        let nop9 = nop(Mode::Long, 9).unwrap();
+        assert!(asm.emit_patchable(nop9).is_err());
+        assert_eq!(asm.offset(), 24);
+        assert!(nop(Mode::Real, 1).is_none());
+        assert!(IncrementalAssembler::real_mode().emit_nop_slot(2).is_err());
+    }
+
+    #[test]
+    fn write_within_words() {
+        let mut words = [0u64; 3];
+        // SAFETY: the words are 24 bytes long, and they aren't used while `code` is alive.
+        let code = unsafe { slice::from_raw_parts_mut(words.as_mut_ptr() as *mut u8, 24) };
+        for offset in 0..16 {
+            let res = write_code(code, offset, &[1, 2, 3, 4, 5]);
+            if offset % 8 <= 3 {
+                assert_eq!(res, Ok(()));
+                assert_eq!(code[offset..offset + 5], [1, 2, 3, 4, 5]);
+            } else {
+                assert!(res.is_err(), "5 bytes at {}", offset);
+            }
+            code.fill(0);
+        }
+
+        // The smallest aligned word that contains the bytes is used.
+        assert_eq!(write_code(&mut code[..7], 4, &[1, 2]), Ok(()));
+        assert_eq!(write_code(&mut code[..7], 6, &[3]), Ok(()));
+        assert_eq!(code[4..7], [1, 2, 3]);
+        assert!(write_code(&mut code[..7], 4, &[1, 2, 3]).is_err());
+
+        // The words are aligned relative to the start of the code, which must be aligned too.
+        assert!(write_code(&mut code[1..], 0, &[1]).is_err());
+    }
+}
@@ -5,8 +5,10 @@ pub type SymbolOffset = u64;
 pub struct Symbol {
     #[allow(unused)]
     pub(crate) ty: SymbolType,
-    /// The offset of this symbol in the text section.
+    /// The offset of this symbol in its section.
     pub(crate) offset: Option<SymbolOffset>,
+    /// The index of the section the symbol is defined in (`.text` is the first section).
+    pub(crate) section: usize,
     /// The attributes of the symbol.
     pub(crate) attrs: u8,
 }
@@ -31,6 +33,7 @@ impl Symbol {
         Self {
             ty,
             offset: None,
+            section: 0,
             attrs,
         }
     }
@@ -39,6 +42,7 @@ impl Symbol {
         Self {
             ty,
             offset: Some(offset),
+            section: 0,
             attrs,
         }
     }
@@ -52,10 +56,10 @@ impl Symbol {
     }
 
     pub fn is_global(&self) -> bool {
-        (self.attrs & SymbolAttribute::Global as u8) == 1
+        (self.attrs & SymbolAttribute::Global as u8) != 0
     }
 
     pub fn is_weak(&self) -> bool {
-        (self.attrs & SymbolAttribute::Weak as u8) == 1
+        (self.attrs & SymbolAttribute::Weak as u8) != 0
     }
 }
@@ -0,0 +1,9 @@
+mov (%eax), %ecx
+lea (%eax,%ecx,4), %edx
+mov (%esp), %eax
+mov 8(%ebp), %eax
+mov (%eax), %cx
+mov %rcx, (%ebx)
+popcnt (%eax), %ecx
+vaddps (%eax), %xmm1, %xmm2
+mov (%rax), %ecx
@@ -0,0 +1,22 @@
+    ret
+    .balign 16
+    ret
+    .p2align 3
+    ret
+    ret
+    ret
+    ret
+    ret
+    ret
+    ret
+    ret
+    ret
+    .align 32
+    ret
+    .data
+    .byte 1
+    .balign 8
+value:
+    .long 2
+    .p2align 4
+    .byte 3
@@ -0,0 +1,69 @@
+    vaddps %xmm2, %xmm1, %xmm0
+    vaddps %ymm2, %ymm1, %ymm0
+    vaddps %ymm10, %ymm1, %ymm0
+    vaddps %ymm2, %ymm11, %ymm12
+    vaddpd (%rax), %ymm1, %ymm0
+    vaddsd %xmm3, %xmm2, %xmm1
+    vaddss 8(%rsp), %xmm2, %xmm1
+    vsubps %ymm3, %ymm4, %ymm5
+    vmulpd %xmm1, %xmm2, %xmm3
+    vdivsd %xmm9, %xmm0, %xmm0
+    vsqrtps %ymm1, %ymm2
+    vxorps %ymm0, %ymm0, %ymm0
+    vandpd %xmm1, %xmm2, %xmm3
+    vmovaps (%rax), %ymm0
+    vmovaps %ymm0, (%rax)
+    vmovups 32(%rbx,%rcx,4), %ymm9
+    vmovups %ymm9, 32(%rbx,%rcx,4)
+    vmovdqa %xmm1, %xmm2
+    vmovdqu (%rsi), %ymm3
+    vmovss (%rax), %xmm1
+    vmovss %xmm1, (%rax)
+    vmovss %xmm3, %xmm2, %xmm1
+    vmovsd 8(%rbx), %xmm14
+    vbroadcastss (%rdi), %ymm0
+    vbroadcastss %xmm1, %ymm0
+    vbroadcastsd %xmm1, %ymm0
+    vzeroupper
+    vzeroall
+    vcvtsi2sd %eax, %xmm1, %xmm1
+    vcvtsi2sd %rax, %xmm1, %xmm1
+    vcvttsd2si %xmm0, %eax
+    vcvttsd2si %xmm0, %rax
+    vucomisd %xmm1, %xmm0
+    vshufps $27, %ymm2, %ymm1, %ymm0
+    vperm2f128 $1, %ymm2, %ymm1, %ymm0
+    vinsertf128 $1, %xmm2, %ymm1, %ymm0
+    vextractf128 $1, %ymm1, %xmm0
+    vmovd %eax, %xmm0
+    vmovq %rax, %xmm1
+    vmovq %xmm1, %rax
+    vmovmskps %ymm2, %eax
+    vptest %ymm1, %ymm0
+    vldmxcsr (%rax)
+    vstmxcsr 4(%rsp)
+    vpxor %xmm0, %xmm0, %xmm0
+    vpxor %ymm8, %ymm9, %ymm10
+    vpaddd %ymm1, %ymm2, %ymm3
+    vpmulld %ymm1, %ymm2, %ymm3
+    vpshufb %xmm1, %xmm2, %xmm3
+    vpcmpeqb (%rdi), %ymm0, %ymm1
+    vpslld $4, %ymm1, %ymm2
+    vpsrlq $32, %xmm9, %xmm10
+    vpsrldq $8, %xmm1, %xmm1
+    vpshufd $78, %ymm2, %ymm3
+    vpmovmskb %ymm0, %eax
+    vpbroadcastd %xmm0, %ymm1
+    vpbroadcastd (%rax), %ymm1
+    vpbroadcastq %xmm0, %xmm1
+    vpermd %ymm2, %ymm1, %ymm0
+    vpermq $78, %ymm1, %ymm0
+    vpsllvd %ymm2, %ymm1, %ymm0
+    vinserti128 $1, %xmm2, %ymm1, %ymm0
+    vextracti128 $1, %ymm1, %xmm0
+    vfmadd231ps %ymm2, %ymm1, %ymm0
+    vfmadd231ps (%rax), %ymm1, %ymm0
+    vfmadd231pd %xmm2, %xmm1, %xmm0
+    vfmadd213ss %xmm2, %xmm1, %xmm0
+    vfmadd132sd %xmm10, %xmm11, %xmm12
+    vfnmadd231ps %ymm2, %ymm1, %ymm0
@@ -0,0 +1,89 @@
+    vaddps %zmm2, %zmm1, %zmm0
+    vaddps %zmm2, %zmm1, %zmm0{%k1}
+    vaddps %zmm2, %zmm1, %zmm0{%k1}{z}
+    vaddps (%rax){1to16}, %zmm1, %zmm0
+    vaddps 4(%rax){1to16}, %zmm1, %zmm0
+    vaddpd (%rax){1to8}, %zmm1, %zmm0
+    vaddpd 8(%rax){1to4}, %ymm1, %ymm0
+    vaddps {rn-sae}, %zmm2, %zmm1, %zmm0
+    vaddps {rd-sae}, %zmm2, %zmm1, %zmm0
+    vaddps {ru-sae}, %zmm2, %zmm1, %zmm0
+    vaddps {rz-sae}, %zmm2, %zmm1, %zmm0
+    vmaxps {sae}, %zmm2, %zmm1, %zmm0
+    vaddps 64(%rax), %zmm1, %zmm0
+    vaddps 128(%rax), %zmm1, %zmm0
+    vaddps 100(%rax), %zmm1, %zmm0
+    vaddps 8128(%rax), %zmm1, %zmm0
+    vaddps 8192(%rax), %zmm1, %zmm0
+    vaddps -8192(%rax), %zmm1, %zmm0
+    vaddps -8256(%rax), %zmm1, %zmm0
+    vaddps 32(%rax), %ymm1, %ymm16
+    vaddps 16(%rax), %xmm17, %xmm0
+    vaddps %xmm18, %xmm1, %xmm0
+    vaddps %ymm2, %ymm17, %ymm0
+    vaddps %zmm2, %zmm1, %zmm31
+    vaddps %zmm26, %zmm21, %zmm9
+    vaddps %xmm2, %xmm1, %xmm0{%k1}
+    vaddss {rn-sae}, %xmm2, %xmm1, %xmm0
+    vaddss 8(%rax), %xmm1, %xmm20
+    vaddsd 8(%rax), %xmm1, %xmm20
+    vaddsd 12(%rax), %xmm1, %xmm20
+    vaddss %xmm1, %xmm2, %xmm3{%k7}{z}
+    vsqrtps %zmm1, %zmm2
+    vsqrtpd (%rbx){1to8}, %zmm3
+    vmovaps %zmm0, 64(%rsp)
+    vmovaps 128(%rsp), %zmm1{%k2}{z}
+    vmovups %zmm30, (%rax,%rcx,8)
+    vmovdqa32 %zmm1, %zmm2
+    vmovdqu64 128(%rdi), %zmm3
+    vmovdqu32 %ymm16, 32(%rsi)
+    vmovss 4(%rax), %xmm16
+    vmovss %xmm16, 4(%rax)
+    vmovsd %xmm3, %xmm2, %xmm17
+    vpaddd %zmm1, %zmm2, %zmm3
+    vpaddd (%rax){1to16}, %zmm2, %zmm3
+    vpaddq %ymm17, %ymm18, %ymm19
+    vpxord %zmm0, %zmm0, %zmm0
+    vpxorq %zmm0, %zmm0, %zmm0
+    vpandd 64(%rdx), %zmm4, %zmm5
+    vpmulld %zmm1, %zmm2, %zmm3
+    vpminsd %zmm1, %zmm2, %zmm3
+    vpcmpeqd %zmm2, %zmm1, %k1
+    vpcmpeqd %zmm2, %zmm1, %k1{%k2}
+    vpcmpeqd (%rax){1to16}, %zmm1, %k3
+    vpslld $4, %zmm1, %zmm2
+    vpslld $4, 64(%rax), %zmm2
+    vpsrlq $3, (%rax){1to8}, %zmm2
+    vpshufd $27, %zmm1, %zmm2
+    vshufps $27, %zmm2, %zmm1, %zmm0
+    vpermilps $27, %zmm1, %zmm0
+    vpermd %zmm2, %zmm1, %zmm0
+    vpermps 64(%rax), %zmm1, %zmm0
+    vfmadd231ps %zmm2, %zmm1, %zmm0
+    vfmadd231ps (%rax){1to16}, %zmm1, %zmm0
+    vfmadd231ps {rn-sae}, %zmm2, %zmm1, %zmm0
+    vfmadd213pd %zmm2, %zmm1, %zmm0{%k1}
+    vfmadd132ps %ymm20, %ymm1, %ymm0
+    vbroadcastss %xmm0, %zmm1
+    vbroadcastss 4(%rax), %zmm1
+    vbroadcastsd 8(%rax), %zmm1
+    vpbroadcastd %xmm0, %zmm1
+    vpbroadcastd 4(%rax), %zmm1{%k1}{z}
+    vpbroadcastd %eax, %zmm1
+    vpbroadcastq %xmm16, %zmm1
+    vcvtdq2ps {rz-sae}, %zmm1, %zmm2
+    vpaddb %zmm1, %zmm2, %zmm3
+    vpaddb 64(%rax), %zmm2, %zmm3
+    vxorps %zmm0, %zmm0, %zmm0
+    vxorpd %zmm1, %zmm1, %zmm1
+    vucomiss {sae}, %xmm1, %xmm0
+    vucomisd 8(%rax), %xmm16
+    vinsertf32x4 $1, %xmm2, %zmm1, %zmm0
+    vinsertf32x4 $1, 16(%rax), %zmm1, %zmm0
+    vextractf32x4 $2, %zmm1, %xmm0
+    vinsertf64x4 $1, 32(%rax), %zmm1, %zmm0
+    vextractf64x4 $1, %zmm1, 32(%rax)
+    vmovd %eax, %xmm16
+    vmovq %rax, %xmm16
+    vcvtsi2sd %rax, {rn-sae}, %xmm1, %xmm2
+    vcvtsi2ss %eax, %xmm1, %xmm20
@@ -0,0 +1,6 @@
+jmp *%rax
+call *%rbx
+call *8(%rbx)
+jmp *(%rbx,%rcx,4)
+ljmp *(%rax)
+lcall *8(%rbx)
@@ -0,0 +1,9 @@
+start:
+	.byte 1, -1, 0xff
+	.word 0x1234, -2
+	.long 0xdeadbeef
+	.quad 0xffffffffffffffff, -42
+	nop
+	.org 0x20
+end:
+	ret
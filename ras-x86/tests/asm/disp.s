@@ -0,0 +1,25 @@
+mov (%rax), %rcx
+mov 0(%rax), %rcx
+mov 127(%rax), %rcx
+mov 128(%rax), %rcx
+mov -128(%rax), %rcx
+mov -129(%rax), %rcx
+mov 2147483647(%rax), %rcx
+mov -2147483648(%rax), %rcx
+mov (%rbp), %rcx
+mov 0(%rbp), %rcx
+mov -8(%rbp), %rcx
+mov 4096(%rbp), %rcx
+mov (%rsp), %rcx
+mov 8(%rsp), %rcx
+mov 256(%rsp), %rcx
+mov (%rbp,%rax,1), %rcx
+mov 127(%rbp,%rax,8), %rcx
+mov -129(%rbx,%rcx,4), %rcx
+mov (%rsp,%rbp,2), %rcx
+mov (,%rcx,8), %rax
+mov 8(,%rbp,1), %rax
+vmovaps 64(%rax), %zmm1
+vmovaps -8192(%rax), %zmm1
+vmovaps 8192(%rax), %zmm1
+vmovaps 32(%rax), %zmm1
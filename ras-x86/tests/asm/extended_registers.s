@@ -0,0 +1,21 @@
+inc %r8
+inc %r15d
+mov %r15w, %r9w
+mov %r8b, %al
+mov %r11b, %r12b
+add %r9, %rax
+add %rax, %r9
+mov (%r12), %eax
+mov (%r13), %eax
+mov 8(%r13), %r14
+mov (%rax,%r12,1), %eax
+mov (%r12,%r13,8), %r10
+mov -128(%rsp,%r9,4), %r10d
+lea 16(%r15,%rbx,2), %rcx
+push %r12
+pop %r15
+mov $1, %r8d
+mov $-1, %r9
+xor %r10d, %r10d
+movq %r11, %xmm1
+vpaddd (%r9,%r10,4), %ymm1, %ymm2
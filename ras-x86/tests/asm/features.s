@@ -0,0 +1,59 @@
+popcnt %ax, %bx
+popcnt %eax, %ebx
+popcnt %rax, %rbx
+popcnt (%rax), %ecx
+lzcnt %ax, %cx
+lzcnt %eax, %ecx
+lzcnt (%rbx), %rdx
+tzcnt %ax, %cx
+tzcnt %rax, %rcx
+andn %ecx, %ebx, %eax
+andn (%rdx), %rbx, %rax
+blsr %ecx, %eax
+blsmsk %rcx, %rax
+blsi (%rdx), %eax
+pdep %ecx, %ebx, %eax
+pext %rcx, %rbx, %rax
+rorx $7, %ecx, %eax
+rorx $7, (%rbx), %rax
+adcx %ecx, %eax
+adox %rcx, %rax
+movbe (%rax), %cx
+movbe %rcx, (%rax)
+crc32 %al, %ecx
+crc32 %cl, %ecx
+crc32 %cx, %ecx
+crc32 %eax, %ecx
+crc32 %rax, %rcx
+rdrand %ax
+rdrand %eax
+rdseed %rax
+aesenc %xmm1, %xmm0
+aesenclast (%rax), %xmm0
+aesdec %xmm1, %xmm0
+aesdeclast %xmm1, %xmm0
+aesimc %xmm1, %xmm0
+aeskeygenassist $1, %xmm1, %xmm0
+vaesenc %xmm2, %xmm1, %xmm0
+pclmulqdq $17, %xmm1, %xmm0
+clflushopt (%rax)
+clwb (%rax)
+cmpxchg16b (%rax)
+xgetbv
+addsubps %xmm1, %xmm0
+haddpd %xmm1, %xmm0
+movddup %xmm1, %xmm0
+lddqu (%rax), %xmm0
+pshufb %xmm1, %xmm0
+pabsd %xmm1, %xmm0
+palignr $3, %xmm1, %xmm0
+pmulld %xmm1, %xmm0
+ptest %xmm1, %xmm0
+pblendw $5, %xmm1, %xmm0
+roundps $1, %xmm1, %xmm0
+pextrd $1, %xmm1, %eax
+pinsrd $1, %eax, %xmm1
+pmovzxbw %xmm1, %xmm0
+pcmpgtq %xmm1, %xmm0
+vcvtph2ps %xmm1, %ymm0
+vcvtps2ph $0, %ymm1, %xmm0
@@ -8,3 +8,14 @@ xor $-256, %rax
 xor $256, %ax
 xor $256, %eax
 xor $256, %rax
+
+add $128, %al
+add $255, %cl
+cmp $200, %al
+mov $255, %bl
+test $128, %al
+shl $255, %eax
+sar $128, %rcx
+bt $200, %eax
+add $128, %eax
+add $255, %ax
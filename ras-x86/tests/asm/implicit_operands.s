@@ -0,0 +1,49 @@
+    sete %al
+    setne %sil
+    setg (%rax)
+    setb 8(%rbx,%rcx,4)
+    cbw
+    cwde
+    cdqe
+    cwd
+    cdq
+    cqo
+    lodsb
+    lodsw
+    lodsq
+    stosw
+    movsw
+    cmpsw
+    scasw
+    insw
+    outsw
+    xlat (%rbx)
+    in %dx, %al
+    in %dx, %eax
+    out %ax, %dx
+    shl %cl, %eax
+    sar %cl, %rdx
+    rol %cl, %bl
+    shld %cl, %ecx, %eax
+    shrd %cl, %rcx, (%rax)
+    int $128
+    int $3
+    mov %ds, %cx
+    mov %fs, %eax
+    mov %ds, %rcx
+    mov %es, (%rbx)
+    mov %cx, %ds
+    mov %rcx, %fs
+    mov (%rbx), %ss
+    push %fs
+    pop %gs
+    movsx %cl, %rax
+    lar (%rbx), %rcx
+    lsl %ecx, %edi
+    smsw %ecx
+    sldt %rcx
+    pinsrw $1, %ecx, %xmm1
+    vmovq (%rax), %xmm1
+    vmovq %xmm1, (%rax)
+    vpermilps $1, %xmm2, %xmm1
+    mov %ecx, %es
@@ -0,0 +1,61 @@
+    emms
+    movd %eax, %mm0
+    movd %mm1, %ebx
+    movd (%rax), %mm2
+    movd %mm3, 4(%rax)
+    movq %rax, %mm0
+    movq %mm7, %rcx
+    movq %mm1, %mm2
+    movq (%rax), %mm3
+    movq %mm4, 8(%rsp)
+    paddb %mm1, %mm0
+    paddw %mm2, %mm1
+    paddd (%rax), %mm2
+    paddq %mm3, %mm4
+    paddsb %mm5, %mm6
+    paddusw %mm7, %mm0
+    psubb %mm1, %mm0
+    psubd 8(%rax), %mm1
+    psubq %mm2, %mm3
+    psubusb %mm1, %mm2
+    pand %mm1, %mm0
+    pandn %mm2, %mm1
+    por %mm3, %mm2
+    pxor %mm0, %mm0
+    pcmpeqb %mm1, %mm0
+    pcmpgtd (%rax), %mm1
+    pmullw %mm1, %mm0
+    pmulhw %mm2, %mm1
+    pmaddwd %mm3, %mm2
+    pmuludq %mm4, %mm3
+    packsswb %mm1, %mm0
+    packuswb %mm2, %mm1
+    punpckhbw %mm1, %mm0
+    punpckldq (%rax), %mm1
+    punpcklwd %mm3, %mm2
+    psllw %mm1, %mm0
+    psrlq (%rax), %mm2
+    psrad %mm3, %mm4
+    psllw $4, %mm0
+    pslld $4, %mm1
+    psllq $63, %mm2
+    psrlw $1, %mm3
+    psrld $2, %mm4
+    psrlq $3, %mm5
+    psraw $4, %mm6
+    psrad $5, %mm7
+    pavgb %mm1, %mm0
+    pmaxub %mm2, %mm1
+    pminsw (%rax), %mm2
+    psadbw %mm3, %mm2
+    pmovmskb %mm1, %eax
+    pextrw $3, %mm2, %ecx
+    pshufw $27, %mm1, %mm0
+    maskmovq %mm1, %mm0
+    movntq %mm2, (%rax)
+    movq2dq %mm1, %xmm0
+    movdq2q %xmm2, %mm3
+    cvtpi2ps %mm1, %xmm0
+    cvttps2pi %xmm1, %mm2
+    paddd %xmm1, %xmm0
+    paddd %mm1, %mm0
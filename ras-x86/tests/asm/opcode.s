@@ -0,0 +1,23 @@
+mov $5, %eax
+mov $5, %rax
+mov $5, %al
+mov $300, %ax
+mov $5, %ecx
+mov $5, %cl
+bswap %eax
+bswap %rcx
+vblendvps %xmm4, %xmm3, %xmm2, %xmm1
+vblendvps %ymm4, %ymm3, %ymm2, %ymm1
+vblendvpd %xmm4, %xmm3, %xmm2, %xmm1
+vblendvpd %ymm4, %ymm3, %ymm2, %ymm1
+vpblendvb %xmm4, %xmm3, %xmm2, %xmm1
+vpblendvb %ymm4, %ymm3, %ymm2, %ymm1
+vblendvps %xmm4, (%rax), %xmm2, %xmm1
+rdrand %eax
+rdseed %rcx
+enter $8, $0
+enter $128, $1
+enter $0, $128
+enter $0, $255
+push $128
+ret $128
@@ -0,0 +1,25 @@
+{load} mov %eax, %ecx
+{store} mov %eax, %ecx
+{load} add %eax, %ecx
+{store} add %eax, %ecx
+{load} vmovaps %xmm1, %xmm2
+{store} vmovaps %xmm1, %xmm2
+{disp32} mov (%rax), %ecx
+{disp32} mov 8(%rax), %ecx
+{disp8} mov (%rax), %ecx
+{disp8} mov (%rbp), %ecx
+{disp32} mov (%rbp), %ecx
+{disp32} mov 8(%rsp), %ecx
+{load} {disp32} mov 8(%rax), %ecx
+{vex} vaddps %xmm1, %xmm2, %xmm3
+{evex} vaddps %xmm1, %xmm2, %xmm3
+{evex} vaddps 64(%rax), %xmm2, %xmm3
+{rex} mov %eax, %ecx
+{rex} add %al, %cl
+{rex} nop
+{rex} mov (%rax), %ecx
+{disp32} jmp foo
+{disp8} jmp foo
+{disp8} jne foo
+foo:
+{disp8} jmp foo
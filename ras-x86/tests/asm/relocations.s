@@ -0,0 +1,16 @@
+start:
+	mov $value, %esi
+	mov $end, %edi
+	mov $value, %rax
+	push $buffer
+	jmp buffer
+	.data
+value:
+	.long 1, start, end
+	.quad value, buffer
+	.bss
+buffer:
+	.skip 8
+	.text
+end:
+	ret
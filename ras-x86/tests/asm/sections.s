@@ -0,0 +1,17 @@
+start:
+	nop
+	.data
+value:
+	.long 42
+	.skip 4
+	.bss
+buffer:
+	.zero 16
+counter:
+	.space 8
+	.section .rodata
+message:
+	.byte 0x68, 0x69, 0
+	.text
+end:
+	ret
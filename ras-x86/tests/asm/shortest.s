@@ -0,0 +1,31 @@
+xor $2, %ax
+add $256, %ax
+add $256, %eax
+add $2, %eax
+add $2, %al
+add $2, %cl
+add $2, %rax
+add $200, %rax
+adc $2, %ax
+sbb $300, %ax
+cmp $2, %eax
+and $-1, %eax
+or $127, %rax
+sub $-128, %eax
+sub $128, %eax
+test $2, %eax
+test $2, %al
+test $2, %cl
+mov $2, %eax
+mov $2, %rax
+mov $-1, %rax
+imul $2, %eax, %ecx
+imul $200, %eax, %ecx
+push $2
+push $128
+shl $1, %eax
+sar $1, %rax
+rol $1, %cl
+shr $1, %si
+shl $2, %eax
+int $3
@@ -0,0 +1,50 @@
+    addsd %xmm1, %xmm0
+    addsd %xmm8, %xmm0
+    addsd %xmm0, %xmm15
+    addss 8(%rax), %xmm2
+    subpd %xmm3, %xmm4
+    mulps (%rbx), %xmm5
+    divsd 16(%rsp), %xmm6
+    sqrtsd %xmm1, %xmm1
+    movsd %xmm1, %xmm2
+    movsd (%rax), %xmm2
+    movsd %xmm9, 8(%rbx)
+    movss (%rcx), %xmm0
+    movss %xmm0, (%rcx)
+    movaps %xmm1, %xmm12
+    movups (%rax,%rcx,4), %xmm3
+    movapd %xmm2, (%rdx)
+    movdqa %xmm0, %xmm1
+    movdqu 32(%rsi), %xmm10
+    pxor %xmm0, %xmm0
+    pxor %xmm11, %xmm14
+    xorps %xmm7, %xmm7
+    xorpd %xmm1, %xmm2
+    andps %xmm3, %xmm4
+    ucomisd %xmm1, %xmm0
+    comiss (%rdi), %xmm2
+    cvtsi2sd %eax, %xmm0
+    cvtsi2sd %rax, %xmm1
+    cvtsi2ss %ebx, %xmm9
+    cvttsd2si %xmm0, %eax
+    cvttsd2si %xmm0, %rax
+    cvtss2sd %xmm1, %xmm2
+    cvtsd2ss %xmm2, %xmm1
+    movd %eax, %xmm0
+    movd %xmm0, %eax
+    movq %rax, %xmm1
+    movq %xmm1, %rax
+    movq %xmm1, %xmm2
+    shufps $27, %xmm1, %xmm0
+    pshufd $78, %xmm2, %xmm3
+    pslld $4, %xmm1
+    psrlq $32, %xmm9
+    paddd %xmm1, %xmm2
+    pcmpeqb (%rax), %xmm0
+    pmovmskb %xmm0, %eax
+    ldmxcsr (%rax)
+    stmxcsr 4(%rsp)
+    sfence
+    lfence
+    mfence
+    pause
@@ -0,0 +1,6 @@
+foo:
+nop
+bar:
+add %rax, %rcx
+jmp *%rax
+end:
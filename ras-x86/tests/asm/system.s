@@ -0,0 +1,40 @@
+    mov %cr0, %rax
+    mov %cr2, %rdx
+    mov %cr3, %rax
+    mov %rax, %cr3
+    mov %cr4, %rbx
+    mov %rbx, %cr4
+    mov %rax, %cr8
+    mov %cr8, %rdx
+    mov %dr0, %rax
+    mov %dr7, %rcx
+    mov %rax, %dr6
+    mov %db7, %rax
+    mov %rdi, %db1
+    lgdt (%rax)
+    lgdt 8(%rsp)
+    lidt (%rbx)
+    lidt 16(%rax,%rcx,8)
+    sgdt (%rax)
+    sidt 8(%rax)
+    ltr %ax
+    ltr (%rax)
+    lldt %cx
+    lmsw %ax
+    str %ax
+    swapgs
+    sysret
+    sysretq
+    sysexit
+    sysexitq
+    syscall
+    iretq
+    wrmsr
+    rdmsr
+    invlpg (%rax)
+    cli
+    sti
+    hlt
+    clts
+    cpuid
+    rdtsc
@@ -0,0 +1,93 @@
+    fld %st(3)
+    fld %st
+    fld %st(0)
+    flds 4(%rax)
+    fldl 8(%rax)
+    fldt (%rax)
+    fld1
+    fldz
+    fldpi
+    fst %st(2)
+    fsts (%rbx)
+    fstl -8(%rbp)
+    fstp %st(1)
+    fstps (%rax)
+    fstpl 16(%rsp)
+    fstpt (%rax)
+    fadd %st(1), %st
+    fadd %st, %st(1)
+    fadd %st(2)
+    fadds (%rax)
+    faddl (%rax)
+    faddp
+    faddp %st(1)
+    faddp %st, %st(1)
+    faddp %st, %st(5)
+    fsub %st(1), %st
+    fsub %st, %st(1)
+    fsub %st(3)
+    fsubs 4(%rax)
+    fsubp
+    fsubp %st(2)
+    fsubp %st, %st(1)
+    fsubr %st(1), %st
+    fsubr %st, %st(1)
+    fsubrp
+    fsubrp %st, %st(1)
+    fmul %st(1), %st
+    fmul %st, %st(7)
+    fmull 8(%rax)
+    fmulp
+    fmulp %st, %st(2)
+    fdiv %st(1), %st
+    fdiv %st, %st(1)
+    fdivs (%rax)
+    fdivp
+    fdivp %st, %st(1)
+    fdivr %st(1), %st
+    fdivr %st, %st(1)
+    fdivrp
+    fdivrp %st, %st(1)
+    fxch
+    fxch %st(2)
+    fcom
+    fcom %st(1)
+    fcoms (%rax)
+    fcompl (%rax)
+    fcompp
+    fucomi %st(1), %st
+    fucomip %st(1), %st
+    fcomip %st(3), %st
+    fcmovb %st(1), %st
+    ffree %st(4)
+    fchs
+    fabs
+    fsqrt
+    fsin
+    fcos
+    filds (%rax)
+    fildl (%rax)
+    fildll (%rax)
+    fildq 8(%rax)
+    fistps (%rax)
+    fistpl (%rax)
+    fistpll (%rax)
+    fisttpll (%rax)
+    fiaddl (%rax)
+    fimuls (%rax)
+    ficompl 4(%rax)
+    fbld (%rax)
+    fbstp (%rax)
+    fnstsw %ax
+    fnstsw (%rax)
+    fstsw %ax
+    fnstcw (%rax)
+    fldcw (%rax)
+    fnstenv (%rax)
+    fldenv (%rax)
+    fnsave (%rax)
+    frstor (%rax)
+    fxsave (%rax)
+    fxrstor (%rax)
+    fninit
+    fwait
@@ -1,17 +1,24 @@
+use std::collections::BTreeSet;
 use std::env;
+use std::fmt::Write as _;
 use std::fs;
-use std::process::{Command, Stdio};
+use std::process::{self, Command, Stdio};
 
+use goblin::elf::reloc::r_to_str;
+use goblin::elf::section_header::{self, SHT_RELA, SHT_STRTAB, SHT_SYMTAB};
+use goblin::elf::sym::{bind_to_str, type_to_str, visibility_to_str, STT_FILE, STT_SECTION};
 use goblin::elf::Elf;
 use ras_x86::assembler::Assembler;
 use ras_x86::parser::parse_asm;
 
 const TEST_CASES: &str = "tests/asm";
-const RAS_TEST_OBJ: &str = "/tmp/ras-test.o";
 
 #[test]
-fn compare_text_section_with_gas() {
+fn compare_with_gas() {
     let test_dir = format!("{}/{}", env!("CARGO_MANIFEST_DIR"), TEST_CASES);
+    // The object file written by `as` (unique to this process, so that concurrent test runs
+    // don't overwrite each other's files).
+    let gas_obj = env::temp_dir().join(format!("ras-{}-test.o", process::id()));
     for path in fs::read_dir(test_dir).unwrap() {
         let path = path.unwrap().path();
         let test_file = path.file_name().unwrap().to_str().unwrap();
@@ -29,7 +36,9 @@ fn compare_text_section_with_gas() {
             .unwrap();
 
         let status = Command::new("as")
-            .args(["-o", RAS_TEST_OBJ, path.to_str().unwrap()])
+            .arg("-o")
+            .arg(&gas_obj)
+            .arg(&path)
             .stdout(Stdio::null())
             .stderr(Stdio::null())
             .status()
@@ -41,7 +50,7 @@ fn compare_text_section_with_gas() {
             panic!("failed to assemble {} with as", path.to_str().unwrap());
         }
 
-        let expected = fs::read(RAS_TEST_OBJ).unwrap();
+        let expected = fs::read(&gas_obj).unwrap();
         let expected_text = read_text_section(&expected);
         let actual_text = read_text_section(&out);
 
@@ -50,7 +59,18 @@ fn compare_text_section_with_gas() {
             "incorrect .text section for \"{}\"",
             test_file
         );
+
+        let diff = ObjectSummary::new(&expected).diff(&ObjectSummary::new(&out));
+        assert!(
+            diff.is_empty(),
+            "the object file assembled from \"{}\" doesn't match the one produced by as \
+             (- as, + ras):\n{}",
+            test_file,
+            diff
+        );
     }
+
+    let _ = fs::remove_file(&gas_obj);
 }
 
 fn read_text_section(input: &[u8]) -> &[u8] {
@@ -68,3 +88,150 @@ fn read_text_section(input: &[u8]) -> &[u8] {
 
     &input[text_section.file_range().unwrap()]
 }
+
+/// The parts of an object file that are compared with the output of `as`.
+///
+/// Everything is normalized to a readable string, so that the differences can be reported as a
+/// line-based diff. The details that depend on how the object file is laid out (the section
+/// indices, the order of the symbols, the string tables) are left out.
+struct ObjectSummary {
+    sections: BTreeSet<String>,
+    symbols: BTreeSet<String>,
+    relocations: BTreeSet<String>,
+}
+
+impl ObjectSummary {
+    fn new(input: &[u8]) -> Self {
+        let elf = Elf::parse(input).expect("failed to parse ELF file");
+        let section_name = |index: usize| {
+            elf.section_headers
+                .get(index)
+                .and_then(|hdr| elf.shdr_strtab.get_at(hdr.sh_name))
+                .unwrap_or_default()
+        };
+
+        let sections = elf
+            .section_headers
+            .iter()
+            .enumerate()
+            .skip(1)
+            // The empty sections (such as the .data and .bss sections `as` always emits) don't
+            // affect the linked program, and the symbol, string and relocation tables are
+            // compared separately.
+            .filter(|(_, hdr)| {
+                hdr.sh_size != 0 && ![SHT_SYMTAB, SHT_STRTAB, SHT_RELA].contains(&hdr.sh_type)
+            })
+            .map(|(index, hdr)| {
+                format!(
+                    "{} type={} flags={} align={}",
+                    section_name(index),
+                    section_header::sht_to_str(hdr.sh_type),
+                    section_flags(hdr.sh_flags),
+                    hdr.sh_addralign
+                )
+            })
+            .collect();
+
+        let symbols = elf
+            .syms
+            .iter()
+            .skip(1)
+            // The section and file symbols are an implementation detail of the assembler.
+            .filter(|sym| ![STT_SECTION, STT_FILE].contains(&sym.st_type()))
+            .map(|sym| {
+                format!(
+                    "{} bind={} type={} vis={} section={} value={:#x} size={}",
+                    elf.strtab.get_at(sym.st_name).unwrap_or_default(),
+                    bind_to_str(sym.st_bind()),
+                    type_to_str(sym.st_type()),
+                    visibility_to_str(sym.st_visibility()),
+                    match sym.st_shndx {
+                        0 => "UND",
+                        index => section_name(index),
+                    },
+                    sym.st_value,
+                    sym.st_size
+                )
+            })
+            .collect();
+
+        let relocations = elf
+            .shdr_relocs
+            .iter()
+            .flat_map(|(index, relocs)| {
+                let section = section_name(elf.section_headers[*index].sh_info as usize);
+                relocs.iter().map(move |reloc| (section, reloc))
+            })
+            .map(|(section, reloc)| {
+                let symbol = elf
+                    .syms
+                    .get(reloc.r_sym)
+                    .map(|sym| match sym.st_type() {
+                        STT_SECTION => section_name(sym.st_shndx),
+                        _ => elf.strtab.get_at(sym.st_name).unwrap_or_default(),
+                    })
+                    .unwrap_or_default();
+
+                format!(
+                    "{}+{:#x} type={} symbol={} addend={}",
+                    section,
+                    reloc.r_offset,
+                    r_to_str(reloc.r_type, elf.header.e_machine),
+                    symbol,
+                    reloc.r_addend.unwrap_or_default()
+                )
+            })
+            .collect();
+
+        Self {
+            sections,
+            symbols,
+            relocations,
+        }
+    }
+
+    /// Returns a diff of the two summaries (an empty string if they're the same).
+    fn diff(&self, other: &Self) -> String {
+        let mut diff = String::new();
+
+        for (name, expected, actual) in [
+            ("sections", &self.sections, &other.sections),
+            ("symbols", &self.symbols, &other.symbols),
+            ("relocations", &self.relocations, &other.relocations),
+        ] {
+            if expected == actual {
+                continue;
+            }
+
+            writeln!(diff, "{}:", name).unwrap();
+            for missing in expected.difference(actual) {
+                writeln!(diff, "  - {}", missing).unwrap();
+            }
+            for unexpected in actual.difference(expected) {
+                writeln!(diff, "  + {}", unexpected).unwrap();
+            }
+        }
+
+        diff
+    }
+}
+
+/// Returns the section flags in the format used by `readelf` (e.g. `AX`).
+fn section_flags(flags: u64) -> String {
+    const FLAGS: &[(u32, char)] = &[
+        (section_header::SHF_WRITE, 'W'),
+        (section_header::SHF_ALLOC, 'A'),
+        (section_header::SHF_EXECINSTR, 'X'),
+        (section_header::SHF_MERGE, 'M'),
+        (section_header::SHF_STRINGS, 'S'),
+        (section_header::SHF_INFO_LINK, 'I'),
+        (section_header::SHF_GROUP, 'G'),
+        (section_header::SHF_TLS, 'T'),
+    ];
+
+    FLAGS
+        .iter()
+        .filter(|(flag, _)| flags & u64::from(*flag) != 0)
+        .map(|(_, c)| *c)
+        .collect()
+}
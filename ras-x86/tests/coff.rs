@@ -0,0 +1,206 @@
+use object::pe::{
+    IMAGE_SCN_ALIGN_16BYTES, IMAGE_SCN_CNT_CODE, IMAGE_SCN_CNT_INITIALIZED_DATA,
+    IMAGE_SCN_CNT_UNINITIALIZED_DATA, IMAGE_SCN_MEM_EXECUTE, IMAGE_SCN_MEM_READ,
+    IMAGE_SCN_MEM_WRITE,
+};
+use object::{
+    Architecture, BinaryFormat, Object, ObjectSection, ObjectSymbol, RelocationKind,
+    RelocationTarget, SectionFlags, SymbolKind,
+};
+use ras_x86::assembler::{Assembler, ObjectFormat};
+use ras_x86::parser::parse_asm;
+use ras_x86::symbol::{Symbol, SymbolAttribute, SymbolType};
+use ras_x86::RasError;
+
+const ASM: &str = "
+main:
+    mov $value, %ecx
+    call exit
+    ret
+    .data
+value:
+    .long 42
+table:
+    .quad value
+    .bss
+buffer:
+    .skip 8
+    .section .rodata
+message:
+    .byte 1
+";
+
+fn coff_object(asm: &str, asm_mode: Assembler, globals: &[&str]) -> Result<Vec<u8>, RasError> {
+    let global = Symbol::new_decl(SymbolType::Quad, SymbolAttribute::Global as u8);
+    let symbols = globals
+        .iter()
+        .map(|name| (name.to_string(), global.clone()))
+        .collect::<Vec<_>>();
+    let mut obj = vec![];
+    asm_mode
+        .object_format(ObjectFormat::Coff)
+        .items(parse_asm(asm).unwrap())
+        .symbols(&symbols)
+        .write_obj(&mut obj)?;
+    Ok(obj)
+}
+
+fn coff_symbol<'a>(file: &'a object::File, name: &str) -> object::Symbol<'a, 'a> {
+    file.symbols()
+        .find(|sym| sym.name() == Ok(name))
+        .unwrap_or_else(|| panic!("missing symbol {}", name))
+}
+
+#[test]
+fn sections() {
+    let obj = coff_object(ASM, Assembler::long_mode(), &["main", "table", "exit"]).unwrap();
+    let file = object::File::parse(&*obj).unwrap();
+    assert_eq!(file.format(), BinaryFormat::Coff);
+    assert_eq!(file.architecture(), Architecture::X86_64);
+
+    let characteristics = |name: &str| {
+        let section = file.section_by_name(name).unwrap();
+        assert_eq!(section.align(), 16);
+        match section.flags() {
+            SectionFlags::Coff { characteristics } => characteristics,
+            flags => panic!("unexpected flags {:?}", flags),
+        }
+    };
+    assert_eq!(
+        characteristics(".text"),
+        IMAGE_SCN_CNT_CODE | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ | IMAGE_SCN_ALIGN_16BYTES
+    );
+    assert_eq!(
+        characteristics(".data"),
+        IMAGE_SCN_CNT_INITIALIZED_DATA
+            | IMAGE_SCN_MEM_READ
+            | IMAGE_SCN_MEM_WRITE
+            | IMAGE_SCN_ALIGN_16BYTES
+    );
+    assert_eq!(
+        characteristics(".bss"),
+        IMAGE_SCN_CNT_UNINITIALIZED_DATA
+            | IMAGE_SCN_MEM_READ
+            | IMAGE_SCN_MEM_WRITE
+            | IMAGE_SCN_ALIGN_16BYTES
+    );
+    assert_eq!(
+        characteristics(".rdata"),
+        IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_READ | IMAGE_SCN_ALIGN_16BYTES
+    );
+}
+
+#[test]
+fn symbols_and_relocations() {
+    let obj = coff_object(ASM, Assembler::long_mode(), &["main", "table", "exit"]).unwrap();
+    let file = object::File::parse(&*obj).unwrap();
+
+    let main = coff_symbol(&file, "main");
+    assert!(main.is_global());
+    assert_eq!(main.kind(), SymbolKind::Text);
+    let table = coff_symbol(&file, "table");
+    assert!(table.is_global());
+    assert_eq!(table.kind(), SymbolKind::Data);
+    assert_eq!(table.address(), 4);
+    assert!(coff_symbol(&file, "value").is_local());
+    assert!(coff_symbol(&file, "buffer").is_local());
+    let exit = coff_symbol(&file, "exit");
+    assert!(exit.is_undefined());
+    assert!(exit.is_global());
+
+    let symbol_name = |target| match target {
+        RelocationTarget::Symbol(index) => file.symbol_by_index(index).unwrap().name().unwrap(),
+        target => panic!("unexpected target {:?}", target),
+    };
+
+    // IMAGE_REL_AMD64_ADDR32, and IMAGE_REL_AMD64_REL32 (relative to the end of the 4 bytes
+    // of the displacement).
+    let text = file.section_by_name(".text").unwrap();
+    let relocs = text
+        .relocations()
+        .map(|(offset, reloc)| {
+            (
+                offset,
+                reloc.kind(),
+                reloc.size(),
+                symbol_name(reloc.target()),
+                reloc.addend(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        relocs,
+        [
+            (1, RelocationKind::Absolute, 32, ".data", 0),
+            (6, RelocationKind::Relative, 32, "exit", -4),
+        ]
+    );
+    // The implicit addend of the reference to the local symbol is its offset in the section.
+    assert_eq!(text.data().unwrap()[1..5], [0, 0, 0, 0]);
+
+    // IMAGE_REL_AMD64_ADDR64.
+    let data = file.section_by_name(".data").unwrap();
+    let relocs = data
+        .relocations()
+        .map(|(offset, reloc)| {
+            (
+                offset,
+                reloc.kind(),
+                reloc.size(),
+                symbol_name(reloc.target()),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(relocs, [(4, RelocationKind::Absolute, 64, ".data")]);
+}
+
+#[test]
+fn mangled_names() {
+    let asm = "
+    main:
+        call exit
+        ret
+    ";
+    let obj = coff_object(asm, Assembler::protected_mode(), &["main", "exit"]).unwrap();
+    let file = object::File::parse(&*obj).unwrap();
+    assert_eq!(file.architecture(), Architecture::I386);
+    assert!(coff_symbol(&file, "_main").is_global());
+    assert!(coff_symbol(&file, "_exit").is_undefined());
+
+    // The names of the 64-bit symbols aren't mangled.
+    let obj = coff_object(asm, Assembler::long_mode(), &["main", "exit"]).unwrap();
+    let file = object::File::parse(&*obj).unwrap();
+    assert!(coff_symbol(&file, "main").is_global());
+    assert!(coff_symbol(&file, "exit").is_undefined());
+}
+
+#[test]
+fn weak_definitions() {
+    let weak = Symbol::new_decl(SymbolType::Quad, SymbolAttribute::Weak as u8);
+    let mut obj = vec![];
+    let err = Assembler::long_mode()
+        .object_format(ObjectFormat::Coff)
+        .items(parse_asm("main:\nret").unwrap())
+        .symbols(&[("main".to_string(), weak)])
+        .write_obj(&mut obj)
+        .unwrap_err();
+    assert!(matches!(err, RasError::Encoding(_)), "{:?}", err);
+}
+
+#[test]
+fn undefined_weak_symbols() {
+    let weak = SymbolAttribute::Global as u8 | SymbolAttribute::Weak as u8;
+    let weak = Symbol::new_decl(SymbolType::Quad, weak);
+    let mut obj = vec![];
+    let err = Assembler::long_mode()
+        .object_format(ObjectFormat::Coff)
+        .items(parse_asm("main:\ncall hook\nret").unwrap())
+        .symbols(&[("hook".to_string(), weak)])
+        .write_obj(&mut obj)
+        .unwrap_err();
+    assert!(
+        matches!(err, RasError::Encoding(ref msg) if msg.contains("undefined weak symbol hook")),
+        "{:?}",
+        err
+    );
+}
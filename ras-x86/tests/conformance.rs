@@ -0,0 +1,439 @@
+//! Check the encoding of every instruction repr from the instruction table against GNU as.
+//!
+//! For each repr that is valid in long mode, the test synthesizes a few representative operand
+//! combinations (every register class, memory operands with every displacement width, and
+//! boundary immediates), assembles each instruction with both ras and `as`, and compares the
+//! bytes. The failures are grouped by mnemonic in the report. The instructions that are known to
+//! fail are listed in `tests/conformance/known_failures.txt`: the test fails if any other
+//! instruction fails (or if a listed instruction no longer fails, so that the list doesn't go
+//! stale).
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::panic;
+use std::path::PathBuf;
+use std::process::{self, Command};
+
+use goblin::elf::Elf;
+use ras_x86::assembler::Assembler;
+use ras_x86::instruction::instruction_table;
+use ras_x86::parser::parse_asm;
+use ras_x86::repr::{InstructionRepr, OperandKind, OperandRepr};
+use ras_x86::{Mnemonic, Mode};
+
+const KNOWN_FAILURES: &str = "tests/conformance/known_failures.txt";
+
+/// The prefix of the labels that mark the start of each test case in the source passed to `as`.
+const CASE_LABEL: &str = "ras_case_";
+
+/// An instruction to assemble with both ras and `as`.
+struct TestCase {
+    mnemonic: Mnemonic,
+    /// The instruction, in AT&T syntax.
+    asm: String,
+}
+
+#[derive(Debug)]
+enum Failure {
+    /// ras failed to assemble an instruction `as` accepts.
+    Missing(String),
+    /// ras and `as` encoded the instruction differently.
+    Mismatch { expected: Vec<u8>, actual: Vec<u8> },
+    /// ras panicked while assembling the instruction.
+    Panic,
+}
+
+#[test]
+fn conformance_with_gas() {
+    let cases = test_cases();
+    let expected = assemble_with_gas(&cases);
+
+    let mut failures: BTreeMap<String, Vec<(&str, Failure)>> = BTreeMap::new();
+    let mut passed = 0;
+
+    for (case, expected) in cases.iter().zip(expected) {
+        // The operand combination isn't valid according to `as`.
+        let expected = match expected {
+            Some(expected) => expected,
+            None => continue,
+        };
+
+        let failure = match panic::catch_unwind(|| assemble_with_ras(&case.asm)) {
+            Ok(Ok(actual)) if actual == expected => {
+                passed += 1;
+                continue;
+            }
+            Ok(Ok(actual)) => Failure::Mismatch { expected, actual },
+            Ok(Err(e)) => Failure::Missing(e),
+            Err(_) => Failure::Panic,
+        };
+
+        failures
+            .entry(format!("{:?}", case.mnemonic))
+            .or_default()
+            .push((&case.asm, failure));
+    }
+
+    let report = failure_report(&failures, passed);
+    let report_path = temp_path("conformance-report.txt");
+    fs::write(&report_path, &report).unwrap();
+    println!("{}", report);
+
+    let known_failures =
+        fs::read_to_string(format!("{}/{}", env!("CARGO_MANIFEST_DIR"), KNOWN_FAILURES)).unwrap();
+    let known_failures = known_failures
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect::<BTreeSet<_>>();
+    let failed = failures
+        .values()
+        .flatten()
+        .map(|(asm, _)| *asm)
+        .collect::<BTreeSet<_>>();
+
+    let new_failures = failed.difference(&known_failures).collect::<Vec<_>>();
+    let fixed = known_failures.difference(&failed).collect::<Vec<_>>();
+
+    assert!(
+        new_failures.is_empty(),
+        "the encodings of {:?} don't match GNU as (see {})",
+        new_failures,
+        report_path.display()
+    );
+    assert!(
+        fixed.is_empty(),
+        "{:?} now match GNU as, please remove them from {}",
+        fixed,
+        KNOWN_FAILURES
+    );
+}
+
+/// Synthesize the test cases for all the instruction reprs that are valid in long mode.
+fn test_cases() -> Vec<TestCase> {
+    let mut seen = HashSet::new();
+    let mut cases = vec![];
+
+    for (mnemonic, reprs) in instruction_table() {
+        for repr in reprs.iter() {
+            if !repr.is_valid_in_mode(&Mode::Long) {
+                continue;
+            }
+
+            for asm in instructions(*mnemonic, repr) {
+                if seen.insert(asm.clone()) {
+                    cases.push(TestCase {
+                        mnemonic: *mnemonic,
+                        asm,
+                    });
+                }
+            }
+        }
+    }
+
+    cases
+}
+
+/// Returns the instructions (in AT&T syntax) that exercise `repr`.
+///
+/// Rather than trying every combination of operands, each operand is varied in turn while the
+/// other ones are fixed to their first sample.
+fn instructions(mnemonic: Mnemonic, repr: &InstructionRepr) -> Vec<String> {
+    let is_indirect_branch = matches!(mnemonic, Mnemonic::JMP | Mnemonic::CALL);
+    let samples = match repr
+        .operands
+        .iter()
+        .map(|op| {
+            let samples = operand_samples(op);
+            if samples.is_empty() {
+                return None;
+            }
+
+            // The target of an indirect jump or call is prefixed with a '*'.
+            if is_indirect_branch && op.kind == OperandKind::ModRmRegMem {
+                return Some(samples.iter().map(|s| format!("*{}", s)).collect());
+            }
+
+            Some(samples)
+        })
+        .collect::<Option<Vec<Vec<String>>>>()
+    {
+        Some(samples) => samples,
+        None => return vec![],
+    };
+
+    let mnemonic = format!("{:?}", mnemonic).to_lowercase();
+    let mut instructions = vec![];
+
+    for (i, op_samples) in samples.iter().enumerate() {
+        for sample in op_samples {
+            // The operands are listed in Intel order in the instruction table.
+            let operands = samples
+                .iter()
+                .enumerate()
+                .map(|(j, s)| if i == j { sample.as_str() } else { &s[0] })
+                .rev()
+                .collect::<Vec<_>>();
+
+            instructions.push(format!("{} {}", mnemonic, operands.join(", ")));
+        }
+    }
+
+    if samples.is_empty() {
+        instructions.push(mnemonic);
+    }
+
+    instructions
+}
+
+/// Returns some sample operands (in AT&T syntax) of the specified kind.
+///
+/// The relative offsets, the memory offsets and the far pointers aren't covered, because `as`
+/// picks the size of their encoding based on the value (or the location) of the target.
+fn operand_samples(op: &OperandRepr) -> Vec<String> {
+    use OperandKind::*;
+
+    let strs = |samples: &[&str]| samples.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+    let gprs = |size: u32| match size {
+        8 => strs(&["%cl", "%bh"]),
+        16 => strs(&["%cx", "%si"]),
+        32 => strs(&["%ecx", "%edi"]),
+        64 => strs(&["%rcx", "%rdi"]),
+        _ => vec![],
+    };
+    let with_mem = |mut regs: Vec<String>| {
+        regs.extend(memory_samples());
+        regs
+    };
+
+    match op.kind {
+        Reg | ModRmReg | OpcodeRd => gprs(op.size),
+        ModRmRegMem => with_mem(gprs(op.size)),
+        R32M16 => with_mem(gprs(32)),
+        R64M16 => with_mem(gprs(64)),
+        Imm => immediate_samples(op.size),
+        Al => match op.size {
+            8 => strs(&["%al"]),
+            16 => strs(&["%ax"]),
+            32 => strs(&["%eax"]),
+            64 => strs(&["%rax"]),
+            _ => vec![],
+        },
+        Zero => strs(&["$0"]),
+        One => strs(&["$1"]),
+        Three => strs(&["$3"]),
+        Cl => strs(&["%cl"]),
+        Dx => strs(&["%dx"]),
+        Sreg => strs(&["%ds", "%fs"]),
+        Cs => strs(&["%cs"]),
+        Ds => strs(&["%ds"]),
+        Es => strs(&["%es"]),
+        Fs => strs(&["%fs"]),
+        Gs => strs(&["%gs"]),
+        Ss => strs(&["%ss"]),
+        Cr => strs(&["%cr0", "%cr3"]),
+        Cr8 => strs(&["%cr8"]),
+        Dr => strs(&["%dr0", "%dr7"]),
+        Mm | Mm1 | Mm2 => strs(&["%mm1", "%mm7"]),
+        MmM32 | MmM64 | Mm2M64 => with_mem(strs(&["%mm1", "%mm7"])),
+        Xmm => strs(&["%xmm1", "%xmm7", "%xmm15"]),
+        XmmM32 | XmmM64 | XmmM128 => with_mem(strs(&["%xmm1", "%xmm7", "%xmm15"])),
+        Ymm => strs(&["%ymm1", "%ymm7", "%ymm15"]),
+        YmmM256 => with_mem(strs(&["%ymm1", "%ymm7", "%ymm15"])),
+        Zmm => strs(&["%zmm1", "%zmm7", "%zmm31"]),
+        ZmmM512 => with_mem(strs(&["%zmm1", "%zmm7", "%zmm31"])),
+        K => strs(&["%k1", "%k7"]),
+        St0 => strs(&["%st"]),
+        Sti => strs(&["%st(1)", "%st(7)"]),
+        M | M8 | M16 | M32 | M64 | M128 | M256 | M512 | M16And16 | M16And32 | M32And32
+        | M16And64 | M32Fp | M64Fp | M80Fp | M16Int | M32Int | M64Int | M80Bcd | M2Byte
+        | M14M28Byte | M94M108Byte | M512Byte => memory_samples(),
+        Moffs
+        | Rel8
+        | Rel16
+        | Rel32
+        | FarPointer16
+        | FarPointer32
+        | MemIndirectFarPointer16
+        | MemIndirectFarPointer32
+        | MemIndirectFarPointer64 => vec![],
+    }
+}
+
+/// Memory operands with every displacement width, and with every addressing form that needs
+/// special handling in the ModRM/SIB bytes.
+fn memory_samples() -> Vec<String> {
+    [
+        "(%rbx)",
+        "8(%rbx)",
+        "-128(%rbx)",
+        "4096(%rbx)",
+        "-2147483648(%rbx)",
+        "(%rsp)",
+        "(%rbp)",
+        "16(%rbx,%rcx,4)",
+        "(,%rcx,8)",
+    ]
+    .iter()
+    .map(|s| s.to_string())
+    .collect()
+}
+
+/// The immediates at the boundaries of each immediate size.
+fn immediate_samples(size: u32) -> Vec<String> {
+    let samples: &[i64] = match size {
+        8 => &[0, 127, -128],
+        16 => &[128, -129, 32767, -32768],
+        32 | 64 => &[32768, -32769, 2147483647, -2147483648],
+        _ => &[],
+    };
+
+    samples.iter().map(|imm| format!("${}", imm)).collect()
+}
+
+fn assemble_with_ras(asm: &str) -> Result<Vec<u8>, String> {
+    let items = parse_asm(asm).map_err(|e| e.to_string())?;
+
+    Assembler::long_mode()
+        .items(items)
+        .dump_text()
+        .map_err(|e| e.to_string())
+}
+
+/// Assemble all the test cases with `as`, returning the encoding of each instruction (or `None`
+/// if `as` rejected it).
+///
+/// Each instruction is preceded by a label, so the encoding of the instructions can be extracted
+/// from the `.text` section using the symbol table.
+fn assemble_with_gas(cases: &[TestCase]) -> Vec<Option<Vec<u8>>> {
+    let src_path = temp_path("conformance.s");
+    let obj_path = temp_path("conformance.o");
+    let mut rejected = HashSet::new();
+
+    loop {
+        let mut src = String::new();
+        // The line numbers of the instructions (used for mapping the errors reported by `as` to
+        // the test cases).
+        let mut lines = BTreeMap::new();
+
+        for (i, case) in cases.iter().enumerate() {
+            if rejected.contains(&i) {
+                continue;
+            }
+
+            writeln!(src, "{}{}:", CASE_LABEL, i).unwrap();
+            writeln!(src, "{}", case.asm).unwrap();
+            lines.insert(lines.len() * 2 + 2, i);
+        }
+
+        fs::write(&src_path, &src).unwrap();
+
+        let output = Command::new("as")
+            .arg("--64")
+            .arg("-o")
+            .arg(&obj_path)
+            .arg(&src_path)
+            .output()
+            .unwrap_or_else(|e| panic!("failed to run as: {}", e));
+
+        // Drop the instructions `as` rejected (`<file>:<line>: Error: <msg>`), the ones with an
+        // ambiguous operand size (ras doesn't support mnemonic suffixes, so there's no way to
+        // specify the operand size of e.g. `add $1, (%rax)`), and the ones whose implicit memory
+        // operand `as` ignores with a warning (e.g. `xlat 8(%rbx)`), and try again.
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let errors = stderr
+            .lines()
+            .filter(|line| {
+                line.contains(": Error: ")
+                    || line.contains("no instruction mnemonic suffix")
+                    || line.contains("is not valid here")
+            })
+            .filter_map(|line| line.strip_prefix(src_path.to_str()?)?.split(':').nth(1))
+            .filter_map(|line| lines.get(&line.parse::<usize>().ok()?))
+            .copied()
+            .collect::<Vec<_>>();
+
+        match (output.status.success(), errors.is_empty()) {
+            (true, true) => break,
+            (false, true) => panic!("failed to assemble the test cases with as:\n{}", stderr),
+            (_, false) => rejected.extend(errors),
+        }
+    }
+
+    let obj = fs::read(&obj_path).unwrap();
+    let _ = fs::remove_file(&src_path);
+    let _ = fs::remove_file(&obj_path);
+    let elf = Elf::parse(&obj).expect("failed to parse ELF file");
+    let text_hdr = elf
+        .section_headers
+        .iter()
+        .find(|hdr| elf.shdr_strtab.get_at(hdr.sh_name) == Some(".text"))
+        .expect("object file does not have a .text section");
+    let text = &obj[text_hdr.file_range().unwrap()];
+
+    // The offset of each test case in the .text section.
+    let mut offsets = elf
+        .syms
+        .iter()
+        .filter_map(|sym| {
+            let name = elf.strtab.get_at(sym.st_name)?;
+            let case = name.strip_prefix(CASE_LABEL)?.parse::<usize>().ok()?;
+            Some((case, sym.st_value as usize))
+        })
+        .collect::<Vec<_>>();
+    offsets.sort_by_key(|(_, offset)| *offset);
+
+    let mut encodings = vec![None; cases.len()];
+    for (i, (case, start)) in offsets.iter().enumerate() {
+        let end = offsets.get(i + 1).map_or(text.len(), |(_, end)| *end);
+        encodings[*case] = Some(text[*start..end].to_vec());
+    }
+
+    encodings
+}
+
+/// Returns a path in the temporary directory that is unique to this process, so that concurrent
+/// test runs don't overwrite each other's files.
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("ras-{}-{}", process::id(), name))
+}
+
+fn failure_report(failures: &BTreeMap<String, Vec<(&str, Failure)>>, passed: usize) -> String {
+    let failed = failures.values().map(Vec::len).sum::<usize>();
+    let mut report = format!(
+        "{} instructions match GNU as, {} don't ({} mnemonics)\n",
+        passed,
+        failed,
+        failures.len()
+    );
+
+    for (mnemonic, failures) in failures {
+        writeln!(report, "\n{} ({} failures):", mnemonic, failures.len()).unwrap();
+        for (asm, failure) in failures {
+            match failure {
+                Failure::Missing(e) => writeln!(report, "  {}: {}", asm, e.trim()),
+                Failure::Mismatch { expected, actual } => writeln!(
+                    report,
+                    "  {}: expected {}, found {}",
+                    asm,
+                    hex(expected),
+                    hex(actual)
+                ),
+                Failure::Panic => writeln!(report, "  {}: panicked", asm),
+            }
+            .unwrap();
+        }
+    }
+
+    report
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
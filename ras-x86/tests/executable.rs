@@ -0,0 +1,88 @@
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+use goblin::elf::program_header::{PF_R, PF_W, PF_X, PT_LOAD};
+use goblin::elf::Elf;
+use ras_x86::assembler::Assembler;
+use ras_x86::executable::ExecutableWriter;
+use ras_x86::parser::parse_asm;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Assemble the program into an executable, run it, and return its exit status.
+fn run(name: &str, asm: &str, exec: &ExecutableWriter) -> i32 {
+    let items = parse_asm(asm).unwrap();
+    let mut exe = vec![];
+    Assembler::long_mode()
+        .items(items)
+        .write_exec(exec, &mut exe)
+        .unwrap();
+
+    let path: PathBuf = std::env::temp_dir().join(format!("ras-{}-{}", name, std::process::id()));
+    fs::write(&path, &exe).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    let status = Command::new(&path).status().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    status.code().unwrap()
+}
+
+#[test]
+fn exit_status() {
+    let asm = "
+        main:
+        mov $60, %eax
+        mov $42, %edi
+        syscall
+    ";
+    let exec = ExecutableWriter::new().base_address(0x10000).entry("main");
+    assert_eq!(run("exit", asm, &exec), 42);
+}
+
+#[test]
+fn data_and_bss() {
+    let asm = "
+        .data
+        value:
+        .long 40
+        .bss
+        counter:
+        .skip 4
+        .text
+        _start:
+        mov $value, %esi
+        mov (%rsi), %edi
+        mov $counter, %ebx
+        add $2, %edi
+        mov %edi, (%rbx)
+        xor %edi, %edi
+        mov (%rbx), %edi
+        mov $60, %eax
+        syscall
+    ";
+    assert_eq!(run("data", asm, &ExecutableWriter::new()), 42);
+
+    let items = parse_asm(asm).unwrap();
+    let out = Assembler::long_mode().items(items).assemble().unwrap();
+    let exe = ExecutableWriter::new().write(&out).unwrap();
+    let elf = Elf::parse(&exe).unwrap();
+    let segments = elf
+        .program_headers
+        .iter()
+        .filter(|header| header.p_type == PT_LOAD)
+        .map(|header| (header.p_flags, header.p_filesz, header.p_memsz))
+        .collect::<Vec<_>>();
+    let headers_len = elf.header.e_phoff + 3 * u64::from(elf.header.e_phentsize);
+    let text_len = out.text().len() as u64;
+    assert_eq!(
+        segments,
+        [
+            (PF_R | PF_X, headers_len + text_len, headers_len + text_len),
+            (PF_R | PF_W, 4, 4),
+            (PF_R | PF_W, 0, 4),
+        ]
+    );
+    assert_eq!(elf.entry, 0x400000 + headers_len);
+}
@@ -0,0 +1,113 @@
+#![cfg(all(target_os = "linux", target_arch = "x86_64"))]
+
+use ras_x86::assembled::RelocationKind;
+use ras_x86::assembler::Assembler;
+use ras_x86::executable::ExecutableWriter;
+use ras_x86::linker::Linker;
+use ras_x86::parser::parse_asm;
+use ras_x86::symbol::{Symbol, SymbolAttribute, SymbolType};
+
+use std::collections::BTreeSet;
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::process::{Command, Stdio};
+
+/// The object assembled by GNU as refers to the data through all the kinds of relocations the
+/// linker supports, and calls a function defined by the object assembled by ras.
+const GAS_MAIN: &str = "
+    .globl _start
+_start:
+    lea value(%rip), %rsi
+    mov (%rsi), %edi
+    mov $table, %rbx
+    mov (%rbx), %rax
+    add (%rax), %edi
+    mov $half, %ecx
+    add (%rcx), %edi
+    call finish
+    .data
+value:
+    .long 40
+half:
+    .long -38
+table:
+    .quad value
+";
+
+const RAS_FINISH: &str = "
+finish:
+    mov $60, %eax
+    syscall
+";
+
+fn gas_object(name: &str, asm: &str) -> Vec<u8> {
+    let dir = std::env::temp_dir();
+    let src = dir.join(format!("ras-link-{}-{}.s", name, std::process::id()));
+    let obj = src.with_extension("o");
+    fs::write(&src, asm).unwrap();
+    let status = Command::new("as")
+        .arg("-o")
+        .args([&obj, &src])
+        .stdout(Stdio::null())
+        .status()
+        .expect("failed to run as");
+    assert!(status.success(), "failed to assemble {} with as", name);
+
+    let data = fs::read(&obj).unwrap();
+    fs::remove_file(&src).unwrap();
+    fs::remove_file(&obj).unwrap();
+    data
+}
+
+fn ras_object(asm: &str, globals: &[&str]) -> Vec<u8> {
+    let global = Symbol::new_decl(SymbolType::Quad, SymbolAttribute::Global as u8);
+    let symbols = globals
+        .iter()
+        .map(|name| (name.to_string(), global.clone()))
+        .collect::<Vec<_>>();
+    let mut obj = vec![];
+    Assembler::long_mode()
+        .items(parse_asm(asm).unwrap())
+        .symbols(&symbols)
+        .write_obj(&mut obj)
+        .unwrap();
+    obj
+}
+
+#[test]
+fn link_gas_and_ras_objects() {
+    let out = Linker::new()
+        .object(gas_object("main", GAS_MAIN))
+        .object(ras_object(RAS_FINISH, &["finish"]))
+        .link()
+        .unwrap();
+
+    let kinds = out
+        .relocations()
+        .iter()
+        .map(|reloc| format!("{:?}", reloc.kind))
+        .collect::<BTreeSet<_>>();
+    let expected = [
+        // The PC32 and PLT32 relocations.
+        RelocationKind::Relative32,
+        RelocationKind::Absolute32,
+        RelocationKind::Absolute32Signed,
+        RelocationKind::Absolute64,
+    ]
+    .iter()
+    .map(|kind| format!("{:?}", kind))
+    .collect::<BTreeSet<_>>();
+    assert_eq!(kinds, expected);
+
+    let exe = ExecutableWriter::new().write(&out).unwrap();
+    let path = std::env::temp_dir().join(format!("ras-link-{}", std::process::id()));
+    fs::write(&path, &exe).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    let status = Command::new(&path).status().unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_eq!(status.code(), Some(42));
+
+    // The same code can be laid out as a flat image.
+    let image = out.flat_binary(0x1000).unwrap();
+    assert_eq!(image.len(), out.text().len() + 16);
+}